@@ -0,0 +1,758 @@
+//! Acquisition loop of the boilert application.
+//! Reads the sensors, publishes values on MQTT, maintains the history and hands the
+//! results over to the UI.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use boilert::model::{EnergyReport, Reading, SensorPayload, SensorStatus, Snapshot};
+use chrono::{DateTime, Utc};
+use tokio::sync::{Mutex, watch};
+use tokio::time;
+
+use crate::alerts::Alerts;
+use crate::board::Board;
+use crate::chart::{CHART_WIDTH, ChartPathBuilder, dashed_path};
+use crate::clock::{ClockStatus, next_boundary, previous_boundary};
+use crate::commands::Controls;
+use crate::config::{Config, PayloadFormat, PublishValue, RangePolicy, SensorConfig};
+use crate::filter::Smoother;
+use crate::forecast::TankModel;
+use crate::format::{self, Unit};
+use crate::health::Health;
+use crate::heater::HeaterStats;
+use crate::history::SensorHistory;
+use crate::hotplug::Presence;
+use crate::logging::{debug, warning};
+use crate::metrics::Metrics;
+use crate::mixing::MixingValveMonitor;
+use crate::mqtt::Publisher;
+use crate::outputs::Outputs;
+use crate::pressure::PressureSensor;
+use crate::sensors::{self, Measurement, TemperatureSensor};
+use crate::ui::UiSnapshot;
+
+/// Handles shared by the acquisition task.
+///
+/// The history lives behind an `Arc` so that it survives a restart of the task
+/// by the supervisor.
+#[derive(Clone)]
+pub struct Acquisition {
+    pub config: Config,
+    pub board: Board,
+    pub publisher: Publisher,
+    /// Latest values for the UI refresh task.
+    pub ui_snapshot: watch::Sender<UiSnapshot>,
+    /// Latest state for the HTTP API and other consumers (`None` until the first cycle).
+    pub snapshot: watch::Sender<Option<Snapshot>>,
+    pub history: Arc<Mutex<Vec<SensorHistory>>>,
+    pub alerts: Alerts,
+    pub clock_status: ClockStatus,
+    pub health: Health,
+    pub metrics: Metrics,
+    /// Heater state, for the forecast.
+    pub heater: HeaterStats,
+    /// 1-Wire sensors missing from the bus.
+    pub presence: Presence,
+    /// Requests of the remote commands (immediate cycle, reset of the extremes).
+    pub controls: Controls,
+    /// Safe settings changed at runtime (publication cadence, thresholds).
+    pub settings: watch::Receiver<Config>,
+    /// Outputs the scripting hooks may switch.
+    pub outputs: Outputs,
+}
+
+impl Acquisition {
+    /// Reads all sensors periodically, publishes the values and updates the UI snapshot.
+    pub async fn run(self) {
+        let Acquisition {
+            config: mut sensor_config,
+            board,
+            publisher,
+            ui_snapshot,
+            snapshot: snapshot_tx,
+            history,
+            alerts,
+            clock_status,
+            health,
+            metrics,
+            heater,
+            presence,
+            controls,
+            mut settings,
+            outputs,
+        } = self;
+
+        // The loop runs at the pace of the fastest sensor; each sensor is read on its own schedule
+        let cycle_interval = sensor_config.cycle_interval();
+        let mut schedule = Schedule::new(cycle_interval, sensor_config.timing.align_to_clock);
+        // History points are taken on wall-clock boundaries (e.g., quarter-hours), not relative
+        // to startup, so they stay aligned after a suspend or a restart of this task.
+        let period = sensor_config.timing.history_period();
+        let mut next_history_at = next_boundary(Utc::now(), period);
+        // Chart paths only change when a history point is added
+        let mut chart_builder = ChartPathBuilder::new(sensor_config.chart.smoothing);
+        let mut history_changed = true;
+
+        #[cfg(feature = "scripting")]
+        let mut script_hooks = sensor_config.scripting.script.as_deref().and_then(|path| {
+            crate::scripting::ScriptHooks::load(path, outputs)
+                .map_err(|e| warning!("Scripting hooks disabled: {:#}", e))
+                .ok()
+        });
+        #[cfg(not(feature = "scripting"))]
+        let _ = outputs;
+        #[cfg(not(feature = "scripting"))]
+        if sensor_config.scripting.script.is_some() {
+            warning!("A script is configured but boilert was built without the `scripting` feature");
+        }
+
+        let backends: Arc<Vec<_>> = Arc::new(sensor_config.sensors.iter().map(|s| sensors::open(s, &board)).collect());
+        let chart_bounds = sensor_config.chart_bounds();
+        for delta in &sensor_config.deltas {
+            for name in [&delta.plus, &delta.minus] {
+                if !sensor_config.sensors.iter().any(|s| &s.name == name) {
+                    warning!("Virtual sensor {}: unknown sensor {}", delta.name, name);
+                }
+            }
+        }
+
+        // The forecast extends the charts to the right of the newest history point
+        let forecast = &sensor_config.forecast;
+        let period_h = sensor_config.timing.history_period_min as f32 / 60.0;
+        let forecast_width = forecast.horizon_h.max(0.0) / period_h;
+
+        // Last valid measurement of each sensor, held during the fault grace period
+        let fault_grace = sensor_config.timing.fault_grace();
+        let mut last_good: Vec<Option<(Measurement, Instant)>> = vec![None; sensor_config.sensors.len()];
+        let mut smoothers: Vec<Smoother> =
+            sensor_config.sensors.iter().map(|s| Smoother::new(s.filter, s.filter_samples)).collect();
+        // Time of the next read of each sensor, and its latest reading, repeated until then
+        let mut next_reads: Vec<Instant> = vec![Instant::now(); sensor_config.sensors.len()];
+        let mut last_readings: Vec<Option<Reading>> = vec![None; sensor_config.sensors.len()];
+        // Lowest and highest valid temperature of each sensor since the last reset
+        let mut extremes: Vec<Option<(f32, f32)>> = vec![None; sensor_config.sensors.len()];
+        let mut energy_kwh = 0.0;
+        let mut avg_temp = 0.0;
+
+        let pressure_sensor = sensor_config.pressure.as_ref().map(|c| Arc::new(PressureSensor::new(c, &board)));
+        let mut last_pressure: Option<(f32, Instant)> = None;
+        let mut mixing_valve = sensor_config.mixing_valve.as_ref().map(MixingValveMonitor::new);
+        let payload_format = sensor_config.mqtt.payload;
+        let retain = sensor_config.mqtt.retain;
+        // Readings waiting for the next publication, on every cycle without a publish interval
+        let mut publish_interval = sensor_config.timing.publish_interval();
+        let publish_value = sensor_config.timing.publish_value;
+        let mut next_publish_at = Instant::now();
+        let mut unpublished: Vec<Unpublished> = vec![Unpublished::default(); sensor_config.sensors.len()];
+        // Report by exception: values staying within the deadband are only published again
+        // once the max silence elapsed
+        let mut deadband_c = sensor_config.timing.publish_deadband_c;
+        let mut max_silence = sensor_config.timing.max_silence();
+        let mut published: Vec<Option<Published>> = vec![None; sensor_config.sensors.len()];
+
+        loop {
+            // A forced cycle reads and publishes every sensor, due or not
+            let (forced, boundary) = tokio::select! {
+                boundary = schedule.next() => (false, boundary),
+                _ = controls.publish_requested() => (true, None),
+            };
+            if controls.take_reset_extremes() {
+                extremes.fill(None);
+            }
+            if settings.has_changed().unwrap_or(false) {
+                let changed = settings.borrow_and_update().clone();
+                sensor_config.timing.publish_interval_s = changed.timing.publish_interval_s;
+                sensor_config.timing.publish_deadband_c = changed.timing.publish_deadband_c;
+                sensor_config.timing.max_silence_s = changed.timing.max_silence_s;
+                sensor_config.pressure = changed.pressure;
+                sensor_config.boiler = changed.boiler;
+                for (sensor, changed) in sensor_config.sensors.iter_mut().zip(changed.sensors) {
+                    sensor.in_tank = changed.in_tank;
+                    sensor.min_valid = changed.min_valid;
+                    sensor.max_valid = changed.max_valid;
+                    sensor.out_of_range = changed.out_of_range;
+                }
+                publish_interval = sensor_config.timing.publish_interval();
+                deadband_c = sensor_config.timing.publish_deadband_c;
+                max_silence = sensor_config.timing.max_silence();
+                next_publish_at = next_publish_at.min(Instant::now() + publish_interval);
+                if let (Some(monitor), Some(config)) = (mixing_valve.as_mut(), &changed.mixing_valve) {
+                    monitor.set_config(config);
+                }
+            }
+            // Aligned cycles carry the time of their boundary, so that series join on equal times
+            let now = boundary.unwrap_or_else(Utc::now);
+            let cycle_start = Instant::now();
+            // Like the reads, a publication due within half a cycle happens now
+            let publish_now = forced || next_publish_at <= cycle_start + cycle_interval / 2;
+            if publish_now {
+                next_publish_at = cycle_start + publish_interval;
+            }
+
+            // Reads block for up to ~750 ms per DS18B20: keep them off the runtime threads,
+            // and run them side by side, the pressure transducer included
+            let pressure_read = pressure_sensor
+                .clone()
+                .map(|sensor| tokio::task::spawn_blocking(move || sensor.read_bar()));
+            // A sensor due within half a cycle is read now rather than a whole cycle late
+            let due: Vec<bool> = next_reads
+                .iter()
+                .zip(&last_readings)
+                .map(|(&at, last)| forced || last.is_none() || at <= cycle_start + cycle_interval / 2)
+                .collect();
+            let results = match read_all(backends.clone(), &due).await {
+                Ok(results) => results,
+                Err(e) => {
+                    warning!("Sensor read task failed: {}", e);
+                    continue;
+                }
+            };
+
+            let mut temps = Vec::new();
+            let mut readings = Vec::new();
+            let sensor_results = sensor_config
+                .sensors
+                .iter()
+                .zip(results)
+                .zip(last_good.iter_mut())
+                .zip(smoothers.iter_mut().zip(extremes.iter_mut()))
+                .zip(next_reads.iter_mut().zip(last_readings.iter_mut()).zip(unpublished.iter_mut()));
+            for ((((sensor, result), last_good), (smoother, extreme)), ((next_read, last_reading), unpublished)) in
+                sensor_results
+            {
+                let Some((result, latency)) = result else {
+                    // Not due: the latest reading stands, and is not published again
+                    if let Some(reading) = last_reading {
+                        temps.push(reading.value);
+                        readings.push(reading.clone());
+                    }
+                    continue;
+                };
+                *next_read = cycle_start + sensor.read_interval(&sensor_config.timing);
+                metrics.record_read_latency(&sensor.name, latency);
+                // Physically impossible values never reach MQTT or the history
+                let (result, suspect) = match result {
+                    Ok(measurement) if !sensor.is_plausible(measurement.temperature) => {
+                        metrics.record_implausible_value(&sensor.name);
+                        out_of_range(sensor, measurement)
+                    }
+                    result => (result, false),
+                };
+                let (measurement, status) = match result {
+                    Ok(measurement) if suspect => (measurement, SensorStatus::Suspect),
+                    Ok(measurement) => {
+                        // Only valid readings are smoothed, before anything else sees them
+                        let measurement = Measurement {
+                            temperature: smoother.push(measurement.temperature),
+                            ..measurement
+                        };
+                        *last_good = Some((measurement, Instant::now()));
+                        (measurement, SensorStatus::Ok)
+                    }
+                    Err(e) => {
+                        // The disconnection is logged once by the rescan
+                        if !presence.is_missing(&sensor.id) {
+                            warning!("Error reading sensor {}: {}", sensor.name, e);
+                        }
+                        health.record_sensor_error();
+                        // Keep the last valid value for a while rather than a meaningless zero
+                        match *last_good {
+                            Some((held, at)) if at.elapsed() <= fault_grace => (held, SensorStatus::Stale),
+                            Some((held, _)) => {
+                                smoother.reset();
+                                (held, SensorStatus::Error)
+                            }
+                            None => (Measurement::default(), SensorStatus::Error),
+                        }
+                    }
+                };
+                let temp = measurement.temperature;
+                debug!("Sensor {}: {:.3} °C, {:?}, read in {} ms", sensor.name, temp, status, latency.as_millis());
+                let reading = Reading {
+                    sensor: sensor.name.clone(),
+                    value: temp,
+                    humidity: measurement.humidity,
+                    pressure_hpa: measurement.pressure_hpa,
+                    status,
+                    timestamp: now,
+                };
+                temps.push(temp);
+                readings.push(reading.clone());
+                *last_reading = Some(reading);
+
+                let previous = *extreme;
+                if status == SensorStatus::Ok {
+                    let (min, max) = extreme.get_or_insert((temp, temp));
+                    *min = min.min(temp);
+                    *max = max.max(temp);
+                }
+                unpublished.add(temp, status, *extreme != previous);
+            }
+
+            // Sensors read since the last publication, at the publish interval
+            if publish_now {
+                let sensors = sensor_config
+                    .sensors
+                    .iter()
+                    .zip(&last_readings)
+                    .zip(extremes.iter().zip(unpublished.iter_mut()).zip(published.iter_mut()));
+                for ((sensor, reading), ((extreme, unpublished), published)) in sensors {
+                    let Some(reading) = reading.as_ref().filter(|_| unpublished.pending) else {
+                        continue;
+                    };
+                    let value = match publish_value {
+                        PublishValue::Average if reading.status.is_valid() => unpublished.average(reading.value),
+                        _ => reading.value,
+                    };
+                    let within_deadband = published.is_some_and(|last| {
+                        last.status == reading.status
+                            && (value - last.value).abs() <= deadband_c
+                            && cycle_start < last.at + max_silence
+                    });
+                    if deadband_c > 0.0 && !forced && within_deadband {
+                        unpublished.skip();
+                        continue;
+                    }
+                    *published = Some(Published {
+                        value,
+                        status: reading.status,
+                        at: cycle_start,
+                    });
+                    let changed = forced || unpublished.extremes_changed;
+                    let extremes = match *extreme {
+                        Some((min, max)) if reading.status == SensorStatus::Ok && changed => [Some(min), Some(max)],
+                        _ => [None, None],
+                    };
+                    *unpublished = Unpublished::default();
+
+                    // Other quantities go to sub-topics of the sensor
+                    let topic = sensor.state_topic(&sensor_config.mqtt.base_topic);
+                    let extras = [
+                        ("humidity", Unit::Percent, reading.humidity),
+                        ("pressure", Unit::Hectopascal, reading.pressure_hpa),
+                        ("min", Unit::Celsius, extremes[0]),
+                        ("max", Unit::Celsius, extremes[1]),
+                    ];
+                    for (quantity, unit, extra) in extras {
+                        if let Some(extra) = extra {
+                            let topic = format!("{}/{}", topic, quantity);
+                            let payload = payload(payload_format, unit, extra, reading.status, now, Some(&sensor.id));
+                            if publisher
+                                .publish(topic, rumqttc::QoS::AtLeastOnce, retain.sensors, payload)
+                                .await
+                                .is_err()
+                            {
+                                metrics.record_publish_failure();
+                            }
+                        }
+                    }
+                    let payload = payload(payload_format, Unit::Celsius, value, reading.status, now, Some(&sensor.id));
+                    if publisher.publish(topic, rumqttc::QoS::AtLeastOnce, retain.sensors, payload).await.is_err() {
+                        metrics.record_publish_failure();
+                    }
+                }
+            }
+
+            // Virtual sensors follow the physical ones in the readings and the history
+            for delta in &sensor_config.deltas {
+                let reading = delta.compute(&readings);
+                delta.check_alert(&reading, &alerts);
+                if publish_now {
+                    let topic = format!("{}/{}", sensor_config.mqtt.base_topic, delta.name);
+                    let payload = payload(payload_format, Unit::Celsius, reading.value, reading.status, now, None);
+                    if publisher.publish(topic, rumqttc::QoS::AtLeastOnce, retain.sensors, payload).await.is_err() {
+                        metrics.record_publish_failure();
+                    }
+                }
+                temps.push(reading.value);
+                readings.push(reading);
+            }
+
+            // Aggregate values of the sensor groups, physical and virtual sensors alike
+            let mut groups = Vec::with_capacity(sensor_config.groups.len());
+            for group in &sensor_config.groups {
+                let stats = group.compute(&readings);
+                groups.push(stats);
+                if !publish_now {
+                    continue;
+                }
+                let topic = format!("{}/groups/{}", sensor_config.mqtt.base_topic, group.name);
+                let values = [
+                    ("min", stats.map(|s| s.min)),
+                    ("max", stats.map(|s| s.max)),
+                    ("avg", stats.map(|s| s.average)),
+                ];
+                for (name, value) in values {
+                    let topic = format!("{}/{}", topic, name);
+                    let payload = value.map_or_else(|| "unavailable".to_string(), |v| format::data(Unit::Celsius, v));
+                    if publisher.publish(topic, rumqttc::QoS::AtLeastOnce, retain.groups, payload).await.is_err() {
+                        metrics.record_publish_failure();
+                    }
+                }
+            }
+
+            let mixing = mixing_valve.as_mut().map(|monitor| monitor.update(&readings, &alerts));
+
+            // Boiler pressure, with the same grace period as the temperatures
+            let mut pressure = None;
+            if let (Some(config), Some(task)) = (&sensor_config.pressure, pressure_read) {
+                let (value, status) = match task.await.map_err(anyhow::Error::from).and_then(|result| result) {
+                    Ok(bar) => {
+                        last_pressure = Some((bar, Instant::now()));
+                        (bar, SensorStatus::Ok)
+                    }
+                    Err(e) => {
+                        warning!("Error reading the pressure: {:#}", e);
+                        health.record_sensor_error();
+                        match last_pressure {
+                            Some((held, at)) if at.elapsed() <= fault_grace => (held, SensorStatus::Stale),
+                            Some((held, _)) => (held, SensorStatus::Error),
+                            None => (0.0, SensorStatus::Error),
+                        }
+                    }
+                };
+                config.check_alert(value, status, &alerts);
+                if publish_now {
+                    let topic = format!("{}/pressure", sensor_config.mqtt.base_topic);
+                    let payload = payload(payload_format, Unit::Bar, value, status, now, None);
+                    if publisher.publish(topic, rumqttc::QoS::AtLeastOnce, retain.pressure, payload).await.is_err() {
+                        metrics.record_publish_failure();
+                    }
+                }
+                pressure = Some((value, status, config.is_low(value)));
+            }
+
+            let mut history = history.lock().await;
+
+            // Re-align the schedule if the clock was set backwards (e.g., after NTP sync)
+            if next_history_at - now > period {
+                next_history_at = next_boundary(now, period);
+            }
+
+            // Update history on every period boundary, once the clock can be trusted
+            let clock_sane = clock_status.is_sane();
+            if clock_sane && now >= next_history_at {
+                for (h, reading) in history.iter_mut().zip(&readings) {
+                    if !reading.status.is_valid() {
+                        // Leave a gap in the chart instead of recording a wrong value
+                        h.mark_gap();
+                    } else {
+                        h.add_point(next_history_at, reading.value);
+                    }
+                }
+                history_changed = true;
+                next_history_at = next_boundary(now, period);
+            } else if clock_sane {
+                // Catch fast transients (heating, draw-off) between two periods
+                for (h, reading) in history.iter_mut().zip(&readings) {
+                    if reading.status == SensorStatus::Ok && h.add_transient_point(now, reading.value) {
+                        history_changed = true;
+                    }
+                }
+            }
+
+            // Without any valid sensor inside the tank, the last energy is kept
+            let stored = stored_energy(&sensor_config, &readings, controls.reference_temp_c());
+            if let Some((tank_temp, energy)) = stored {
+                avg_temp = tank_temp;
+                energy_kwh = energy;
+                heater.record_tank_temperature(avg_temp);
+            }
+            // Publish the total energy to a dedicated MQTT topic
+            if publish_now && stored.is_some() {
+                let energy_topic = sensor_config.mqtt.energy_topic();
+                let payload = payload(payload_format, Unit::KilowattHour, energy_kwh, SensorStatus::Ok, now, None);
+                if publisher.publish(energy_topic, rumqttc::QoS::AtLeastOnce, retain.energy, payload).await.is_err() {
+                    metrics.record_publish_failure();
+                }
+            }
+
+            // Let the user script react to the readings and events, and publish derived values
+            #[cfg(feature = "scripting")]
+            if let Some(hooks) = script_hooks.as_mut() {
+                hooks.reload_if_changed();
+                hooks.on_alerts(&alerts.active());
+                if sensor_config.heater.is_enabled() {
+                    hooks.on_heater(heater.is_on());
+                }
+                let readings: Vec<(String, f32)> = sensor_config
+                    .sensors
+                    .iter()
+                    .map(|s| s.name.clone())
+                    .zip(temps.iter().copied())
+                    .collect();
+                hooks.on_cycle(&readings, energy_kwh);
+                for message in hooks.take_published() {
+                    let topic = format!("{}/{}", sensor_config.mqtt.base_topic, message.topic);
+                    if publisher.publish(topic, rumqttc::QoS::AtLeastOnce, false, message.payload).await.is_err() {
+                        metrics.record_publish_failure();
+                    }
+                }
+            }
+
+            // Publish the acquisition time of this cycle so consumers can date the values
+            if clock_sane && publish_now {
+                let timestamp_topic = format!("{}/timestamp", sensor_config.mqtt.base_topic);
+                let payload = now.to_rfc3339();
+                if publisher.publish(timestamp_topic, rumqttc::QoS::AtLeastOnce, retain.timestamp, payload).await.is_err() {
+                    metrics.record_publish_failure();
+                }
+            }
+
+            health.record_read(now);
+            let valid: Vec<bool> = readings.iter().map(|r| r.status.is_valid()).collect();
+            let snapshot = Snapshot {
+                timestamp: now,
+                readings,
+                energy: EnergyReport {
+                    energy_kwh,
+                    average_temp_c: avg_temp,
+                    reference_temp_c: controls.reference_temp_c(),
+                    volume_l: sensor_config.boiler.volume_l,
+                },
+                pressure_bar: pressure
+                    .filter(|&(_, status, _)| status != SensorStatus::Error)
+                    .map(|(value, _, _)| value),
+                alerts: alerts.active(),
+            };
+            // The whole cycle in one document, for consumers that want a consistent set of values
+            if payload_format == PayloadFormat::Json
+                && publish_now
+                && let Ok(document) = serde_json::to_vec(&snapshot)
+            {
+                let topic = format!("{}/state", sensor_config.mqtt.base_topic);
+                if publisher.publish(topic, rumqttc::QoS::AtLeastOnce, retain.state, document).await.is_err() {
+                    metrics.record_publish_failure();
+                }
+            }
+            snapshot_tx.send_replace(Some(snapshot));
+
+            // Hand the results over to the UI task, which refreshes the display on its own cadence
+            ui_snapshot.send_modify(|snapshot| {
+                snapshot.temps = temps;
+                snapshot.valid = valid;
+                snapshot.energy_kwh = energy_kwh;
+                snapshot.pressure_bar = pressure.map(|(value, _, _)| value);
+                snapshot.pressure_valid = pressure.is_some_and(|(_, status, _)| status != SensorStatus::Error);
+                snapshot.pressure_low = pressure.is_some_and(|(_, _, low)| low);
+                snapshot.mixing = mixing;
+                snapshot.groups = groups;
+                if history_changed {
+                    // Rebuild the paths in place to keep their buffers
+                    snapshot.history_paths.resize_with(history.len(), String::new);
+                    snapshot.forecast_paths.resize_with(history.len(), String::new);
+                    snapshot.chart_ranges.clear();
+                    snapshot.chart_width = CHART_WIDTH + forecast_width;
+                    let heating = heater.is_on();
+                    let charts = history
+                        .iter()
+                        .zip(&chart_bounds)
+                        .zip(snapshot.history_paths.iter_mut().zip(snapshot.forecast_paths.iter_mut()));
+                    for (i, ((h, &bounds), (path, forecast_path))) in charts.enumerate() {
+                        let range = chart_builder.build(h.chart_points(), bounds, path);
+                        snapshot.chart_ranges.push(range);
+
+                        // Forecast of the tank sensors, once their model can be fitted
+                        forecast_path.clear();
+                        let in_tank = sensor_config.sensors.get(i).is_some_and(|s| s.in_tank);
+                        if forecast_width > 0.0
+                            && in_tank
+                            && let Some(model) = TankModel::fit(h.points(), forecast.ambient_c, period_h * 2.0)
+                            && let Some(last) = h.points().back()
+                        {
+                            let points = crate::forecast::chart_points(&model, forecast, last.value, heating, period_h);
+                            dashed_path(&points, range, forecast_path);
+                        }
+                    }
+                }
+            });
+            history_changed = false;
+            drop(history);
+
+            metrics.record_loop_duration(cycle_start.elapsed());
+        }
+    }
+}
+
+/// Readings of a sensor since its last publication.
+#[derive(Debug, Default, Clone)]
+struct Unpublished {
+    /// A reading is waiting to be published.
+    pending: bool,
+    /// Sum and number of the valid temperatures read, for `publish_value = "average"`.
+    sum: f64,
+    count: u32,
+    /// The lowest or highest temperature changed.
+    extremes_changed: bool,
+}
+
+impl Unpublished {
+    fn add(&mut self, temp: f32, status: SensorStatus, extremes_changed: bool) {
+        self.pending = true;
+        if status == SensorStatus::Ok {
+            self.sum += f64::from(temp);
+            self.count += 1;
+        }
+        self.extremes_changed |= extremes_changed;
+    }
+
+    /// Drops the readings not worth publishing, keeping the change of the extremes for the
+    /// next publication.
+    fn skip(&mut self) {
+        *self = Unpublished {
+            extremes_changed: self.extremes_changed,
+            ..Unpublished::default()
+        };
+    }
+
+    /// Average of the valid temperatures read, or `latest` if none was.
+    fn average(&self, latest: f32) -> f32 {
+        if self.count == 0 { latest } else { (self.sum / f64::from(self.count)) as f32 }
+    }
+}
+
+/// Last publication of a sensor, compared with the deadband.
+#[derive(Debug, Clone, Copy)]
+struct Published {
+    value: f32,
+    status: SensorStatus,
+    at: Instant,
+}
+
+/// Start of the acquisition cycles: every interval from startup, or on the wall-clock
+/// boundaries of the interval with `[timing] align_to_clock`.
+struct Schedule {
+    interval: time::Interval,
+    alignment: Option<chrono::Duration>,
+    /// Boundary of the last aligned cycle (`None` before the first cycle).
+    last: Option<DateTime<Utc>>,
+}
+
+impl Schedule {
+    fn new(cycle_interval: Duration, align_to_clock: bool) -> Self {
+        let mut interval = time::interval(cycle_interval);
+        // A cycle slower than the interval delays the next one instead of causing a burst
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        Self {
+            interval,
+            alignment: align_to_clock.then(|| chrono::Duration::seconds(cycle_interval.as_secs() as i64)),
+            last: None,
+        }
+    }
+
+    /// Waits for the next cycle, returning its boundary when aligned. The first cycle
+    /// starts at once, so the display does not wait for the first boundary.
+    async fn next(&mut self) -> Option<DateTime<Utc>> {
+        let Some(period) = self.alignment else {
+            self.interval.tick().await;
+            return None;
+        };
+        let now = Utc::now();
+        let from = match self.last {
+            None => {
+                self.last = Some(previous_boundary(now, period));
+                return None;
+            }
+            // A boundary is never used twice, unless the clock was set backwards. A cycle
+            // slower than the interval skips the boundaries it missed.
+            Some(last) if last - now <= period => last.max(now),
+            Some(_) => now,
+        };
+        let boundary = next_boundary(from, period);
+        time::sleep((boundary - now).to_std().unwrap_or_default()).await;
+        self.last = Some(boundary);
+        Some(boundary)
+    }
+}
+
+/// Reads the `due` sensors concurrently, each on its own blocking thread, timing each read.
+///
+/// The cycle takes as long as the slowest sensor rather than the sum of all reads.
+/// Results are in the order of the backends, `None` for the sensors not due.
+async fn read_all(
+    backends: Arc<Vec<Box<dyn TemperatureSensor>>>,
+    due: &[bool],
+) -> Result<Vec<Option<(anyhow::Result<Measurement>, Duration)>>, tokio::task::JoinError> {
+    let tasks: Vec<_> = (0..backends.len())
+        .map(|i| {
+            let backends = backends.clone();
+            due[i].then(|| {
+                tokio::task::spawn_blocking(move || {
+                    let start = Instant::now();
+                    let result = backends[i].read_measurement();
+                    (result, start.elapsed())
+                })
+            })
+        })
+        .collect();
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        results.push(match task {
+            Some(task) => Some(task.await?),
+            None => None,
+        });
+    }
+    Ok(results)
+}
+
+/// Applies the `out_of_range` policy of `sensor` to a physically impossible measurement.
+/// Returns the measurement to use, and whether it is suspect.
+pub fn out_of_range(sensor: &SensorConfig, measurement: Measurement) -> (anyhow::Result<Measurement>, bool) {
+    match sensor.out_of_range {
+        RangePolicy::Reject => (Err(anyhow::anyhow!("Implausible value {:.2} °C", measurement.temperature)), false),
+        RangePolicy::Clamp => {
+            let temperature = sensor.clamp_to_valid(measurement.temperature);
+            (Ok(Measurement { temperature, ..measurement }), false)
+        }
+        RangePolicy::MarkSuspect => (Ok(measurement), true),
+    }
+}
+
+/// Average temperature of the valid sensors inside the tank and the thermal energy
+/// stored above `reference_temp_c`, in kWh; `None` without any valid sensor in the tank.
+///
+/// Formula: E = (m * cp * delta_T) / 3600,
+/// here: heated volume * energy_coefficient * (avg_temp - reference_temp) / 1000, with the
+/// coefficient integrated over the temperatures when it varies (see [`crate::water`]).
+pub fn stored_energy(config: &Config, readings: &[Reading], reference_temp_c: f32) -> Option<(f32, f32)> {
+    let tank_temps: Vec<f32> = config
+        .sensors
+        .iter()
+        .zip(readings)
+        .filter(|(sensor, reading)| sensor.in_tank && reading.status.is_valid())
+        .map(|(_, reading)| reading.value)
+        .collect();
+    if tank_temps.is_empty() {
+        return None;
+    }
+    let avg_temp = tank_temps.iter().sum::<f32>() / tank_temps.len() as f32;
+    let wh_per_l = config.boiler.energy_coefficient.wh_per_l(reference_temp_c, avg_temp);
+    Some((avg_temp, config.boiler.heated_volume_l() * wh_per_l / 1000.0))
+}
+
+/// MQTT payload of a value. Plain: the number, or `unavailable` for an invalid or suspect
+/// sensor. JSON: a [`SensorPayload`], whose status tells whether the value can be used.
+fn payload(
+    payload_format: PayloadFormat,
+    unit: Unit,
+    value: f32,
+    status: SensorStatus,
+    timestamp: DateTime<Utc>,
+    sensor_id: Option<&str>,
+) -> String {
+    match payload_format {
+        PayloadFormat::Plain if status.is_valid() => format::data(unit, value),
+        PayloadFormat::Plain => "unavailable".to_string(),
+        PayloadFormat::Json => {
+            let payload = SensorPayload {
+                value: (status != SensorStatus::Error).then(|| format::round(unit, value)),
+                unit: unit.symbol().to_string(),
+                ts: timestamp,
+                sensor_id: sensor_id.map(str::to_string),
+                status,
+            };
+            serde_json::to_string(&payload).unwrap_or_default()
+        }
+    }
+}
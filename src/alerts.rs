@@ -0,0 +1,121 @@
+//! Alert tracking for the boilert application.
+//! Keeps the list of active alerts and forwards new ones to MQTT, along with a history
+//! of the alerts raised and cleared, saved with the runtime state.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+pub use boilert::model::Alert;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::logging::warning;
+use crate::messages::Message;
+use crate::mqtt::Publisher;
+
+/// Number of alert events kept in the history, the oldest dropped first.
+const HISTORY_EVENTS: usize = 500;
+
+/// An alert raised or cleared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertEvent {
+    pub timestamp: DateTime<Utc>,
+    pub id: String,
+    /// Message of the alert when raised, empty when cleared.
+    pub message: String,
+    pub raised: bool,
+}
+
+/// Shared registry of active alerts.
+///
+/// Cloning an `Alerts` handle is cheap; all clones share the same list.
+#[derive(Clone)]
+pub struct Alerts {
+    active: Arc<Mutex<Vec<Alert>>>,
+    history: Arc<Mutex<VecDeque<AlertEvent>>>,
+    publisher: Publisher,
+    base_topic: String,
+}
+
+impl Alerts {
+    pub fn new(publisher: Publisher, base_topic: &str) -> Self {
+        Self {
+            active: Arc::new(Mutex::new(Vec::new())),
+            history: Arc::new(Mutex::new(VecDeque::new())),
+            publisher,
+            base_topic: base_topic.to_string(),
+        }
+    }
+
+    /// Raises an alert, or updates its message if it is already active.
+    ///
+    /// The message is rendered with the template configured for `id`, if any, then
+    /// logged and published to `{base_topic}/alerts/{id}`.
+    pub fn raise(&self, id: &str, message: impl Into<Message>) {
+        let message = message.into().render(id);
+        {
+            let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+            match active.iter_mut().find(|a| a.id == id) {
+                Some(alert) => alert.message = message.clone(),
+                None => {
+                    active.push(Alert {
+                        id: id.to_string(),
+                        message: message.clone(),
+                    });
+                    self.record(id, &message, true);
+                }
+            }
+        }
+        warning!("ALERT [{}]: {}", id, message);
+        let topic = format!("{}/alerts/{}", self.base_topic, id);
+        let _ = self.publisher.try_publish(topic, rumqttc::QoS::AtLeastOnce, false, message);
+    }
+
+    /// Clears an active alert. Does nothing if the alert is not active.
+    pub fn clear(&self, id: &str) {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        let before = active.len();
+        active.retain(|a| a.id != id);
+        if active.len() != before {
+            warning!("Alert cleared [{}]", id);
+            self.record(id, "", false);
+            let topic = format!("{}/alerts/{}", self.base_topic, id);
+            let _ = self.publisher.try_publish(topic, rumqttc::QoS::AtLeastOnce, false, "");
+        }
+    }
+
+    /// Returns a snapshot of the currently active alerts, oldest first.
+    pub fn active(&self) -> Vec<Alert> {
+        self.active.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Returns the alerts raised and cleared, oldest first.
+    pub fn history(&self) -> Vec<AlertEvent> {
+        self.history.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+    }
+
+    /// Restores the history saved by the previous run. Must be called before the saved
+    /// alerts are raised again, so that they are not recorded twice.
+    pub fn restore_history(&self, events: &[AlertEvent]) {
+        let skip = events.len().saturating_sub(HISTORY_EVENTS);
+        *self.history.lock().unwrap_or_else(|e| e.into_inner()) = events.iter().skip(skip).cloned().collect();
+    }
+
+    /// Adds an event to the history, unless the alert raised was already raised last,
+    /// as when a saved alert is raised again at startup.
+    fn record(&self, id: &str, message: &str, raised: bool) {
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        if raised && history.iter().rev().find(|e| e.id == id).is_some_and(|e| e.raised) {
+            return;
+        }
+        if history.len() >= HISTORY_EVENTS {
+            history.pop_front();
+        }
+        history.push_back(AlertEvent {
+            timestamp: Utc::now(),
+            id: id.to_string(),
+            message: message.to_string(),
+            raised,
+        });
+    }
+}
@@ -0,0 +1,203 @@
+//! Audit log of control actions and configuration changes.
+//! Entries are appended as JSON lines to a file and the most recent ones are kept
+//! in memory for the UI and the HTTP API.
+
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use boilert::model::{AuditEntry, AuditSource};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::logging::{info, warning};
+
+/// Number of entries kept in memory.
+const RECENT_ENTRIES: usize = 100;
+
+/// Audit log settings.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Path of the JSON lines file.
+    pub path: String,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            path: "boilert-audit.jsonl".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct AuditState {
+    recent: VecDeque<AuditEntry>,
+    /// Fingerprint of `config.toml` recorded by the last configuration entry.
+    config_fingerprint: Option<String>,
+}
+
+/// Shared handle to the audit log.
+#[derive(Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    state: Arc<Mutex<AuditState>>,
+    /// Incremented on every new entry, so that readers can detect changes.
+    version: Arc<AtomicU64>,
+}
+
+impl AuditLog {
+    /// Opens the audit log, loading its most recent entries.
+    pub fn open(config: &AuditConfig) -> Self {
+        let path = PathBuf::from(&config.path);
+        let entries = match read_all(&path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warning!("Ignoring audit log: {:#}", e);
+                Vec::new()
+            }
+        };
+        let config_fingerprint = entries
+            .iter()
+            .rev()
+            .find(|e| e.source == AuditSource::Config)
+            .and_then(|e| e.detail.rsplit(' ').next())
+            .map(str::to_string);
+        let skip = entries.len().saturating_sub(RECENT_ENTRIES);
+        Self {
+            path,
+            state: Arc::new(Mutex::new(AuditState {
+                recent: entries.into_iter().skip(skip).collect(),
+                config_fingerprint,
+            })),
+            version: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, AuditState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records an edit of `config.toml` since the last recorded configuration.
+    ///
+    /// The first start only records the fingerprint of the initial configuration.
+    pub fn record_config(&self, fingerprint: &str) {
+        let previous = self.state().config_fingerprint.replace(fingerprint.to_string());
+        let action = match previous {
+            Some(previous) if previous == fingerprint => return,
+            Some(_) => "configuration changed",
+            None => "configuration loaded",
+        };
+        self.record(AuditSource::Config, action, format!("config.toml fingerprint {}", fingerprint));
+    }
+
+    /// Records an action.
+    pub fn record(&self, source: AuditSource, action: &str, detail: impl Into<String>) {
+        let entry = AuditEntry {
+            timestamp: Utc::now(),
+            source,
+            action: action.to_string(),
+            detail: detail.into(),
+        };
+        info!("Audit [{}] {}: {}", entry.source.as_str(), entry.action, entry.detail);
+        if let Err(e) = append(&self.path, &entry) {
+            warning!("Failed to write audit log {}: {:#}", self.path.display(), e);
+        }
+        {
+            let mut state = self.state();
+            if state.recent.len() >= RECENT_ENTRIES {
+                state.recent.pop_front();
+            }
+            state.recent.push_back(entry);
+        }
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the most recent entries, oldest first.
+    pub fn recent(&self) -> Vec<AuditEntry> {
+        self.state().recent.iter().cloned().collect()
+    }
+
+    /// Returns all the entries of the log file, or the most recent ones if it cannot be read.
+    pub fn all(&self) -> Vec<AuditEntry> {
+        read_all(&self.path).unwrap_or_else(|e| {
+            warning!("{:#}", e);
+            self.recent()
+        })
+    }
+
+    /// Changes whenever an entry is recorded.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Relaxed)
+    }
+}
+
+fn append(path: &Path, entry: &AuditEntry) -> Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(&line)?;
+    Ok(())
+}
+
+/// Reads all the entries of the audit log at `path` (none if it does not exist).
+///
+/// # Errors
+/// Returns an error if the file cannot be read or contains an invalid line.
+pub fn read_all(path: &Path) -> Result<Vec<AuditEntry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read audit log {}", path.display()))?;
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line).with_context(|| format!("Invalid entry at line {} of {}", i + 1, path.display()))
+        })
+        .collect()
+}
+
+/// Fingerprint of a configuration file: FNV-1a hash of its content, in hexadecimal.
+///
+/// # Errors
+/// Returns an error if the file cannot be read.
+pub fn config_fingerprint(path: &Path) -> Result<String> {
+    let content = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let hash = content
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3));
+    Ok(format!("{:016x}", hash))
+}
+
+/// Writes `entries` as CSV: timestamp, source, action, detail.
+pub fn write_csv(out: &mut impl Write, entries: &[AuditEntry]) -> std::io::Result<()> {
+    writeln!(out, "timestamp,source,action,detail")?;
+    for entry in entries {
+        writeln!(
+            out,
+            "{},{},{},{}",
+            entry.timestamp.to_rfc3339(),
+            entry.source.as_str(),
+            csv_field(&entry.action),
+            csv_field(&entry.detail)
+        )?;
+    }
+    Ok(())
+}
+
+/// Quotes a CSV field when needed.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,65 @@
+//! Authentication and roles of the remote interfaces.
+//! Requests to the HTTP API carry a bearer token identifying a user and its role. MQTT
+//! messages carry no identity: the commands received over MQTT rely on the broker's ACLs,
+//! and the controls of the touch screen on the PIN of the display lock.
+
+use serde::Deserialize;
+
+use crate::http::{Request, Response};
+use crate::logging::warning;
+
+/// Access level of a user. Each role includes the rights of the previous ones.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Can read data (readings, history, metrics).
+    Viewer,
+    /// Can also act on the installation (setpoints, boost, configuration).
+    Operator,
+}
+
+/// A user of the remote interfaces.
+#[derive(Debug, Deserialize, Clone)]
+pub struct UserConfig {
+    /// Name used in the logs.
+    pub name: String,
+    /// Secret sent as `Authorization: Bearer <token>`.
+    pub token: String,
+    pub role: Role,
+}
+
+/// Authentication settings. Without users, the remote interfaces are open.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub users: Vec<UserConfig>,
+}
+
+impl AuthConfig {
+    /// Checks that `request` comes from a user with at least the `required` role.
+    ///
+    /// Returns the response to send instead (401 or 403) if it does not.
+    pub fn authorize(&self, request: &Request, required: Role) -> Result<(), Response> {
+        if self.users.is_empty() {
+            return Ok(());
+        }
+        let token = request
+            .authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        let Some(user) = token.and_then(|token| self.users.iter().find(|u| constant_time_eq(&u.token, token))) else {
+            return Err(Response::text(401, "authentication required\n"));
+        };
+        if user.role < required {
+            warning!("Access denied to {} for user {} ({:?} role required)", request.path, user.name, required);
+            return Err(Response::text(403, format!("{:?} role required\n", required)));
+        }
+        Ok(())
+    }
+}
+
+/// Compares two secrets in a time independent of where they differ.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,68 @@
+//! Statistics days and billing periods.
+//! Daily statistics start at a configurable time rather than midnight (e.g., when the
+//! tariff switches), and period totals restart on the day of the month the utility
+//! bill starts, so both can be checked against the bill. A statistics day is named
+//! after the date it starts on.
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, NaiveTime};
+use serde::Deserialize;
+
+/// Settings from the `[billing]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BillingConfig {
+    /// Local time the statistics day starts, as "HH:MM".
+    pub day_start: String,
+    /// Day of the month the billing period starts (1 to 28).
+    pub period_start_day: u32,
+    /// Price of a kWh, to estimate the cost of the heater energy (no cost if absent).
+    pub price_per_kwh: Option<f64>,
+}
+
+impl Default for BillingConfig {
+    fn default() -> Self {
+        Self {
+            day_start: "00:00".to_string(),
+            period_start_day: 1,
+            price_per_kwh: None,
+        }
+    }
+}
+
+impl BillingConfig {
+    /// Checks the day start time and the ranges of the settings.
+    pub fn validate(&self) -> Result<()> {
+        self.day_start_time()?;
+        if !(1..=28).contains(&self.period_start_day) {
+            bail!("[billing] period_start_day must be between 1 and 28");
+        }
+        if self.price_per_kwh.is_some_and(|price| !price.is_finite() || price < 0.0) {
+            bail!("[billing] price_per_kwh must not be negative");
+        }
+        Ok(())
+    }
+
+    fn day_start_time(&self) -> Result<NaiveTime> {
+        NaiveTime::parse_from_str(&self.day_start, "%H:%M")
+            .with_context(|| format!("[billing] day_start {:?} is not a HH:MM time", self.day_start))
+    }
+
+    /// Statistics day of a local time: the date of the last day start.
+    pub fn day(&self, now: DateTime<Local>) -> NaiveDate {
+        let start = self.day_start_time().unwrap_or(NaiveTime::MIN);
+        let now = now.naive_local();
+        if now.time() >= start { now.date() } else { now.date().pred_opt().unwrap_or(now.date()) }
+    }
+
+    /// First statistics day of the billing period holding `day`.
+    pub fn period_start(&self, day: NaiveDate) -> NaiveDate {
+        let start = day.with_day(self.period_start_day).unwrap_or(day);
+        if start <= day { start } else { start - Months::new(1) }
+    }
+
+    /// Estimated cost of an energy, if a price is set.
+    pub fn cost(&self, energy_kwh: f64) -> Option<f64> {
+        self.price_per_kwh.map(|price| price * energy_kwh)
+    }
+}
@@ -0,0 +1,185 @@
+//! Board/hardware abstraction layer.
+//! Resolves the device paths (1-Wire, I2C, SPI, GPIO) of the board selected in
+//! `config.toml`, so the same binary runs on different single-board computers.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::scenario::Scenario;
+use crate::sensors::RawCapture;
+use crate::simulation::Faults;
+use crate::tank_sim::TankSim;
+
+/// Supported board families.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BoardKind {
+    /// No hardware: sensors return simulated values (development workstation).
+    Simulated,
+    /// Raspberry Pi 2/3/4 and Zero.
+    RaspberryPi,
+    /// Raspberry Pi 5 (header GPIOs are on the RP1 chip).
+    RaspberryPi5,
+    /// Orange Pi boards based on Allwinner SoCs.
+    OrangePi,
+    /// BeagleBone Black/Green.
+    BeagleBone,
+    /// Any other Linux board; set the device paths explicitly.
+    Generic,
+}
+
+impl Default for BoardKind {
+    /// Real hardware when built with the `pi` feature, simulation otherwise.
+    fn default() -> Self {
+        if cfg!(feature = "pi") {
+            BoardKind::RaspberryPi
+        } else {
+            BoardKind::Simulated
+        }
+    }
+}
+
+/// Hardware settings from the `[hardware]` section. Every path can be overridden;
+/// unset values default to the usual ones of the selected board.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct HardwareConfig {
+    /// Board family.
+    pub board: BoardKind,
+    /// Directory of the 1-Wire devices (default `/sys/bus/w1/devices`).
+    pub w1_dir: Option<String>,
+    /// Default I2C bus number (`/dev/i2c-<n>`).
+    pub i2c_bus: Option<u8>,
+    /// Default SPI bus number (`/dev/spidev<bus>.<cs>`).
+    pub spi_bus: Option<u8>,
+    /// GPIO character device (e.g., `/dev/gpiochip0`).
+    pub gpio_chip: Option<String>,
+}
+
+/// Resolved hardware description of the running board.
+#[derive(Debug, Clone)]
+pub struct Board {
+    kind: BoardKind,
+    w1_dir: PathBuf,
+    i2c_bus: u8,
+    spi_bus: u8,
+    gpio_chip: PathBuf,
+    /// Faults injected into the reads of the simulated board.
+    faults: Faults,
+    /// Raw data of failed reads, for debugging.
+    capture: RawCapture,
+    /// Temperatures replayed by the simulated sensors (random values if absent).
+    scenario: Option<Arc<Scenario>>,
+    /// Physical model of the tank read by `tank` sensors.
+    tank: Option<TankSim>,
+}
+
+impl Board {
+    /// Resolves the board from its configuration, applying per-board defaults.
+    pub fn from_config(config: &HardwareConfig) -> Self {
+        // (I2C bus, SPI bus, GPIO chip) exposed on the expansion header
+        let (i2c_bus, spi_bus, gpio_chip) = match config.board {
+            BoardKind::RaspberryPi | BoardKind::Simulated | BoardKind::Generic => (1, 0, "/dev/gpiochip0"),
+            // Kernels before 6.6.45 expose the RP1 header GPIOs as gpiochip4
+            BoardKind::RaspberryPi5 => (1, 0, "/dev/gpiochip4"),
+            BoardKind::OrangePi => (0, 1, "/dev/gpiochip0"),
+            BoardKind::BeagleBone => (2, 1, "/dev/gpiochip0"),
+        };
+        Self {
+            kind: config.board,
+            w1_dir: PathBuf::from(config.w1_dir.as_deref().unwrap_or("/sys/bus/w1/devices")),
+            i2c_bus: config.i2c_bus.unwrap_or(i2c_bus),
+            spi_bus: config.spi_bus.unwrap_or(spi_bus),
+            gpio_chip: PathBuf::from(config.gpio_chip.as_deref().unwrap_or(gpio_chip)),
+            faults: Faults::default(),
+            capture: RawCapture::default(),
+            scenario: None,
+            tank: None,
+        }
+    }
+
+    /// Sets the faults injected into simulated reads.
+    pub fn with_faults(mut self, faults: Faults) -> Self {
+        self.faults = faults;
+        self
+    }
+
+    /// Faults injected into simulated reads.
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+
+    /// Sets the scenario replayed by the simulated sensors.
+    pub fn with_scenario(mut self, scenario: Scenario) -> Self {
+        self.scenario = Some(Arc::new(scenario));
+        self
+    }
+
+    /// Scenario replayed by the simulated sensors, if any.
+    pub fn scenario(&self) -> Option<&Arc<Scenario>> {
+        self.scenario.as_ref()
+    }
+
+    /// Sets the tank model read by `tank` sensors.
+    pub fn with_tank(mut self, tank: TankSim) -> Self {
+        self.tank = Some(tank);
+        self
+    }
+
+    /// Tank model read by `tank` sensors, if any.
+    pub fn tank(&self) -> Option<&TankSim> {
+        self.tank.as_ref()
+    }
+
+    /// Sets the capture of the raw data of failed reads.
+    pub fn with_capture(mut self, capture: RawCapture) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Capture of the raw data of failed reads.
+    pub fn capture(&self) -> &RawCapture {
+        &self.capture
+    }
+
+    /// Returns `true` if sensors should be simulated instead of read from hardware.
+    pub fn is_simulated(&self) -> bool {
+        self.kind == BoardKind::Simulated
+    }
+
+    /// Directory containing one sub-directory per 1-Wire device.
+    pub fn w1_dir(&self) -> &Path {
+        &self.w1_dir
+    }
+
+    /// Device node of an I2C bus, the board's default bus if `bus` is `None`.
+    pub fn i2c_device(&self, bus: Option<u8>) -> PathBuf {
+        PathBuf::from(format!("/dev/i2c-{}", bus.unwrap_or(self.i2c_bus)))
+    }
+
+    /// Device node of an SPI chip select, on the board's default bus if `bus` is `None`.
+    pub fn spi_device(&self, bus: Option<u8>, chip_select: u8) -> PathBuf {
+        PathBuf::from(format!("/dev/spidev{}.{}", bus.unwrap_or(self.spi_bus), chip_select))
+    }
+
+    /// GPIO character device of the expansion header.
+    pub fn gpio_chip(&self) -> &Path {
+        &self.gpio_chip
+    }
+}
+
+impl std::fmt::Display for Board {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} (1-Wire: {}, I2C: {}, SPI: {}, GPIO: {})",
+            self.kind,
+            self.w1_dir().display(),
+            self.i2c_device(None).display(),
+            self.spi_device(None, 0).display(),
+            self.gpio_chip().display()
+        )
+    }
+}
@@ -0,0 +1,269 @@
+//! High-rate sampling ("burst") of a single sensor.
+//! On request (touch screen, MQTT or HTTP API), one sensor is read as fast as it allows
+//! for a few minutes and the trace is written to its own CSV file, apart from the
+//! history. Useful to diagnose fast phenomena such as mixing valve oscillations or
+//! heating element cycling.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use boilert::model::AuditSource;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::audit::AuditLog;
+use crate::board::Board;
+use crate::config::SensorConfig;
+use crate::format::{self, Unit};
+use crate::logging::{info, warning};
+use crate::mqtt::{Publisher, Subscriptions};
+use crate::sensors::{self, TemperatureSensor};
+
+/// Duration of a burst when none is given, in minutes.
+pub const DEFAULT_MINUTES: u32 = 10;
+/// Shortest time between two samples, for sensors answering instantly.
+const MIN_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Burst sampling settings from the `[burst]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BurstConfig {
+    /// Directory of the trace files.
+    pub dir: String,
+    /// Longest burst accepted, in minutes.
+    pub max_minutes: u32,
+}
+
+impl Default for BurstConfig {
+    fn default() -> Self {
+        Self {
+            dir: "bursts".to_string(),
+            max_minutes: 60,
+        }
+    }
+}
+
+/// State of the current or last burst.
+#[derive(Debug, Clone, Serialize)]
+pub struct BurstStatus {
+    pub sensor: String,
+    pub started: DateTime<Utc>,
+    /// Planned end of the burst.
+    pub until: DateTime<Utc>,
+    /// Path of the trace file.
+    pub path: String,
+    /// Samples recorded so far, errors included.
+    pub samples: u64,
+    /// Failed reads.
+    pub errors: u64,
+    /// `false` once the burst is over.
+    pub active: bool,
+}
+
+/// Shared burst sampler. Only one sensor is sampled at a time.
+///
+/// Cloning a `Bursts` handle is cheap; all clones share the same burst.
+#[derive(Clone)]
+pub struct Bursts {
+    config: BurstConfig,
+    sensors: Vec<SensorConfig>,
+    board: Board,
+    publisher: Publisher,
+    base_topic: String,
+    audit: AuditLog,
+    status: watch::Sender<Option<BurstStatus>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Bursts {
+    pub fn new(
+        config: &BurstConfig,
+        sensors: &[SensorConfig],
+        board: Board,
+        publisher: Publisher,
+        base_topic: &str,
+        audit: AuditLog,
+    ) -> Self {
+        Self {
+            config: config.clone(),
+            sensors: sensors.to_vec(),
+            board,
+            publisher,
+            base_topic: base_topic.to_string(),
+            audit,
+            status: watch::channel(None).0,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Names of the sensors that can be sampled, in the order of the configuration.
+    pub fn sensor_names(&self) -> Vec<String> {
+        self.sensors.iter().map(|s| s.name.clone()).collect()
+    }
+
+    /// Longest burst accepted, in minutes.
+    pub fn max_minutes(&self) -> u32 {
+        self.config.max_minutes
+    }
+
+    /// State of the current or last burst.
+    pub fn status(&self) -> Option<BurstStatus> {
+        self.status.borrow().clone()
+    }
+
+    /// Follows the state of the bursts.
+    pub fn subscribe(&self) -> watch::Receiver<Option<BurstStatus>> {
+        self.status.subscribe()
+    }
+
+    /// Starts sampling `sensor` for `minutes`.
+    ///
+    /// # Errors
+    /// Returns an error if the sensor is unknown, the duration out of range, another
+    /// burst is running or the trace file cannot be created.
+    pub fn start(&self, sensor: &str, minutes: u32, source: AuditSource) -> Result<BurstStatus> {
+        let Some(sensor_config) = self.sensors.iter().find(|s| s.name == sensor) else {
+            bail!("Unknown sensor {}", sensor);
+        };
+        if minutes == 0 || minutes > self.config.max_minutes {
+            bail!("The duration must be between 1 and {} minutes", self.config.max_minutes);
+        }
+
+        let started = Utc::now();
+        let dir = PathBuf::from(&self.config.dir);
+        let path = dir.join(format!("{}-{}.csv", sensor, started.format("%Y%m%d-%H%M%S")));
+        let status = BurstStatus {
+            sensor: sensor.to_string(),
+            started,
+            until: started + chrono::Duration::minutes(minutes as i64),
+            path: path.display().to_string(),
+            samples: 0,
+            errors: 0,
+            active: true,
+        };
+        // Check and claim in one step, the requests may come from several interfaces
+        let claimed = self.status.send_if_modified(|current| {
+            if current.as_ref().is_some_and(|s| s.active) {
+                return false;
+            }
+            *current = Some(status.clone());
+            true
+        });
+        if !claimed {
+            bail!("A burst is already running");
+        }
+        let out = std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))
+            .and_then(|()| File::create(&path).with_context(|| format!("Failed to create {}", path.display())))
+            .and_then(|file| {
+                let mut out = BufWriter::new(file);
+                writeln!(out, "timestamp,value,error")?;
+                Ok(out)
+            });
+        let out = match out {
+            Ok(out) => out,
+            Err(e) => {
+                self.status.send_replace(None);
+                return Err(e);
+            }
+        };
+
+        self.stop.store(false, Ordering::Relaxed);
+        info!("Burst sampling of {} for {} min to {}", sensor, minutes, status.path);
+        self.audit.record(source, "burst sampling", format!("{} for {} min", sensor, minutes));
+
+        let backend = sensors::open(sensor_config, &self.board);
+        let duration = Duration::from_secs(minutes as u64 * 60);
+        tokio::spawn(self.clone().record(backend, out, duration));
+        Ok(status)
+    }
+
+    /// Stops the running burst early. Returns `false` if no burst is running.
+    pub fn stop(&self, source: AuditSource) -> bool {
+        let Some(status) = self.status().filter(|s| s.active) else {
+            return false;
+        };
+        self.stop.store(true, Ordering::Relaxed);
+        self.audit.record(source, "burst stopped", status.sensor);
+        true
+    }
+
+    /// Samples the sensor until the end of the burst, then publishes the summary on
+    /// `{base_topic}/events/burst`.
+    async fn record(self, backend: Box<dyn TemperatureSensor>, out: BufWriter<File>, duration: Duration) {
+        let sampler = self.clone();
+        let result = tokio::task::spawn_blocking(move || sampler.sample(backend.as_ref(), out, duration)).await;
+        if let Ok(Err(e)) = &result {
+            warning!("Burst sampling failed: {:#}", e);
+        }
+        self.status.send_modify(|status| {
+            if let Some(status) = status {
+                status.active = false;
+            }
+        });
+        if let Some(status) = self.status() {
+            info!("Burst sampling of {} done: {} samples, {} errors", status.sensor, status.samples, status.errors);
+            if let Ok(payload) = serde_json::to_vec(&status) {
+                let topic = format!("{}/events/burst", self.base_topic);
+                let _ = self.publisher.publish(topic, rumqttc::QoS::AtLeastOnce, false, payload).await;
+            }
+        }
+    }
+
+    /// Reads the sensor back to back and writes one CSV line per sample.
+    fn sample(&self, backend: &dyn TemperatureSensor, mut out: BufWriter<File>, duration: Duration) -> Result<()> {
+        let start = Instant::now();
+        while start.elapsed() < duration && !self.stop.load(Ordering::Relaxed) {
+            let read_start = Instant::now();
+            let result = backend.read();
+            let timestamp = Utc::now().to_rfc3339();
+            match &result {
+                Ok(value) => writeln!(out, "{},{},", timestamp, format::data(Unit::Celsius, *value))?,
+                Err(e) => writeln!(out, "{},,\"{}\"", timestamp, e.to_string().replace('"', "'"))?,
+            }
+            self.status.send_modify(|status| {
+                if let Some(status) = status {
+                    status.samples += 1;
+                    status.errors += result.is_err() as u64;
+                }
+            });
+            if let Some(rest) = MIN_SAMPLE_INTERVAL.checked_sub(read_start.elapsed()) {
+                std::thread::sleep(rest);
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Starts and stops bursts from `{base_topic}/burst/{sensor}` messages: a duration in
+/// minutes starts the burst, `stop` or `0` stops it.
+pub fn follow(bursts: &Bursts, subscriptions: &Subscriptions) {
+    let prefix = format!("{}/burst/", bursts.base_topic);
+    let bursts = bursts.clone();
+    subscriptions.subscribe(format!("{}+", prefix), move |topic, payload| {
+        let Some(sensor) = topic.strip_prefix(&prefix) else {
+            return;
+        };
+        let command = String::from_utf8_lossy(payload);
+        let command = command.trim();
+        if command == "stop" || command == "0" {
+            bursts.stop(AuditSource::Mqtt);
+            return;
+        }
+        match command.parse::<u32>() {
+            Ok(minutes) => {
+                if let Err(e) = bursts.start(sensor, minutes, AuditSource::Mqtt) {
+                    warning!("Burst: ignoring command for {}: {:#}", sensor, e);
+                }
+            }
+            Err(_) => warning!("Burst: ignoring command for {}: invalid duration {:?}", sensor, command),
+        }
+    });
+}
@@ -0,0 +1,152 @@
+//! SVG path generation for the charts drawn by Slint's `Path` element.
+//! Paths are built in a fixed coordinate space and scaled to the data range.
+
+use std::fmt::Write;
+
+use crate::history::HISTORY_POINTS;
+
+/// Width of the chart coordinate space: one unit per history point, x from 0 to 95.
+pub const CHART_WIDTH: f32 = (HISTORY_POINTS - 1) as f32;
+/// Height of the chart coordinate space: y from 0 (top) to 100 (bottom).
+pub const CHART_HEIGHT: f32 = 100.0;
+
+/// Fraction of the data range added above and below the curve.
+const PADDING: f32 = 0.1;
+/// Smallest value range shown, in °C, so that a flat curve does not turn sensor
+/// noise into large swings.
+const MIN_SPAN: f32 = 2.0;
+
+/// A point of a chart curve.
+#[derive(Debug, Clone, Copy)]
+pub struct ChartPoint {
+    /// Position in chart units (0 to `CHART_WIDTH`).
+    pub x: f32,
+    /// Value, scaled to the chart height.
+    pub value: f32,
+    /// Starts a new segment instead of joining the previous point (missing data).
+    pub gap: bool,
+}
+
+/// Fixed bounds of the Y axis, in °C. Unset bounds follow the data.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChartBounds {
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+}
+
+/// Values at the bottom and at the top of a chart, in °C.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChartRange {
+    pub bottom: f32,
+    pub top: f32,
+}
+
+/// Builds chart paths, reusing its scratch buffers from one call to the next.
+///
+/// The Y axis is scaled to the min/max of the data (with padding), unless fixed
+/// bounds are given. Optional smoothing applies a moving average over `smoothing` points.
+#[derive(Debug, Default)]
+pub struct ChartPathBuilder {
+    smoothing: usize,
+    points: Vec<ChartPoint>,
+    smoothed: Vec<f32>,
+}
+
+impl ChartPathBuilder {
+    /// Creates a builder; `smoothing` is the moving average window (0 or 1 disables it).
+    pub fn new(smoothing: usize) -> Self {
+        Self {
+            smoothing,
+            ..Self::default()
+        }
+    }
+
+    /// Writes the path of `points` into `out`, replacing its content but keeping its
+    /// allocation, and returns the range of the Y axis.
+    pub fn build(
+        &mut self,
+        points: impl IntoIterator<Item = ChartPoint>,
+        bounds: ChartBounds,
+        out: &mut String,
+    ) -> ChartRange {
+        out.clear();
+        self.points.clear();
+        self.points.extend(points);
+        if self.points.is_empty() {
+            return ChartRange::default();
+        }
+
+        self.smooth();
+        let range = self.range(bounds);
+        let span = range.top - range.bottom;
+
+        for (i, (point, &value)) in self.points.iter().zip(&self.smoothed).enumerate() {
+            let x = point.x.clamp(0.0, CHART_WIDTH);
+            let y = ((range.top - value) / span * CHART_HEIGHT).clamp(0.0, CHART_HEIGHT);
+            let command = if i == 0 || point.gap { 'M' } else { 'L' };
+            let _ = write!(out, "{} {:.2} {:.2} ", command, x, y);
+        }
+        range
+    }
+
+    /// Range of the Y axis: the fixed bounds, the others fitted to the smoothed values.
+    fn range(&self, bounds: ChartBounds) -> ChartRange {
+        let (min, max) = self
+            .smoothed
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| (min.min(v), max.max(v)));
+        let center = (min + max) / 2.0;
+        let span = (max - min).max(MIN_SPAN) * (1.0 + 2.0 * PADDING);
+        let (auto_bottom, auto_top) = (center - span / 2.0, center + span / 2.0);
+        match (bounds.min, bounds.max) {
+            (Some(bottom), Some(top)) => ChartRange { bottom, top },
+            (Some(bottom), None) => ChartRange {
+                bottom,
+                top: auto_top.max(bottom + MIN_SPAN),
+            },
+            (None, Some(top)) => ChartRange {
+                bottom: auto_bottom.min(top - MIN_SPAN),
+                top,
+            },
+            (None, None) => ChartRange {
+                bottom: auto_bottom,
+                top: auto_top,
+            },
+        }
+    }
+
+    /// Fills `smoothed` with the trailing moving average of the point values.
+    fn smooth(&mut self) {
+        self.smoothed.clear();
+        let window = self.smoothing.max(1);
+        let mut sum = 0.0;
+        for i in 0..self.points.len() {
+            sum += self.points[i].value;
+            if i >= window {
+                sum -= self.points[i - window].value;
+            }
+            self.smoothed.push(sum / (i + 1).min(window) as f32);
+        }
+    }
+}
+
+/// Writes `points` into `out` as a dashed line (every other segment drawn), scaled to
+/// `range`. Used for the forecast, which Slint's `Path` cannot dash by itself.
+pub fn dashed_path(points: &[ChartPoint], range: ChartRange, out: &mut String) {
+    out.clear();
+    let span = range.top - range.bottom;
+    if span <= 0.0 {
+        return;
+    }
+    let y = |value: f32| ((range.top - value) / span * CHART_HEIGHT).clamp(0.0, CHART_HEIGHT);
+    for pair in points.windows(2).step_by(2) {
+        let _ = write!(
+            out,
+            "M {:.2} {:.2} L {:.2} {:.2} ",
+            pair[0].x,
+            y(pair[0].value),
+            pair[1].x,
+            y(pair[1].value)
+        );
+    }
+}
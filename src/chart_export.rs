@@ -0,0 +1,400 @@
+//! Chart image export.
+//! Draws the temperature history of the sensors on a single chart with a time axis and
+//! a legend, as a standalone SVG image or, with the `png` feature, a PNG. Used by
+//! `boilert chart` and by the `[chart.export]` section, which exports the chart every
+//! day (or on request) and can publish it on MQTT so that the home automation can
+//! forward it with its notifications.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use serde::Deserialize;
+use tokio::sync::{Mutex, Notify};
+
+use crate::history::{HistoryPoint, SensorHistory};
+use crate::logging::{info, warning};
+use crate::mqtt::Publisher;
+
+/// Colors of the curves, in the order of the sensors.
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#17becf",
+];
+/// Candidate spacings of the horizontal grid lines, in °C.
+const VALUE_STEPS: [f32; 7] = [0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0];
+/// Candidate spacings of the time labels, in minutes.
+const TIME_STEPS: [i64; 8] = [5, 15, 30, 60, 120, 180, 360, 720];
+/// Maximum number of grid lines or time labels.
+const MAX_TICKS: usize = 8;
+/// Font of the labels, with the fonts commonly installed on a Raspberry Pi first (the
+/// PNG renderer does not resolve `sans-serif` to an installed font by itself).
+const FONT: &str = r#"font-family="DejaVu Sans, Liberation Sans, Arial, sans-serif" font-size="12""#;
+/// Margins around the plot area: left, right, top, bottom (time labels and legend).
+const MARGINS: (f32, f32, f32, f32) = (60.0, 20.0, 40.0, 60.0);
+
+/// File format of an exported chart.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    /// Vector image, readable by any browser.
+    #[default]
+    Svg,
+    /// Raster image (requires the `png` feature).
+    Png,
+}
+
+impl ImageFormat {
+    /// Format matching the extension of `path`, if any.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "svg" => Some(Self::Svg),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Svg => "svg",
+            Self::Png => "png",
+        }
+    }
+}
+
+/// Chart export settings (`[chart.export]`).
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ChartExportConfig {
+    /// Local time of the daily export, "HH:MM" (disabled if absent).
+    pub daily_at: Option<String>,
+    /// Directory of the exported images, named `chart-YYYY-MM-DD-HHMM.{svg,png}`.
+    pub directory: String,
+    pub format: ImageFormat,
+    /// Hours of history shown, up to the whole history.
+    pub hours: f32,
+    /// Sensors shown, physical or virtual (all if empty).
+    pub sensors: Vec<String>,
+    /// Size of the image, in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// Publish each exported image on `{base_topic}/chart` (retained).
+    pub publish: bool,
+}
+
+impl Default for ChartExportConfig {
+    fn default() -> Self {
+        Self {
+            daily_at: None,
+            directory: "charts".to_string(),
+            format: ImageFormat::Svg,
+            hours: 24.0,
+            sensors: Vec::new(),
+            width: 900,
+            height: 500,
+            publish: false,
+        }
+    }
+}
+
+impl ChartExportConfig {
+    /// Checks the settings against the names of the configured sensors.
+    pub fn validate(&self, sensor_names: &[String]) -> Result<()> {
+        self.daily_time()?;
+        if !self.hours.is_finite() || self.hours <= 0.0 {
+            bail!("[chart.export] hours must be positive");
+        }
+        if !(200..=4000).contains(&self.width) || !(150..=4000).contains(&self.height) {
+            bail!("[chart.export] the image must be between 200x150 and 4000x4000 pixels");
+        }
+        if let Some(unknown) = self.sensors.iter().find(|s| !sensor_names.contains(s)) {
+            bail!("[chart.export] unknown sensor {:?}", unknown);
+        }
+        if self.format == ImageFormat::Png && !cfg!(feature = "png") {
+            bail!("[chart.export] format = \"png\" requires building with the `png` feature");
+        }
+        Ok(())
+    }
+
+    /// Local time of the daily export.
+    pub fn daily_time(&self) -> Result<Option<NaiveTime>> {
+        self.daily_at
+            .as_deref()
+            .map(|at| {
+                NaiveTime::parse_from_str(at, "%H:%M")
+                    .with_context(|| format!("[chart.export] daily_at {:?} is not a HH:MM time", at))
+            })
+            .transpose()
+    }
+}
+
+/// A chart of several sensors over a time window.
+#[derive(Debug, Clone)]
+pub struct ChartImage {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    width: u32,
+    height: u32,
+    series: Vec<(String, Vec<HistoryPoint>)>,
+}
+
+impl ChartImage {
+    /// Creates an empty chart of the `hours` before `to`.
+    pub fn new(to: DateTime<Utc>, hours: f32, width: u32, height: u32) -> Self {
+        let span = chrono::Duration::seconds((hours.max(0.0) * 3600.0) as i64);
+        Self {
+            from: to - span,
+            to,
+            width,
+            height,
+            series: Vec::new(),
+        }
+    }
+
+    /// Adds the curve of a sensor, keeping the points inside the time window.
+    pub fn add_series(&mut self, name: &str, points: impl IntoIterator<Item = HistoryPoint>) {
+        let points = points
+            .into_iter()
+            .filter(|p| p.timestamp >= self.from && p.timestamp <= self.to && p.value.is_finite())
+            .collect();
+        self.series.push((name.to_string(), points));
+    }
+
+    /// Renders the chart in `format`.
+    pub fn render(&self, format: ImageFormat) -> Result<Vec<u8>> {
+        let svg = self.svg();
+        match format {
+            ImageFormat::Svg => Ok(svg.into_bytes()),
+            #[cfg(feature = "png")]
+            ImageFormat::Png => rasterize(&svg),
+            #[cfg(not(feature = "png"))]
+            ImageFormat::Png => bail!("PNG export requires building with the `png` feature"),
+        }
+    }
+
+    /// Renders the chart as an SVG document.
+    pub fn svg(&self) -> String {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let (left, right, top, bottom) = MARGINS;
+        let (plot_w, plot_h) = (width - left - right, height - top - bottom);
+        let (low, high, step) = self.value_axis();
+        let span_s = (self.to - self.from).num_seconds().max(1) as f32;
+        let x = |t: DateTime<Utc>| left + (t - self.from).num_seconds() as f32 / span_s * plot_w;
+        let y = |v: f32| top + (high - v) / (high - low) * plot_h;
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" {}>"#,
+            FONT,
+            w = self.width,
+            h = self.height
+        );
+        let _ = writeln!(out, r#"<rect width="100%" height="100%" fill="white"/>"#);
+        let _ = writeln!(
+            out,
+            r#"<text x="{}" y="24" font-size="15">{} – {}</text>"#,
+            left,
+            local(self.from).format("%d/%m/%Y %H:%M"),
+            local(self.to).format("%d/%m/%Y %H:%M")
+        );
+
+        // Value grid and labels
+        let lines = ((high - low) / step).round() as usize;
+        for i in 0..=lines {
+            let value = low + step * i as f32;
+            let _ = writeln!(
+                out,
+                r##"<line x1="{}" x2="{}" y1="{y:.1}" y2="{y:.1}" stroke="#ddd"/>"##,
+                left,
+                left + plot_w,
+                y = y(value)
+            );
+            let _ = writeln!(
+                out,
+                r#"<text x="{}" y="{:.1}" text-anchor="end">{} °C</text>"#,
+                left - 6.0,
+                y(value) + 4.0,
+                value
+            );
+        }
+
+        // Time labels, on round local times
+        let tick_min = TIME_STEPS
+            .into_iter()
+            .find(|&m| span_s / 60.0 / m as f32 <= MAX_TICKS as f32)
+            .unwrap_or(TIME_STEPS[TIME_STEPS.len() - 1]);
+        let offset_s = local(self.from).offset().local_minus_utc() as i64;
+        let tick_s = tick_min * 60;
+        let mut tick = (self.from.timestamp() + offset_s + tick_s - 1).div_euclid(tick_s) * tick_s - offset_s;
+        while let Some(at) = DateTime::from_timestamp(tick, 0).filter(|at| *at <= self.to) {
+            let _ = writeln!(
+                out,
+                r##"<line x1="{x:.1}" x2="{x:.1}" y1="{}" y2="{}" stroke="#eee"/>"##,
+                top,
+                top + plot_h,
+                x = x(at)
+            );
+            let _ = writeln!(
+                out,
+                r#"<text x="{:.1}" y="{}" text-anchor="middle">{}</text>"#,
+                x(at),
+                top + plot_h + 18.0,
+                local(at).format("%H:%M")
+            );
+            tick += tick_s;
+        }
+        let _ = writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black"/>"#,
+            left, top, plot_w, plot_h
+        );
+
+        // Curves, interrupted where data is missing, and their legend
+        let mut legend_x = left;
+        for (i, (name, points)) in self.series.iter().enumerate() {
+            let color = COLORS[i % COLORS.len()];
+            let mut path = String::new();
+            for (j, point) in points.iter().enumerate() {
+                let command = if j == 0 || point.gap { 'M' } else { 'L' };
+                let _ = write!(path, "{} {:.1} {:.1} ", command, x(point.timestamp), y(point.value));
+            }
+            if !path.is_empty() {
+                let _ = writeln!(
+                    out,
+                    r#"<path d="{}" fill="none" stroke="{}" stroke-width="2" stroke-linejoin="round"/>"#,
+                    path.trim_end(),
+                    color
+                );
+            }
+            let legend_y = height - 16.0;
+            let _ = writeln!(
+                out,
+                r#"<rect x="{:.1}" y="{:.1}" width="14" height="4" fill="{}"/><text x="{:.1}" y="{:.1}">{}</text>"#,
+                legend_x,
+                legend_y - 6.0,
+                color,
+                legend_x + 18.0,
+                legend_y,
+                escape(name)
+            );
+            legend_x += 30.0 + 7.0 * name.chars().count() as f32;
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    /// Bottom, top and grid spacing of the value axis, on round values around the data.
+    fn value_axis(&self) -> (f32, f32, f32) {
+        let (min, max) = self
+            .series
+            .iter()
+            .flat_map(|(_, points)| points)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), p| (min.min(p.value), max.max(p.value)));
+        let (min, max) = if min <= max { (min, max) } else { (0.0, 1.0) };
+        let step = VALUE_STEPS
+            .into_iter()
+            .find(|step| (max - min) / step <= (MAX_TICKS - 1) as f32)
+            .unwrap_or(VALUE_STEPS[VALUE_STEPS.len() - 1]);
+        let low = (min / step).floor() * step;
+        let high = ((max / step).ceil() * step).max(low + step);
+        (low, high, step)
+    }
+}
+
+fn local(at: DateTime<Utc>) -> DateTime<Local> {
+    at.with_timezone(&Local)
+}
+
+/// Escapes the characters of `text` that are special in XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Rasterizes an SVG document to PNG, with the fonts of the system for the labels.
+#[cfg(feature = "png")]
+fn rasterize(svg: &str) -> Result<Vec<u8>> {
+    use resvg::{tiny_skia, usvg};
+
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_str(svg, &options).context("Failed to parse the chart")?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height()).context("Empty chart")?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap.encode_png().context("Failed to encode the chart")
+}
+
+/// Builds the chart of `config` from the histories, named by `names`.
+async fn chart(config: &ChartExportConfig, history: &Mutex<Vec<SensorHistory>>, names: &[String]) -> ChartImage {
+    let mut chart = ChartImage::new(Utc::now(), config.hours, config.width, config.height);
+    let history = history.lock().await;
+    for (name, sensor) in names.iter().zip(history.iter()) {
+        if config.sensors.is_empty() || config.sensors.contains(name) {
+            chart.add_series(name, sensor.points().iter().copied());
+        }
+    }
+    chart
+}
+
+/// Exports the chart to the configured directory and returns the path of the image.
+fn save(config: &ChartExportConfig, image: &[u8]) -> Result<PathBuf> {
+    let directory = Path::new(&config.directory);
+    std::fs::create_dir_all(directory).with_context(|| format!("Failed to create {}", directory.display()))?;
+    let name = format!("chart-{}.{}", Local::now().format("%Y-%m-%d-%H%M"), config.format.extension());
+    let path = directory.join(name);
+    crate::state::write_atomic(&path, image)?;
+    Ok(path)
+}
+
+/// Exports the chart every day at `daily_at` and whenever `requests` is notified (the
+/// `{base_topic}/chart/export` command), then publishes it if configured.
+pub async fn export_loop(
+    config: ChartExportConfig,
+    history: Arc<Mutex<Vec<SensorHistory>>>,
+    names: Vec<String>,
+    requests: Arc<Notify>,
+    publisher: Publisher,
+    base_topic: String,
+) {
+    let daily_at = config.daily_time().ok().flatten();
+    loop {
+        let wait = daily_at.map_or(Duration::MAX, next_occurrence);
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = requests.notified() => {}
+        }
+
+        let image = match chart(&config, &history, &names).await.render(config.format) {
+            Ok(image) => image,
+            Err(e) => {
+                warning!("Chart export failed: {:#}", e);
+                continue;
+            }
+        };
+        match save(&config, &image) {
+            Ok(path) => info!("Chart exported to {}", path.display()),
+            Err(e) => warning!("Chart export failed: {:#}", e),
+        }
+        if config.publish {
+            let topic = format!("{}/chart", base_topic);
+            if let Err(e) = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, image).await {
+                warning!("Chart export: failed to publish the image: {}", e);
+            }
+        }
+    }
+}
+
+/// Time until the next `at` local time.
+fn next_occurrence(at: NaiveTime) -> Duration {
+    let now = Local::now();
+    let today = now.date_naive().and_time(at);
+    let next = [today, today + chrono::Duration::days(1)]
+        .into_iter()
+        .filter_map(|naive| Local.from_local_datetime(&naive).earliest())
+        .find(|next| *next > now);
+    next.and_then(|next| (next - now).to_std().ok())
+        .unwrap_or(Duration::from_secs(24 * 3600))
+}
@@ -0,0 +1,34 @@
+//! `boilert chart`: image of the saved temperature history.
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use crate::chart_export::{ChartImage, ImageFormat};
+use crate::config::Config;
+use crate::state;
+
+/// Renders the last `hours` of the history saved in the runtime state file (all the
+/// sensors, or those of `sensors`) and writes the image to `out`.
+pub fn run(config: &Config, hours: f32, sensors: &[String], format: ImageFormat, out: &mut dyn Write) -> Result<()> {
+    let names = config.sensor_names();
+    if let Some(unknown) = sensors.iter().find(|s| !names.contains(s)) {
+        bail!("Unknown sensor {:?} (configured: {})", unknown, names.join(", "));
+    }
+    let path = Path::new(&config.state.path);
+    let Some(saved) = state::load(path)? else {
+        bail!("No history saved in {} yet", path.display());
+    };
+
+    let export = config.chart.export.clone().unwrap_or_default();
+    let to = saved.saved_at.unwrap_or_else(chrono::Utc::now);
+    let mut chart = ChartImage::new(to, hours, export.width, export.height);
+    for name in names.iter().filter(|name| sensors.is_empty() || sensors.contains(name)) {
+        if let Some(points) = saved.history_for(name) {
+            chart.add_series(name, points.iter().copied());
+        }
+    }
+    out.write_all(&chart.render(format)?)?;
+    Ok(())
+}
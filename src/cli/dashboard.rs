@@ -0,0 +1,135 @@
+//! `boilert dashboard`: ready-to-import dashboard definitions matching the configuration.
+
+use std::fmt::Write as _;
+use std::io::Write;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde_json::{Value, json};
+
+use crate::config::Config;
+use crate::discovery::slug;
+
+/// Dashboard flavour to generate.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum DashboardFormat {
+    /// Grafana dashboard JSON querying the Prometheus `/metrics` endpoint.
+    Grafana,
+    /// Home Assistant Lovelace card YAML using the MQTT sensor entities.
+    HomeAssistant,
+}
+
+/// Writes the dashboard definition to `out`.
+pub fn run(config: &Config, format: DashboardFormat, out: &mut dyn Write) -> Result<()> {
+    let names = config.sensor_names();
+    match format {
+        DashboardFormat::Grafana => {
+            serde_json::to_writer_pretty(&mut *out, &grafana(&names))?;
+            writeln!(out)?;
+        }
+        DashboardFormat::HomeAssistant => out.write_all(home_assistant(&names).as_bytes())?,
+    }
+    Ok(())
+}
+
+/// Builds a Grafana dashboard with the energy, a chart of all sensors and one
+/// panel per sensor. The Prometheus data source is chosen at import.
+fn grafana(names: &[String]) -> Value {
+    let datasource = json!({ "type": "prometheus", "uid": "${DS_PROMETHEUS}" });
+    let mut panels = vec![
+        json!({
+            "id": 1,
+            "type": "stat",
+            "title": "Stored energy",
+            "datasource": datasource,
+            "gridPos": { "x": 0, "y": 0, "w": 6, "h": 8 },
+            "fieldConfig": { "defaults": { "unit": "kwatth", "decimals": 1 }, "overrides": [] },
+            "targets": [{ "refId": "A", "datasource": datasource, "expr": "boilert_energy_kwh" }],
+        }),
+        json!({
+            "id": 2,
+            "type": "timeseries",
+            "title": "Temperatures",
+            "datasource": datasource,
+            "gridPos": { "x": 6, "y": 0, "w": 18, "h": 8 },
+            "fieldConfig": { "defaults": { "unit": "celsius" }, "overrides": [] },
+            "targets": [{
+                "refId": "A",
+                "datasource": datasource,
+                "expr": "boilert_temperature_celsius",
+                "legendFormat": "{{sensor}}",
+            }],
+        }),
+    ];
+    for (i, name) in names.iter().enumerate() {
+        panels.push(json!({
+            "id": 3 + i,
+            "type": "timeseries",
+            "title": name,
+            "datasource": datasource,
+            "gridPos": { "x": (i % 3) * 8, "y": 8 + (i / 3) * 8, "w": 8, "h": 8 },
+            "fieldConfig": { "defaults": { "unit": "celsius" }, "overrides": [] },
+            "targets": [{
+                "refId": "A",
+                "datasource": datasource,
+                "expr": format!("boilert_temperature_celsius{{sensor=\"{}\"}}", name),
+                "legendFormat": name,
+            }],
+        }));
+    }
+    json!({
+        "__inputs": [{
+            "name": "DS_PROMETHEUS",
+            "label": "Prometheus",
+            "type": "datasource",
+            "pluginId": "prometheus",
+            "pluginName": "Prometheus",
+        }],
+        "title": "boilert",
+        "tags": ["boilert"],
+        "timezone": "browser",
+        "schemaVersion": 39,
+        "time": { "from": "now-24h", "to": "now" },
+        "refresh": "1m",
+        "panels": panels,
+    })
+}
+
+/// Home Assistant entity ID of a boilert value published on MQTT, as created by an
+/// MQTT sensor whose `unique_id` is `boilert_<name>`.
+pub fn entity_id(name: &str) -> String {
+    format!("sensor.boilert_{}", slug(name))
+}
+
+/// Builds a Lovelace vertical stack with the current values and a 24-hour history.
+fn home_assistant(names: &[String]) -> String {
+    let entities: Vec<String> = std::iter::once(entity_id("energy"))
+        .chain(names.iter().map(|name| entity_id(name)))
+        .collect();
+    let mut yaml = String::new();
+    let _ = writeln!(yaml, "# boilert card: paste into a dashboard with \"Edit dashboard > Raw configuration editor\"");
+    let _ = writeln!(yaml, "# or as the YAML of a manual card. Expected entities:");
+    for entity in &entities {
+        let _ = writeln!(yaml, "#   {}", entity);
+    }
+    let _ = writeln!(yaml, "type: vertical-stack");
+    let _ = writeln!(yaml, "cards:");
+    let _ = writeln!(yaml, "  - type: entities");
+    let _ = writeln!(yaml, "    title: boilert");
+    let _ = writeln!(yaml, "    entities:");
+    let _ = writeln!(yaml, "      - entity: {}", entities[0]);
+    let _ = writeln!(yaml, "        name: Stored energy");
+    for (entity, name) in entities[1..].iter().zip(names) {
+        let _ = writeln!(yaml, "      - entity: {}", entity);
+        // JSON strings are valid YAML scalars, whatever the sensor name contains
+        let _ = writeln!(yaml, "        name: {}", Value::from(name.as_str()));
+    }
+    let _ = writeln!(yaml, "  - type: history-graph");
+    let _ = writeln!(yaml, "    title: Temperatures");
+    let _ = writeln!(yaml, "    hours_to_show: 24");
+    let _ = writeln!(yaml, "    entities:");
+    for entity in &entities[1..] {
+        let _ = writeln!(yaml, "      - entity: {}", entity);
+    }
+    yaml
+}
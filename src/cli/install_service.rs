@@ -0,0 +1,182 @@
+//! `boilert install-service`: systemd unit generation and installation.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result, bail};
+
+/// Options of the `install-service` subcommand.
+#[derive(Debug)]
+pub struct InstallOptions {
+    /// Configuration file the service loads.
+    pub config_path: PathBuf,
+    /// Directories under which USB sticks are mounted, written by the exports.
+    pub mount_roots: Vec<String>,
+    /// Account the service runs as.
+    pub user: String,
+    /// Create `user` as a system account if it does not exist.
+    pub create_user: bool,
+    /// Path of the unit file to write.
+    pub unit_path: PathBuf,
+    /// Print the unit instead of installing it.
+    pub dry_run: bool,
+    /// Start the service right away.
+    pub start: bool,
+}
+
+/// Groups giving access to the hardware (1-Wire/GPIO, I2C, SPI, serial ports) and to the
+/// display. Only the ones that exist on the system are added to the unit.
+const HARDWARE_GROUPS: [&str; 7] = ["gpio", "i2c", "spi", "dialout", "video", "input", "render"];
+
+/// Seconds without a watchdog keep-alive after which systemd restarts boilert.
+const WATCHDOG_SEC: u32 = 60;
+
+/// Generates the unit for the current executable, working directory and configuration
+/// file, then installs and enables it (or prints it with `dry_run`).
+pub fn run(options: &InstallOptions) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to locate the boilert executable")?;
+    let working_dir = std::env::current_dir()?;
+    if !options.config_path.exists() {
+        bail!(
+            "No {} in {}: run install-service from the directory holding the configuration, or pass --config",
+            options.config_path.display(),
+            working_dir.display()
+        );
+    }
+    // The service reads the configuration given here, wherever it runs from
+    let config_path = working_dir.join(&options.config_path);
+    let config_path = (config_path != working_dir.join("config.toml")).then_some(config_path);
+    let groups: Vec<&str> = HARDWARE_GROUPS.iter().copied().filter(|g| group_exists(g)).collect();
+    let unit = render_unit(&exe, config_path.as_deref(), &working_dir, &options.mount_roots, &options.user, &groups);
+
+    if options.dry_run {
+        print!("{}", unit);
+        return Ok(());
+    }
+
+    if options.create_user && !user_exists(&options.user) {
+        run_command(
+            "useradd",
+            &["--system", "--no-create-home", "--shell", "/usr/sbin/nologin", &options.user],
+        )?;
+        println!("Created system user {}", options.user);
+    }
+    if !user_exists(&options.user) {
+        bail!("User {} does not exist (use --create-user)", options.user);
+    }
+
+    std::fs::write(&options.unit_path, unit).with_context(|| {
+        format!(
+            "Failed to write {} (are you root? try sudo)",
+            options.unit_path.display()
+        )
+    })?;
+    println!("Wrote {}", options.unit_path.display());
+
+    let name = options
+        .unit_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "boilert.service".to_string());
+    run_command("systemctl", &["daemon-reload"])?;
+    if options.start {
+        run_command("systemctl", &["enable", "--now", &name])?;
+    } else {
+        run_command("systemctl", &["enable", &name])?;
+    }
+    println!("Enabled {}", name);
+    Ok(())
+}
+
+/// Renders the systemd unit.
+fn render_unit(
+    exe: &Path,
+    config_path: Option<&Path>,
+    working_dir: &Path,
+    mount_roots: &[String],
+    user: &str,
+    groups: &[&str],
+) -> String {
+    let config_arg = config_path.map(|path| format!(" --config {}", path.display())).unwrap_or_default();
+    // The runtime configuration changes are saved to the configuration file, and a
+    // self-update replaces the executable and keeps its boot-attempt marker next to it
+    let mut writable: Vec<&Path> = config_path.and_then(Path::parent).into_iter().collect();
+    if cfg!(feature = "update")
+        && let Some(dir) = exe.parent()
+    {
+        writable.push(dir);
+    }
+    let mut extra_dirs = String::new();
+    for (i, dir) in writable.iter().enumerate() {
+        if *dir != working_dir && !writable[..i].contains(dir) {
+            extra_dirs.push_str(&format!(" {}", dir.display()));
+        }
+    }
+    // USB exports; `-`: the roots that do not exist are skipped
+    for root in mount_roots {
+        extra_dirs.push_str(&format!(" -{}", root));
+    }
+    let mut unit = format!(
+        "[Unit]
+Description=boilert water boiler monitoring
+Wants=network-online.target
+After=network-online.target time-sync.target
+
+[Service]
+Type=notify
+ExecStart={exe}{config_arg}
+WorkingDirectory={dir}
+User={user}
+Restart=on-failure
+RestartSec=5
+WatchdogSec={watchdog}
+NotifyAccess=main
+
+# Hardening
+NoNewPrivileges=yes
+ProtectSystem=strict
+ReadWritePaths={dir}{extra_dirs}
+ProtectHome=yes
+PrivateTmp=yes
+ProtectKernelModules=yes
+ProtectControlGroups=yes
+RestrictSUIDSGID=yes
+LockPersonality=yes
+RestrictRealtime=yes
+",
+        exe = exe.display(),
+        config_arg = config_arg,
+        extra_dirs = extra_dirs,
+        dir = working_dir.display(),
+        user = user,
+        watchdog = WATCHDOG_SEC,
+    );
+    if !groups.is_empty() {
+        unit.push_str(&format!("SupplementaryGroups={}\n", groups.join(" ")));
+    }
+    unit.push_str("\n[Install]\nWantedBy=multi-user.target\n");
+    unit
+}
+
+fn group_exists(group: &str) -> bool {
+    std::fs::read_to_string("/etc/group")
+        .map(|content| content.lines().any(|l| l.split(':').next() == Some(group)))
+        .unwrap_or(false)
+}
+
+fn user_exists(user: &str) -> bool {
+    std::fs::read_to_string("/etc/passwd")
+        .map(|content| content.lines().any(|l| l.split(':').next() == Some(user)))
+        .unwrap_or(false)
+}
+
+fn run_command(program: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        bail!("{} {} failed ({})", program, args.join(" "), status);
+    }
+    Ok(())
+}
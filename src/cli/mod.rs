@@ -0,0 +1,248 @@
+//! Command-line interface of the boilert application.
+//! Without a subcommand, boilert runs the monitoring application; subcommands
+//! provide installation and diagnostic helpers.
+
+mod chart;
+mod dashboard;
+mod install_service;
+mod mqtt_test;
+mod read;
+mod sensors;
+mod simulate;
+#[cfg(feature = "update")]
+mod update;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use boilert::model::AuditSource;
+use clap::{Parser, Subcommand};
+
+use crate::audit::{self, AuditLog};
+use crate::board::Board;
+use crate::chart_export::ImageFormat;
+use crate::config::Config;
+use crate::logging::LogLevel;
+
+/// Water boiler monitoring with 1-Wire sensors, MQTT and a Slint UI.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Configuration file.
+    #[arg(long, global = true, default_value = "config.toml")]
+    pub config: PathBuf,
+    /// Log level, instead of `[debug] log_level`.
+    #[arg(long, global = true, value_enum)]
+    pub log_level: Option<LogLevel>,
+    /// Run without the display, like `[runtime] headless = true`.
+    #[arg(long)]
+    pub headless: bool,
+    /// List the detected and configured sensors with live readings, then exit
+    /// (same as `boilert sensors`).
+    #[arg(long)]
+    pub list_sensors: bool,
+    /// Stop the instance holding the lock file, and start even if another instance is
+    /// online on the base topic.
+    #[arg(long)]
+    pub takeover: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Export the audit log of control actions and configuration changes.
+    Audit {
+        /// Write CSV instead of JSON lines.
+        #[arg(long)]
+        csv: bool,
+    },
+    /// Render the saved temperature history as an SVG or PNG image.
+    Chart {
+        /// Hours of history shown, up to the whole history.
+        #[arg(long, default_value_t = 24.0)]
+        hours: f32,
+        /// Sensor to show; repeat for several (default: all).
+        #[arg(long = "sensor")]
+        sensors: Vec<String>,
+        /// Image format (default: from the extension of the output, else SVG).
+        #[arg(long, value_enum)]
+        format: Option<ImageFormat>,
+        /// Write to this file instead of stdout.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Print a dashboard definition matching the configured sensors.
+    Dashboard {
+        /// Grafana JSON (Prometheus data source) or Home Assistant Lovelace YAML.
+        #[arg(value_enum)]
+        format: dashboard::DashboardFormat,
+        /// Write to this file instead of stdout.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Read every sensor once and print the values and the stored energy, without the UI or MQTT.
+    Read {
+        /// Print the values as one JSON document, like the `state` topic.
+        #[arg(long)]
+        json: bool,
+    },
+    /// List detected and configured sensors with live readings and read latencies.
+    Sensors,
+    /// Run the `[simulation.tank]` model offline and print the temperatures as CSV.
+    Simulate {
+        /// Simulated duration, in hours.
+        #[arg(long, default_value_t = 24.0)]
+        hours: f32,
+        /// Interval between two rows, in minutes.
+        #[arg(long, default_value_t = 1.0)]
+        every: f32,
+        /// Write to this file instead of stdout.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Serve the sensors of the local 1-Wire bus to `remote-w1` sensors of other machines.
+    W1Agent {
+        /// Address and port to listen on.
+        #[arg(long, default_value = "0.0.0.0:7070")]
+        listen: String,
+    },
+    /// Check the connection to the configured MQTT broker.
+    MqttTest {
+        /// Also subscribe to the test topic and wait for the message to come back.
+        #[arg(long)]
+        round_trip: bool,
+        /// Give up after this many seconds.
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+    },
+    /// Generate, install and enable a systemd unit for the current directory.
+    InstallService {
+        /// Account the service runs as.
+        #[arg(long, default_value = "boilert")]
+        user: String,
+        /// Create the account as a system user if it does not exist.
+        #[arg(long)]
+        create_user: bool,
+        /// Path of the unit file.
+        #[arg(long, default_value = "/etc/systemd/system/boilert.service")]
+        unit_path: PathBuf,
+        /// Print the unit instead of installing it.
+        #[arg(long)]
+        dry_run: bool,
+        /// Start the service immediately.
+        #[arg(long)]
+        now: bool,
+    },
+    /// Check for a new signed release, install it and restart the service.
+    #[cfg(feature = "update")]
+    Update {
+        /// Only report whether an update is available.
+        #[arg(long)]
+        check: bool,
+        /// Install the release even if it is not newer than the running version.
+        #[arg(long)]
+        force: bool,
+        /// Do not restart the service after installing.
+        #[arg(long)]
+        no_restart: bool,
+    },
+}
+
+/// Runs a subcommand to completion. `config_path` is the file `config` was loaded from.
+pub fn run(command: Command, config: &Config, config_path: &Path) -> Result<()> {
+    match command {
+        Command::Audit { csv } => export_audit(config, csv),
+        Command::Chart {
+            hours,
+            sensors,
+            format,
+            output,
+        } => {
+            let format = format.or_else(|| output.as_deref().and_then(ImageFormat::from_path)).unwrap_or_default();
+            match output {
+                Some(path) => {
+                    let mut image = Vec::new();
+                    chart::run(config, hours, &sensors, format, &mut image)?;
+                    std::fs::write(&path, image)?;
+                    println!("Chart written to {}", path.display());
+                    Ok(())
+                }
+                None => chart::run(config, hours, &sensors, format, &mut std::io::stdout().lock()),
+            }
+        }
+        Command::Dashboard { format, output } => match output {
+            Some(path) => {
+                let mut file = std::fs::File::create(&path)?;
+                dashboard::run(config, format, &mut file)?;
+                println!("Dashboard written to {}", path.display());
+                Ok(())
+            }
+            None => dashboard::run(config, format, &mut std::io::stdout().lock()),
+        },
+        Command::Read { json } => read::run(config, json, &mut std::io::stdout().lock()),
+        Command::Sensors => sensors::run(config),
+        Command::Simulate { hours, every, output } => match output {
+            Some(path) => {
+                let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+                simulate::run(config, hours, every, &mut file)?;
+                file.flush()?;
+                println!("Simulation written to {}", path.display());
+                Ok(())
+            }
+            None => simulate::run(config, hours, every, &mut std::io::stdout().lock()),
+        },
+        Command::W1Agent { listen } => crate::sensors::serve_w1_agent(&Board::from_config(&config.hardware), &listen),
+        Command::MqttTest { round_trip, timeout } => {
+            mqtt_test::run(config, round_trip, Duration::from_secs(timeout))
+        }
+        Command::InstallService {
+            user,
+            create_user,
+            unit_path,
+            dry_run,
+            now,
+        } => {
+            let options = install_service::InstallOptions {
+                config_path: config_path.to_path_buf(),
+                mount_roots: config.usb_export.mount_roots.clone(),
+                user,
+                create_user,
+                unit_path,
+                dry_run,
+                start: now,
+            };
+            install_service::run(&options)?;
+            if !options.dry_run {
+                AuditLog::open(&config.audit).record(
+                    AuditSource::Cli,
+                    "service installed",
+                    format!("{} (user {})", options.unit_path.display(), options.user),
+                );
+            }
+            Ok(())
+        }
+        #[cfg(feature = "update")]
+        Command::Update {
+            check,
+            force,
+            no_restart,
+        } => update::run(config, check, force, !no_restart),
+    }
+}
+
+/// Writes the whole audit log to stdout.
+fn export_audit(config: &Config, csv: bool) -> Result<()> {
+    let entries = audit::read_all(Path::new(&config.audit.path))?;
+    let mut out = std::io::stdout().lock();
+    if csv {
+        audit::write_csv(&mut out, &entries)?;
+    } else {
+        for entry in &entries {
+            writeln!(out, "{}", serde_json::to_string(entry)?)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! `boilert mqtt-test`: broker connectivity check.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow, bail};
+use rumqttc::{AsyncClient, Event, EventLoop, Packet, QoS};
+
+use crate::config::Config;
+use crate::mqtt;
+
+/// Connects to the configured broker, publishes a test message to
+/// `{base_topic}/test` and, with `round_trip`, waits to receive it back.
+pub fn run(config: &Config, round_trip: bool, timeout: Duration) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    runtime.block_on(async {
+        match tokio::time::timeout(timeout, test(config, round_trip)).await {
+            Ok(result) => result,
+            Err(_) => bail!(
+                "No answer from the broker within {}s: check the network, firewall and broker load",
+                timeout.as_secs()
+            ),
+        }
+    })
+}
+
+async fn test(config: &Config, round_trip: bool) -> Result<()> {
+    let client_id = format!("boilert-mqtt-test-{}", std::process::id());
+    let options = mqtt::options(&config.mqtt, &client_id)?;
+    let (client, mut eventloop) = AsyncClient::new(options, 10);
+    let topic = format!("{}/test", config.mqtt.base_topic);
+    let payload = format!("boilert mqtt-test {}", chrono::Utc::now().to_rfc3339());
+
+    println!("Connecting to {}:{} ...", config.mqtt.host, config.mqtt.port);
+    wait_for(&mut eventloop, |packet| matches!(packet, Packet::ConnAck(_))).await?;
+    println!("Connected");
+
+    if round_trip {
+        client.subscribe(&topic, QoS::AtLeastOnce).await?;
+        wait_for(&mut eventloop, |packet| matches!(packet, Packet::SubAck(_))).await?;
+        println!("Subscribed to {}", topic);
+    }
+
+    let start = Instant::now();
+    client.publish(&topic, QoS::AtLeastOnce, false, payload.clone()).await?;
+    wait_for(&mut eventloop, |packet| matches!(packet, Packet::PubAck(_))).await?;
+    println!("Published test message to {} (acknowledged by the broker)", topic);
+
+    if round_trip {
+        wait_for(&mut eventloop, |packet| {
+            matches!(packet, Packet::Publish(p) if p.topic == topic && p.payload[..] == *payload.as_bytes())
+        })
+        .await?;
+        println!("Round-trip OK in {} ms", start.elapsed().as_millis());
+    }
+
+    client.disconnect().await?;
+    Ok(())
+}
+
+/// Polls the event loop until an incoming packet matches `predicate`.
+///
+/// # Errors
+/// Returns the connection error, with a troubleshooting hint when one is known.
+async fn wait_for(eventloop: &mut EventLoop, predicate: impl Fn(&Packet) -> bool) -> Result<()> {
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(packet)) if predicate(&packet) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => {
+                return Err(match mqtt::troubleshooting_hint(&e) {
+                    Some(hint) => anyhow!("{}\nHint: {}", e, hint),
+                    None => anyhow!("{}", e),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! `boilert read`: a single acquisition pass, for shell scripts and cron jobs.
+
+use std::io::Write;
+
+use anyhow::{Result, bail};
+use boilert::model::{EnergyReport, Reading, SensorStatus, Snapshot};
+use chrono::Utc;
+
+use crate::acquisition::{out_of_range, stored_energy};
+use crate::board::Board;
+use crate::config::Config;
+use crate::format::{self, Unit};
+use crate::pressure::PressureSensor;
+use crate::sensors;
+use crate::tank_sim::TankSim;
+
+/// Reads every configured sensor and the pressure once, like an acquisition cycle
+/// without smoothing, and writes the values and the stored energy to `out`: one JSON
+/// [`Snapshot`] with `json`, else a table.
+///
+/// # Errors
+/// Returns an error, once the values are written, if a sensor could not be read.
+pub fn run(config: &Config, json: bool, out: &mut impl Write) -> Result<()> {
+    let mut board = Board::from_config(&config.hardware);
+    if let Some(tank) = &config.simulation.tank
+        && board.is_simulated()
+    {
+        board = board.with_tank(TankSim::new(tank, config.simulation.speed));
+    }
+
+    let now = Utc::now();
+    let readings: Vec<Reading> = config
+        .sensors
+        .iter()
+        .map(|sensor| {
+            let (result, suspect) = match sensors::open(sensor, &board).read_measurement() {
+                Ok(measurement) if !sensor.is_plausible(measurement.temperature) => out_of_range(sensor, measurement),
+                result => (result, false),
+            };
+            let (measurement, status) = match result {
+                Ok(measurement) if suspect => (measurement, SensorStatus::Suspect),
+                Ok(measurement) => (measurement, SensorStatus::Ok),
+                Err(e) => {
+                    eprintln!("Error reading sensor {}: {:#}", sensor.name, e);
+                    (Default::default(), SensorStatus::Error)
+                }
+            };
+            Reading {
+                sensor: sensor.name.clone(),
+                value: measurement.temperature,
+                humidity: measurement.humidity,
+                pressure_hpa: measurement.pressure_hpa,
+                status,
+                timestamp: now,
+            }
+        })
+        .collect();
+    let pressure_bar = config.pressure.as_ref().and_then(|pressure| {
+        PressureSensor::new(pressure, &board)
+            .read_bar()
+            .inspect_err(|e| eprintln!("Error reading the pressure: {:#}", e))
+            .ok()
+    });
+
+    let reference_temp_c = config.boiler.reference_temp_c;
+    let (average_temp_c, energy_kwh) = stored_energy(config, &readings, reference_temp_c).unwrap_or_default();
+    let snapshot = Snapshot {
+        timestamp: now,
+        readings,
+        energy: EnergyReport {
+            energy_kwh,
+            average_temp_c,
+            reference_temp_c,
+            volume_l: config.boiler.volume_l,
+        },
+        pressure_bar,
+        alerts: Vec::new(),
+    };
+
+    if json {
+        writeln!(out, "{}", serde_json::to_string(&snapshot)?)?;
+    } else {
+        for reading in &snapshot.readings {
+            let value = match reading.status {
+                SensorStatus::Error => "-".to_string(),
+                _ => format::text(Unit::Celsius, reading.value),
+            };
+            writeln!(out, "{:<20} {:>12}  {:?}", reading.sensor, value, reading.status)?;
+        }
+        if let Some(bar) = snapshot.pressure_bar {
+            writeln!(out, "{:<20} {:>12}", "pressure", format::text(Unit::Bar, bar))?;
+        }
+        writeln!(out, "{:<20} {:>12}", "energy", format::text(Unit::KilowattHour, energy_kwh))?;
+    }
+
+    let failed = snapshot.readings.iter().filter(|r| r.status == SensorStatus::Error).count();
+    if failed > 0 {
+        bail!("{} of {} sensors could not be read", failed, snapshot.readings.len());
+    }
+    Ok(())
+}
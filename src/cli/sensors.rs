@@ -0,0 +1,80 @@
+//! `boilert sensors`: sensor discovery and test.
+
+use std::time::Instant;
+
+use anyhow::Result;
+
+use crate::board::Board;
+use crate::config::Config;
+use crate::sensors::{self, RawCapture, SensorBackend, TemperatureSensor, W1Sensor};
+use crate::tank_sim::TankSim;
+
+/// Prints every detected 1-Wire device and every configured sensor with a live
+/// reading, and flags configured sensors that are missing from the bus.
+pub fn run(config: &Config) -> Result<()> {
+    let mut board = Board::from_config(&config.hardware).with_capture(RawCapture::from_config(&config.debug));
+    if let Some(tank) = &config.simulation.tank
+        && board.is_simulated()
+    {
+        board = board.with_tank(TankSim::new(tank, config.simulation.speed));
+    }
+    println!("Board: {}", board);
+
+    let detected = if board.is_simulated() {
+        Vec::new()
+    } else {
+        sensors::discover_w1(&board)?
+    };
+
+    println!();
+    println!("{:<20} {:<12} {:>10} {:>9}  STATUS", "ID", "NAME", "READING", "LATENCY");
+
+    let mut missing = 0;
+    for sensor in &config.sensors {
+        let status = match sensors::backend(sensor, &board) {
+            SensorBackend::Dummy => "simulated",
+            SensorBackend::I2c => "i2c",
+            SensorBackend::Mcp3008 => "analog",
+            SensorBackend::Modbus => "modbus",
+            SensorBackend::RemoteW1 => "remote",
+            SensorBackend::Mqtt => "mqtt",
+            SensorBackend::Http => "http",
+            SensorBackend::Tank => "simulated",
+            SensorBackend::W1 if detected.contains(&sensor.id) => "ok",
+            SensorBackend::W1 => {
+                missing += 1;
+                "MISSING"
+            }
+        };
+        print_reading(sensors::open(sensor, &board).as_ref(), &sensor.id, &sensor.name, status);
+    }
+    for id in detected.iter().filter(|id| !config.sensors.iter().any(|s| &s.id == *id)) {
+        print_reading(&W1Sensor::new(&board, id), id, "-", "not configured");
+    }
+
+    println!();
+    println!(
+        "{} detected, {} configured, {} missing",
+        detected.len(),
+        config.sensors.len(),
+        missing
+    );
+    Ok(())
+}
+
+fn print_reading(sensor: &dyn TemperatureSensor, id: &str, name: &str, status: &str) {
+    let start = Instant::now();
+    let result = sensor.read_measurement();
+    let latency = format!("{} ms", start.elapsed().as_millis());
+    match result {
+        Ok(m) => {
+            let humidity = m.humidity.map(|h| format!(", {:.1} %RH", h)).unwrap_or_default();
+            let pressure = m.pressure_hpa.map(|p| format!(", {:.1} hPa", p)).unwrap_or_default();
+            println!(
+                "{:<20} {:<12} {:>7.2} °C {:>9}  {}{}{}",
+                id, name, m.temperature, latency, status, humidity, pressure
+            )
+        }
+        Err(e) => println!("{:<20} {:<12} {:>10} {:>9}  {} ({:#})", id, name, "-", latency, status, e),
+    }
+}
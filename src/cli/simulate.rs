@@ -0,0 +1,57 @@
+//! `boilert simulate`: offline run of the tank model.
+
+use std::io::Write;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::format::{self, Unit};
+use crate::sensors::SensorBackend;
+use crate::tank_sim::{self, TankSimConfig};
+
+/// Runs the `[simulation.tank]` model for `hours` as fast as possible and writes, every
+/// `every_min`, the state of the element and the temperatures of the `tank` sensors (of
+/// every layer without any) as CSV to `out`, then prints the totals of the run.
+pub fn run(config: &Config, hours: f32, every_min: f32, out: &mut dyn Write) -> Result<()> {
+    let tank = config.simulation.tank.clone().unwrap_or_else(|| {
+        eprintln!("No [simulation.tank] section: simulating the default tank");
+        TankSimConfig::default()
+    });
+    let mut columns: Vec<(String, f32)> = config
+        .sensors
+        .iter()
+        .filter(|s| s.backend == Some(SensorBackend::Tank))
+        .map(|s| (s.name.clone(), s.tank.height))
+        .collect();
+    if columns.is_empty() {
+        columns = (0..tank.layers)
+            .map(|i| (format!("layer{}", i + 1), (i as f32 + 0.5) / tank.layers as f32))
+            .collect();
+    }
+
+    let names: Vec<&str> = columns.iter().map(|(name, _)| name.as_str()).collect();
+    writeln!(out, "minute,element,{}", names.join(","))?;
+    let mut result = Ok(());
+    let duration = Duration::from_secs_f32(hours.max(0.0) * 3600.0);
+    let every = Duration::from_secs_f32(every_min.max(1.0 / 60.0) * 60.0);
+    let totals = tank_sim::run_offline(&tank, duration, every, |at, element_on, temperature| {
+        let values: Vec<String> = columns.iter().map(|&(_, h)| format::data(Unit::Celsius, temperature(h))).collect();
+        let minute = at.as_secs_f32() / 60.0;
+        if result.is_ok() {
+            result = writeln!(out, "{},{},{}", minute, u8::from(element_on), values.join(","));
+        }
+    });
+    result?;
+
+    eprintln!(
+        "{} simulated: element on for {} ({} cycles, {}), {} drawn, coldest outlet {}",
+        format::text(Unit::Hour, (totals.elapsed_s / 3600.0) as f32),
+        format::text(Unit::Hour, (totals.element_on_s / 3600.0) as f32),
+        totals.element_cycles,
+        format::text(Unit::KilowattHour, totals.energy_kwh as f32),
+        format::text(Unit::Litre, totals.drawn_l as f32),
+        totals.coldest_outlet_c.map_or("-".to_string(), |t| format::text(Unit::Celsius, t)),
+    );
+    Ok(())
+}
@@ -0,0 +1,37 @@
+//! `boilert update`: signed self-update.
+
+use anyhow::Result;
+use boilert::model::AuditSource;
+
+use crate::audit::AuditLog;
+use crate::config::Config;
+use crate::update;
+
+/// Looks up the latest release and installs it if it is newer.
+pub fn run(config: &Config, check_only: bool, force: bool, restart: bool) -> Result<()> {
+    let release = update::latest_release(&config.update)?;
+    let newer = update::is_newer(&release.version)?;
+    println!(
+        "Running {}, latest release is {}{}",
+        env!("CARGO_PKG_VERSION"),
+        release.version,
+        if newer { " (update available)" } else { "" }
+    );
+    if check_only || !(newer || force) {
+        return Ok(());
+    }
+
+    update::install(&config.update, &release)?;
+    AuditLog::open(&config.audit).record(
+        AuditSource::Cli,
+        "update installed",
+        format!("{} -> {}", env!("CARGO_PKG_VERSION"), release.version),
+    );
+    if restart {
+        update::restart_service(&config.update)?;
+        println!("Restarted {}", config.update.service);
+    } else {
+        println!("Restart boilert to run the new version");
+    }
+    Ok(())
+}
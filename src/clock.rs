@@ -0,0 +1,97 @@
+//! Wall-clock helpers for the boilert application.
+//! Computes schedule boundaries aligned on local time (e.g., :00/:15/:30/:45)
+//! and checks that the system clock can be trusted.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::{DateTime, Duration, Local, Offset, TimeZone, Utc};
+
+use crate::alerts::Alerts;
+
+/// Returns the first instant strictly after `now` that is a multiple of `period`
+/// in local time.
+///
+/// With a 15-minute period this yields the next :00, :15, :30 or :45, so scheduled
+/// work stays aligned regardless of when the application started or how long the
+/// system was suspended.
+pub fn next_boundary(now: DateTime<Utc>, period: Duration) -> DateTime<Utc> {
+    let period_s = period.num_seconds().max(1);
+    let offset_s = Local
+        .offset_from_utc_datetime(&now.naive_utc())
+        .fix()
+        .local_minus_utc() as i64;
+    let local_s = now.timestamp() + offset_s;
+    let next_local_s = (local_s.div_euclid(period_s) + 1) * period_s;
+    DateTime::from_timestamp(next_local_s - offset_s, 0).unwrap_or(now + period)
+}
+
+/// Returns the last boundary at or before `now` (see [`next_boundary`]).
+pub fn previous_boundary(now: DateTime<Utc>, period: Duration) -> DateTime<Utc> {
+    next_boundary(now, period) - period
+}
+
+/// Earliest date considered plausible for the system clock (2025-01-01T00:00:00Z).
+/// A Raspberry Pi without RTC boots with a clock far in the past until NTP kicks in.
+const MIN_PLAUSIBLE_TIMESTAMP: i64 = 1_735_689_600;
+
+/// Flag file maintained by systemd-timesyncd once the clock is synchronized.
+const TIMESYNC_FLAG: &str = "/run/systemd/timesync/synchronized";
+
+/// Interval between two periodic clock checks.
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Shared flag telling whether the system clock can be trusted.
+///
+/// Time-stamped data (history points, timestamps published on MQTT) is only
+/// recorded while the clock is sane.
+#[derive(Clone, Default)]
+pub struct ClockStatus(Arc<AtomicBool>);
+
+impl ClockStatus {
+    /// Returns `true` if the last check found the clock plausible and synchronized.
+    pub fn is_sane(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, sane: bool) {
+        self.0.store(sane, Ordering::Relaxed);
+    }
+}
+
+/// Checks the plausibility of the system clock.
+///
+/// # Errors
+/// Returns a human-readable reason if the clock is before [`MIN_PLAUSIBLE_TIMESTAMP`]
+/// or if systemd-timesyncd is present but reports the clock as not synchronized.
+pub fn check() -> Result<(), String> {
+    let now = Utc::now();
+    if now.timestamp() < MIN_PLAUSIBLE_TIMESTAMP {
+        return Err(format!("system clock is implausible ({})", now.to_rfc3339()));
+    }
+    // Only trust the timesyncd flag when timesyncd is actually running.
+    let timesync_dir = Path::new(TIMESYNC_FLAG).parent();
+    if timesync_dir.is_some_and(|d| d.exists()) && !Path::new(TIMESYNC_FLAG).exists() {
+        return Err("system clock is not synchronized (NTP)".to_string());
+    }
+    Ok(())
+}
+
+/// Periodically re-checks the clock, updating `status` and the `clock` alert.
+pub async fn monitor(status: ClockStatus, alerts: Alerts) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        match check() {
+            Ok(()) => {
+                status.set(true);
+                alerts.clear("clock");
+            }
+            Err(reason) => {
+                status.set(false);
+                alerts.raise("clock", reason);
+            }
+        }
+    }
+}
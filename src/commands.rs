@@ -0,0 +1,171 @@
+//! Remote control over MQTT.
+//! Commands published on `{base_topic}/cmd/{command}` act on the running instance, so it
+//! can be operated from a home automation system without a shell on the device. Each
+//! command is recorded in the audit log and answered on `{base_topic}/ack`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Result, bail};
+use boilert::model::AuditSource;
+use chrono::Utc;
+use serde::Serialize;
+use tokio::sync::{Notify, watch};
+
+use crate::audit::AuditLog;
+use crate::format::{self, Unit};
+use crate::logging::{info, warning};
+use crate::mqtt::{Publisher, Subscriptions};
+
+/// Cold water temperatures accepted by `set_reference_temp`, in °C.
+const REFERENCE_TEMP_RANGE: std::ops::RangeInclusive<f32> = 0.0..=40.0;
+
+/// Requests of the remote commands, picked up by the acquisition loop and by `main`.
+///
+/// Cloning a `Controls` handle is cheap; all clones share the same requests.
+#[derive(Clone)]
+pub struct Controls {
+    force_publish: Arc<Notify>,
+    reset_extremes: Arc<AtomicBool>,
+    reference_temp_c: watch::Sender<f32>,
+    restart: watch::Sender<bool>,
+}
+
+impl Controls {
+    /// Creates the controls, starting from the configured cold water temperature.
+    pub fn new(reference_temp_c: f32) -> Self {
+        Self {
+            force_publish: Arc::new(Notify::new()),
+            reset_extremes: Arc::new(AtomicBool::new(false)),
+            reference_temp_c: watch::channel(reference_temp_c).0,
+            restart: watch::channel(false).0,
+        }
+    }
+
+    /// Completes when an immediate acquisition cycle is requested.
+    pub async fn publish_requested(&self) {
+        self.force_publish.notified().await;
+    }
+
+    /// Returns `true` once after the minimum and maximum values were reset.
+    pub fn take_reset_extremes(&self) -> bool {
+        self.reset_extremes.swap(false, Ordering::Relaxed)
+    }
+
+    /// Cold water temperature of the energy computation, in °C.
+    pub fn reference_temp_c(&self) -> f32 {
+        *self.reference_temp_c.borrow()
+    }
+
+    /// `true` once a restart was requested.
+    pub fn restart_requested(&self) -> bool {
+        *self.restart.borrow()
+    }
+
+    /// Completes when a restart is requested.
+    pub async fn wait_for_restart(&self) {
+        let _ = self.restart.subscribe().wait_for(|&restart| restart).await;
+    }
+
+    /// Changes the cold water temperature of the energy computation and publishes the
+    /// values again.
+    ///
+    /// # Errors
+    /// Returns an error if the temperature is out of range.
+    pub fn set_reference_temp(&self, value: f32) -> Result<()> {
+        check_reference_temp(value)?;
+        self.reference_temp_c.send_replace(value);
+        self.force_publish.notify_one();
+        Ok(())
+    }
+
+    /// Runs `command` with its payload, returning what was done.
+    fn run(&self, command: &str, payload: &str) -> Result<String> {
+        match command {
+            "reset_minmax" => {
+                self.reset_extremes.store(true, Ordering::Relaxed);
+                self.force_publish.notify_one();
+                Ok(String::new())
+            }
+            "force_publish" => {
+                self.force_publish.notify_one();
+                Ok(String::new())
+            }
+            "set_reference_temp" => {
+                let Ok(value) = payload.trim().parse::<f32>() else {
+                    bail!("invalid temperature {:?}", payload);
+                };
+                self.set_reference_temp(value)?;
+                Ok(format::text(Unit::Celsius, value))
+            }
+            "restart" => {
+                self.restart.send_replace(true);
+                Ok(String::new())
+            }
+            other => bail!("unknown command {:?}", other),
+        }
+    }
+}
+
+/// Checks a cold water temperature of the energy computation.
+pub fn check_reference_temp(value: f32) -> Result<()> {
+    if !REFERENCE_TEMP_RANGE.contains(&value) {
+        bail!(
+            "the temperature must be between {} and {} °C",
+            REFERENCE_TEMP_RANGE.start(),
+            REFERENCE_TEMP_RANGE.end()
+        );
+    }
+    Ok(())
+}
+
+/// Answer to a command, published on `{base_topic}/ack`.
+#[derive(Debug, Serialize)]
+struct Ack<'a> {
+    command: &'a str,
+    ok: bool,
+    /// What was done, or why the command was refused.
+    #[serde(skip_serializing_if = "str::is_empty")]
+    detail: &'a str,
+    timestamp: chrono::DateTime<Utc>,
+}
+
+/// Runs the commands received on `{base_topic}/cmd/{command}` and acknowledges them.
+pub fn follow(
+    controls: &Controls,
+    subscriptions: &Subscriptions,
+    publisher: Publisher,
+    base_topic: &str,
+    audit: AuditLog,
+) {
+    let controls = controls.clone();
+    let prefix = format!("{}/cmd/", base_topic);
+    let ack_topic = format!("{}/ack", base_topic);
+    subscriptions.subscribe(format!("{}#", prefix), move |topic, payload| {
+        let Some(command) = topic.strip_prefix(&prefix) else {
+            return;
+        };
+        let payload = String::from_utf8_lossy(payload);
+        let result = controls.run(command, &payload);
+        let detail = match &result {
+            Ok(detail) => {
+                info!("Remote command {}", command);
+                audit.record(AuditSource::Mqtt, "remote command", format!("{} {}", command, detail).trim_end());
+                detail.clone()
+            }
+            Err(e) => {
+                warning!("Ignoring {} command: {:#}", topic, e);
+                format!("{:#}", e)
+            }
+        };
+        let ack = Ack {
+            command,
+            ok: result.is_ok(),
+            detail: &detail,
+            timestamp: Utc::now(),
+        };
+        if let Ok(document) = serde_json::to_vec(&ack) {
+            let _ = publisher.try_publish(ack_topic.clone(), rumqttc::QoS::AtLeastOnce, false, document);
+        }
+    });
+}
@@ -2,8 +2,55 @@
 //! Handles loading settings from `config.toml`.
 
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::fs;
-use anyhow::{Context, Result};
+use std::path::Path;
+use std::time::Duration;
+use anyhow::{Context, Result, bail};
+
+use crate::logging::warning;
+
+pub use crate::audit::AuditConfig;
+pub use crate::auth::AuthConfig;
+pub use crate::billing::BillingConfig;
+pub use crate::board::HardwareConfig;
+pub use crate::burst::BurstConfig;
+pub use crate::chart::ChartBounds;
+pub use crate::chart_export::ChartExportConfig;
+pub use crate::delta::DeltaSensorConfig;
+pub use crate::discovery::DiscoveryConfig;
+pub use crate::display_lock::DisplayLockConfig;
+pub use crate::draws::PipesConfig;
+pub use crate::efficiency::EfficiencyConfig;
+pub use crate::filter::SmoothingFilter;
+pub use crate::flow::FlowConfig;
+pub use crate::forecast::ForecastConfig;
+pub use crate::format::FormatConfig;
+pub use crate::group::GroupConfig;
+pub use crate::heater::HeaterConfig;
+pub use crate::history::HistoryDensity;
+pub use crate::instance_lock::InstanceLockConfig;
+pub use crate::maintenance::MaintenanceConfig;
+pub use crate::messages::MessagesConfig;
+pub use crate::mixing::MixingValveConfig;
+pub use crate::pressure::PressureConfig;
+pub use crate::mqtt::{
+    BrokerConfig, FailoverConfig, LegacyTopicsConfig, MqttTlsConfig, OfflineConfig, PayloadFormat, Protocol,
+    RetainConfig, V5Config,
+};
+pub use crate::outputs::OutputConfig;
+pub use crate::preferences::PreferencesConfig;
+pub use crate::remote::RemoteConfig;
+pub use crate::season::SeasonConfig;
+pub use crate::selftest::SelfTestConfig;
+pub use crate::sensors::{
+    AnalogConfig, DebugConfig, HttpSensorConfig, I2cChip, ModbusConfig, RemoteW1Config, SensorBackend, TankProbeConfig,
+    TopicSensorConfig,
+};
+pub use crate::simulation::SimulationConfig;
+pub use crate::stats::StatsConfig;
+pub use crate::usb_export::UsbExportConfig;
+pub use crate::water::EnergyCoefficient;
 
 /// Configuration for a specific temperature sensor.
 #[derive(Debug, Deserialize, Clone)]
@@ -12,6 +59,187 @@ pub struct SensorConfig {
     pub name: String,
     /// 1-Wire device ID (e.g., "28-000000000001").
     pub id: String,
+    /// Backend reading the sensor (defaults to `dummy` on the simulated board, `w1` otherwise).
+    #[serde(default)]
+    pub backend: Option<SensorBackend>,
+    /// Chip of an `i2c` sensor (default `sht31`).
+    #[serde(default)]
+    pub chip: Option<I2cChip>,
+    /// I2C or SPI bus number of an `i2c` or `mcp3008` sensor (default: the board's bus).
+    #[serde(default)]
+    pub bus: Option<u8>,
+    /// I2C address of an `i2c` sensor (default: the chip's, e.g. `0x44` for the SHT31).
+    #[serde(default)]
+    pub address: Option<u8>,
+    /// SPI chip select of the ADC of an `mcp3008` sensor (default 0).
+    #[serde(default)]
+    pub chip_select: Option<u8>,
+    /// ADC channel (0 to 7) of an `mcp3008` sensor (default 0).
+    #[serde(default)]
+    pub channel: Option<u8>,
+    /// Divider and conversion parameters of an `mcp3008` sensor.
+    #[serde(default)]
+    pub analog: AnalogConfig,
+    /// Serial settings and register of a `modbus` sensor.
+    #[serde(default)]
+    pub modbus: ModbusConfig,
+    /// Remote machine of a `remote-w1` sensor.
+    #[serde(default)]
+    pub remote_w1: RemoteW1Config,
+    /// Topic of an `mqtt` sensor.
+    #[serde(default)]
+    pub mqtt: TopicSensorConfig,
+    /// URL of an `http` sensor.
+    #[serde(default)]
+    pub http: HttpSensorConfig,
+    /// Position of a `tank` sensor in the simulated tank.
+    #[serde(default)]
+    pub tank: TankProbeConfig,
+    /// `false` for sensors outside the tank (e.g., room ambient), which are published
+    /// but left out of the energy calculation.
+    #[serde(default = "default_in_tank")]
+    pub in_tank: bool,
+    /// Fixed bottom of the chart, in °C (default: fitted to the data).
+    #[serde(default)]
+    pub chart_min: Option<f32>,
+    /// Fixed top of the chart, in °C (default: fitted to the data).
+    #[serde(default)]
+    pub chart_max: Option<f32>,
+    /// Calibration offset added to the temperature, in °C (applied after `scale`).
+    #[serde(default)]
+    pub offset_c: f32,
+    /// Calibration factor applied to the temperature read.
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    /// Lowest plausible temperature, in °C (after calibration).
+    #[serde(default)]
+    pub min_valid: Option<f32>,
+    /// Highest plausible temperature, in °C (after calibration).
+    #[serde(default)]
+    pub max_valid: Option<f32>,
+    /// Handling of the temperatures outside `min_valid`..`max_valid`.
+    #[serde(default)]
+    pub out_of_range: RangePolicy,
+    /// Conversion resolution of a `w1` sensor, in bits (9 to 12), written to the device
+    /// at startup (default: left as stored in the sensor).
+    #[serde(default)]
+    pub resolution_bits: Option<u8>,
+    /// Smoothing of the temperatures (default: none).
+    #[serde(default)]
+    pub filter: SmoothingFilter,
+    /// Number of readings the filter works on.
+    #[serde(default = "default_filter_samples")]
+    pub filter_samples: usize,
+    /// Interval between two reads of the sensor, in seconds (default:
+    /// `[timing] acquisition_interval_s`).
+    #[serde(default)]
+    pub interval_s: Option<f32>,
+    /// Topic the values are published on, replacing `{base_topic}/{name}` (e.g., a
+    /// legacy topic expected by existing automations).
+    #[serde(default)]
+    pub topic: Option<String>,
+}
+
+/// Handling of an implausible temperature.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RangePolicy {
+    /// Treat the read as failed: the last valid value is held as for any read error.
+    #[default]
+    Reject,
+    /// Replace the value with the nearest limit.
+    Clamp,
+    /// Keep the value with the `suspect` status; it is neither published nor stored.
+    MarkSuspect,
+}
+
+impl SensorConfig {
+    /// Creates the configuration of a 1-Wire sensor with default settings.
+    pub fn w1(name: String, id: String) -> Self {
+        Self {
+            name,
+            id,
+            backend: Some(SensorBackend::W1),
+            chip: None,
+            bus: None,
+            address: None,
+            chip_select: None,
+            channel: None,
+            analog: AnalogConfig::default(),
+            modbus: ModbusConfig::default(),
+            remote_w1: RemoteW1Config::default(),
+            mqtt: TopicSensorConfig::default(),
+            http: HttpSensorConfig::default(),
+            tank: TankProbeConfig::default(),
+            in_tank: true,
+            chart_min: None,
+            chart_max: None,
+            offset_c: 0.0,
+            scale: 1.0,
+            min_valid: None,
+            max_valid: None,
+            out_of_range: RangePolicy::Reject,
+            resolution_bits: None,
+            filter: SmoothingFilter::None,
+            filter_samples: default_filter_samples(),
+            interval_s: None,
+            topic: None,
+        }
+    }
+
+    /// Returns `true` if `temperature` is within the plausible range of the sensor.
+    pub fn is_plausible(&self, temperature: f32) -> bool {
+        self.min_valid.is_none_or(|min| temperature >= min) && self.max_valid.is_none_or(|max| temperature <= max)
+    }
+
+    /// Brings `temperature` back within the plausible range of the sensor.
+    pub fn clamp_to_valid(&self, temperature: f32) -> f32 {
+        let temperature = self.min_valid.map_or(temperature, |min| temperature.max(min));
+        self.max_valid.map_or(temperature, |max| temperature.min(max))
+    }
+
+    /// Topic the values of the sensor are published on; the other quantities go to its sub-topics.
+    pub fn state_topic(&self, base_topic: &str) -> String {
+        self.topic.clone().unwrap_or_else(|| format!("{}/{}", base_topic, self.name))
+    }
+
+    /// Interval between two reads of the sensor.
+    pub fn read_interval(&self, timing: &TimingConfig) -> Duration {
+        self.interval_s.map_or(timing.acquisition_interval(), Duration::from_secs_f32)
+    }
+
+    /// Fixed bounds of the chart of the sensor.
+    pub fn chart_bounds(&self) -> ChartBounds {
+        ChartBounds {
+            min: self.chart_min,
+            max: self.chart_max,
+        }
+    }
+}
+
+/// Returns `true` if `topic` can be published on: not empty and without wildcards.
+fn is_valid_topic(topic: &str) -> bool {
+    !topic.trim().is_empty() && !topic.contains(['+', '#'])
+}
+
+fn default_in_tank() -> bool {
+    true
+}
+
+fn default_filter_samples() -> usize {
+    5
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_keep_alive_s() -> u64 {
+    5
+}
+
+fn default_clean_session() -> bool {
+    true
 }
 
 /// MQTT connection settings.
@@ -23,6 +251,110 @@ pub struct MqttConfig {
     pub port: u16,
     /// Base topic for publishing sensor data.
     pub base_topic: String,
+    /// MQTT protocol version, "3.1.1" or "5".
+    #[serde(default)]
+    pub protocol: Protocol,
+    /// Client ID on the broker (default: `boilert-{hostname}`). Two clients with the
+    /// same ID disconnect each other.
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// User name and password on the broker (anonymous if absent).
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Interval of the keep-alive pings, in seconds (at least 5).
+    #[serde(default = "default_keep_alive_s")]
+    pub keep_alive_s: u64,
+    /// Start a new session on each connection (clean start with MQTT 5). Without it,
+    /// the broker keeps the subscriptions and queued messages while boilert is away.
+    #[serde(default = "default_clean_session")]
+    pub clean_session: bool,
+    /// Format of the sensor values.
+    #[serde(default)]
+    pub payload: PayloadFormat,
+    /// Values published with the retain flag (none by default).
+    #[serde(default)]
+    pub retain: RetainConfig,
+    /// QoS of the published messages, 0 to 2 (default: 1, 0 for the metrics).
+    #[serde(default)]
+    pub qos: Option<u8>,
+    /// QoS of the topics matching a filter relative to the base topic (e.g.,
+    /// `"alerts/#" = 1`), overriding `qos`.
+    #[serde(default)]
+    pub qos_topics: BTreeMap<String, u8>,
+    /// Previous topic scheme, still published during a transition period.
+    #[serde(default)]
+    pub legacy: Option<LegacyTopicsConfig>,
+    /// TLS connection to the broker.
+    #[serde(default)]
+    pub tls: MqttTlsConfig,
+    /// Home Assistant MQTT discovery (disabled if absent).
+    #[serde(default)]
+    pub discovery: Option<DiscoveryConfig>,
+    /// Buffering while the broker is unreachable and reconnection delays.
+    #[serde(default)]
+    pub offline: OfflineConfig,
+    /// Properties of the messages, with MQTT 5.
+    #[serde(default)]
+    pub v5: V5Config,
+    /// Other brokers the values are also published to.
+    #[serde(default)]
+    pub brokers: Vec<BrokerConfig>,
+    /// Topic the stored energy is published on, replacing `{base_topic}/energy`.
+    #[serde(default)]
+    pub energy_topic: Option<String>,
+    /// Secondary broker used while the main one is unreachable.
+    #[serde(default)]
+    pub failover: Option<FailoverConfig>,
+}
+
+impl MqttConfig {
+    /// Topic the stored energy is published on.
+    pub fn energy_topic(&self) -> String {
+        self.energy_topic.clone().unwrap_or_else(|| format!("{}/energy", self.base_topic))
+    }
+
+    /// Checks the client ID, the keep-alive, the QoS levels, the TLS settings, the
+    /// reconnection delays, the MQTT 5 properties, the other brokers and the failover broker.
+    fn validate(&self) -> Result<()> {
+        if self.client_id.as_ref().is_some_and(|id| id.trim().is_empty()) {
+            bail!("[mqtt] client_id must not be empty");
+        }
+        if !(5..=u64::from(u16::MAX)).contains(&self.keep_alive_s) {
+            bail!("[mqtt] keep_alive_s must be between 5 and {}", u16::MAX);
+        }
+        if let Some(qos) = self.qos {
+            crate::mqtt::qos_level(qos).context("[mqtt] qos")?;
+        }
+        for (filter, &qos) in &self.qos_topics {
+            crate::mqtt::qos_level(qos).with_context(|| format!("[mqtt.qos_topics] {:?}", filter))?;
+        }
+        self.offline.validate()?;
+        self.v5.validate(self.protocol)?;
+        self.tls.validate()?;
+        for (i, broker) in self.brokers.iter().enumerate() {
+            if broker.name.trim().is_empty() {
+                bail!("[[mqtt.brokers]] name must not be empty");
+            }
+            if self.brokers[..i].iter().any(|other| other.name == broker.name) {
+                bail!("[[mqtt.brokers]] {:?} is defined twice", broker.name);
+            }
+            let config = broker.mqtt_config(self);
+            if config.client_id.as_ref().is_some_and(|id| id.trim().is_empty()) {
+                bail!("[[mqtt.brokers]] {}: client_id must not be empty", broker.name);
+            }
+            config.v5.validate(config.protocol).with_context(|| format!("[[mqtt.brokers]] {}", broker.name))?;
+            config.tls.validate().with_context(|| format!("[[mqtt.brokers]] {}", broker.name))?;
+        }
+        if let Some(failover) = &self.failover {
+            failover.validate()?;
+        }
+        if self.energy_topic.as_ref().is_some_and(|topic| !is_valid_topic(topic)) {
+            bail!("[mqtt] energy_topic must not be empty or contain wildcards");
+        }
+        Ok(())
+    }
 }
 
 /// Boiler physical and calculation parameters.
@@ -32,8 +364,295 @@ pub struct BoilerConfig {
     pub volume_l: f32,
     /// Reference temperature for energy calculation in Celsius.
     pub reference_temp_c: f32,
-    /// Energy coefficient (Wh per liter per Kelvin). Default is usually 1.162.
-    pub energy_coefficient: f32,
+    /// Energy coefficient (Wh per liter per Kelvin): a number (usually 1.162), one
+    /// number per temperature range, or `"water"` for the water property table.
+    pub energy_coefficient: EnergyCoefficient,
+    /// Volume below the lowest sensor, in liters, left out of the stored energy: it
+    /// stays at the cold water temperature.
+    #[serde(default)]
+    pub dead_volume_l: f32,
+}
+
+impl BoilerConfig {
+    /// Checks the volumes and the energy coefficient.
+    pub fn validate(&self) -> Result<()> {
+        if !self.volume_l.is_finite() || self.volume_l <= 0.0 {
+            bail!("[boiler] volume_l must be positive");
+        }
+        if !(0.0..self.volume_l).contains(&self.dead_volume_l) {
+            bail!("[boiler] dead_volume_l must be at least 0 and below volume_l");
+        }
+        self.energy_coefficient.validate()
+    }
+
+    /// Volume of the water whose heat is counted, in liters.
+    pub fn heated_volume_l(&self) -> f32 {
+        self.volume_l - self.dead_volume_l
+    }
+}
+
+/// Health reporting settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct HealthConfig {
+    /// Path of the JSON status file rewritten periodically (disabled if absent).
+    pub status_file: Option<String>,
+}
+
+/// Embedded HTTP server settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct HttpConfig {
+    /// Address to listen on (e.g., "0.0.0.0:8080"). The server is disabled if absent.
+    pub listen: Option<String>,
+}
+
+/// Scripting hooks settings (requires the `scripting` feature).
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ScriptingConfig {
+    /// Path of the Rhai script defining the hooks (disabled if absent).
+    pub script: Option<String>,
+}
+
+/// Self-update settings (requires the `update` feature).
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct UpdateConfig {
+    /// URL of a JSON release manifest (`version`, `url`, optional `signature_url`).
+    pub url: Option<String>,
+    /// GitHub repository ("owner/repo") whose latest release is used when `url` is absent.
+    pub github: Option<String>,
+    /// Minisign public key the release binaries must be signed with.
+    pub public_key: Option<String>,
+    /// systemd unit restarted after an update.
+    pub service: String,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            github: None,
+            public_key: None,
+            service: "boilert.service".to_string(),
+        }
+    }
+}
+
+/// Runtime state persistence settings.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct StateConfig {
+    /// Path of the JSON file holding the runtime state.
+    pub path: String,
+    /// Interval between two saves, in seconds.
+    pub save_interval_s: u64,
+    /// Raise a `power_loss` alert when an unclean shutdown is detected at startup.
+    pub notify_power_loss: bool,
+}
+
+impl Default for StateConfig {
+    fn default() -> Self {
+        Self {
+            path: "boilert-state.json".to_string(),
+            save_interval_s: 60,
+            notify_power_loss: true,
+        }
+    }
+}
+
+/// Runtime profile, trading responsiveness for resource usage.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuntimeProfile {
+    /// Multi-threaded runtime, UI refreshed on every acquisition cycle.
+    #[default]
+    Standard,
+    /// Current-thread runtime, smaller buffers and reduced UI refresh rate,
+    /// for small boards such as the Pi Zero W.
+    Lightweight,
+}
+
+impl RuntimeProfile {
+    /// Capacity of the MQTT request channel.
+    pub fn mqtt_channel_capacity(self) -> usize {
+        match self {
+            RuntimeProfile::Standard => 10,
+            RuntimeProfile::Lightweight => 4,
+        }
+    }
+
+    /// Default interval between two UI refreshes, in seconds.
+    pub fn ui_refresh_s(self) -> f32 {
+        match self {
+            RuntimeProfile::Standard => 1.0,
+            RuntimeProfile::Lightweight => 10.0,
+        }
+    }
+}
+
+/// Acquisition, history and UI cadences.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TimingConfig {
+    /// Interval between two acquisition cycles, in seconds.
+    pub acquisition_interval_s: f32,
+    /// Time between two history points, in minutes. Must divide a day so that
+    /// points stay aligned on the wall clock.
+    pub history_period_min: u32,
+    /// Interval between two UI refreshes, in seconds (defaults to the runtime profile's).
+    pub ui_refresh_s: Option<f32>,
+    /// Rate of change, in °C per minute, above which extra history points are recorded
+    /// between two periods (0: disabled).
+    pub transient_rate_c_per_min: f32,
+    /// Change, in °C, below which idle history points are merged (0: disabled).
+    pub idle_delta_c: f32,
+    /// Time during which the last valid value of a failing sensor is kept, in seconds.
+    /// The sensor is marked invalid afterwards.
+    pub fault_grace_s: f32,
+    /// Interval between two rescans of the 1-Wire bus for disconnected and reconnected
+    /// sensors, in seconds (0: disabled).
+    pub w1_rescan_s: f32,
+    /// Start the acquisition cycles on the wall-clock boundaries of the cycle interval
+    /// (e.g., every minute at :00) and time-stamp the values with the boundary.
+    pub align_to_clock: bool,
+    /// Interval between two publications of the values on MQTT, in seconds (0: every cycle).
+    pub publish_interval_s: f32,
+    /// Value of a sensor published when it was read several times since the last publication.
+    pub publish_value: PublishValue,
+    /// Change of a sensor below which it is not published again, in °C (0: publish every value).
+    pub publish_deadband_c: f32,
+    /// Longest time a sensor within the deadband stays unpublished, in seconds.
+    pub max_silence_s: f32,
+}
+
+/// Value of a sensor published at the publish interval (`publish_value` in `[timing]`).
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PublishValue {
+    /// The latest reading.
+    #[default]
+    Latest,
+    /// The average of the valid readings since the last publication.
+    Average,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            acquisition_interval_s: 2.0,
+            history_period_min: 15,
+            ui_refresh_s: None,
+            transient_rate_c_per_min: 0.5,
+            idle_delta_c: 0.2,
+            fault_grace_s: 30.0,
+            w1_rescan_s: 30.0,
+            align_to_clock: false,
+            publish_interval_s: 0.0,
+            publish_value: PublishValue::Latest,
+            publish_deadband_c: 0.0,
+            max_silence_s: 600.0,
+        }
+    }
+}
+
+impl TimingConfig {
+    /// Interval between two acquisition cycles.
+    pub fn acquisition_interval(&self) -> Duration {
+        Duration::from_secs_f32(self.acquisition_interval_s)
+    }
+
+    /// Time between two history points.
+    pub fn history_period(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.history_period_min as i64)
+    }
+
+    /// Adaptive density of the history points.
+    pub fn history_density(&self) -> HistoryDensity {
+        HistoryDensity {
+            transient_rate_c_per_min: self.transient_rate_c_per_min,
+            idle_delta_c: self.idle_delta_c,
+        }
+    }
+
+    /// Time during which the last valid value of a failing sensor is kept.
+    pub fn fault_grace(&self) -> Duration {
+        Duration::from_secs_f32(self.fault_grace_s)
+    }
+
+    /// Interval between two rescans of the 1-Wire bus, `None` if disabled.
+    pub fn w1_rescan_interval(&self) -> Option<Duration> {
+        (self.w1_rescan_s > 0.0).then(|| Duration::from_secs_f32(self.w1_rescan_s.max(1.0)))
+    }
+
+    /// Interval between two publications of the values, zero to publish every cycle.
+    pub fn publish_interval(&self) -> Duration {
+        Duration::from_secs_f32(self.publish_interval_s.max(0.0))
+    }
+
+    /// Longest time a sensor within the deadband stays unpublished.
+    pub fn max_silence(&self) -> Duration {
+        Duration::from_secs_f32(self.max_silence_s.max(0.0))
+    }
+
+    /// Interval between two UI refreshes for the given profile.
+    pub fn ui_refresh_interval(&self, profile: RuntimeProfile) -> Duration {
+        Duration::from_secs_f32(self.ui_refresh_s.unwrap_or(profile.ui_refresh_s()))
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(0.1..=3600.0).contains(&self.acquisition_interval_s) {
+            bail!("[timing] acquisition_interval_s must be between 0.1 and 3600");
+        }
+        if self.history_period_min == 0 || 1440 % self.history_period_min != 0 {
+            bail!("[timing] history_period_min must divide a day (e.g., 1, 5, 15, 60)");
+        }
+        if self.history_period_min as f32 * 60.0 < self.acquisition_interval_s {
+            bail!("[timing] history_period_min must not be shorter than the acquisition interval");
+        }
+        if let Some(ui_refresh_s) = self.ui_refresh_s
+            && !(0.1..=60.0).contains(&ui_refresh_s)
+        {
+            bail!("[timing] ui_refresh_s must be between 0.1 and 60");
+        }
+        if self.transient_rate_c_per_min < 0.0 || self.idle_delta_c < 0.0 {
+            bail!("[timing] transient_rate_c_per_min and idle_delta_c must not be negative");
+        }
+        if !(0.0..=86400.0).contains(&self.fault_grace_s) {
+            bail!("[timing] fault_grace_s must be between 0 and 86400");
+        }
+        if self.w1_rescan_s < 0.0 {
+            bail!("[timing] w1_rescan_s must not be negative");
+        }
+        if !(0.0..=86400.0).contains(&self.publish_interval_s) {
+            bail!("[timing] publish_interval_s must be between 0 and 86400");
+        }
+        if !(0.0..=100.0).contains(&self.publish_deadband_c) {
+            bail!("[timing] publish_deadband_c must be between 0 and 100");
+        }
+        if !(1.0..=86400.0).contains(&self.max_silence_s) {
+            bail!("[timing] max_silence_s must be between 1 and 86400");
+        }
+        Ok(())
+    }
+}
+
+/// Chart rendering settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ChartConfig {
+    /// Moving average window applied to the chart curves, in points (0: no smoothing).
+    pub smoothing: usize,
+    /// Export of the chart as an image (disabled if absent).
+    pub export: Option<ChartExportConfig>,
+}
+
+/// Process runtime settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// Resource profile of the runtime.
+    pub profile: RuntimeProfile,
+    /// Run without the Slint UI (MQTT, HTTP and state file only).
+    pub headless: bool,
 }
 
 /// The root configuration object for the application.
@@ -47,19 +666,447 @@ pub struct Config {
     /// Physical characteristics and calculation constants for the water boiler.
     pub boiler: BoilerConfig,
     /// List of temperature sensors to monitor.
+    #[serde(default)]
     pub sensors: Vec<SensorConfig>,
+    /// Differential virtual sensors computed from the configured sensors.
+    #[serde(default)]
+    pub deltas: Vec<DeltaSensorConfig>,
+    /// Groups of sensors with aggregate values.
+    #[serde(default)]
+    pub groups: Vec<GroupConfig>,
+    /// Relays, plugs and coils switched by boilert.
+    #[serde(default)]
+    pub outputs: Vec<OutputConfig>,
+    /// Add the 1-Wire sensors found on the bus that are not configured.
+    #[serde(default)]
+    pub auto_discover: bool,
+    /// Heating element state source and runtime statistics.
+    #[serde(default)]
+    pub heater: HeaterConfig,
+    /// Statistics day and billing period.
+    #[serde(default)]
+    pub billing: BillingConfig,
+    /// Pulse flow meter on the hot water outlet.
+    #[serde(default)]
+    pub flow: FlowConfig,
+    /// Boiler pressure transducer (disabled if absent).
+    #[serde(default)]
+    pub pressure: Option<PressureConfig>,
+    /// Thermostatic mixing valve monitoring (disabled if absent).
+    #[serde(default)]
+    pub mixing_valve: Option<MixingValveConfig>,
+    /// Inlet and outlet pipe sensors, for the temperature of the draws (disabled if absent).
+    #[serde(default)]
+    pub pipes: Option<PipesConfig>,
+    /// Maintenance reminders.
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceConfig>,
+    /// Status file settings for external watchdogs.
+    #[serde(default)]
+    pub health: HealthConfig,
+    /// Embedded HTTP server (health endpoint).
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// Users and roles of the remote interfaces.
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// Audit log of control actions and configuration changes.
+    #[serde(default)]
+    pub audit: AuditConfig,
+    /// Persistence of the runtime state across restarts.
+    #[serde(default)]
+    pub state: StateConfig,
+    /// Protection against a second instance.
+    #[serde(default)]
+    pub instance_lock: InstanceLockConfig,
+    /// Runtime profile and headless mode.
+    #[serde(default)]
+    pub runtime: RuntimeConfig,
+    /// PIN lock of the touch screen controls.
+    #[serde(default)]
+    pub display_lock: DisplayLockConfig,
+    /// File of the display preferences chosen on the device.
+    #[serde(default)]
+    pub preferences: PreferencesConfig,
+    /// Acquisition, history and UI cadences.
+    #[serde(default)]
+    pub timing: TimingConfig,
+    /// Chart rendering.
+    #[serde(default)]
+    pub chart: ChartConfig,
+    /// Temperature forecast drawn on the charts.
+    #[serde(default)]
+    pub forecast: ForecastConfig,
+    /// High-rate sampling of a single sensor on request.
+    #[serde(default)]
+    pub burst: BurstConfig,
+    /// Weekly efficiency score and insights.
+    #[serde(default)]
+    pub efficiency: EfficiencyConfig,
+    /// Heating season detection and seasonal statistics.
+    #[serde(default)]
+    pub season: SeasonConfig,
+    /// Hourly and daily statistics published on MQTT.
+    #[serde(default)]
+    pub stats: StatsConfig,
+    /// Export of the alerts, audit log and history to a USB stick.
+    #[serde(default)]
+    pub usb_export: UsbExportConfig,
+    /// Startup self-test.
+    #[serde(default)]
+    pub selftest: SelfTestConfig,
+    /// Fault injection on the simulated board.
+    #[serde(default)]
+    pub simulation: SimulationConfig,
+    /// Debugging aids.
+    #[serde(default)]
+    pub debug: DebugConfig,
+    /// Decimal places, rounding and separators of the values shown and published.
+    #[serde(default)]
+    pub format: FormatConfig,
+    /// Alert message templates.
+    #[serde(default)]
+    pub messages: MessagesConfig,
+    /// Other boilert instances shown on this display.
+    #[serde(default)]
+    pub remotes: Vec<RemoteConfig>,
+    /// Board selection and device paths.
+    #[serde(default)]
+    pub hardware: HardwareConfig,
+    /// User scripting hooks.
+    #[serde(default)]
+    pub scripting: ScriptingConfig,
+    /// Self-update source and signing key.
+    #[serde(default)]
+    #[cfg_attr(not(feature = "update"), allow(dead_code))]
+    pub update: UpdateConfig,
+    /// Settings overridden by `BOILERT_*` environment variables, set by [`Config::load`].
+    #[serde(skip)]
+    pub env_overrides: Vec<EnvOverride>,
 }
 
 impl Config {
-    /// Loads and parses the configuration from `config.toml` in the current directory.
+    /// Loads and parses the configuration from `path` (`config.toml` in the current
+    /// directory unless `--config` is given), with the environment overrides.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read, if the TOML content is invalid
+    /// or if a setting is out of range.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let (content, applied, unknown) = apply_env_overrides(&content)?;
+        let mut config = Self::parse(&content)?;
+        for o in unknown {
+            warning!("Ignoring {}: {} is not a setting", o.variable, o.key);
+        }
+        config.env_overrides = applied;
+        Ok(config)
+    }
+
+    /// Parses and checks the content of `config.toml`.
     ///
     /// # Errors
-    /// Returns an error if the file cannot be read or if the TOML content is invalid.
-    pub fn load() -> Result<Self> {
-        let content = fs::read_to_string("config.toml")
-            .context("Failed to read config.toml")?;
-        let config: Config = toml::from_str(&content)
+    /// Returns an error if the TOML content is invalid or if a setting is out of range.
+    pub fn parse(content: &str) -> Result<Self> {
+        let config: Config = toml::from_str(content)
             .context("Failed to parse config.toml")?;
+        config.timing.validate()?;
+        config.boiler.validate()?;
+        config.mqtt.validate()?;
+        if let Some(tank) = &config.simulation.tank {
+            tank.validate()?;
+        }
+        config.format.validate()?;
+        config.messages.validate()?;
+        config.heater.heatup.validate()?;
+        config.billing.validate()?;
+        config.display_lock.validate()?;
+        config.usb_export.validate()?;
+        config.efficiency.validate()?;
+        if let Some(pressure) = &config.pressure {
+            pressure.validate()?;
+        }
+        if let Some(mixing_valve) = &config.mixing_valve {
+            let names: Vec<String> = config.sensors.iter().map(|s| s.name.clone()).collect();
+            mixing_valve.validate(&names)?;
+        }
+        if let Some(pipes) = &config.pipes {
+            pipes.validate(&config.sensors)?;
+        }
+        config.season.validate(&config.sensors.iter().map(|s| s.name.clone()).collect::<Vec<_>>())?;
+        for sensor in &config.sensors {
+            if let (Some(min), Some(max)) = (sensor.chart_min, sensor.chart_max)
+                && min >= max
+            {
+                bail!("Sensor {}: chart_min must be lower than chart_max", sensor.name);
+            }
+            if sensor.scale == 0.0 {
+                bail!("Sensor {}: scale must not be 0", sensor.name);
+            }
+            if let (Some(min), Some(max)) = (sensor.min_valid, sensor.max_valid)
+                && min >= max
+            {
+                bail!("Sensor {}: min_valid must be lower than max_valid", sensor.name);
+            }
+            if sensor.resolution_bits.is_some_and(|bits| !(9..=12).contains(&bits)) {
+                bail!("Sensor {}: resolution_bits must be between 9 and 12", sensor.name);
+            }
+            if !(1..=100).contains(&sensor.filter_samples) {
+                bail!("Sensor {}: filter_samples must be between 1 and 100", sensor.name);
+            }
+            if let Some(interval_s) = sensor.interval_s
+                && !(0.1..=config.timing.history_period_min as f32 * 60.0).contains(&interval_s)
+            {
+                bail!("Sensor {}: interval_s must be between 0.1 s and the history period", sensor.name);
+            }
+            if !(0.0..=1.0).contains(&sensor.tank.height) {
+                bail!("Sensor {}: [sensors.tank] height must be between 0.0 and 1.0", sensor.name);
+            }
+            if sensor.backend == Some(SensorBackend::RemoteW1) && sensor.remote_w1.host.is_empty() {
+                bail!("Sensor {}: [sensors.remote_w1] host is required", sensor.name);
+            }
+            if sensor.backend == Some(SensorBackend::Mqtt)
+                && (sensor.mqtt.topic.is_empty() || sensor.mqtt.topic.contains(['+', '#']))
+            {
+                bail!("Sensor {}: [sensors.mqtt] topic is required and cannot contain wildcards", sensor.name);
+            }
+            if sensor.backend == Some(SensorBackend::Http)
+                && !(sensor.http.url.starts_with("http://") || sensor.http.url.starts_with("https://"))
+            {
+                bail!("Sensor {}: [sensors.http] url must start with http:// or https://", sensor.name);
+            }
+            if sensor.topic.as_ref().is_some_and(|topic| !is_valid_topic(topic)) {
+                bail!("Sensor {}: topic must not be empty or contain wildcards", sensor.name);
+            }
+        }
+        // Two values on one topic would overwrite each other
+        let topics: Vec<String> = config
+            .sensors
+            .iter()
+            .map(|s| s.state_topic(&config.mqtt.base_topic))
+            .chain(std::iter::once(config.mqtt.energy_topic()))
+            .collect();
+        for (i, topic) in topics.iter().enumerate() {
+            if topics[..i].contains(topic) {
+                bail!("Topic {} is used by two values: change the sensor topic or [mqtt] energy_topic", topic);
+            }
+        }
+        let interval = config.cycle_interval();
+        if config.timing.align_to_clock
+            && (interval.subsec_nanos() != 0 || interval.as_secs() == 0 || 86400 % interval.as_secs() != 0)
+        {
+            bail!("[timing] align_to_clock requires a cycle interval of whole seconds dividing a day (e.g., 30)");
+        }
+        let names = config.sensor_names();
+        if let Some(export) = &config.chart.export {
+            export.validate(&names)?;
+        }
+        for (i, group) in config.groups.iter().enumerate() {
+            group.validate(&names)?;
+            if config.groups[..i].iter().any(|g| g.name == group.name) {
+                bail!("Group {}: the name is used twice", group.name);
+            }
+        }
+        for (i, output) in config.outputs.iter().enumerate() {
+            output.validate()?;
+            if config.outputs[..i].iter().any(|o| o.name == output.name) {
+                bail!("Output {}: the name is used twice", output.name);
+            }
+        }
+        for (i, task) in config.maintenance.iter().enumerate() {
+            task.validate()?;
+            if config.maintenance[..i].iter().any(|t| t.name == task.name) {
+                bail!("Maintenance {}: the name is used twice", task.name);
+            }
+            if task.every_heating_hours.is_some() && !config.heater.is_enabled() {
+                bail!("Maintenance {}: every_heating_hours requires the heater statistics ([heater])", task.name);
+            }
+            if task.every_litres.is_some() && config.flow.line.is_none() {
+                bail!("Maintenance {}: every_litres requires the flow meter ([flow] line)", task.name);
+            }
+        }
+        for delta in &config.deltas {
+            if let (Some(min), Some(max)) = (delta.chart_min, delta.chart_max)
+                && min >= max
+            {
+                bail!("Virtual sensor {}: chart_min must be lower than chart_max", delta.name);
+            }
+            if config.sensors.iter().any(|s| s.name == delta.name) {
+                bail!("Virtual sensor {}: the name is already used by a sensor", delta.name);
+            }
+        }
         Ok(config)
     }
+
+    /// Names of the physical sensors followed by the virtual ones, in the order of
+    /// the readings and histories.
+    pub fn sensor_names(&self) -> Vec<String> {
+        self.sensors
+            .iter()
+            .map(|s| s.name.clone())
+            .chain(self.deltas.iter().map(|d| d.name.clone()))
+            .collect()
+    }
+
+    /// Interval between two acquisition cycles: the shortest read interval, so that
+    /// every sensor is read on time.
+    pub fn cycle_interval(&self) -> Duration {
+        self.sensors
+            .iter()
+            .map(|s| s.read_interval(&self.timing))
+            .fold(self.timing.acquisition_interval(), Duration::min)
+    }
+
+    /// Chart bounds of the physical sensors followed by the virtual ones.
+    pub fn chart_bounds(&self) -> Vec<ChartBounds> {
+        self.sensors
+            .iter()
+            .map(|s| s.chart_bounds())
+            .chain(self.deltas.iter().map(|d| d.chart_bounds()))
+            .collect()
+    }
+}
+
+/// Prefix of the environment variables overriding the configuration.
+const ENV_PREFIX: &str = "BOILERT_";
+
+/// Setting overridden by an environment variable.
+#[derive(Debug, Clone)]
+pub struct EnvOverride {
+    /// Name of the variable, e.g. `BOILERT_MQTT__HOST`.
+    pub variable: String,
+    /// Key of the setting, e.g. `mqtt.host`.
+    pub key: String,
+    /// Value of the variable.
+    pub value: String,
+}
+
+/// Settings overridden by `BOILERT_*` environment variables: the rest of the name, in
+/// lower case, is the key of the setting with `__` between the levels (`BOILERT_MQTT__HOST`
+/// sets `host` in `[mqtt]`, `BOILERT_SENSORS__0__OFFSET_C` the first `[[sensors]]`).
+/// Variables whose name is not valid UTF-8 are ignored.
+pub fn env_overrides() -> Vec<EnvOverride> {
+    let mut overrides: Vec<EnvOverride> = std::env::vars_os()
+        .filter_map(|(variable, value)| {
+            let variable = variable.into_string().ok()?;
+            let key = variable.strip_prefix(ENV_PREFIX)?.to_ascii_lowercase().replace("__", ".");
+            let value = value.to_string_lossy().into_owned();
+            Some(EnvOverride { variable, key, value })
+        })
+        .filter(|o| !o.key.is_empty())
+        .collect();
+    overrides.sort_by(|a, b| a.key.cmp(&b.key));
+    overrides
+}
+
+/// Applies the [`env_overrides`] to the TOML `content`. A value is read as TOML (number,
+/// boolean, quoted string, array) and otherwise taken as a string; it is kept as written
+/// for a setting that is a string, e.g. a numeric `BOILERT_MQTT__PASSWORD`.
+///
+/// # Errors
+/// Returns an error if `content` is not valid TOML or if a variable names a key inside a
+/// value that is not a table.
+pub fn with_env_overrides(content: &str) -> Result<String> {
+    Ok(apply_env_overrides(content)?.0)
+}
+
+/// Like [`with_env_overrides`], also returning the overrides applied and the ones whose
+/// key is not a setting, which the configuration ignores.
+fn apply_env_overrides(content: &str) -> Result<(String, Vec<EnvOverride>, Vec<EnvOverride>)> {
+    let overrides = env_overrides();
+    if overrides.is_empty() {
+        return Ok((content.to_string(), Vec::new(), Vec::new()));
+    }
+    let mut doc: toml_edit::DocumentMut = content.parse().context("Failed to parse config.toml")?;
+    let mut as_string = Vec::with_capacity(overrides.len());
+    for o in &overrides {
+        let (_, key) = o.key.rsplit_once('.').unwrap_or(("", &o.key));
+        let table = override_table(&mut doc, o)?;
+        let string = table.get(key).and_then(toml_edit::Item::as_str).is_some();
+        let value = match o.value.parse::<toml_edit::Value>() {
+            Ok(value) if !string => value,
+            _ => toml_edit::Value::from(o.value.as_str()),
+        };
+        table.insert(key, toml_edit::Item::Value(value));
+        as_string.push(string);
+    }
+    // A setting missing from the file is only known to be a string when the value is
+    // refused: take the value as a string then
+    let content = loop {
+        let content = doc.to_string();
+        let Err(e) = toml::from_str::<Config>(&content) else {
+            break content;
+        };
+        let Ok(parsed) = toml_edit::ImDocument::parse(content.as_str()) else {
+            break content;
+        };
+        let refused = overrides.iter().zip(&as_string).position(|(o, &string)| {
+            !string
+                && value_span(parsed.as_table(), &o.key)
+                    .zip(e.span())
+                    .is_some_and(|(value, error)| value.contains(&error.start))
+        });
+        let Some(i) = refused else {
+            break content;
+        };
+        let o = &overrides[i];
+        let (_, key) = o.key.rsplit_once('.').unwrap_or(("", &o.key));
+        override_table(&mut doc, o)?.insert(key, toml_edit::value(o.value.as_str()));
+        as_string[i] = true;
+    };
+
+    // Keys the configuration does not know, e.g. `mqtt.hots`, as `section.key`
+    let mut ignored = Vec::new();
+    let _ = serde_ignored::deserialize(toml::Deserializer::new(&content), |path| {
+        let path = path.to_string();
+        ignored.push(path.split('.').filter(|s| *s != "?").collect::<Vec<_>>().join("."));
+    })
+    .map(|_: Config| ());
+    let (unknown, applied) = overrides.into_iter().partition(|o| {
+        ignored.iter().any(|key| o.key == *key || o.key.strip_prefix(key.as_str()).is_some_and(|r| r.starts_with('.')))
+    });
+    Ok((content, applied, unknown))
+}
+
+/// Table of `doc` holding the setting of `o`, created if needed.
+fn override_table<'a>(
+    doc: &'a mut toml_edit::DocumentMut,
+    o: &EnvOverride,
+) -> Result<&'a mut dyn toml_edit::TableLike> {
+    let (parents, _) = o.key.rsplit_once('.').unwrap_or(("", &o.key));
+    let mut table: &mut dyn toml_edit::TableLike = doc.as_table_mut();
+    let mut segments = parents.split('.').filter(|s| !s.is_empty());
+    while let Some(segment) = segments.next() {
+        let item = table.entry(segment).or_insert_with(toml_edit::table);
+        table = match item {
+            // `[[sensors]]` and the like: the next level is the index of the entry
+            toml_edit::Item::ArrayOfTables(array) => {
+                let len = array.len();
+                segments
+                    .next()
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .and_then(|index| array.get_mut(index))
+                    .with_context(|| format!("{}: {} has {} entries, indexed from 0", o.variable, segment, len))?
+            }
+            item => item
+                .as_table_like_mut()
+                .with_context(|| format!("{}: {} is not a table", o.variable, segment))?,
+        };
+    }
+    Ok(table)
+}
+
+/// Position of the value of the setting `key` in the parsed document.
+fn value_span(root: &toml_edit::Table, key: &str) -> Option<std::ops::Range<usize>> {
+    let (parents, key) = key.rsplit_once('.').unwrap_or(("", key));
+    let mut table: &dyn toml_edit::TableLike = root;
+    let mut segments = parents.split('.').filter(|s| !s.is_empty());
+    while let Some(segment) = segments.next() {
+        table = match table.get(segment)? {
+            toml_edit::Item::ArrayOfTables(array) => array.get(segments.next()?.parse().ok()?)?,
+            item => item.as_table_like()?,
+        };
+    }
+    table.get(key)?.span()
 }
@@ -0,0 +1,93 @@
+//! Differential ("delta-T") virtual sensors.
+//! A virtual sensor is the difference between two configured sensors (e.g., solar
+//! collector minus tank bottom, flow minus return). It gets its own history, chart and
+//! MQTT topic like a physical sensor, and can raise an alert outside a range.
+
+use boilert::model::{Reading, SensorStatus};
+use serde::Deserialize;
+
+use crate::alerts::Alerts;
+use crate::chart::ChartBounds;
+use crate::format::{self, Unit};
+use crate::messages::Message;
+
+/// Configuration of a differential virtual sensor.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeltaSensorConfig {
+    /// Name of the virtual sensor, also used as its MQTT topic (e.g., "dT_solar").
+    pub name: String,
+    /// Sensor whose temperature is subtracted from.
+    pub plus: String,
+    /// Sensor whose temperature is subtracted.
+    pub minus: String,
+    /// Raise an alert when the difference goes above this value, in °C.
+    #[serde(default)]
+    pub alert_above: Option<f32>,
+    /// Raise an alert when the difference goes below this value, in °C.
+    #[serde(default)]
+    pub alert_below: Option<f32>,
+    /// Fixed bottom of the chart, in °C (default: fitted to the data).
+    #[serde(default)]
+    pub chart_min: Option<f32>,
+    /// Fixed top of the chart, in °C (default: fitted to the data).
+    #[serde(default)]
+    pub chart_max: Option<f32>,
+}
+
+impl DeltaSensorConfig {
+    /// Fixed bounds of the chart of the virtual sensor.
+    pub fn chart_bounds(&self) -> ChartBounds {
+        ChartBounds {
+            min: self.chart_min,
+            max: self.chart_max,
+        }
+    }
+
+    /// Computes the difference from the readings of the physical sensors.
+    ///
+    /// The result has the `Error` status if either sensor is invalid or not configured,
+    /// and the `Stale` status if either holds its last valid value.
+    pub fn compute(&self, readings: &[Reading]) -> Reading {
+        let find = |name: &str| readings.iter().find(|r| r.sensor == name);
+        let (value, status, timestamp) = match (find(&self.plus), find(&self.minus)) {
+            (Some(plus), Some(minus)) => {
+                let status = match (plus.status, minus.status) {
+                    (a, b) if !a.is_valid() || !b.is_valid() => SensorStatus::Error,
+                    (SensorStatus::Stale, _) | (_, SensorStatus::Stale) => SensorStatus::Stale,
+                    _ => SensorStatus::Ok,
+                };
+                (plus.value - minus.value, status, plus.timestamp)
+            }
+            _ => (0.0, SensorStatus::Error, chrono::Utc::now()),
+        };
+        Reading {
+            sensor: self.name.clone(),
+            value,
+            humidity: None,
+            pressure_hpa: None,
+            status,
+            timestamp,
+        }
+    }
+
+    /// Raises or clears the range alert of the virtual sensor. Invalid readings leave
+    /// the alert as it is.
+    pub fn check_alert(&self, reading: &Reading, alerts: &Alerts) {
+        if !reading.status.is_valid() {
+            return;
+        }
+        let id = format!("delta_{}", self.name);
+        let value = reading.value;
+        let text = |t| format::text(Unit::Celsius, t);
+        let (side, threshold) = match (self.alert_above, self.alert_below) {
+            (Some(above), _) if value > above => ("above", above),
+            (_, Some(below)) if value < below => ("below", below),
+            _ => return alerts.clear(&id),
+        };
+        let message = Message::new(format!("{} is {}, {} {}", self.name, text(value), side, text(threshold)))
+            .with("sensor", &self.name)
+            .with("value", text(value))
+            .with("threshold", text(threshold));
+        alerts.raise(&id, message);
+    }
+}
@@ -0,0 +1,148 @@
+//! Home Assistant MQTT discovery.
+//! Publishes a retained discovery payload for every sensor, the stored energy, the
+//! boiler pressure and the heater energy meter at startup, so Home Assistant creates the
+//! entities by itself, grouped under one device.
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::config::{Config, MqttConfig};
+use crate::logging::{info, warning};
+use crate::mqtt::{PayloadFormat, Publisher, status_topic};
+
+/// Discovery settings from the `[mqtt.discovery]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    /// Discovery prefix Home Assistant listens to.
+    pub prefix: String,
+    /// Identifier of this boilert, unique among the instances sharing a broker.
+    pub node_id: String,
+    /// Name of the device in Home Assistant, which prefixes the entity IDs.
+    pub device_name: String,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            prefix: "homeassistant".to_string(),
+            node_id: "boilert".to_string(),
+            device_name: "boilert".to_string(),
+        }
+    }
+}
+
+/// Lowercase name with the characters Home Assistant does not accept in IDs replaced
+/// by `_`.
+pub fn slug(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect()
+}
+
+/// An entity to announce: name, state topic, unit, device class and state class.
+struct Entity {
+    name: String,
+    topic: String,
+    /// Format of the payloads of `topic`.
+    format: PayloadFormat,
+    unit: &'static str,
+    device_class: &'static str,
+    /// `measurement`, or `total_increasing` for a meter.
+    state_class: &'static str,
+}
+
+fn entities(config: &Config) -> Vec<Entity> {
+    let base_topic = &config.mqtt.base_topic;
+    let format = config.mqtt.payload;
+    let mut entities: Vec<Entity> = config
+        .sensor_names()
+        .into_iter()
+        .map(|name| Entity {
+            topic: match config.sensors.iter().find(|s| s.name == name) {
+                Some(sensor) => sensor.state_topic(base_topic),
+                None => format!("{}/{}", base_topic, name),
+            },
+            name,
+            format,
+            unit: "°C",
+            device_class: "temperature",
+            state_class: "measurement",
+        })
+        .collect();
+    entities.push(Entity {
+        name: "Energy".to_string(),
+        topic: config.mqtt.energy_topic(),
+        format,
+        unit: "kWh",
+        device_class: "energy_storage",
+        state_class: "measurement",
+    });
+    if config.pressure.is_some() {
+        entities.push(Entity {
+            name: "Pressure".to_string(),
+            topic: format!("{}/pressure", base_topic),
+            format,
+            unit: "bar",
+            device_class: "pressure",
+            state_class: "measurement",
+        });
+    }
+    // For the energy dashboard of Home Assistant
+    if config.heater.is_enabled() {
+        entities.push(Entity {
+            name: "Heater energy".to_string(),
+            topic: format!("{}/heater/meter_kwh", base_topic),
+            format: PayloadFormat::Plain,
+            unit: "kWh",
+            device_class: "energy",
+            state_class: "total_increasing",
+        });
+    }
+    entities
+}
+
+/// Template extracting the value from the payload of the state topic.
+fn value_template(format: PayloadFormat) -> &'static str {
+    match format {
+        // Invalid readings are published as `unavailable`, which is not a number
+        PayloadFormat::Plain => "{{ value | float(none) }}",
+        PayloadFormat::Json => "{{ value_json.value }}",
+    }
+}
+
+/// Discovery payload of an entity.
+fn payload(entity: &Entity, discovery: &DiscoveryConfig, config: &MqttConfig) -> Value {
+    json!({
+        "name": entity.name,
+        "unique_id": format!("{}_{}", discovery.node_id, slug(&entity.name)),
+        "state_topic": entity.topic,
+        "value_template": value_template(entity.format),
+        "unit_of_measurement": entity.unit,
+        "device_class": entity.device_class,
+        "state_class": entity.state_class,
+        "availability_topic": status_topic(config),
+        "device": {
+            "identifiers": [discovery.node_id],
+            "name": discovery.device_name,
+            "manufacturer": "boilert",
+            "model": "boilert",
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        },
+    })
+}
+
+/// Publishes the discovery payloads (retained), if `[mqtt.discovery]` is configured.
+pub async fn announce(config: Config, publisher: Publisher) {
+    let Some(discovery) = &config.mqtt.discovery else {
+        return;
+    };
+    let entities = entities(&config);
+    for entity in &entities {
+        let topic = format!("{}/sensor/{}/{}/config", discovery.prefix, discovery.node_id, slug(&entity.name));
+        let payload = payload(entity, discovery, &config.mqtt).to_string();
+        if let Err(e) = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, payload).await {
+            warning!("Home Assistant discovery: {}", e);
+            return;
+        }
+    }
+    info!("Home Assistant discovery: {} entities announced", entities.len());
+}
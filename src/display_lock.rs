@@ -0,0 +1,176 @@
+//! Lock of the touch screen controls.
+//! With a PIN set, the display starts locked: the values stay visible but the pages
+//! with controls (diagnostics, burst sampling, sensor mapping wizard, audit log) only
+//! open once the PIN is entered on the keypad. The display locks itself again a while
+//! after it was unlocked, so a panel in a shared place is not left open.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use boilert::model::AuditSource;
+use serde::Deserialize;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::audit::AuditLog;
+
+/// Wrong PINs accepted before the keypad is blocked.
+const MAX_ATTEMPTS: u32 = 5;
+/// Time the keypad stays blocked after too many wrong PINs.
+const BLOCK_DURATION: Duration = Duration::from_secs(60);
+
+/// Settings from the `[display_lock]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct DisplayLockConfig {
+    /// PIN unlocking the controls, 4 to 8 digits (no lock if absent).
+    pub pin: Option<String>,
+    /// Time after which an unlocked display locks again, in seconds.
+    pub relock_after_s: u64,
+}
+
+impl Default for DisplayLockConfig {
+    fn default() -> Self {
+        Self {
+            pin: None,
+            relock_after_s: 120,
+        }
+    }
+}
+
+impl DisplayLockConfig {
+    /// Checks the PIN and the relock delay.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(pin) = &self.pin
+            && !((4..=8).contains(&pin.len()) && pin.bytes().all(|b| b.is_ascii_digit()))
+        {
+            bail!("[display_lock] pin must be 4 to 8 digits");
+        }
+        if self.relock_after_s == 0 {
+            bail!("[display_lock] relock_after_s must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+/// State of the lock, as shown on the display.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LockView {
+    /// `true` when a PIN is configured.
+    pub enabled: bool,
+    /// `true` while the controls are hidden.
+    pub locked: bool,
+    /// Outcome of the last PIN entered (empty when none).
+    pub status: String,
+}
+
+/// Wrong PINs entered since the last unlock.
+#[derive(Debug, Default)]
+struct Attempts {
+    failures: u32,
+    blocked_until: Option<Instant>,
+}
+
+/// Handle locking and unlocking the display controls.
+///
+/// Cloning a `DisplayLock` handle is cheap; all clones share the same lock.
+#[derive(Clone)]
+pub struct DisplayLock {
+    pin: Option<String>,
+    relock_after: Duration,
+    view: watch::Sender<LockView>,
+    attempts: Arc<Mutex<Attempts>>,
+    audit: AuditLog,
+}
+
+impl DisplayLock {
+    /// Creates the lock, locked if a PIN is configured.
+    pub fn new(config: &DisplayLockConfig, audit: AuditLog) -> Self {
+        let enabled = config.pin.is_some();
+        let (view, _) = watch::channel(LockView {
+            enabled,
+            locked: enabled,
+            status: String::new(),
+        });
+        Self {
+            pin: config.pin.clone(),
+            relock_after: Duration::from_secs(config.relock_after_s),
+            view,
+            attempts: Arc::new(Mutex::new(Attempts::default())),
+            audit,
+        }
+    }
+
+    /// `true` when a PIN is configured.
+    pub fn is_enabled(&self) -> bool {
+        self.pin.is_some()
+    }
+
+    /// Receiver following the state of the lock.
+    pub fn subscribe(&self) -> watch::Receiver<LockView> {
+        self.view.subscribe()
+    }
+
+    /// Unlocks the controls if `pin` is right. After `MAX_ATTEMPTS` wrong PINs in a row,
+    /// the keypad is blocked for a minute.
+    pub fn unlock(&self, pin: &str) {
+        let Some(expected) = &self.pin else {
+            return;
+        };
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if let Some(until) = attempts.blocked_until.filter(|&until| until > now) {
+            let remaining = (until - now).as_secs() + 1;
+            self.set_status(format!("Trop d'essais, réessayer dans {} s", remaining));
+            return;
+        }
+        if pin == expected {
+            *attempts = Attempts::default();
+            self.view.send_modify(|view| {
+                view.locked = false;
+                view.status.clear();
+            });
+            self.audit.record(AuditSource::Ui, "display unlocked", "");
+            return;
+        }
+        attempts.failures += 1;
+        if attempts.failures >= MAX_ATTEMPTS {
+            attempts.failures = 0;
+            attempts.blocked_until = Some(now + BLOCK_DURATION);
+            self.audit.record(AuditSource::Ui, "display unlock blocked", format!("{} wrong PINs", MAX_ATTEMPTS));
+            self.set_status(format!("Trop d'essais, réessayer dans {} s", BLOCK_DURATION.as_secs()));
+        } else {
+            self.set_status("Code incorrect".to_string());
+        }
+    }
+
+    /// Hides the controls again.
+    pub fn lock(&self) {
+        if self.is_enabled() {
+            self.view.send_if_modified(|view| {
+                let changed = !view.locked || !view.status.is_empty();
+                view.locked = true;
+                view.status.clear();
+                changed
+            });
+        }
+    }
+
+    fn set_status(&self, status: String) {
+        self.view.send_modify(|view| view.status = status);
+    }
+
+    /// Locks the display `relock_after_s` after each unlock.
+    pub async fn relock_loop(self) {
+        let mut views = self.subscribe();
+        loop {
+            if views.wait_for(|view| !view.locked).await.is_err() {
+                return;
+            }
+            // Locked by hand in the meantime: nothing left to do
+            let _ = time::timeout(self.relock_after, views.wait_for(|view| view.locked)).await;
+            self.lock();
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Hot water draws seen on the pipes of the tank.
+//! Sensors strapped to the cold water inlet and the hot water outlet show what the taps
+//! actually get: during a draw, the outlet pipe reaches the delivered temperature, and
+//! its difference with the inlet is the heating the water received from the tank. A draw
+//! is detected by the flow meter when there is one, otherwise by the outlet pipe warming.
+
+use std::collections::VecDeque;
+
+use anyhow::{Result, bail};
+use boilert::model::Snapshot;
+use chrono::{DateTime, TimeDelta, Utc};
+use rumqttc::QoS;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::config::SensorConfig;
+use crate::flow::FlowMeter;
+use crate::format::{self, Unit};
+use crate::logging::info;
+use crate::mqtt::Publisher;
+
+/// Period over which the resting temperature of the outlet pipe is taken.
+const BASELINE_WINDOW: TimeDelta = TimeDelta::minutes(5);
+
+/// Pipe sensor settings from the `[pipes]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PipesConfig {
+    /// Name of the sensor on the hot water outlet pipe.
+    pub outlet: String,
+    /// Name of the sensor on the cold water inlet pipe (no ΔT if absent).
+    pub inlet: Option<String>,
+    /// Warming of the outlet pipe that starts a draw without a flow meter, in °C.
+    pub rise_c: f32,
+    /// Lowest delivered temperature that counts as comfortable, in °C.
+    pub comfort_min_c: f32,
+}
+
+impl Default for PipesConfig {
+    fn default() -> Self {
+        Self {
+            outlet: String::new(),
+            inlet: None,
+            rise_c: 3.0,
+            comfort_min_c: 40.0,
+        }
+    }
+}
+
+impl PipesConfig {
+    /// Checks the settings against the configured sensors.
+    pub fn validate(&self, sensors: &[SensorConfig]) -> Result<()> {
+        for name in std::iter::once(&self.outlet).chain(&self.inlet) {
+            let Some(sensor) = sensors.iter().find(|s| &s.name == name) else {
+                bail!("[pipes] unknown sensor {:?}", name);
+            };
+            // A pipe reading would drag the stored energy down to the mains temperature
+            if sensor.in_tank {
+                bail!("[pipes] sensor {} must be set to in_tank = false", name);
+            }
+        }
+        if self.inlet.as_ref() == Some(&self.outlet) {
+            bail!("[pipes] inlet and outlet must be different sensors");
+        }
+        if !self.rise_c.is_finite() || self.rise_c <= 0.0 {
+            bail!("[pipes] rise_c must be positive");
+        }
+        Ok(())
+    }
+}
+
+/// Summary of a draw, published once it is over.
+#[derive(Debug, Clone, Serialize)]
+pub struct DrawReport {
+    pub started_at: DateTime<Utc>,
+    pub duration_s: i64,
+    /// Highest outlet temperature of the draw, the water delivered once the pipe is warm.
+    pub delivered_c: f32,
+    /// Lowest inlet temperature of the draw, close to the mains temperature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inlet_c: Option<f32>,
+    /// `delivered_c - inlet_c`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta_t_c: Option<f32>,
+    /// Volume drawn, with a flow meter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_l: Option<f64>,
+    /// The delivered temperature reached `comfort_min_c`.
+    pub comfortable: bool,
+}
+
+/// Draw in progress.
+struct Draw {
+    started_at: DateTime<Utc>,
+    /// Volume of the flow meter at the start.
+    start_volume_l: Option<f64>,
+    delivered_c: f32,
+    inlet_c: Option<f32>,
+}
+
+/// Task following the pipe sensors across acquisition cycles.
+#[derive(Clone)]
+pub struct DrawTracker {
+    pub config: PipesConfig,
+    pub snapshots: watch::Receiver<Option<Snapshot>>,
+    /// Flow meter, when configured.
+    pub flow: Option<FlowMeter>,
+    pub publisher: Publisher,
+    pub base_topic: String,
+}
+
+impl DrawTracker {
+    /// Detects the draws, publishes the delivered temperature and ΔT on every cycle of a
+    /// draw, and the summary of each draw once it is over.
+    pub async fn run(self) {
+        let Self { config, mut snapshots, flow, publisher, base_topic } = self;
+        // Recent outlet readings while no water is drawn
+        let mut resting: VecDeque<(DateTime<Utc>, f32)> = VecDeque::new();
+        let mut draw: Option<Draw> = None;
+        while snapshots.changed().await.is_ok() {
+            let Some(snapshot) = snapshots.borrow_and_update().clone() else {
+                continue;
+            };
+            let value = |name: &str| {
+                snapshot.readings.iter().find(|r| r.sensor == name && r.status.is_valid()).map(|r| r.value)
+            };
+            let Some(outlet) = value(&config.outlet) else {
+                continue;
+            };
+            let inlet = config.inlet.as_deref().and_then(value);
+            let now = snapshot.timestamp;
+
+            let drawing = match (&flow, &draw) {
+                (Some(flow), _) => flow.rate_l_min() > 0.0,
+                // The pipe cools down once the tap is closed
+                (None, Some(draw)) => outlet > draw.delivered_c - config.rise_c,
+                (None, None) => {
+                    while resting.front().is_some_and(|&(at, _)| now - at > BASELINE_WINDOW) {
+                        resting.pop_front();
+                    }
+                    let baseline = resting.iter().map(|&(_, value)| value).reduce(f32::min);
+                    resting.push_back((now, outlet));
+                    baseline.is_some_and(|baseline| outlet >= baseline + config.rise_c)
+                }
+            };
+
+            if !drawing {
+                if let Some(draw) = draw.take() {
+                    let report = DrawReport {
+                        started_at: draw.started_at,
+                        duration_s: (now - draw.started_at).num_seconds().max(0),
+                        delivered_c: draw.delivered_c,
+                        inlet_c: draw.inlet_c,
+                        delta_t_c: draw.inlet_c.map(|inlet| draw.delivered_c - inlet),
+                        volume_l: draw.start_volume_l.zip(flow.as_ref()).map(|(start, f)| f.volume_l() - start),
+                        comfortable: draw.delivered_c >= config.comfort_min_c,
+                    };
+                    info!(
+                        "Hot water draw of {} s delivered at {}{}",
+                        report.duration_s,
+                        format::text(Unit::Celsius, report.delivered_c),
+                        if report.comfortable { "" } else { ", below the comfort temperature" }
+                    );
+                    publish(&publisher, &base_topic, "active", false, "off").await;
+                    if let Ok(payload) = serde_json::to_vec(&report) {
+                        publish(&publisher, &base_topic, "last", true, payload).await;
+                    }
+                    resting.clear();
+                }
+                continue;
+            }
+
+            let draw = draw.get_or_insert_with(|| Draw {
+                started_at: now,
+                start_volume_l: flow.as_ref().map(|f| f.volume_l()),
+                delivered_c: outlet,
+                inlet_c: None,
+            });
+            if draw.started_at == now {
+                publish(&publisher, &base_topic, "active", false, "on").await;
+            }
+            draw.delivered_c = draw.delivered_c.max(outlet);
+            if let Some(inlet) = inlet {
+                draw.inlet_c = Some(draw.inlet_c.map_or(inlet, |min| min.min(inlet)));
+            }
+            publish(&publisher, &base_topic, "delivered", false, format::data(Unit::Celsius, outlet)).await;
+            if let Some(inlet) = inlet {
+                publish(&publisher, &base_topic, "delta_t", false, format::data(Unit::Celsius, outlet - inlet)).await;
+            }
+        }
+    }
+}
+
+/// Publishes `payload` on `{base_topic}/draw/{name}`.
+async fn publish(publisher: &Publisher, base_topic: &str, name: &str, retain: bool, payload: impl Into<Vec<u8>>) {
+    let topic = format!("{}/draw/{}", base_topic, name);
+    let _ = publisher.publish(topic, QoS::AtLeastOnce, retain, payload).await;
+}
@@ -0,0 +1,425 @@
+//! Weekly efficiency score and insights.
+//! The energy stored in the tank is followed cycle after cycle: a rise is heating, a
+//! fast drop (or any drop while water flows or the element heats) is hot water drawn,
+//! and a slow drop while idle is standby loss. Together with the heating done during
+//! the expensive hours and the disinfection cycles reached at the top of the tank, the
+//! last 7 days are summed up in a score out of 100 and a few plain-language insights,
+//! shown on the efficiency page and published on `{base_topic}/efficiency`.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use boilert::model::Snapshot;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::billing::BillingConfig;
+use crate::clock::ClockStatus;
+use crate::flow::FlowMeter;
+use crate::heater::HeaterStats;
+use crate::mqtt::Publisher;
+
+/// Days of records kept: the last week and the four weeks it is compared with.
+const KEPT_DAYS: usize = 35;
+/// Change of the stored energy below which nothing is accounted, in kWh, so that the
+/// noise of the sensors is not counted as heating and losses.
+const ENERGY_STEP_KWH: f64 = 0.05;
+/// Longest interval between two snapshots still accounted; longer ones are gaps.
+const MAX_GAP: chrono::Duration = chrono::Duration::minutes(10);
+/// Interval between two publications of the report.
+const REPORT_INTERVAL: Duration = Duration::from_secs(3600);
+/// Observed time the score needs over the last 7 days.
+const MIN_OBSERVED_S: u64 = 24 * 3600;
+/// Change of the standby losses reported as an insight, in percent.
+const LOSS_CHANGE_PERCENT: f64 = 10.0;
+/// Share of the heating during the peak hours reported as an insight.
+const PEAK_RATIO_WARNING: f64 = 0.2;
+
+/// Efficiency score settings (`[efficiency]`).
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct EfficiencyConfig {
+    /// Expensive hours, as local "HH:MM-HH:MM" ranges (none by default).
+    pub peak_hours: Vec<String>,
+    /// Drop of the stored energy above which water is being drawn, in kW.
+    pub draw_threshold_kw: f64,
+    /// Temperature the top of the tank must hold to disinfect it, in °C.
+    pub legionella_temp_c: f32,
+    /// Minutes it must be held, at least once a week.
+    pub legionella_minutes: u32,
+}
+
+impl Default for EfficiencyConfig {
+    fn default() -> Self {
+        Self {
+            peak_hours: Vec::new(),
+            draw_threshold_kw: 1.0,
+            legionella_temp_c: 60.0,
+            legionella_minutes: 60,
+        }
+    }
+}
+
+impl EfficiencyConfig {
+    /// Checks the peak hours and the thresholds.
+    pub fn validate(&self) -> Result<()> {
+        self.peak_ranges()?;
+        if !self.draw_threshold_kw.is_finite() || self.draw_threshold_kw <= 0.0 {
+            bail!("[efficiency] draw_threshold_kw must be positive");
+        }
+        if self.legionella_minutes == 0 {
+            bail!("[efficiency] legionella_minutes must be positive");
+        }
+        Ok(())
+    }
+
+    /// Start and end of each peak range.
+    fn peak_ranges(&self) -> Result<Vec<(NaiveTime, NaiveTime)>> {
+        self.peak_hours
+            .iter()
+            .map(|range| {
+                let parsed = range.split_once('-').and_then(|(start, end)| {
+                    let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+                    let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+                    Some((start, end))
+                });
+                parsed.with_context(|| format!("[efficiency] peak_hours {:?} is not a HH:MM-HH:MM range", range))
+            })
+            .collect()
+    }
+}
+
+/// Energy balance of a statistics day.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DailyEfficiency {
+    pub date: NaiveDate,
+    /// Energy stored by heating, in kWh.
+    pub heated_kwh: f64,
+    /// Part of it stored during the peak hours, in kWh.
+    pub peak_heated_kwh: f64,
+    /// Energy drawn as hot water, in kWh.
+    pub drawn_kwh: f64,
+    /// Energy lost while idle, in kWh.
+    pub standby_loss_kwh: f64,
+    /// Longest time the top of the tank held the disinfection temperature, in seconds.
+    pub legionella_hold_s: u64,
+    /// Time followed, in seconds.
+    pub observed_s: u64,
+}
+
+impl DailyEfficiency {
+    fn new(date: NaiveDate) -> Self {
+        Self {
+            date,
+            heated_kwh: 0.0,
+            peak_heated_kwh: 0.0,
+            drawn_kwh: 0.0,
+            standby_loss_kwh: 0.0,
+            legionella_hold_s: 0,
+            observed_s: 0,
+        }
+    }
+}
+
+/// Plain-language observation on the last 7 days.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Insight {
+    /// The standby losses per day changed since the four weeks before, in percent.
+    StandbyLossChange { percent: i32 },
+    /// More energy was lost while idle than drawn as hot water.
+    LossesAboveDraws { loss_percent: u32 },
+    /// Share of the heating done during the peak hours, in percent.
+    PeakHeating { percent: u32 },
+    /// The top of the tank did not hold the disinfection temperature long enough.
+    NoDisinfection { temp_c: f32, minutes: u32 },
+}
+
+/// Score and insights of the last 7 days, published on `{base_topic}/efficiency`.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct EfficiencyReport {
+    /// Score out of 100 (`None` until a day was observed).
+    pub score: Option<u32>,
+    /// Share of the energy drawn as hot water rather than lost, 0 to 1.
+    pub useful_ratio: Option<f64>,
+    /// Share of the heating done during the peak hours, 0 to 1 (`None` without peak hours).
+    pub peak_ratio: Option<f64>,
+    /// The top of the tank held the disinfection temperature long enough at least once.
+    pub legionella_ok: bool,
+    pub drawn_kwh: f64,
+    pub standby_loss_kwh: f64,
+    /// Standby losses per observed day, in kWh.
+    pub standby_loss_kwh_per_day: Option<f64>,
+    pub insights: Vec<Insight>,
+}
+
+/// Shared efficiency records and report.
+///
+/// Cloning an `Efficiency` handle is cheap; all clones share the same records.
+#[derive(Clone)]
+pub struct Efficiency {
+    config: EfficiencyConfig,
+    billing: BillingConfig,
+    days: Arc<Mutex<VecDeque<DailyEfficiency>>>,
+    report: watch::Sender<EfficiencyReport>,
+}
+
+impl Efficiency {
+    /// Creates the handle, starting from the records saved in the runtime state.
+    pub fn new(config: &EfficiencyConfig, billing: &BillingConfig, saved: &[DailyEfficiency]) -> Self {
+        let skip = saved.len().saturating_sub(KEPT_DAYS);
+        Self {
+            config: config.clone(),
+            billing: billing.clone(),
+            days: Arc::new(Mutex::new(saved.iter().skip(skip).cloned().collect())),
+            report: watch::channel(EfficiencyReport::default()).0,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<DailyEfficiency>> {
+        self.days.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Daily records, oldest first, for the runtime state.
+    pub fn days(&self) -> Vec<DailyEfficiency> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// Latest report.
+    pub fn report(&self) -> EfficiencyReport {
+        self.report.borrow().clone()
+    }
+
+    /// Follows the reports.
+    pub fn subscribe(&self) -> watch::Receiver<EfficiencyReport> {
+        self.report.subscribe()
+    }
+
+    /// Updates the record of the statistics day of `now` with `update`.
+    fn update(&self, now: DateTime<Utc>, update: impl FnOnce(&mut DailyEfficiency)) {
+        let date = self.billing.day(now.with_timezone(&Local));
+        let mut days = self.lock();
+        if days.back().is_none_or(|day| day.date != date) {
+            days.push_back(DailyEfficiency::new(date));
+            while days.len() > KEPT_DAYS {
+                days.pop_front();
+            }
+        }
+        if let Some(day) = days.back_mut() {
+            update(day);
+        }
+    }
+
+    /// Computes the report of the 7 days up to `today`.
+    fn compute(&self, today: NaiveDate) -> EfficiencyReport {
+        let days = self.lock();
+        let week_start = today - chrono::Duration::days(6);
+        let month_start = week_start - chrono::Duration::days(28);
+        let week: Vec<&DailyEfficiency> = days.iter().filter(|d| d.date >= week_start && d.date <= today).collect();
+        let before: Vec<&DailyEfficiency> =
+            days.iter().filter(|d| d.date >= month_start && d.date < week_start).collect();
+
+        let sum = |days: &[&DailyEfficiency], field: fn(&DailyEfficiency) -> f64| -> f64 {
+            days.iter().map(|d| field(d)).sum()
+        };
+        let observed_s: u64 = week.iter().map(|d| d.observed_s).sum();
+        let drawn_kwh = sum(&week, |d| d.drawn_kwh);
+        let standby_loss_kwh = sum(&week, |d| d.standby_loss_kwh);
+        let heated_kwh = sum(&week, |d| d.heated_kwh);
+        let loss_per_day = |days: &[&DailyEfficiency]| {
+            let observed_days = days.iter().map(|d| d.observed_s).sum::<u64>() as f64 / 86400.0;
+            (observed_days >= 1.0).then(|| sum(days, |d| d.standby_loss_kwh) / observed_days)
+        };
+
+        let useful_ratio =
+            (drawn_kwh + standby_loss_kwh > ENERGY_STEP_KWH).then(|| drawn_kwh / (drawn_kwh + standby_loss_kwh));
+        let peak_ratio = (!self.config.peak_hours.is_empty() && heated_kwh > ENERGY_STEP_KWH)
+            .then(|| sum(&week, |d| d.peak_heated_kwh) / heated_kwh);
+        let hold_s = u64::from(self.config.legionella_minutes) * 60;
+        let legionella_ok = week.iter().any(|d| d.legionella_hold_s >= hold_s);
+
+        let mut report = EfficiencyReport {
+            score: None,
+            useful_ratio,
+            peak_ratio,
+            legionella_ok,
+            drawn_kwh,
+            standby_loss_kwh,
+            standby_loss_kwh_per_day: loss_per_day(&week),
+            insights: Vec::new(),
+        };
+        if observed_s < MIN_OBSERVED_S {
+            return report;
+        }
+
+        // Weighted average of the parts known, each from 0 to 1
+        let parts = [
+            useful_ratio.map(|ratio| (ratio, 50.0)),
+            peak_ratio.map(|ratio| (1.0 - ratio, 25.0)),
+            Some((if legionella_ok { 1.0 } else { 0.0 }, 25.0)),
+        ];
+        let (points, weights) =
+            parts.iter().flatten().fold((0.0, 0.0), |(p, w), (value, weight)| (p + value * weight, w + weight));
+        report.score = Some((points / weights * 100.0).round() as u32);
+
+        if let (Some(now), Some(then)) = (loss_per_day(&week), loss_per_day(&before))
+            && then > 0.0
+        {
+            let change = (now - then) / then * 100.0;
+            if change.abs() >= LOSS_CHANGE_PERCENT {
+                report.insights.push(Insight::StandbyLossChange { percent: change.round() as i32 });
+            }
+        }
+        if let Some(ratio) = useful_ratio.filter(|&ratio| ratio < 0.5) {
+            report.insights.push(Insight::LossesAboveDraws { loss_percent: ((1.0 - ratio) * 100.0).round() as u32 });
+        }
+        if let Some(ratio) = peak_ratio.filter(|&ratio| ratio >= PEAK_RATIO_WARNING) {
+            report.insights.push(Insight::PeakHeating { percent: (ratio * 100.0).round() as u32 });
+        }
+        if !legionella_ok {
+            report.insights.push(Insight::NoDisinfection {
+                temp_c: self.config.legionella_temp_c,
+                minutes: self.config.legionella_minutes,
+            });
+        }
+        report
+    }
+}
+
+/// Energy balance in progress, between two accounted changes.
+struct Balance {
+    /// Time of the last snapshot.
+    last: Option<DateTime<Utc>>,
+    /// Time and stored energy of the last accounted change.
+    anchor: Option<(DateTime<Utc>, f64)>,
+    reference_temp_c: f32,
+    /// Since when the top of the tank holds the disinfection temperature.
+    hold_since: Option<DateTime<Utc>>,
+    /// A drop since the anchor happened while water flowed or the element heated.
+    drawing: bool,
+}
+
+impl Balance {
+    fn reset(&mut self) {
+        self.last = None;
+        self.anchor = None;
+        self.hold_since = None;
+        self.drawing = false;
+    }
+}
+
+/// Task accounting every acquisition cycle in the daily records.
+#[derive(Clone)]
+pub struct EfficiencyTracker {
+    pub efficiency: Efficiency,
+    pub snapshots: watch::Receiver<Option<Snapshot>>,
+    /// Names of the sensors inside the tank.
+    pub in_tank: Vec<String>,
+    /// Heater and flow meter, when configured.
+    pub heater: Option<HeaterStats>,
+    pub flow: Option<FlowMeter>,
+    pub publisher: Publisher,
+    pub base_topic: String,
+    pub clock_status: ClockStatus,
+}
+
+impl EfficiencyTracker {
+    /// Accounts every acquisition cycle and publishes the report every hour, only while
+    /// the clock is sane.
+    pub async fn run(self) {
+        let Self { efficiency, mut snapshots, in_tank, heater, flow, publisher, base_topic, clock_status } = self;
+        let peak_ranges = efficiency.config.peak_ranges().unwrap_or_default();
+        let mut balance = Balance { last: None, anchor: None, reference_temp_c: 0.0, hold_since: None, drawing: false };
+        let mut reported: Option<Instant> = None;
+        while snapshots.changed().await.is_ok() {
+            let Some(snapshot) = snapshots.borrow_and_update().clone() else {
+                continue;
+            };
+            if !clock_status.is_sane() {
+                balance.reset();
+                continue;
+            }
+            let now = snapshot.timestamp;
+            let energy = f64::from(snapshot.energy.energy_kwh);
+            if snapshot.energy.reference_temp_c != balance.reference_temp_c {
+                // The energy is counted from another temperature: start over from here
+                balance.reset();
+                balance.reference_temp_c = snapshot.energy.reference_temp_c;
+            }
+            let Some(last_at) = balance.last.filter(|&at| now > at && now - at <= MAX_GAP) else {
+                balance.reset();
+                balance.last = Some(now);
+                balance.anchor = Some((now, energy));
+                continue;
+            };
+            balance.last = Some(now);
+
+            let heating = heater.as_ref().is_some_and(|h| h.is_on());
+            let flowing = flow.as_ref().is_some_and(|f| f.rate_l_min() > 0.0);
+            balance.drawing |= heating || flowing;
+            let top = snapshot
+                .readings
+                .iter()
+                .filter(|r| in_tank.contains(&r.sensor) && r.status.is_valid())
+                .map(|r| r.value)
+                .reduce(f32::max);
+            let hold_s = if top.is_some_and(|t| t >= efficiency.config.legionella_temp_c) {
+                let since = *balance.hold_since.get_or_insert(now);
+                Some((now - since).num_seconds().max(0) as u64)
+            } else {
+                balance.hold_since = None;
+                None
+            };
+            let local_time = now.with_timezone(&Local).time();
+            let peak = peak_ranges.iter().any(|&(start, end)| in_range(local_time, start, end));
+
+            let (anchor_at, anchor_energy) = balance.anchor.unwrap_or((now, energy));
+            let change = energy - anchor_energy;
+            let accounted = change.abs() >= ENERGY_STEP_KWH;
+            let hours = (now - anchor_at).num_milliseconds().max(1) as f64 / 3_600_000.0;
+            let drawn = change < 0.0 && (balance.drawing || -change / hours >= efficiency.config.draw_threshold_kw);
+            efficiency.update(now, |day| {
+                day.observed_s += (now - last_at).num_seconds().max(0) as u64;
+                if let Some(hold_s) = hold_s {
+                    day.legionella_hold_s = day.legionella_hold_s.max(hold_s);
+                }
+                if !accounted {
+                    return;
+                }
+                if change > 0.0 {
+                    day.heated_kwh += change;
+                    if peak {
+                        day.peak_heated_kwh += change;
+                    }
+                } else if drawn {
+                    day.drawn_kwh -= change;
+                } else {
+                    day.standby_loss_kwh -= change;
+                }
+            });
+            if accounted {
+                balance.anchor = Some((now, energy));
+                balance.drawing = false;
+            }
+
+            if reported.is_none_or(|at| at.elapsed() >= REPORT_INTERVAL) {
+                reported = Some(Instant::now());
+                let report = efficiency.compute(efficiency.billing.day(Local::now()));
+                if efficiency.report.send_if_modified(|current| std::mem::replace(current, report.clone()) != report)
+                    && let Ok(payload) = serde_json::to_vec(&report)
+                {
+                    let topic = format!("{}/efficiency", base_topic);
+                    let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, payload).await;
+                }
+            }
+        }
+    }
+}
+
+/// `true` if `time` falls between `start` and `end`, across midnight if `end` is earlier.
+fn in_range(time: NaiveTime, start: NaiveTime, end: NaiveTime) -> bool {
+    if start <= end { time >= start && time < end } else { time >= start || time < end }
+}
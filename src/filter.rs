@@ -0,0 +1,66 @@
+//! Smoothing of the sensor readings.
+//! A per-sensor filter over the last valid temperatures removes the jitter of the
+//! probes (typically ±0.3 °C) before the value is shown, published and recorded.
+
+use std::collections::VecDeque;
+
+use serde::Deserialize;
+
+/// Filter applied to the temperatures of a sensor.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SmoothingFilter {
+    /// Raw readings.
+    #[default]
+    None,
+    /// Mean of the last `filter_samples` readings.
+    MovingAverage,
+    /// Median of the last `filter_samples` readings; ignores isolated spikes.
+    Median,
+}
+
+/// Smoothing state of one sensor.
+#[derive(Debug)]
+pub struct Smoother {
+    filter: SmoothingFilter,
+    samples: usize,
+    window: VecDeque<f32>,
+}
+
+impl Smoother {
+    pub fn new(filter: SmoothingFilter, samples: usize) -> Self {
+        Self {
+            filter,
+            samples: samples.max(1),
+            window: VecDeque::with_capacity(samples),
+        }
+    }
+
+    /// Adds a valid reading and returns the filtered temperature. Until the window is
+    /// full, the filter works on the readings received so far.
+    pub fn push(&mut self, temperature: f32) -> f32 {
+        if self.filter == SmoothingFilter::None {
+            return temperature;
+        }
+        if self.window.len() == self.samples {
+            self.window.pop_front();
+        }
+        self.window.push_back(temperature);
+        let filtered = match self.filter {
+            SmoothingFilter::None => temperature,
+            SmoothingFilter::MovingAverage => self.window.iter().sum::<f32>() / self.window.len() as f32,
+            SmoothingFilter::Median => {
+                let mut sorted: Vec<f32> = self.window.iter().copied().collect();
+                sorted.sort_by(f32::total_cmp);
+                let middle = sorted.len() / 2;
+                if sorted.len().is_multiple_of(2) { (sorted[middle - 1] + sorted[middle]) / 2.0 } else { sorted[middle] }
+            }
+        };
+        (filtered * 100.0).round() / 100.0
+    }
+
+    /// Forgets the readings, so that a sensor back from a fault does not start from old values.
+    pub fn reset(&mut self) {
+        self.window.clear();
+    }
+}
@@ -0,0 +1,181 @@
+//! Hot water flow meter.
+//! Counts the pulses of a hall-effect flow sensor on a GPIO input and derives the
+//! instantaneous flow and the cumulative volume drawn. The volume is persisted with
+//! the runtime state.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use gpio_cdev::{Chip, EventRequestFlags, LineRequestFlags};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::board::Board;
+use crate::format::{self, Unit};
+use crate::logging::warning;
+use crate::mqtt::Publisher;
+
+/// Delay before retrying after the GPIO line could not be read.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Flow meter settings from the `[flow]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct FlowConfig {
+    /// GPIO line of the pulse output, on the board's GPIO chip (disabled if absent).
+    pub line: Option<u32>,
+    /// Pulses per litre, from the sensor datasheet (e.g., 450 for a YF-S201).
+    pub pulses_per_litre: f32,
+    /// Period over which the flow is averaged and published, in seconds.
+    pub window_s: f32,
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        Self {
+            line: None,
+            pulses_per_litre: 450.0,
+            window_s: 5.0,
+        }
+    }
+}
+
+impl FlowConfig {
+    /// Averaging and publishing period.
+    pub fn window(&self) -> Duration {
+        Duration::from_secs_f32(self.window_s.max(1.0))
+    }
+}
+
+/// Persisted flow meter totals.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlowTotals {
+    /// Cumulative volume drawn, in litres.
+    pub volume_l: f64,
+}
+
+/// Shared flow meter readings.
+///
+/// Cloning a `FlowMeter` handle is cheap; all clones share the same counters.
+#[derive(Clone)]
+pub struct FlowMeter {
+    /// Pulses counted since startup.
+    pulses: Arc<AtomicU64>,
+    /// Flow of the last window, in l/min.
+    rate_l_min: Arc<Mutex<f32>>,
+    /// Volume of the previous runs, in litres.
+    base_volume_l: f64,
+    pulses_per_litre: f64,
+}
+
+impl FlowMeter {
+    /// Creates the meter, starting from the totals of the previous run.
+    pub fn new(config: &FlowConfig, totals: FlowTotals) -> Self {
+        Self {
+            pulses: Arc::new(AtomicU64::new(0)),
+            rate_l_min: Arc::new(Mutex::new(0.0)),
+            base_volume_l: totals.volume_l,
+            pulses_per_litre: config.pulses_per_litre as f64,
+        }
+    }
+
+    /// Counts pulses from the sensor.
+    fn add_pulses(&self, pulses: u64) {
+        self.pulses.fetch_add(pulses, Ordering::Relaxed);
+    }
+
+    /// Instantaneous flow, in l/min.
+    pub fn rate_l_min(&self) -> f32 {
+        *self.rate_l_min.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Cumulative volume drawn, in litres.
+    pub fn volume_l(&self) -> f64 {
+        self.base_volume_l + self.pulses.load(Ordering::Relaxed) as f64 / self.pulses_per_litre
+    }
+
+    /// Copy of the totals, for persistence.
+    pub fn totals(&self) -> FlowTotals {
+        FlowTotals {
+            volume_l: self.volume_l(),
+        }
+    }
+}
+
+/// Counts the pulses of the configured GPIO line, or simulates draws on the simulated
+/// board. Returns after an error so the supervisor restarts it.
+pub async fn count_pulses(meter: FlowMeter, board: Board, line: u32) {
+    if board.is_simulated() {
+        simulate(meter).await;
+        return;
+    }
+    let chip = board.gpio_chip().to_path_buf();
+    let result = tokio::task::spawn_blocking(move || -> Result<()> {
+        let mut chip = Chip::new(&chip).with_context(|| format!("Failed to open {}", chip.display()))?;
+        let events = chip
+            .get_line(line)
+            .and_then(|l| l.events(LineRequestFlags::INPUT, EventRequestFlags::FALLING_EDGE, "boilert-flow"))
+            .with_context(|| format!("Failed to watch GPIO line {}", line))?;
+        for event in events {
+            event.context("Failed to read a flow pulse")?;
+            meter.add_pulses(1);
+        }
+        Ok(())
+    })
+    .await;
+    if let Ok(Err(e)) = result {
+        warning!("Flow meter: {:#}", e);
+    }
+    tokio::time::sleep(RETRY_DELAY).await;
+}
+
+/// Simulates hot water draws: a few minutes at 4 to 10 l/min from time to time.
+async fn simulate(meter: FlowMeter) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    let mut draw: Option<(f64, u32)> = None;
+    let mut remainder = 0.0;
+    loop {
+        ticker.tick().await;
+        draw = match draw {
+            Some((rate, seconds)) if seconds > 0 => Some((rate, seconds - 1)),
+            Some(_) => None,
+            None if rand::thread_rng().gen_bool(0.005) => {
+                Some((rand::thread_rng().gen_range(4.0..10.0), rand::thread_rng().gen_range(30..300)))
+            }
+            None => None,
+        };
+        if let Some((rate_l_min, _)) = draw {
+            remainder += rate_l_min / 60.0 * meter.pulses_per_litre;
+            meter.add_pulses(remainder as u64);
+            remainder = remainder.fract();
+        }
+    }
+}
+
+/// Computes the flow over each window and publishes it with the volume on
+/// `{base_topic}/flow/rate` (l/min) and `{base_topic}/flow/volume` (l).
+pub async fn publish_loop(meter: FlowMeter, window: Duration, publisher: Publisher, base_topic: String) {
+    let mut ticker = tokio::time::interval(window);
+    let mut last = (meter.pulses.load(Ordering::Relaxed), Instant::now());
+    loop {
+        ticker.tick().await;
+        let now = (meter.pulses.load(Ordering::Relaxed), Instant::now());
+        let minutes = now.1.duration_since(last.1).as_secs_f64() / 60.0;
+        if minutes > 0.0 {
+            let rate = (now.0 - last.0) as f64 / meter.pulses_per_litre / minutes;
+            *meter.rate_l_min.lock().unwrap_or_else(|e| e.into_inner()) = rate as f32;
+        }
+        last = now;
+
+        let values = [
+            ("rate", format::data(Unit::LitrePerMinute, meter.rate_l_min())),
+            ("volume", format::data(Unit::Litre, meter.volume_l() as f32)),
+        ];
+        for (name, value) in values {
+            let topic = format!("{}/flow/{}", base_topic, name);
+            let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, false, value).await;
+        }
+    }
+}
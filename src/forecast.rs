@@ -0,0 +1,129 @@
+//! Temperature forecast drawn after the history on the charts.
+//! A tank model is fitted to the history of each sensor: an exponential heat loss
+//! towards the ambient temperature (Newton's law of cooling) and a constant heating
+//! rate. The forecast assumes no hot water is drawn.
+
+use std::collections::VecDeque;
+
+use serde::Deserialize;
+
+use crate::chart::{CHART_WIDTH, ChartPoint};
+use crate::history::HistoryPoint;
+
+/// Minimum number of cooling samples needed to fit the heat loss.
+const MIN_COOLING_SAMPLES: usize = 3;
+/// Rises slower than this, in °C per hour, are not taken for heating.
+const MIN_HEATING_RATE_C_PER_H: f32 = 1.0;
+/// Samples closer than this to the ambient temperature, in °C, are too noisy to fit.
+const MIN_ABOVE_AMBIENT_C: f32 = 2.0;
+
+/// Forecast settings from the `[forecast]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ForecastConfig {
+    /// Time projected after the last point, in hours (0: no forecast).
+    pub horizon_h: f32,
+    /// Temperature of the room around the tank, in °C.
+    pub ambient_c: f32,
+}
+
+impl Default for ForecastConfig {
+    fn default() -> Self {
+        Self {
+            horizon_h: 4.0,
+            ambient_c: 20.0,
+        }
+    }
+}
+
+/// Heat-loss and heating model of a tank sensor, fitted to its history.
+#[derive(Debug, Clone, Copy)]
+pub struct TankModel {
+    /// Cooling rate constant, per hour: `T(t) = Ta + (T0 - Ta) * exp(-loss_per_h * t)`.
+    pub loss_per_h: f32,
+    /// Temperature rise while the heater is on, in °C per hour.
+    pub heating_c_per_h: Option<f32>,
+    /// Highest temperature of the history, taken as the thermostat cut-off.
+    pub ceiling_c: f32,
+}
+
+impl TankModel {
+    /// Fits the model to consecutive history points. Falling pairs give the heat loss,
+    /// rising pairs the heating rate; medians keep hot water draws from skewing them.
+    ///
+    /// Returns `None` until enough cooling has been recorded.
+    pub fn fit(points: &VecDeque<HistoryPoint>, ambient_c: f32, max_step_h: f32) -> Option<Self> {
+        let mut losses = Vec::new();
+        let mut rises = Vec::new();
+        for (a, b) in points.iter().zip(points.iter().skip(1)) {
+            let dt_h = (b.timestamp - a.timestamp).num_seconds() as f32 / 3600.0;
+            if b.gap || dt_h <= 0.0 || dt_h > max_step_h {
+                continue;
+            }
+            let (above_a, above_b) = (a.value - ambient_c, b.value - ambient_c);
+            if above_a < MIN_ABOVE_AMBIENT_C || above_b < MIN_ABOVE_AMBIENT_C / 2.0 {
+                continue;
+            }
+            if b.value < a.value {
+                losses.push((above_a / above_b).ln() / dt_h);
+            } else {
+                let rate = (b.value - a.value) / dt_h;
+                if rate >= MIN_HEATING_RATE_C_PER_H {
+                    rises.push(rate);
+                }
+            }
+        }
+        if losses.len() < MIN_COOLING_SAMPLES {
+            return None;
+        }
+        let ceiling_c = points.iter().map(|p| p.value).fold(f32::NEG_INFINITY, f32::max);
+        Some(Self {
+            loss_per_h: median(&mut losses),
+            heating_c_per_h: (!rises.is_empty()).then(|| median(&mut rises)),
+            ceiling_c,
+        })
+    }
+
+    /// Temperature `hours` after `start_c`. With `heating`, the temperature rises up
+    /// to the ceiling, then cools down.
+    pub fn project(&self, start_c: f32, ambient_c: f32, heating: bool, hours: f32) -> f32 {
+        let (peak_c, cooling_h) = match self.heating_c_per_h {
+            Some(rate) if heating && start_c < self.ceiling_c => {
+                let heating_h = (self.ceiling_c - start_c) / rate;
+                if hours <= heating_h {
+                    return start_c + rate * hours;
+                }
+                (self.ceiling_c, hours - heating_h)
+            }
+            _ => (start_c, hours),
+        };
+        ambient_c + (peak_c - ambient_c) * (-self.loss_per_h * cooling_h).exp()
+    }
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    values[values.len() / 2]
+}
+
+/// Points of the forecast in chart coordinates, starting at the newest history point
+/// (x = `CHART_WIDTH`) with two points per history period.
+pub fn chart_points(
+    model: &TankModel,
+    config: &ForecastConfig,
+    start_c: f32,
+    heating: bool,
+    period_h: f32,
+) -> Vec<ChartPoint> {
+    let steps = (config.horizon_h / period_h * 2.0).ceil() as usize;
+    (0..=steps)
+        .map(|i| {
+            let hours = i as f32 * period_h / 2.0;
+            ChartPoint {
+                x: CHART_WIDTH + i as f32 / 2.0,
+                value: model.project(start_c, config.ambient_c, heating, hours),
+                gap: false,
+            }
+        })
+        .collect()
+}
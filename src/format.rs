@@ -0,0 +1,227 @@
+//! Formatting of measured values.
+//! One policy, set from the `[format]` section, gives the decimal places of each unit and
+//! the rounding mode used everywhere a value is turned into text: the UI, the MQTT
+//! payloads, the CSV exports and the alert messages. Machine-readable output always uses
+//! a dot and no thousands separator; the locale only applies to text shown to people.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use serde::Deserialize;
+
+/// Unit of a formatted value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Celsius,
+    /// Temperatures shown in °F (see the display preferences).
+    Fahrenheit,
+    /// Relative humidity.
+    Percent,
+    /// Atmospheric pressure.
+    Hectopascal,
+    /// Boiler pressure.
+    Bar,
+    KilowattHour,
+    LitrePerMinute,
+    Litre,
+    Hour,
+}
+
+impl Unit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Celsius => "°C",
+            Unit::Fahrenheit => "°F",
+            Unit::Percent => "%",
+            Unit::Hectopascal => "hPa",
+            Unit::Bar => "bar",
+            Unit::KilowattHour => "kWh",
+            Unit::LitrePerMinute => "l/min",
+            Unit::Litre => "l",
+            Unit::Hour => "h",
+        }
+    }
+}
+
+/// Decimal places of each unit.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Decimals {
+    pub temperature: u8,
+    pub humidity: u8,
+    pub pressure_hpa: u8,
+    pub pressure_bar: u8,
+    pub energy: u8,
+    pub flow: u8,
+    pub volume: u8,
+    pub hours: u8,
+}
+
+impl Default for Decimals {
+    fn default() -> Self {
+        Self {
+            temperature: 2,
+            humidity: 1,
+            pressure_hpa: 1,
+            pressure_bar: 2,
+            energy: 2,
+            flow: 1,
+            volume: 1,
+            hours: 2,
+        }
+    }
+}
+
+/// Rounding to the last decimal place shown.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rounding {
+    /// Halves away from zero (45.25 → 45.3).
+    #[default]
+    HalfUp,
+    /// Halves to the even digit (45.25 → 45.2), without bias over many values.
+    HalfEven,
+    /// Towards zero (45.29 → 45.2).
+    Truncate,
+}
+
+/// Separators of the text shown to people.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    /// 1,234.5
+    #[default]
+    En,
+    /// 1 234,5
+    Fr,
+    /// 1.234,5
+    De,
+    /// 1'234.5 (Switzerland)
+    Ch,
+}
+
+impl Locale {
+    /// Decimal and thousands separators.
+    fn separators(self) -> (char, char) {
+        match self {
+            Locale::En => ('.', ','),
+            // Narrow no-break space, so that the number is never split across lines
+            Locale::Fr => (',', '\u{202f}'),
+            Locale::De => (',', '.'),
+            Locale::Ch => ('.', '\''),
+        }
+    }
+}
+
+/// Formatting settings from the `[format]` section.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct FormatConfig {
+    pub decimals: Decimals,
+    pub rounding: Rounding,
+    pub locale: Locale,
+}
+
+impl FormatConfig {
+    /// Checks the decimal places.
+    pub fn validate(&self) -> Result<()> {
+        let d = &self.decimals;
+        let all = [d.temperature, d.humidity, d.pressure_hpa, d.pressure_bar, d.energy, d.flow, d.volume, d.hours];
+        if all.iter().any(|&decimals| decimals > 6) {
+            bail!("[format] decimals must be between 0 and 6");
+        }
+        Ok(())
+    }
+
+    fn decimals(&self, unit: Unit) -> usize {
+        let d = &self.decimals;
+        usize::from(match unit {
+            Unit::Celsius | Unit::Fahrenheit => d.temperature,
+            Unit::Percent => d.humidity,
+            Unit::Hectopascal => d.pressure_hpa,
+            Unit::Bar => d.pressure_bar,
+            Unit::KilowattHour => d.energy,
+            Unit::LitrePerMinute => d.flow,
+            Unit::Litre => d.volume,
+            Unit::Hour => d.hours,
+        })
+    }
+}
+
+static POLICY: OnceLock<FormatConfig> = OnceLock::new();
+
+/// Sets the formatting policy of the process. Only the first call has an effect; until
+/// then, the defaults apply.
+pub fn init(config: &FormatConfig) {
+    let _ = POLICY.set(config.clone());
+}
+
+fn policy() -> &'static FormatConfig {
+    POLICY.get_or_init(FormatConfig::default)
+}
+
+/// Rounds `value` to the decimal places of `unit`, with the configured rounding mode.
+pub fn round(unit: Unit, value: f32) -> f32 {
+    let policy = policy();
+    let factor = 10f64.powi(policy.decimals(unit) as i32);
+    // Start from the shortest decimal form of the value (23.15, not 23.149999618) and
+    // drop the representation error left in the f64, so that halves are seen as such
+    let decimal: f64 = value.to_string().parse().unwrap_or(f64::from(value));
+    let scaled = (decimal * factor * 1e6).round() / 1e6;
+    let rounded = match policy.rounding {
+        Rounding::HalfUp => scaled.round(),
+        Rounding::HalfEven => scaled.round_ties_even(),
+        Rounding::Truncate => scaled.trunc(),
+    };
+    (rounded / factor) as f32
+}
+
+/// Formats `value` for machines (MQTT payloads, CSV): rounded, with a dot and without
+/// the unit (e.g., "45.25").
+pub fn data(unit: Unit, value: f32) -> String {
+    // Avoid "-0.0" for small negative values
+    let value = round(unit, value) + 0.0;
+    format!("{:.*}", policy().decimals(unit), value)
+}
+
+/// Formats a duration for people, to the largest units that matter (e.g., "45 s",
+/// "12 min", "2 h 05 min").
+pub fn duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{} s", seconds),
+        60..3600 => format!("{} min", seconds / 60),
+        _ => format!("{} h {:02} min", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Formats `value` with its unit for people (UI, alerts), with the separators of the
+/// configured locale (e.g., "1 234,5 kWh").
+pub fn text(unit: Unit, value: f32) -> String {
+    let policy = policy();
+    let (decimal_separator, thousands_separator) = policy.locale.separators();
+    let number = data(unit, value);
+    let (sign, number) = match number.strip_prefix('-') {
+        Some(number) => ("-", number),
+        None => ("", number.as_str()),
+    };
+    let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let mut out = String::from(sign);
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            out.push(thousands_separator);
+        }
+        out.push(digit);
+    }
+    if !fraction.is_empty() {
+        out.push(decimal_separator);
+        out.push_str(fraction);
+    }
+    // No space before the percent sign in English
+    if unit == Unit::Percent && policy.locale == Locale::En {
+        format!("{}{}", out, unit.symbol())
+    } else {
+        format!("{} {}", out, unit.symbol())
+    }
+}
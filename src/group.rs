@@ -0,0 +1,62 @@
+//! Sensor groups.
+//! A group gathers sensors of one zone (e.g., the tank, the solar loop) and gives the
+//! lowest, highest and average temperature of its valid sensors on every cycle, with
+//! their own MQTT topics and a row on the statistics page.
+
+use anyhow::{Result, bail};
+use boilert::model::Reading;
+use serde::Deserialize;
+
+/// Configuration of a sensor group.
+#[derive(Debug, Deserialize, Clone)]
+pub struct GroupConfig {
+    /// Name of the group, also used in its MQTT topics (e.g., "tank").
+    pub name: String,
+    /// Names of the sensors of the group (physical or virtual).
+    pub sensors: Vec<String>,
+}
+
+/// Aggregate values of a group over its valid sensors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GroupStats {
+    pub min: f32,
+    pub max: f32,
+    pub average: f32,
+    /// Number of valid sensors the values are computed from.
+    pub count: usize,
+}
+
+impl GroupConfig {
+    /// Checks the group against the names of the configured sensors.
+    pub fn validate(&self, sensor_names: &[String]) -> Result<()> {
+        if self.name.is_empty() || self.name.contains(['/', '+', '#']) {
+            bail!("Group {:?}: the name must be a valid MQTT topic level", self.name);
+        }
+        if self.sensors.is_empty() {
+            bail!("Group {}: no sensors", self.name);
+        }
+        if let Some(unknown) = self.sensors.iter().find(|s| !sensor_names.contains(s)) {
+            bail!("Group {}: unknown sensor {:?}", self.name, unknown);
+        }
+        Ok(())
+    }
+
+    /// Computes the values of the group from the readings of this cycle, `None` if
+    /// none of its sensors is valid.
+    pub fn compute(&self, readings: &[Reading]) -> Option<GroupStats> {
+        let values: Vec<f32> = readings
+            .iter()
+            .filter(|r| r.status.is_valid() && self.sensors.contains(&r.sensor))
+            .map(|r| r.value)
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        Some(GroupStats {
+            min: values.iter().copied().fold(f32::INFINITY, f32::min),
+            max: values.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            average: values.iter().sum::<f32>() / values.len() as f32,
+            count: values.len(),
+        })
+    }
+}
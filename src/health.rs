@@ -0,0 +1,157 @@
+//! Health reporting for external watchdogs.
+//! Tracks liveness information and exposes it as a JSON status file and on `/healthz`.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::alerts::Alerts;
+use crate::http::Response;
+use crate::logging::warning;
+
+/// The application is reported unhealthy if no acquisition cycle completed for this long,
+/// or for [`MISSED_CYCLES`] cycles when they are further apart.
+const MAX_READ_AGE: Duration = Duration::from_secs(30);
+/// Cycles that may be missed before the application is reported unhealthy.
+const MISSED_CYCLES: u32 = 3;
+
+/// Interval between two rewrites of the status file.
+const STATUS_FILE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
+struct HealthState {
+    last_read: Option<DateTime<Utc>>,
+    mqtt_connected: bool,
+    /// Secondary broker in use, with `[mqtt.failover]`.
+    mqtt_failover: Option<String>,
+    sensor_errors: u64,
+    mqtt_errors: u64,
+}
+
+/// Shared liveness information, updated by the background tasks.
+#[derive(Clone)]
+pub struct Health {
+    state: Arc<Mutex<HealthState>>,
+    started_at: DateTime<Utc>,
+    /// Age of the last cycle above which the application is unhealthy.
+    max_read_age: Duration,
+}
+
+/// Serialized health report, written to the status file and returned by `/healthz`.
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    /// `"ok"` or `"unhealthy"`.
+    pub status: &'static str,
+    pub started_at: DateTime<Utc>,
+    pub last_read: Option<DateTime<Utc>>,
+    pub mqtt_connected: bool,
+    /// Address of the secondary broker, while in use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mqtt_failover: Option<String>,
+    pub sensor_errors: u64,
+    pub mqtt_errors: u64,
+    pub active_alerts: usize,
+}
+
+impl Health {
+    /// Health of an application running an acquisition cycle every `cycle_interval`.
+    pub fn new(cycle_interval: Duration) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(HealthState::default())),
+            started_at: Utc::now(),
+            max_read_age: MAX_READ_AGE.max(cycle_interval * MISSED_CYCLES),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, HealthState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records the completion of an acquisition cycle.
+    pub fn record_read(&self, at: DateTime<Utc>) {
+        self.state().last_read = Some(at);
+    }
+
+    /// Counts a failed sensor read.
+    pub fn record_sensor_error(&self) {
+        self.state().sensor_errors += 1;
+    }
+
+    /// Updates the MQTT connection state, counting transitions to disconnected as errors.
+    pub fn set_mqtt_connected(&self, connected: bool) {
+        let mut state = self.state();
+        if !connected {
+            state.mqtt_errors += 1;
+        }
+        state.mqtt_connected = connected;
+    }
+
+    /// Records the secondary broker in use (`None` when on the main broker).
+    pub fn set_mqtt_failover(&self, address: Option<String>) {
+        self.state().mqtt_failover = address;
+    }
+
+    /// Address of the secondary broker, while in use.
+    pub fn mqtt_failover(&self) -> Option<String> {
+        self.state().mqtt_failover.clone()
+    }
+
+    /// Returns `true` if the MQTT broker is currently connected.
+    pub fn is_mqtt_connected(&self) -> bool {
+        self.state().mqtt_connected
+    }
+
+    /// Returns `true` if an acquisition cycle completed recently.
+    pub fn is_alive(&self) -> bool {
+        self.state()
+            .last_read
+            .is_some_and(|t| (Utc::now() - t).to_std().unwrap_or_default() <= self.max_read_age)
+    }
+
+    /// Builds the current health report.
+    ///
+    /// The application is healthy when a cycle completed recently and MQTT is connected.
+    pub fn report(&self, alerts: &Alerts) -> HealthReport {
+        let fresh = self.is_alive();
+        let state = self.state();
+        HealthReport {
+            status: if fresh && state.mqtt_connected { "ok" } else { "unhealthy" },
+            started_at: self.started_at,
+            last_read: state.last_read,
+            mqtt_connected: state.mqtt_connected,
+            mqtt_failover: state.mqtt_failover.clone(),
+            sensor_errors: state.sensor_errors,
+            mqtt_errors: state.mqtt_errors,
+            active_alerts: alerts.active().len(),
+        }
+    }
+}
+
+/// Handles `GET /healthz`: 200 when healthy, 503 otherwise, with the JSON report.
+pub fn handle_healthz(health: &Health, alerts: &Alerts) -> Response {
+    let report = health.report(alerts);
+    let status = if report.status == "ok" { 200 } else { 503 };
+    Response::json(status, &report)
+}
+
+/// Rewrites the status file periodically.
+pub async fn write_status_file(health: Health, alerts: Alerts, path: String) {
+    let mut interval = tokio::time::interval(STATUS_FILE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let report = health.report(&alerts);
+        if let Err(e) = write_report(Path::new(&path), &report) {
+            warning!("Failed to write status file {}: {:#}", path, e);
+        }
+    }
+}
+
+/// Writes the report atomically, so readers never see a partially written file.
+fn write_report(path: &Path, report: &HealthReport) -> Result<()> {
+    let content = serde_json::to_vec_pretty(report)?;
+    crate::state::write_atomic(path, &content)
+}
@@ -0,0 +1,723 @@
+//! Heating element runtime statistics.
+//! Follows the heater state reported on MQTT (e.g., by a smart relay) and accumulates
+//! the on-time, the number of switch cycles and the estimated electrical energy.
+//! Totals are persisted with the runtime state; daily and billing period totals are
+//! published at the end of each statistics day and period (see [`crate::billing`]), and
+//! an alert warns when the relay approaches its rated cycle count.
+//! An energy meter, which only ever increases until it is reset on request, feeds
+//! consumers expecting a monotonic counter (energy dashboards, utility meters).
+//! With a GPIO input wired to the auxiliary contact of the heater contactor, the
+//! statistics follow the actual contactor state instead, and an alert is raised when
+//! it disagrees with the reported command (welded contact, failed relay).
+//! The heat-up curve of each cycle is compared with a learned baseline to detect scale
+//! build-up or a failing element (see [`crate::heatup`]).
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use boilert::model::AuditSource;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use gpio_cdev::{Chip, EventRequestFlags, LineRequestFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::Alerts;
+use crate::audit::AuditLog;
+use crate::billing::BillingConfig;
+use crate::board::Board;
+use crate::format::{self, Unit};
+use crate::heatup::{Baseline, Curve, HeatUpConfig, Outcome};
+use crate::logging::{info, warning};
+use crate::messages::Message;
+use crate::mqtt::{Publisher, Subscriptions};
+
+/// Interval between two publications of the statistics.
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Settling time of the auxiliary contact after an edge.
+const CONTACT_DEBOUNCE: Duration = Duration::from_millis(20);
+
+/// Delay before retrying after the feedback input could not be read.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Total gaps under which a day or billing period still counts as complete, in seconds.
+const PARTIAL_GAP_S: f64 = 300.0;
+
+/// Observed time under which the gaps of a day or period are never interpolated, in seconds.
+const MIN_OBSERVED_S: f64 = 3600.0;
+
+/// How the gaps of a day or billing period are accounted (`gap_policy`): time during
+/// which boilert was stopped or the heater state was not known yet.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GapPolicy {
+    /// Only the observed on-time counts.
+    #[default]
+    Exclude,
+    /// The gaps are filled at the duty cycle of the observed time.
+    Interpolate,
+}
+
+/// Heater settings from the `[heater]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct HeaterConfig {
+    /// Topic reporting the heater state (`on`/`off`, `1`/`0`, `true`/`false`).
+    /// Statistics are disabled if absent.
+    pub state_topic: Option<String>,
+    /// Rated power of the heating element, in W.
+    pub power_w: f32,
+    /// Switch cycles the relay is rated for (no warning if absent).
+    pub relay_cycle_limit: Option<u64>,
+    /// Fraction of `relay_cycle_limit` at which the service warning is raised.
+    pub relay_warning_ratio: f32,
+    /// GPIO line of the contactor auxiliary contact, on the board's GPIO chip
+    /// (disabled if absent).
+    pub feedback_line: Option<u32>,
+    /// The contact pulls the line low when the contactor is closed.
+    pub feedback_active_low: bool,
+    /// Time the contactor may disagree with the command before the alert, in seconds.
+    pub feedback_delay_s: f32,
+    /// Heat-up curve fingerprinting (`[heater.heatup]`).
+    pub heatup: HeatUpConfig,
+    /// Accounting of the gaps in the daily and billing period totals.
+    pub gap_policy: GapPolicy,
+}
+
+impl Default for HeaterConfig {
+    fn default() -> Self {
+        Self {
+            state_topic: None,
+            power_w: 3000.0,
+            relay_cycle_limit: None,
+            relay_warning_ratio: 0.9,
+            feedback_line: None,
+            feedback_active_low: true,
+            feedback_delay_s: 5.0,
+            heatup: HeatUpConfig::default(),
+            gap_policy: GapPolicy::Exclude,
+        }
+    }
+}
+
+impl HeaterConfig {
+    /// `true` if the heater state is known, from the state topic or the contactor.
+    pub fn is_enabled(&self) -> bool {
+        self.state_topic.is_some() || self.feedback_line.is_some()
+    }
+}
+
+/// Persisted heater totals.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HeaterTotals {
+    /// Cumulative on-time, in seconds.
+    pub on_time_s: f64,
+    /// Cumulative number of off-to-on switches.
+    pub cycles: u64,
+    /// Statistics day of the daily counters.
+    pub day: Option<NaiveDate>,
+    /// On-time of `day`, in seconds.
+    pub day_on_time_s: f64,
+    /// Switches of `day`.
+    pub day_cycles: u64,
+    /// Time of `day` during which the heater state was known, in seconds (`None` in the
+    /// state saved by older versions).
+    #[serde(default)]
+    pub day_observed_s: Option<f64>,
+    /// First day of the billing period of the period counters.
+    #[serde(default)]
+    pub period: Option<NaiveDate>,
+    /// On-time of the billing period, in seconds.
+    #[serde(default)]
+    pub period_on_time_s: f64,
+    /// Switches of the billing period.
+    #[serde(default)]
+    pub period_cycles: u64,
+    /// Time of the billing period during which the heater state was known, in seconds.
+    #[serde(default)]
+    pub period_observed_s: Option<f64>,
+    /// Energy meter, in kWh (`None` in the state saved by older versions).
+    #[serde(default)]
+    pub meter_kwh: Option<f64>,
+    /// Last reset of the energy meter (`None` if never reset).
+    #[serde(default)]
+    pub meter_reset: Option<DateTime<Utc>>,
+    /// Heat-up baseline and the outcome of the last cycles.
+    #[serde(default)]
+    pub heatup: Baseline,
+}
+
+/// Totals of a completed day, published on `{base_topic}/heater/daily`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyHeater {
+    pub date: NaiveDate,
+    pub runtime_h: f64,
+    pub cycles: u64,
+    pub energy_kwh: f64,
+    /// Fraction of the day the heater was on.
+    pub duty_cycle: f64,
+    /// Estimated cost of the energy, with `[billing] price_per_kwh`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    #[serde(flatten)]
+    pub gaps: Gaps,
+}
+
+/// Totals of a completed billing period, published on `{base_topic}/heater/billing`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BillingPeriodHeater {
+    /// First statistics day of the period.
+    pub start: NaiveDate,
+    /// Last statistics day of the period.
+    pub end: NaiveDate,
+    pub runtime_h: f64,
+    pub cycles: u64,
+    pub energy_kwh: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    #[serde(flatten)]
+    pub gaps: Gaps,
+}
+
+/// Gaps of a completed day or billing period.
+#[derive(Debug, Clone, Serialize)]
+pub struct Gaps {
+    /// Fraction of the day or period during which the heater state was known (absent
+    /// when it started before boilert recorded it).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<f64>,
+    /// The gaps exceed `PARTIAL_GAP_S`: the totals miss part of the day or period.
+    pub partial: bool,
+    /// The runtime, energy and cost include an estimate of the gaps.
+    pub interpolated: bool,
+}
+
+/// Counters of a completed day or billing period.
+#[derive(Debug)]
+struct Completed {
+    /// First day.
+    start: NaiveDate,
+    on_time_s: f64,
+    cycles: u64,
+    observed_s: Option<f64>,
+}
+
+impl Completed {
+    /// On-time with the gaps accounted per `policy`, over a day or period lasting
+    /// `length_s` seconds.
+    fn on_time_s(&self, length_s: f64, policy: GapPolicy) -> (f64, Gaps) {
+        let Some(observed_s) = self.observed_s else {
+            let gaps = Gaps {
+                coverage: None,
+                partial: false,
+                interpolated: false,
+            };
+            return (self.on_time_s, gaps);
+        };
+        let observed_s = observed_s.min(length_s);
+        let partial = length_s - observed_s > PARTIAL_GAP_S;
+        let interpolated = partial && policy == GapPolicy::Interpolate && observed_s >= MIN_OBSERVED_S;
+        let on_time_s = if interpolated {
+            self.on_time_s * length_s / observed_s
+        } else {
+            self.on_time_s
+        };
+        let gaps = Gaps {
+            coverage: Some(observed_s / length_s),
+            partial,
+            interpolated,
+        };
+        (on_time_s, gaps)
+    }
+}
+
+#[derive(Debug, Default)]
+struct HeaterState {
+    totals: HeaterTotals,
+    /// Current state, unknown until the first message.
+    on: Option<bool>,
+    /// Last time the on-time was accounted.
+    settled_at: Option<Instant>,
+    /// State reported on the state topic, unknown until the first message.
+    commanded: Option<bool>,
+    /// Start of the current disagreement between the contactor and the command.
+    mismatch_since: Option<Instant>,
+    /// Heat-up curve of the cycle in progress.
+    curve: Option<Curve>,
+}
+
+/// Shared heater statistics.
+#[derive(Clone)]
+pub struct HeaterStats {
+    state: Arc<Mutex<HeaterState>>,
+    power_w: f32,
+    /// The state is read from the contactor rather than the state topic.
+    has_feedback: bool,
+    heatup: HeatUpConfig,
+}
+
+impl HeaterStats {
+    /// Creates the statistics, starting from the totals of the previous run.
+    pub fn new(config: &HeaterConfig, mut totals: HeaterTotals) -> Self {
+        // Start the meter from the energy accumulated so far
+        totals.meter_kwh.get_or_insert(config.power_w as f64 * totals.on_time_s / 3600.0 / 1000.0);
+        Self {
+            state: Arc::new(Mutex::new(HeaterState {
+                totals,
+                ..Default::default()
+            })),
+            power_w: config.power_w,
+            has_feedback: config.feedback_line.is_some(),
+            heatup: config.heatup.clone(),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, HeaterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records the state reported on the state topic. Without a contactor feedback, it
+    /// is taken as the heater state.
+    fn set_commanded(&self, on: bool) {
+        self.state().commanded = Some(on);
+        if !self.has_feedback {
+            self.set_on(on);
+        }
+    }
+
+    /// Records the heater state, counting a cycle on every off-to-on switch. The heat-up
+    /// curve is recorded from the switch-on and assessed at the switch-off.
+    fn set_on(&self, on: bool) {
+        let mut state = self.state();
+        settle(&mut state, self.power_w);
+        if on && state.on == Some(false) {
+            state.totals.cycles += 1;
+            state.totals.day_cycles += 1;
+            state.totals.period_cycles += 1;
+            if self.heatup.enabled {
+                state.curve = Some(Curve::default());
+            }
+        } else if !on && let Some(curve) = state.curve.take() {
+            match state.totals.heatup.finish(curve, &self.heatup) {
+                Outcome::Ignored => {}
+                Outcome::Learned(cycles) => {
+                    info!("Heater heat-up baseline: {} of {} cycles learned", cycles, self.heatup.baseline_cycles)
+                }
+                Outcome::Compared(c) => info!(
+                    "Heater heat-up: +{:.1} K in {:.0} min, {:.0}% of the baseline (+{:.1} K)",
+                    c.rise_k,
+                    c.minutes,
+                    c.ratio * 100.0,
+                    c.baseline_k
+                ),
+            }
+        }
+        state.on = Some(on);
+    }
+
+    /// Adds a reading of the average tank temperature to the heat-up curve of the cycle
+    /// in progress, if any.
+    pub fn record_tank_temperature(&self, temp_c: f32) {
+        if let Some(curve) = self.state().curve.as_mut() {
+            curve.record(temp_c, &self.heatup);
+        }
+    }
+
+    /// Forgets the heat-up baseline, to learn it again (e.g., after descaling or replacing
+    /// the element). Returns the number of cycles it held.
+    pub fn reset_heatup(&self) -> u32 {
+        std::mem::take(&mut self.state().totals.heatup).cycles
+    }
+
+    /// `true` if the heater is reported on.
+    pub fn is_on(&self) -> bool {
+        self.state().on == Some(true)
+    }
+
+    /// Returns the commanded and the actual state once they have disagreed for `delay`.
+    fn contactor_mismatch(&self, delay: Duration) -> Option<(bool, bool)> {
+        let mut state = self.state();
+        match (state.commanded, state.on) {
+            (Some(commanded), Some(on)) if commanded != on => {
+                let since = *state.mismatch_since.get_or_insert_with(Instant::now);
+                (since.elapsed() >= delay).then_some((commanded, on))
+            }
+            _ => {
+                state.mismatch_since = None;
+                None
+            }
+        }
+    }
+
+    /// Copy of the totals, for persistence.
+    pub fn totals(&self) -> HeaterTotals {
+        let mut state = self.state();
+        settle(&mut state, self.power_w);
+        state.totals.clone()
+    }
+
+    /// Resets the energy meter to zero. Returns its value before the reset, in kWh.
+    pub fn reset_meter(&self) -> f64 {
+        let mut state = self.state();
+        settle(&mut state, self.power_w);
+        state.totals.meter_reset = Some(Utc::now());
+        state.totals.meter_kwh.replace(0.0).unwrap_or_default()
+    }
+
+    /// Estimated electrical energy for an on-time, in kWh.
+    fn energy_kwh(&self, on_time_s: f64) -> f64 {
+        self.power_w as f64 * on_time_s / 3600.0 / 1000.0
+    }
+}
+
+/// Adds the on-time elapsed since the last call, and its energy at `power_w` to the meter.
+/// The elapsed time counts as observed once the heater state is known.
+///
+/// The meter accumulates the energy as it goes, so a later change of `power_w` does not
+/// rewrite it (the runtime-based totals are recomputed with the current power).
+fn settle(state: &mut HeaterState, power_w: f32) {
+    let now = Instant::now();
+    if let Some(on) = state.on
+        && let Some(settled_at) = state.settled_at
+    {
+        let elapsed = now.duration_since(settled_at).as_secs_f64();
+        let totals = &mut state.totals;
+        for observed_s in [&mut totals.day_observed_s, &mut totals.period_observed_s].into_iter().flatten() {
+            *observed_s += elapsed;
+        }
+        if on {
+            totals.on_time_s += elapsed;
+            totals.day_on_time_s += elapsed;
+            totals.period_on_time_s += elapsed;
+            *totals.meter_kwh.get_or_insert(0.0) += power_w as f64 * elapsed / 3600.0 / 1000.0;
+        }
+    }
+    state.settled_at = Some(now);
+}
+
+/// Resets the daily counters when the day changes, returning the counters of the
+/// completed day.
+fn roll_over(totals: &mut HeaterTotals, today: NaiveDate) -> Option<Completed> {
+    match totals.day {
+        Some(day) if day == today => None,
+        previous => {
+            let completed = previous.map(|start| Completed {
+                start,
+                on_time_s: totals.day_on_time_s,
+                cycles: totals.day_cycles,
+                observed_s: totals.day_observed_s,
+            });
+            totals.day = Some(today);
+            totals.day_on_time_s = 0.0;
+            totals.day_cycles = 0;
+            // The first day started before it was followed: its coverage is unknown
+            totals.day_observed_s = previous.map(|_| 0.0);
+            completed
+        }
+    }
+}
+
+/// Resets the period counters when the billing period starting on `start` begins,
+/// returning the counters of the completed period.
+fn roll_over_period(totals: &mut HeaterTotals, start: NaiveDate) -> Option<Completed> {
+    match totals.period {
+        Some(period) if period == start => None,
+        previous => {
+            let completed = previous.map(|period| Completed {
+                start: period,
+                on_time_s: totals.period_on_time_s,
+                cycles: totals.period_cycles,
+                observed_s: totals.period_observed_s,
+            });
+            totals.period = Some(start);
+            totals.period_on_time_s = 0.0;
+            totals.period_cycles = 0;
+            totals.period_observed_s = previous.map(|_| 0.0);
+            completed
+        }
+    }
+}
+
+/// Mention of the gaps of a partial day or period, for the log.
+fn gap_note(gaps: &Gaps) -> String {
+    match (gaps.partial, gaps.coverage) {
+        (true, Some(coverage)) if gaps.interpolated => format!(" (interpolated, {:.0} % observed)", coverage * 100.0),
+        (true, Some(coverage)) => format!(" (partial, {:.0} % observed)", coverage * 100.0),
+        _ => String::new(),
+    }
+}
+
+/// Parses a heater state payload.
+fn parse_state(payload: &[u8]) -> Option<bool> {
+    match String::from_utf8_lossy(payload).trim().to_ascii_lowercase().as_str() {
+        "on" | "1" | "true" => Some(true),
+        "off" | "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Follows the heater state topic.
+pub fn follow(config: &HeaterConfig, stats: &HeaterStats, subscriptions: &Subscriptions) {
+    let Some(topic) = config.state_topic.clone() else {
+        return;
+    };
+    let stats = stats.clone();
+    subscriptions.subscribe(topic, move |topic, payload| match parse_state(payload) {
+        Some(on) => stats.set_commanded(on),
+        None => warning!("Heater: ignoring state {:?} on {}", String::from_utf8_lossy(payload), topic),
+    });
+}
+
+/// Follows the auxiliary contact of the heater contactor on the configured GPIO line,
+/// or the command on the simulated board, and raises the `heater_contactor` alert
+/// while the contactor disagrees with the command. Returns after an error so the
+/// supervisor restarts it.
+pub async fn follow_feedback(stats: HeaterStats, config: HeaterConfig, board: Board, alerts: Alerts) {
+    let Some(line) = config.feedback_line else {
+        return;
+    };
+    let delay = Duration::from_secs_f32(config.feedback_delay_s.max(0.0));
+    let watcher = if board.is_simulated() {
+        None
+    } else {
+        let (stats, chip) = (stats.clone(), board.gpio_chip().to_path_buf());
+        Some(tokio::task::spawn_blocking(move || watch_contact(&stats, &chip, line, config.feedback_active_low)))
+    };
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        ticker.tick().await;
+        if watcher.as_ref().is_some_and(|w| w.is_finished()) {
+            break;
+        }
+        // The simulated contactor follows the command
+        let commanded = stats.state().commanded;
+        if watcher.is_none()
+            && let Some(commanded) = commanded
+        {
+            stats.set_on(commanded);
+        }
+        let text = match stats.contactor_mismatch(delay) {
+            Some((false, true)) => "Heater contactor closed while commanded off: welded contact or stuck relay",
+            Some((true, false)) => "Heater contactor open while commanded on: failed relay, coil or wiring",
+            _ => {
+                alerts.clear("heater_contactor");
+                continue;
+            }
+        };
+        alerts.raise("heater_contactor", Message::new(text).with("duration", format::duration(delay)));
+    }
+    if let Some(watcher) = watcher {
+        match watcher.await {
+            Ok(Err(e)) => warning!("Heater contactor feedback: {:#}", e),
+            Ok(Ok(())) => warning!("Heater contactor feedback: GPIO event stream ended"),
+            Err(e) => warning!("Heater contactor feedback: {}", e),
+        }
+    }
+    tokio::time::sleep(RETRY_DELAY).await;
+}
+
+/// Reads the state of the auxiliary contact at startup and on every edge.
+fn watch_contact(stats: &HeaterStats, chip: &std::path::Path, line: u32, active_low: bool) -> Result<()> {
+    let mut chip = Chip::new(chip).with_context(|| format!("Failed to open {}", chip.display()))?;
+    let flags = if active_low {
+        LineRequestFlags::INPUT | LineRequestFlags::ACTIVE_LOW
+    } else {
+        LineRequestFlags::INPUT
+    };
+    let mut events = chip
+        .get_line(line)
+        .and_then(|l| l.events(flags, EventRequestFlags::BOTH_EDGES, "boilert-heater"))
+        .with_context(|| format!("Failed to watch GPIO line {}", line))?;
+    stats.set_on(events.get_value()? == 1);
+    while let Some(event) = events.next() {
+        event.context("Failed to read the contactor feedback")?;
+        // Contacts bounce: read the level once it has settled
+        std::thread::sleep(CONTACT_DEBOUNCE);
+        stats.set_on(events.get_value()? == 1);
+    }
+    Ok(())
+}
+
+/// Resets the energy meter on `{base_topic}/heater/meter/reset` messages with the
+/// `reset` payload.
+pub fn follow_meter_reset(stats: &HeaterStats, subscriptions: &Subscriptions, base_topic: &str, audit: AuditLog) {
+    let stats = stats.clone();
+    subscriptions.subscribe(format!("{}/heater/meter/reset", base_topic), move |topic, payload| {
+        let command = String::from_utf8_lossy(payload);
+        if command.trim() != "reset" {
+            warning!("Heater: ignoring {:?} on {}", command, topic);
+            return;
+        }
+        let previous = stats.reset_meter();
+        info!("Heater energy meter reset (was {:.3} kWh)", previous);
+        audit.record(AuditSource::Mqtt, "energy meter reset", format::text(Unit::KilowattHour, previous as f32));
+    });
+}
+
+/// Forgets the heat-up baseline on `{base_topic}/heater/heatup/reset` messages with the
+/// `reset` payload.
+pub fn follow_heatup_reset(stats: &HeaterStats, subscriptions: &Subscriptions, base_topic: &str, audit: AuditLog) {
+    let stats = stats.clone();
+    subscriptions.subscribe(format!("{}/heater/heatup/reset", base_topic), move |topic, payload| {
+        let command = String::from_utf8_lossy(payload);
+        if command.trim() != "reset" {
+            warning!("Heater: ignoring {:?} on {}", command, topic);
+            return;
+        }
+        let cycles = stats.reset_heatup();
+        info!("Heater heat-up baseline reset (was {} cycles)", cycles);
+        audit.record(AuditSource::Mqtt, "heat-up baseline reset", format!("{} cycles", cycles));
+    });
+}
+
+/// Publishes the heater statistics every minute and the daily and billing period totals
+/// once they are over, and raises the relay service and heat-up alerts.
+pub async fn publish_loop(
+    stats: HeaterStats,
+    config: HeaterConfig,
+    billing: BillingConfig,
+    publisher: Publisher,
+    base_topic: String,
+    alerts: Alerts,
+) {
+    let mut interval = tokio::time::interval(PUBLISH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let today = billing.day(Local::now());
+        let (totals, completed, completed_period) = {
+            let mut state = stats.state();
+            settle(&mut state, stats.power_w);
+            let completed = roll_over(&mut state.totals, today);
+            let completed_period = roll_over_period(&mut state.totals, billing.period_start(today));
+            (state.totals.clone(), completed, completed_period)
+        };
+
+        if let Some(completed) = completed {
+            let (on_time_s, gaps) = completed.on_time_s(86400.0, config.gap_policy);
+            let date = completed.start;
+            let daily = DailyHeater {
+                date,
+                runtime_h: on_time_s / 3600.0,
+                cycles: completed.cycles,
+                energy_kwh: stats.energy_kwh(on_time_s),
+                duty_cycle: on_time_s / 86400.0,
+                cost: billing.cost(stats.energy_kwh(on_time_s)),
+                gaps,
+            };
+            info!(
+                "Heater on {}: {:.1} h, {} cycles, {:.1} kWh{}",
+                date,
+                daily.runtime_h,
+                daily.cycles,
+                daily.energy_kwh,
+                gap_note(&daily.gaps)
+            );
+            if let Ok(payload) = serde_json::to_vec(&daily) {
+                let topic = format!("{}/heater/daily", base_topic);
+                let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, payload).await;
+            }
+        }
+        if let Some(completed) = completed_period {
+            let start = completed.start;
+            let end = billing.period_start(today).pred_opt().unwrap_or(start);
+            let length_s = ((end - start).num_days() + 1) as f64 * 86400.0;
+            let (on_time_s, gaps) = completed.on_time_s(length_s, config.gap_policy);
+            let period = BillingPeriodHeater {
+                start,
+                end,
+                runtime_h: on_time_s / 3600.0,
+                cycles: completed.cycles,
+                energy_kwh: stats.energy_kwh(on_time_s),
+                cost: billing.cost(stats.energy_kwh(on_time_s)),
+                gaps,
+            };
+            info!(
+                "Heater from {} to {}: {:.1} h, {} cycles, {:.1} kWh{}",
+                start,
+                end,
+                period.runtime_h,
+                period.cycles,
+                period.energy_kwh,
+                gap_note(&period.gaps)
+            );
+            if let Ok(payload) = serde_json::to_vec(&period) {
+                let topic = format!("{}/heater/billing", base_topic);
+                let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, payload).await;
+            }
+        }
+
+        let mut values = vec![
+            ("runtime_h", format::data(Unit::Hour, (totals.on_time_s / 3600.0) as f32)),
+            ("cycles", totals.cycles.to_string()),
+            ("energy_kwh", format::data(Unit::KilowattHour, stats.energy_kwh(totals.on_time_s) as f32)),
+            ("today/runtime_h", format::data(Unit::Hour, (totals.day_on_time_s / 3600.0) as f32)),
+            ("today/cycles", totals.day_cycles.to_string()),
+            ("today/energy_kwh", format::data(Unit::KilowattHour, stats.energy_kwh(totals.day_on_time_s) as f32)),
+            ("period/runtime_h", format::data(Unit::Hour, (totals.period_on_time_s / 3600.0) as f32)),
+            ("period/cycles", totals.period_cycles.to_string()),
+            ("period/energy_kwh", format::data(Unit::KilowattHour, stats.energy_kwh(totals.period_on_time_s) as f32)),
+        ];
+        let costs = [("today/cost", totals.day_on_time_s), ("period/cost", totals.period_on_time_s)];
+        for (name, on_time_s) in costs {
+            if let Some(cost) = billing.cost(stats.energy_kwh(on_time_s)) {
+                values.push((name, format!("{:.2}", cost)));
+            }
+        }
+        for (name, value) in values {
+            let topic = format!("{}/heater/{}", base_topic, name);
+            let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, false, value).await;
+        }
+        // Retained, so a consumer connecting later never sees the meter go back to nothing
+        let topic = format!("{}/heater/meter_kwh", base_topic);
+        let meter = format::data(Unit::KilowattHour, totals.meter_kwh.unwrap_or_default() as f32);
+        let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, meter).await;
+        let contactor = stats.state().on;
+        if stats.has_feedback
+            && let Some(on) = contactor
+        {
+            let topic = format!("{}/heater/contactor", base_topic);
+            let state = if on { "on" } else { "off" };
+            let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, state).await;
+        }
+        if let Some(reset) = totals.meter_reset {
+            let topic = format!("{}/heater/meter_reset", base_topic);
+            let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, reset.to_rfc3339()).await;
+        }
+
+        if let Some(limit) = config.relay_cycle_limit {
+            let warning_at = (limit as f64 * config.relay_warning_ratio as f64) as u64;
+            if totals.cycles >= warning_at {
+                let text = format!("Heater relay at {} of {} rated cycles, plan its replacement", totals.cycles, limit);
+                let message = Message::new(text)
+                    .with("value", totals.cycles.to_string())
+                    .with("threshold", limit.to_string());
+                alerts.raise("heater_relay", message);
+            } else {
+                alerts.clear("heater_relay");
+            }
+        }
+
+        if config.heatup.enabled {
+            let baseline = &totals.heatup;
+            if let Ok(payload) = serde_json::to_vec(&baseline.report(&config.heatup)) {
+                let topic = format!("{}/heater/heatup", base_topic);
+                let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, payload).await;
+            }
+            match &baseline.last {
+                Some(last) if baseline.slow_cycles >= config.heatup.slow_cycles => {
+                    let text = format!(
+                        "Heater heat-up at {:.0}% of its baseline for {} cycles in a row: \
+                         check the element for scale or wear",
+                        last.ratio * 100.0,
+                        baseline.slow_cycles
+                    );
+                    let message = Message::new(text)
+                        .with("value", format::text(Unit::Percent, last.ratio * 100.0))
+                        .with("threshold", format::text(Unit::Percent, config.heatup.warning_ratio * 100.0));
+                    alerts.raise("heater_heatup", message);
+                }
+                _ => alerts.clear("heater_heatup"),
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Heat-up curve fingerprinting.
+//! While the heater is on, the rise of the average tank temperature since the switch-on
+//! is sampled at fixed offsets. This curve does not depend on the starting temperature,
+//! so the curves of all cycles can be compared with a baseline learned from the first
+//! ones. Scale on the element, or a failing element, heats the water more slowly: when
+//! several cycles in a row rise markedly slower than the baseline, a maintenance alert
+//! is raised.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Baseline rise under which a comparison is meaningless (cycle too short to heat), in K.
+const MIN_BASELINE_RISE_K: f64 = 0.1;
+
+/// Fingerprinting settings from the `[heater.heatup]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct HeatUpConfig {
+    pub enabled: bool,
+    /// Interval between two samples of the curve, in minutes.
+    pub step_min: f32,
+    /// Samples of a curve: it covers `samples * step_min` minutes after the switch-on.
+    pub samples: usize,
+    /// Samples a cycle must last to be taken into account (shorter cycles are ignored).
+    pub min_samples: usize,
+    /// Cycles averaged into the baseline.
+    pub baseline_cycles: u32,
+    /// Rise, as a fraction of the baseline, under which a cycle is slow.
+    pub warning_ratio: f32,
+    /// Consecutive slow cycles before the alert.
+    pub slow_cycles: u32,
+}
+
+impl Default for HeatUpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            step_min: 5.0,
+            samples: 6,
+            min_samples: 2,
+            baseline_cycles: 5,
+            warning_ratio: 0.8,
+            slow_cycles: 3,
+        }
+    }
+}
+
+impl HeatUpConfig {
+    /// Checks the ranges of the settings.
+    pub fn validate(&self) -> Result<()> {
+        if !self.step_min.is_finite() || self.step_min <= 0.0 {
+            bail!("[heater.heatup] step_min must be positive");
+        }
+        if self.min_samples == 0 || self.min_samples > self.samples {
+            bail!("[heater.heatup] min_samples must be between 1 and samples");
+        }
+        if self.baseline_cycles == 0 || self.slow_cycles == 0 {
+            bail!("[heater.heatup] baseline_cycles and slow_cycles must be at least 1");
+        }
+        if !(self.warning_ratio > 0.0 && self.warning_ratio < 1.0) {
+            bail!("[heater.heatup] warning_ratio must be between 0 and 1");
+        }
+        Ok(())
+    }
+
+    fn step(&self) -> Duration {
+        Duration::from_secs_f32(self.step_min * 60.0)
+    }
+}
+
+/// Curve of the cycle in progress.
+#[derive(Debug, Default)]
+pub struct Curve {
+    /// Time and tank temperature of the first reading after the switch-on.
+    start: Option<(Instant, f32)>,
+    /// Rise since the start at each elapsed step, in K.
+    rises: Vec<f32>,
+}
+
+impl Curve {
+    /// Records a reading of the average tank temperature.
+    pub fn record(&mut self, temp_c: f32, config: &HeatUpConfig) {
+        let (started, start_c) = *self.start.get_or_insert((Instant::now(), temp_c));
+        let elapsed = started.elapsed();
+        // A reading missed over a whole step repeats the next one, keeping the offsets aligned
+        while self.rises.len() < config.samples && elapsed >= config.step() * (self.rises.len() as u32 + 1) {
+            self.rises.push(temp_c - start_c);
+        }
+    }
+}
+
+/// Comparison of a cycle with the baseline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comparison {
+    /// End of the cycle.
+    pub time: DateTime<Utc>,
+    /// Minutes of the curve compared.
+    pub minutes: f32,
+    /// Rise of the cycle at the end of the compared part, in K.
+    pub rise_k: f32,
+    /// Rise of the baseline at the same offset, in K.
+    pub baseline_k: f32,
+    /// Rise of the cycle relative to the baseline, over the compared part.
+    pub ratio: f32,
+}
+
+/// What became of a completed cycle.
+#[derive(Debug)]
+pub enum Outcome {
+    /// Too short, or without tank readings.
+    Ignored,
+    /// Added to the baseline, which now holds this many cycles.
+    Learned(u32),
+    Compared(Comparison),
+}
+
+/// State of the fingerprinting, published on `{base_topic}/heater/heatup`.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// `false` while the baseline is being learned.
+    pub learned: bool,
+    /// Cycles in the baseline.
+    pub cycles: u32,
+    /// Mean rise of the baseline at each step, in K.
+    pub baseline_k: Vec<f32>,
+    pub step_min: f32,
+    /// Slow cycles in a row.
+    pub slow_cycles: u32,
+    pub last: Option<Comparison>,
+}
+
+/// Learned baseline, persisted with the heater totals.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Baseline {
+    /// Sum of the rises of the learned cycles at each step, in K.
+    pub rise_sums_k: Vec<f64>,
+    /// Learned cycles that reached each step.
+    pub counts: Vec<u32>,
+    /// Cycles learned.
+    pub cycles: u32,
+    /// Slow cycles in a row, up to the last one.
+    pub slow_cycles: u32,
+    /// Last cycle compared with the baseline.
+    pub last: Option<Comparison>,
+}
+
+impl Baseline {
+    /// `true` once enough cycles were learned to compare the next ones.
+    pub fn is_learned(&self, config: &HeatUpConfig) -> bool {
+        self.cycles >= config.baseline_cycles
+    }
+
+    /// Mean rise of the learned cycles at the step `i`, in K.
+    fn rise_k(&self, i: usize) -> Option<f64> {
+        let count = *self.counts.get(i).filter(|&&count| count > 0)?;
+        Some(self.rise_sums_k[i] / count as f64)
+    }
+
+    /// Current state, for publication.
+    pub fn report(&self, config: &HeatUpConfig) -> Report {
+        Report {
+            learned: self.is_learned(config),
+            cycles: self.cycles,
+            baseline_k: (0..self.counts.len()).map_while(|i| self.rise_k(i)).map(|rise| rise as f32).collect(),
+            step_min: config.step_min,
+            slow_cycles: self.slow_cycles,
+            last: self.last.clone(),
+        }
+    }
+
+    /// Learns a completed cycle, or compares it once the baseline is complete.
+    pub fn finish(&mut self, curve: Curve, config: &HeatUpConfig) -> Outcome {
+        let rises = curve.rises;
+        if rises.len() < config.min_samples {
+            return Outcome::Ignored;
+        }
+        if !self.is_learned(config) {
+            if self.rise_sums_k.len() < rises.len() {
+                self.rise_sums_k.resize(rises.len(), 0.0);
+                self.counts.resize(rises.len(), 0);
+            }
+            for (i, &rise) in rises.iter().enumerate() {
+                self.rise_sums_k[i] += rise as f64;
+                self.counts[i] += 1;
+            }
+            self.cycles += 1;
+            return Outcome::Learned(self.cycles);
+        }
+
+        // Compare over the steps both curves reached
+        let common: Vec<(f64, f64)> =
+            rises.iter().enumerate().map_while(|(i, &rise)| Some((rise as f64, self.rise_k(i)?))).collect();
+        let baseline_sum: f64 = common.iter().map(|(_, baseline)| baseline).sum();
+        let Some(&(rise_k, baseline_k)) = common.last() else {
+            return Outcome::Ignored;
+        };
+        if common.len() < config.min_samples || baseline_sum < MIN_BASELINE_RISE_K {
+            return Outcome::Ignored;
+        }
+        let ratio = (common.iter().map(|(rise, _)| rise).sum::<f64>() / baseline_sum) as f32;
+        if ratio < config.warning_ratio {
+            self.slow_cycles += 1;
+        } else {
+            self.slow_cycles = 0;
+        }
+        let comparison = Comparison {
+            time: Utc::now(),
+            minutes: common.len() as f32 * config.step_min,
+            rise_k: rise_k as f32,
+            baseline_k: baseline_k as f32,
+            ratio,
+        };
+        self.last = Some(comparison.clone());
+        Outcome::Compared(comparison)
+    }
+}
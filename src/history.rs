@@ -0,0 +1,173 @@
+//! Temperature history management.
+//! Stores time-stamped points at a wall-clock aligned cadence for the charts. Extra
+//! points are recorded during fast transients (heating, draw-off) and flat idle runs
+//! are merged, so the same storage covers the day with more detail where it matters.
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::chart::ChartPoint;
+
+/// Number of points kept per sensor: 24 hours * 4 points/hour with the default
+/// 15-minute period (see `[timing] history_period_min`).
+pub const HISTORY_POINTS: usize = 96;
+
+/// A single recorded temperature value.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HistoryPoint {
+    /// Wall-clock time of the sample.
+    pub timestamp: DateTime<Utc>,
+    /// Temperature in Celsius.
+    pub value: f32,
+    /// `true` if the point follows an outage (e.g., a power loss): the chart is
+    /// not drawn from the previous point.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub gap: bool,
+}
+
+/// Adaptive point density of a history.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HistoryDensity {
+    /// Rate of change, in °C per minute, above which a transient point is recorded
+    /// between two periods (0: disabled).
+    pub transient_rate_c_per_min: f32,
+    /// Change, in °C, below which a point on a flat run is merged with the next
+    /// one (0: disabled).
+    pub idle_delta_c: f32,
+}
+
+/// Minimum spacing of transient points, as a fraction of the history period.
+const TRANSIENT_SPACING_DIVISOR: i32 = 5;
+
+/// Buffer to store historical temperature data for a single sensor.
+pub struct SensorHistory {
+    /// Ring buffer of the last `HISTORY_POINTS` points, oldest first.
+    points: VecDeque<HistoryPoint>,
+    /// Time between two points. Points are aligned on multiples of it (e.g., :00/:15/:30/:45).
+    period: Duration,
+    /// Marks the next point as following an outage.
+    gap_pending: bool,
+    density: HistoryDensity,
+}
+
+impl SensorHistory {
+    /// Creates a history pre-filled with `initial_val`, with timestamps going
+    /// back from the last boundary before `now`.
+    pub fn new(initial_val: f32, now: DateTime<Utc>, period: Duration) -> Self {
+        let last = crate::clock::previous_boundary(now, period);
+        let points = (0..HISTORY_POINTS)
+            .rev()
+            .map(|i| HistoryPoint {
+                timestamp: last - period * i as i32,
+                value: initial_val,
+                gap: false,
+            })
+            .collect();
+        Self {
+            points,
+            period,
+            gap_pending: false,
+            density: HistoryDensity::default(),
+        }
+    }
+
+    /// Creates a history from previously saved points, keeping the most recent
+    /// `HISTORY_POINTS`.
+    pub fn from_points(points: &[HistoryPoint], period: Duration) -> Self {
+        let skip = points.len().saturating_sub(HISTORY_POINTS);
+        Self {
+            points: points[skip..].iter().copied().collect(),
+            period,
+            gap_pending: false,
+            density: HistoryDensity::default(),
+        }
+    }
+
+    /// Enables transient points and idle merging (both disabled by default).
+    pub fn with_density(mut self, density: HistoryDensity) -> Self {
+        self.density = density;
+        self
+    }
+
+    /// Returns the points, oldest first.
+    pub fn points(&self) -> &VecDeque<HistoryPoint> {
+        &self.points
+    }
+
+    /// Appends the point of a period boundary.
+    ///
+    /// When the previous point sits in the middle of a flat run (it differs from both
+    /// its neighbours by less than `idle_delta_c`), it is dropped: the chart line is
+    /// unchanged and the room is left for transient points.
+    pub fn add_point(&mut self, timestamp: DateTime<Utc>, value: f32) {
+        let idle = self.density.idle_delta_c;
+        if idle > 0.0 && !self.gap_pending && self.points.len() >= 2 {
+            let last = self.points[self.points.len() - 1];
+            let before = self.points[self.points.len() - 2];
+            if !last.gap && (last.value - before.value).abs() < idle && (value - last.value).abs() < idle {
+                self.points.pop_back();
+            }
+        }
+        self.push(timestamp, value);
+    }
+
+    /// Appends an extra point between two period boundaries if the temperature changes
+    /// faster than `transient_rate_c_per_min` since the last point.
+    ///
+    /// Returns `true` if the point was recorded.
+    pub fn add_transient_point(&mut self, timestamp: DateTime<Utc>, value: f32) -> bool {
+        let rate = self.density.transient_rate_c_per_min;
+        let Some(last) = self.points.back() else {
+            return false;
+        };
+        let elapsed = timestamp - last.timestamp;
+        if rate <= 0.0 || self.gap_pending || elapsed < self.period / TRANSIENT_SPACING_DIVISOR {
+            return false;
+        }
+        let minutes = elapsed.num_seconds() as f32 / 60.0;
+        if (value - last.value).abs() / minutes < rate {
+            return false;
+        }
+        self.push(timestamp, value);
+        true
+    }
+
+    /// Appends a point, dropping the points that fell out of the chart window (except
+    /// one, where the line starts) and the oldest one when the buffer is full.
+    fn push(&mut self, timestamp: DateTime<Utc>, value: f32) {
+        let window_start = timestamp - self.period * (HISTORY_POINTS - 1) as i32;
+        while self.points.len() >= 2 && self.points[1].timestamp <= window_start {
+            self.points.pop_front();
+        }
+        if self.points.len() >= HISTORY_POINTS {
+            self.points.pop_front();
+        }
+        let gap = std::mem::take(&mut self.gap_pending);
+        self.points.push_back(HistoryPoint { timestamp, value, gap });
+    }
+
+    /// Records that data is missing since the last point (e.g., after a power loss),
+    /// so the next point starts a new segment of the chart.
+    pub fn mark_gap(&mut self) {
+        self.gap_pending = true;
+    }
+
+    /// Returns the points in chart coordinates.
+    ///
+    /// X ranges from 0 to 95 (`HISTORY_POINTS - 1`) and is derived from the point
+    /// timestamps, the newest point being at 95.
+    pub fn chart_points(&self) -> impl Iterator<Item = ChartPoint> + '_ {
+        let newest = self.points.back().map(|p| p.timestamp);
+        let period_s = self.period.num_seconds().max(1) as f32;
+        self.points.iter().map(move |point| {
+            let age = newest.map_or(0.0, |n| (n - point.timestamp).num_seconds() as f32 / period_s);
+            ChartPoint {
+                x: ((HISTORY_POINTS - 1) as f32 - age).max(0.0),
+                value: point.value,
+                gap: point.gap,
+            }
+        })
+    }
+}
@@ -0,0 +1,125 @@
+//! 1-Wire hot-plug.
+//! The bus is rescanned periodically: a configured sensor that vanishes from it is
+//! reported as disconnected, and its readings resume as soon as it reappears, without
+//! a restart. Each transition is logged and published on
+//! `{base_topic}/{sensor}/availability` (`online` or `offline`, retained), under the `topic`
+//! of the sensor when it has one.
+
+use std::collections::HashSet;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::board::Board;
+use crate::config::SensorConfig;
+use crate::logging::{info, warning};
+use crate::mqtt::Publisher;
+use crate::sensors::{self, SensorBackend};
+
+/// Shared presence of the 1-Wire sensors on the bus.
+///
+/// Cloning a `Presence` handle is cheap; all clones share the same state.
+#[derive(Clone, Default)]
+pub struct Presence {
+    /// IDs of the configured sensors missing from the bus at the last scan.
+    missing: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Presence {
+    /// Returns `true` if the sensor `id` was missing from the bus at the last scan.
+    pub fn is_missing(&self, id: &str) -> bool {
+        self.missing.lock().unwrap_or_else(|e| e.into_inner()).contains(id)
+    }
+}
+
+/// Asks every 1-Wire bus master that has stopped searching for a new search.
+///
+/// The kernel only searches the bus `search_count` times (a parameter of the `wire`
+/// module, unlimited by default); afterwards, sensors plugged in never show up. The
+/// search runs on the kernel's own cadence, so new devices appear on a later scan.
+fn request_search(board: &Board) -> Result<()> {
+    for entry in fs::read_dir(board.w1_dir())?.filter_map(|entry| entry.ok()) {
+        if !entry.file_name().to_string_lossy().starts_with("w1_bus_master") {
+            continue;
+        }
+        let search = entry.path().join("w1_master_search");
+        // -1 searches forever, a positive count is still in progress
+        if fs::read_to_string(&search).is_ok_and(|count| count.trim() == "0") {
+            fs::write(&search, "1")?;
+        }
+    }
+    Ok(())
+}
+
+/// Rescans the 1-Wire bus every `interval` and tracks the configured 1-Wire sensors.
+///
+/// The first scan publishes the availability of every sensor; later ones only the changes.
+pub async fn rescan_loop(
+    presence: Presence,
+    board: Board,
+    sensors: Vec<SensorConfig>,
+    interval: Duration,
+    publisher: Publisher,
+    base_topic: String,
+) {
+    let sensors: Vec<SensorConfig> =
+        sensors.into_iter().filter(|s| sensors::backend(s, &board) == SensorBackend::W1).collect();
+    let mut ticker = tokio::time::interval(interval);
+    // Availability of each sensor as last published (`None` before the first scan)
+    let mut online: Vec<Option<bool>> = vec![None; sensors.len()];
+    let mut search_failed = false;
+    loop {
+        ticker.tick().await;
+        let scan_board = board.clone();
+        let scan = tokio::task::spawn_blocking(move || {
+            let search = request_search(&scan_board);
+            (search, sensors::discover_w1(&scan_board))
+        })
+        .await;
+        let Ok((search, detected)) = scan else {
+            continue;
+        };
+        if let Err(e) = search
+            && !search_failed
+        {
+            warning!("Failed to request a 1-Wire bus search: {:#}", e);
+            search_failed = true;
+        }
+        let detected = match detected {
+            Ok(detected) => detected,
+            Err(e) => {
+                warning!("1-Wire rescan failed: {:#}", e);
+                continue;
+            }
+        };
+
+        for (sensor, online) in sensors.iter().zip(online.iter_mut()) {
+            let present = detected.contains(&sensor.id);
+            if *online == Some(present) {
+                continue;
+            }
+            if !present {
+                info!("Sensor {} ({}) disconnected from the 1-Wire bus", sensor.name, sensor.id);
+            } else if *online == Some(false) {
+                info!("Sensor {} ({}) reconnected", sensor.name, sensor.id);
+                // The sensor came back with the resolution stored in its EEPROM
+                let (sensor, board) = (sensor.clone(), board.clone());
+                let _ = tokio::task::spawn_blocking(move || sensors::apply_resolution(&sensor, &board)).await;
+            }
+            *online = Some(present);
+            {
+                let mut missing = presence.missing.lock().unwrap_or_else(|e| e.into_inner());
+                if present {
+                    missing.remove(&sensor.id);
+                } else {
+                    missing.insert(sensor.id.clone());
+                }
+            }
+            let topic = format!("{}/availability", sensor.state_topic(&base_topic));
+            let payload = if present { "online" } else { "offline" };
+            let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, payload).await;
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Minimal embedded HTTP/1.1 server.
+//! Serves the small set of endpoints exposed by boilert (e.g., `/healthz`)
+//! without pulling a full web framework onto the device.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::logging::warning;
+
+/// Time a client has to send the request line and the headers.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest request line and headers accepted, in bytes.
+const MAX_HEAD_BYTES: u64 = 8192;
+
+/// A parsed HTTP request.
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string.
+    pub path: String,
+    /// Query string, without the leading `?`.
+    pub query: String,
+    /// Value of the `Authorization` header, if any.
+    pub authorization: Option<String>,
+}
+
+/// An HTTP response to send back.
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into().into_bytes(),
+        }
+    }
+
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Self {
+                status,
+                content_type: "application/json",
+                body,
+            },
+            Err(e) => Self::text(500, format!("serialization error: {}", e)),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self::text(404, "not found")
+    }
+}
+
+impl Request {
+    /// Value of the query parameter `name`, if present (no percent-decoding).
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Request handler shared by all connections.
+pub type Handler = Arc<dyn Fn(Request) -> Response + Send + Sync>;
+
+/// Accepts connections on `listen` and answers each request with `handler`.
+///
+/// # Errors
+/// Returns an error if the address cannot be bound.
+pub async fn serve(listen: String, handler: Handler) -> Result<()> {
+    let listener = TcpListener::bind(&listen)
+        .await
+        .with_context(|| format!("Failed to bind HTTP server on {}", listen))?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, handler).await {
+                warning!("HTTP connection error: {:#}", e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(stream: TcpStream, handler: Handler) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    // A slow or endless client must not hold the task or grow the buffers
+    let response = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut reader)).await {
+        Ok(Ok(Some(request))) => handler(request),
+        Ok(Ok(None)) => Response::text(431, "request line or headers too large\n"),
+        Ok(Err(_)) => Response::text(400, "malformed request\n"),
+        Err(_) => Response::text(408, "request timeout\n"),
+    };
+    write_response(&mut writer, &response).await
+}
+
+/// Reads the request line and the headers, `None` if they exceed [`MAX_HEAD_BYTES`].
+async fn read_request(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<Option<Request>> {
+    let mut reader = reader.take(MAX_HEAD_BYTES);
+    let mut request_line = String::new();
+    if !read_head_line(&mut reader, &mut request_line).await? {
+        return Ok(None);
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    // Only the Authorization header is used, the others are skipped
+    let mut authorization = None;
+    loop {
+        let mut line = String::new();
+        if !read_head_line(&mut reader, &mut line).await? {
+            return Ok(None);
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("authorization")
+        {
+            authorization = Some(value.trim().to_string());
+        }
+    }
+
+    Ok(Some(Request {
+        method,
+        path: path.to_string(),
+        query: query.to_string(),
+        authorization,
+    }))
+}
+
+/// Reads one line of the request head into `line`. Returns `false` if the line was cut
+/// by the size limit; a connection closed early ends the head.
+async fn read_head_line(reader: &mut tokio::io::Take<impl AsyncBufRead + Unpin>, line: &mut String) -> Result<bool> {
+    let read = reader.read_line(line).await?;
+    Ok(read == 0 || line.ends_with('\n') || reader.limit() > 0)
+}
+
+async fn write_response(writer: &mut OwnedWriteHalf, response: &Response) -> Result<()> {
+    let challenge = if response.status == 401 {
+        "WWW-Authenticate: Bearer\r\n"
+    } else {
+        ""
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.content_type,
+        response.body.len(),
+        challenge
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(&response.body).await?;
+    writer.shutdown().await?;
+    Ok(())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
@@ -0,0 +1,176 @@
+//! Protection against two boilert instances driving the same outputs and topics.
+//! A lock file keeps a second instance from starting on the same device, and a look at
+//! the availability topic of the broker catches an instance running elsewhere with the
+//! same `base_topic`. `--takeover` stops the local instance holding the lock and starts
+//! regardless of the broker.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use rumqttc::{AsyncClient, Event, Packet, QoS};
+use serde::Deserialize;
+
+use crate::config::MqttConfig;
+use crate::logging::{info, warning};
+use crate::mqtt;
+
+/// Time given to the instance holding the lock to exit on a takeover.
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Settings from the `[instance_lock]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct InstanceLockConfig {
+    /// Lock file held while boilert runs.
+    pub path: String,
+    /// Refuse to start while another instance is online on the base topic.
+    pub check_broker: bool,
+    /// Time to wait for the broker, in seconds; an unreachable broker does not prevent the start.
+    pub broker_timeout_s: u64,
+}
+
+impl Default for InstanceLockConfig {
+    fn default() -> Self {
+        Self {
+            path: "boilert.lock".to_string(),
+            check_broker: true,
+            broker_timeout_s: 5,
+        }
+    }
+}
+
+/// Lock of the running instance, released when dropped (or by the kernel when the
+/// process dies).
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Takes the lock file at `path`, stopping the instance holding it first with `takeover`.
+    ///
+    /// # Errors
+    /// Returns an error if another instance holds the lock, or still holds it after a takeover.
+    pub fn acquire(path: &Path, takeover: bool) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open the lock file {}", path.display()))?;
+        if !try_lock(&file) {
+            let pid = holder(&mut file);
+            let holder = pid.map_or("another boilert instance".to_string(), |pid| format!("boilert (PID {})", pid));
+            if !takeover {
+                bail!(
+                    "{} already runs with the lock file {}: stop it first, or start with --takeover to replace it",
+                    holder,
+                    path.display()
+                );
+            }
+            let Some(pid) = pid else {
+                bail!("Cannot take over from {}: its PID is unknown", holder);
+            };
+            info!("Taking over from {}", holder);
+            // SAFETY: `kill` has no memory safety requirements.
+            unsafe { libc::kill(pid, libc::SIGTERM) };
+            let start = Instant::now();
+            while !try_lock(&file) {
+                if start.elapsed() > TAKEOVER_TIMEOUT {
+                    bail!("{} did not exit within {} s", holder, TAKEOVER_TIMEOUT.as_secs());
+                }
+                std::thread::sleep(Duration::from_millis(200));
+            }
+        }
+        // Leave our PID for the next takeover
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(Self { _file: file })
+    }
+}
+
+/// Takes an exclusive lock on `file` without waiting.
+fn try_lock(file: &File) -> bool {
+    // SAFETY: the descriptor is valid for the lifetime of `file`.
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) == 0 }
+}
+
+/// PID written to the lock file by the instance holding it.
+fn holder(file: &mut File) -> Option<libc::pid_t> {
+    let mut content = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok().filter(|&pid| pid > 0)
+}
+
+/// Checks that no other instance is online on the availability topic of `mqtt`.
+///
+/// A previous run of this device that was killed can leave `online` behind until the
+/// broker publishes its last will, so a retained `online` gets the keep-alive period to
+/// turn `offline` before it counts as another instance.
+///
+/// # Errors
+/// Returns an error if another instance is online, unless `takeover` is set.
+pub async fn check_broker(config: &InstanceLockConfig, mqtt: &MqttConfig, takeover: bool) -> Result<()> {
+    let topic = mqtt::status_topic(mqtt);
+    let timeout = Duration::from_secs(config.broker_timeout_s);
+    let grace = Duration::from_secs(mqtt.keep_alive_s) * 3 / 2 + Duration::from_secs(2);
+    let online = match online_elsewhere(mqtt, &topic, timeout, grace).await {
+        Ok(online) => online,
+        Err(e) => {
+            warning!("Instance check: broker not reachable ({:#}), starting anyway", e);
+            return Ok(());
+        }
+    };
+    match (online, takeover) {
+        (false, _) => Ok(()),
+        (true, true) => {
+            warning!("Another instance is online on {}, taking over", topic);
+            Ok(())
+        }
+        (true, false) => bail!(
+            "Another boilert instance is online on {}: give this device its own [mqtt] base_topic, \
+             or start with --takeover once the other one is stopped",
+            topic
+        ),
+    }
+}
+
+/// Subscribes to `topic` and reports whether it still reads `online` once `grace` elapsed.
+/// A broker silent for `timeout` counts as no other instance.
+async fn online_elsewhere(mqtt: &MqttConfig, topic: &str, timeout: Duration, grace: Duration) -> Result<bool> {
+    let client_id = format!("{}-check", mqtt::client_id(mqtt));
+    let mut options = mqtt::options(mqtt, &client_id)?;
+    options.set_clean_session(true);
+    let (client, mut eventloop) = AsyncClient::new(options, 10);
+    client.subscribe(topic, QoS::AtLeastOnce).await?;
+
+    let mut online = false;
+    let mut deadline = Instant::now() + timeout;
+    loop {
+        let event = match tokio::time::timeout_at(deadline.into(), eventloop.poll()).await {
+            Ok(event) => event,
+            // Nothing (more) retained on the topic
+            Err(_) => return Ok(online),
+        };
+        match event? {
+            Event::Incoming(Packet::SubAck(_)) => {
+                // A retained value comes right after the acknowledgement
+                deadline = Instant::now() + Duration::from_secs(1);
+            }
+            Event::Incoming(Packet::Publish(publish)) if publish.topic == topic => {
+                online = publish.payload[..] == *b"online";
+                if !online {
+                    return Ok(false);
+                }
+                deadline = Instant::now() + grace;
+            }
+            _ => {}
+        }
+    }
+}
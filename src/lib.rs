@@ -0,0 +1,6 @@
+//! Library part of boilert.
+//!
+//! Exposes the data model shared by the application, its HTTP API, MQTT JSON
+//! payloads and alternative frontends.
+
+pub mod model;
@@ -0,0 +1,426 @@
+//! Runtime configuration over MQTT and from the edits of `config.toml`.
+//! A JSON merge patch published on `{base_topic}/config/set` changes a few settings that
+//! are safe to change while running (thresholds, cold water temperature, publication
+//! cadence), for a device with no keyboard attached. The patch is checked like the whole
+//! configuration, written to `config.toml` keeping its comments and layout, applied to
+//! the running tasks, and answered on `{base_topic}/config`.
+//! An edit of the file itself is applied the same way, along with the boiler parameters
+//! and the plausible ranges of the sensors; the other changes, sensor names included, are
+//! logged as waiting for a restart, without dropping the MQTT session or the display.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result, bail};
+use boilert::model::AuditSource;
+use chrono::Utc;
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio::sync::watch;
+
+use crate::audit::{self, AuditLog};
+use crate::commands::{self, Controls};
+use crate::config::{self, Config};
+use crate::logging::{info, warning};
+use crate::mqtt::{Publisher, Subscriptions};
+use crate::state;
+
+/// Settings that can be changed over MQTT, as `section.key`.
+const SAFE_SETTINGS: &[&str] = &[
+    "boiler.reference_temp_c",
+    "timing.publish_interval_s",
+    "timing.publish_deadband_c",
+    "timing.max_silence_s",
+    "mixing_valve.setpoint_c",
+    "mixing_valve.tolerance_c",
+    "mixing_valve.deviation_min",
+    "mixing_valve.scald_above_c",
+    "pressure.alarm_below_bar",
+];
+/// Sections whose presence enables a feature: their settings are only changed when
+/// they are already configured.
+const OPTIONAL_SECTIONS: &[&str] = &["mixing_valve", "pressure"];
+/// Settings also applied when the file is edited, besides the safe settings. `*` stands
+/// for the index of any sensor; a section covers every setting inside it.
+const RELOADABLE_SETTINGS: &[&str] = &[
+    "boiler",
+    "mixing_valve.sensor",
+    "sensors.*.in_tank",
+    "sensors.*.min_valid",
+    "sensors.*.max_valid",
+    "sensors.*.out_of_range",
+];
+/// Time left to an editor to finish saving the file before it is read: a save raises
+/// several events.
+const RELOAD_SETTLE: Duration = Duration::from_millis(500);
+
+/// Configuration shared with the tasks that follow its changes.
+///
+/// Cloning a `LiveConfig` handle is cheap; all clones share the same configuration.
+#[derive(Clone)]
+pub struct LiveConfig {
+    path: PathBuf,
+    current: watch::Sender<Config>,
+    loaded: Arc<Mutex<Loaded>>,
+}
+
+/// Configuration file as last read or written.
+#[derive(Default)]
+struct Loaded {
+    modified: Option<SystemTime>,
+    /// Its settings, with the environment overrides.
+    settings: toml::Table,
+}
+
+impl Loaded {
+    fn read(path: &Path) -> Self {
+        Self {
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+            settings: std::fs::read_to_string(path)
+                .ok()
+                .and_then(|content| config::with_env_overrides(&content).ok())
+                .and_then(|content| content.parse().ok())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Changes found in the configuration file, as `section.key`.
+#[derive(Debug, Default)]
+pub struct Reload {
+    /// Settings applied to the running tasks.
+    pub applied: Vec<String>,
+    /// Settings used once boilert is restarted.
+    pub restart: Vec<String>,
+}
+
+impl LiveConfig {
+    pub fn new(path: impl Into<PathBuf>, config: &Config) -> Self {
+        let path = path.into();
+        Self {
+            loaded: Arc::new(Mutex::new(Loaded::read(&path))),
+            path,
+            current: watch::channel(config.clone()).0,
+        }
+    }
+
+    /// Follows the changes of the configuration.
+    pub fn subscribe(&self) -> watch::Receiver<Config> {
+        self.current.subscribe()
+    }
+
+    /// Applies the JSON merge `patch` to the safe settings and saves `config.toml`.
+    /// Returns the settings changed, as `section.key = value`.
+    ///
+    /// # Errors
+    /// Returns an error if the patch is not a JSON object of safe settings, if the
+    /// resulting configuration is invalid, or if it cannot be saved; nothing is changed then.
+    pub fn apply(&self, patch: &[u8]) -> Result<Vec<String>> {
+        let patch: Value = serde_json::from_slice(patch).context("the patch is not valid JSON")?;
+        let mut changes = Vec::new();
+        flatten(&patch, "", &mut changes)?;
+        if changes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let content = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+        let mut doc: toml_edit::DocumentMut =
+            content.parse().with_context(|| format!("Failed to parse {}", self.path.display()))?;
+        let overrides = config::env_overrides();
+        for (path, value) in &changes {
+            if !SAFE_SETTINGS.contains(&path.as_str()) {
+                bail!("{} cannot be changed remotely", path);
+            }
+            // The saved value would not be used
+            if let Some(o) = overrides.iter().find(|o| &o.key == path) {
+                bail!("{} is set by the environment variable {}", path, o.variable);
+            }
+            let Some((section, key)) = path.split_once('.') else {
+                continue;
+            };
+            if OPTIONAL_SECTIONS.contains(&section) && doc.get(section).is_none() {
+                bail!("{}: [{}] is not configured", path, section);
+            }
+            let table = doc
+                .entry(section)
+                .or_insert_with(toml_edit::table)
+                .as_table_like_mut()
+                .with_context(|| format!("[{}] is not a table", section))?;
+            // Integer settings refuse a float, float settings accept an integer
+            let value = match (value.as_i64(), value.as_f64()) {
+                (Some(integer), _) => toml_edit::value(integer),
+                (None, Some(float)) => toml_edit::value(float),
+                _ => bail!("{} must be a number", path),
+            };
+            table.insert(key, value);
+        }
+        let content = doc.to_string();
+        let config = Config::parse(&config::with_env_overrides(&content)?)?;
+        commands::check_reference_temp(config.boiler.reference_temp_c)?;
+        state::write_atomic(&self.path, content.as_bytes())?;
+        // Not an edit to reload
+        *self.loaded.lock().unwrap_or_else(|e| e.into_inner()) = Loaded::read(&self.path);
+
+        self.current.send_modify(|current| {
+            current.boiler.reference_temp_c = config.boiler.reference_temp_c;
+            current.timing.publish_interval_s = config.timing.publish_interval_s;
+            current.timing.publish_deadband_c = config.timing.publish_deadband_c;
+            current.timing.max_silence_s = config.timing.max_silence_s;
+            current.mixing_valve = config.mixing_valve.clone();
+            current.pressure = config.pressure.clone();
+        });
+        Ok(changes.iter().map(|(path, value)| format!("{} = {}", path, value)).collect())
+    }
+
+    /// Reads the file again if it was modified since it was last read or written, and
+    /// applies the settings that can change while running. Returns `None` if the file was
+    /// not modified.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or if the configuration is invalid;
+    /// nothing is changed then, and the same modification is not reported again.
+    pub fn reload(&self) -> Result<Option<Reload>> {
+        let modified = std::fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if modified.is_none() || modified == loaded.modified {
+            return Ok(None);
+        }
+        loaded.modified = modified;
+        let content = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+        let content = config::with_env_overrides(&content)?;
+        let config = Config::parse(&content)?;
+        commands::check_reference_temp(config.boiler.reference_temp_c)?;
+        let settings: toml::Table = content.parse()?;
+
+        let mut changed = Vec::new();
+        let old = toml::Value::Table(std::mem::replace(&mut loaded.settings, settings.clone()));
+        changed_keys("", Some(&old), Some(&toml::Value::Table(settings)), &mut changed);
+        let (applied, restart): (Vec<String>, Vec<String>) = changed.into_iter().partition(|key| is_reloadable(key));
+        // Sensors added or removed: the positions in the list no longer match
+        let same_sensors = !restart.iter().any(|key| key == "sensors");
+        self.current.send_modify(|current| {
+            current.boiler = config.boiler.clone();
+            current.timing.publish_interval_s = config.timing.publish_interval_s;
+            current.timing.publish_deadband_c = config.timing.publish_deadband_c;
+            current.timing.max_silence_s = config.timing.max_silence_s;
+            // Enabling or disabling a feature needs a restart
+            if let (Some(current), Some(mixing)) = (current.mixing_valve.as_mut(), &config.mixing_valve) {
+                current.sensor = mixing.sensor.clone();
+                current.setpoint_c = mixing.setpoint_c;
+                current.tolerance_c = mixing.tolerance_c;
+                current.deviation_min = mixing.deviation_min;
+                current.scald_above_c = mixing.scald_above_c;
+            }
+            if let (Some(current), Some(pressure)) = (current.pressure.as_mut(), &config.pressure) {
+                current.alarm_below_bar = pressure.alarm_below_bar;
+            }
+            if same_sensors {
+                for (current, sensor) in current.sensors.iter_mut().zip(&config.sensors) {
+                    current.in_tank = sensor.in_tank;
+                    current.min_valid = sensor.min_valid;
+                    current.max_valid = sensor.max_valid;
+                    current.out_of_range = sensor.out_of_range;
+                }
+            }
+        });
+        Ok(Some(Reload { applied, restart }))
+    }
+
+    /// Current values of the safe settings (`null` when their section is not configured).
+    fn settings(&self) -> Map<String, Value> {
+        let config = self.current.borrow();
+        SAFE_SETTINGS
+            .iter()
+            .map(|&path| (path.to_string(), setting(&config, path).map_or(Value::Null, Value::from)))
+            .collect()
+    }
+}
+
+/// Collects the keys whose value differs between `old` and `new`, as `section.key`; the
+/// tables of lists of the same length are compared one by one (`sensors.0.name`).
+fn changed_keys(key: &str, old: Option<&toml::Value>, new: Option<&toml::Value>, keys: &mut Vec<String>) {
+    let child = |name: &str| if key.is_empty() { name.to_string() } else { format!("{}.{}", key, name) };
+    match (old, new) {
+        (Some(toml::Value::Table(old)), Some(toml::Value::Table(new))) => {
+            for name in old.keys().chain(new.keys()).collect::<BTreeSet<_>>() {
+                changed_keys(&child(name), old.get(name), new.get(name), keys);
+            }
+        }
+        (Some(toml::Value::Array(old)), Some(toml::Value::Array(new)))
+            if old.len() == new.len() && old.iter().chain(new).all(toml::Value::is_table) =>
+        {
+            for (i, (old, new)) in old.iter().zip(new).enumerate() {
+                changed_keys(&child(&i.to_string()), Some(old), Some(new), keys);
+            }
+        }
+        (old, new) if old != new => keys.push(key.to_string()),
+        _ => {}
+    }
+}
+
+/// `true` if the setting `key` is applied when the file is edited.
+fn is_reloadable(key: &str) -> bool {
+    let pattern: Vec<&str> = key.split('.').map(|s| if s.parse::<usize>().is_ok() { "*" } else { s }).collect();
+    let pattern = pattern.join(".");
+    SAFE_SETTINGS.iter().chain(RELOADABLE_SETTINGS).any(|setting| {
+        pattern == *setting || pattern.strip_prefix(setting).is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// Value of the safe setting `path` in `config`.
+fn setting(config: &Config, path: &str) -> Option<f64> {
+    let mixing = config.mixing_valve.as_ref();
+    let value = match path {
+        "boiler.reference_temp_c" => config.boiler.reference_temp_c,
+        "timing.publish_interval_s" => config.timing.publish_interval_s,
+        "timing.publish_deadband_c" => config.timing.publish_deadband_c,
+        "timing.max_silence_s" => config.timing.max_silence_s,
+        "mixing_valve.setpoint_c" => mixing?.setpoint_c,
+        "mixing_valve.tolerance_c" => mixing?.tolerance_c,
+        "mixing_valve.deviation_min" => mixing?.deviation_min,
+        "mixing_valve.scald_above_c" => mixing?.scald_above_c,
+        "pressure.alarm_below_bar" => config.pressure.as_ref()?.alarm_below_bar?,
+        _ => return None,
+    };
+    Some(f64::from(value))
+}
+
+/// Collects the leaves of the JSON object `value` as `section.key` paths.
+fn flatten(value: &Value, prefix: &str, leaves: &mut Vec<(String, Value)>) -> Result<()> {
+    let Value::Object(object) = value else {
+        bail!("the patch must be a JSON object, e.g. {{\"timing\": {{\"publish_interval_s\": 60}}}}");
+    };
+    for (key, value) in object {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            Value::Object(_) => flatten(value, &path, leaves)?,
+            _ => leaves.push((path, value.clone())),
+        }
+    }
+    Ok(())
+}
+
+/// Answer to a patch, published on `{base_topic}/config`.
+#[derive(Debug, Serialize)]
+struct Confirmation<'a> {
+    ok: bool,
+    /// Settings changed, as `section.key = value`.
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    changed: &'a [String],
+    /// Why the patch was refused.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Current values of the safe settings.
+    settings: Map<String, Value>,
+    timestamp: chrono::DateTime<Utc>,
+}
+
+/// Applies the patches received on `{base_topic}/config/set` and confirms them on
+/// `{base_topic}/config`. An empty patch (`{}`) only reports the current values.
+pub fn follow(
+    live_config: &LiveConfig,
+    controls: &Controls,
+    subscriptions: &Subscriptions,
+    publisher: Publisher,
+    base_topic: &str,
+    audit: AuditLog,
+) {
+    let live_config = live_config.clone();
+    let controls = controls.clone();
+    let confirm_topic = format!("{}/config", base_topic);
+    subscriptions.subscribe(format!("{}/config/set", base_topic), move |topic, payload| {
+        let result = live_config.apply(payload);
+        let changed = result.as_deref().unwrap_or_default();
+        match &result {
+            Ok(changed) if changed.is_empty() => {}
+            Ok(changed) => {
+                info!("Configuration changed remotely: {}", changed.join(", "));
+                audit.record(AuditSource::Mqtt, "configuration changed", changed.join(", "));
+                if changed.iter().any(|change| change.starts_with("boiler.reference_temp_c ")) {
+                    let reference_temp_c = live_config.current.borrow().boiler.reference_temp_c;
+                    let _ = controls.set_reference_temp(reference_temp_c);
+                }
+            }
+            Err(e) => warning!("Ignoring {} patch: {:#}", topic, e),
+        }
+        let confirmation = Confirmation {
+            ok: result.is_ok(),
+            changed,
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            settings: live_config.settings(),
+            timestamp: Utc::now(),
+        };
+        if let Ok(document) = serde_json::to_vec(&confirmation) {
+            let _ = publisher.try_publish(confirm_topic.clone(), rumqttc::QoS::AtLeastOnce, false, document);
+        }
+    });
+}
+
+/// Task applying the edits of `config.toml`.
+#[derive(Clone)]
+pub struct ConfigWatcher {
+    pub live_config: LiveConfig,
+    pub controls: Controls,
+    pub audit: AuditLog,
+}
+
+impl ConfigWatcher {
+    /// Watches the file, applies what can change while running and logs the changes
+    /// waiting for a restart.
+    pub async fn run(self) {
+        let path = self.live_config.path.display().to_string();
+        let (tx, mut edits) = tokio::sync::mpsc::unbounded_channel();
+        let file_name = self.live_config.path.file_name().map(|name| name.to_owned());
+        let mut watcher = match notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event
+                && !event.kind.is_access()
+                && event.paths.iter().any(|p| p.file_name() == file_name.as_deref())
+            {
+                let _ = tx.send(());
+            }
+        }) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                warning!("Cannot watch {}: {:#}", path, e);
+                return;
+            }
+        };
+        // The directory rather than the file: editors such as vim save by renaming a new
+        // file over the old one, which a watch of the file itself loses
+        let dir = self.live_config.path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        if let Err(e) = watcher.watch(dir.unwrap_or(Path::new(".")), RecursiveMode::NonRecursive) {
+            warning!("Cannot watch {}: {:#}", path, e);
+            return;
+        }
+        while edits.recv().await.is_some() {
+            tokio::time::sleep(RELOAD_SETTLE).await;
+            while edits.try_recv().is_ok() {}
+            let reload = match self.live_config.reload() {
+                Ok(Some(reload)) => reload,
+                Ok(None) => continue,
+                Err(e) => {
+                    warning!("Ignoring the edit of {}: {:#}", path, e);
+                    continue;
+                }
+            };
+            if let Ok(fingerprint) = audit::config_fingerprint(&self.live_config.path) {
+                self.audit.record_config(&fingerprint);
+            }
+            if !reload.applied.is_empty() {
+                info!("Configuration reloaded from {}: {}", path, reload.applied.join(", "));
+                if reload.applied.iter().any(|key| key == "boiler.reference_temp_c") {
+                    let reference_temp_c = self.live_config.current.borrow().boiler.reference_temp_c;
+                    let _ = self.controls.set_reference_temp(reference_temp_c);
+                }
+            }
+            if !reload.restart.is_empty() {
+                warning!("{} changed: restart boilert to apply {}", path, reload.restart.join(", "));
+            }
+        }
+    }
+}
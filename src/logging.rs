@@ -0,0 +1,265 @@
+//! Runtime log level and diagnostics switches.
+//! Informational messages go through [`info!`] and detailed traces through [`debug!`],
+//! which only print when the current level allows it; errors and warnings ([`warning!`])
+//! are always written. The level starts from `[debug] log_level` and, like the raw
+//! capture of failed reads, can be changed at runtime over MQTT or HTTP to diagnose a
+//! device in the field without restarting it. The last lines written are kept in memory
+//! for the log viewer of the display.
+
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+use anyhow::{Result, bail};
+use boilert::model::AuditSource;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::audit::AuditLog;
+use crate::mqtt::Subscriptions;
+use crate::sensors::RawCapture;
+
+/// Verbosity of the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// Errors and warnings only.
+    Warn,
+    /// Also the events of normal operation.
+    #[default]
+    Info,
+    /// Also a trace of every reading and MQTT message received.
+    Debug,
+}
+
+impl LogLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            other => bail!("unknown log level {:?} (expected warn, info or debug)", other),
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Current log level.
+pub fn level() -> LogLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Warn,
+        1 => LogLevel::Info,
+        _ => LogLevel::Debug,
+    }
+}
+
+/// Sets the log level of the process.
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns `true` if messages of `level` are printed.
+pub fn enabled(level: LogLevel) -> bool {
+    level <= self::level()
+}
+
+/// Number of lines kept for the log viewer.
+const RECENT_LINES: usize = 500;
+
+/// A line written to the log.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub timestamp: DateTime<Utc>,
+    pub level: LogLevel,
+    /// Module that wrote it, e.g. `mqtt` or `sensors::http`.
+    pub module: &'static str,
+    pub text: String,
+}
+
+/// Last lines written, oldest first.
+static RECENT: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+/// Number of lines written since startup.
+static WRITTEN: AtomicU64 = AtomicU64::new(0);
+
+/// Writes a message to stdout (stderr for `warn`) if the current level allows it, and
+/// keeps it for the log viewer. Called by the logging macros.
+pub fn write(level: LogLevel, module_path: &'static str, args: fmt::Arguments<'_>) {
+    if !enabled(level) {
+        return;
+    }
+    let text = args.to_string();
+    match level {
+        LogLevel::Warn => eprintln!("{}", text),
+        _ => println!("{}", text),
+    }
+    // Without the crate name; `main` for the root module
+    let module = module_path.split_once("::").map_or("main", |(_, module)| module);
+    let mut recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    if recent.len() == RECENT_LINES {
+        recent.pop_front();
+    }
+    recent.push_back(LogLine {
+        timestamp: Utc::now(),
+        level,
+        module,
+        text,
+    });
+    WRITTEN.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Number of lines written since startup, which changes with every new line.
+pub fn written() -> u64 {
+    WRITTEN.load(Ordering::Relaxed)
+}
+
+/// Lines shown by the log viewer.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LogFilter {
+    /// Most verbose level shown.
+    pub level: LogLevel,
+    /// Module shown, with its submodules (all if `None`).
+    pub module: Option<String>,
+    /// Text the lines must contain, ignoring case (all if empty).
+    pub search: String,
+}
+
+impl LogFilter {
+    fn matches(&self, line: &LogLine) -> bool {
+        line.level <= self.level
+            && self.module.as_deref().is_none_or(|module| {
+                line.module == module || line.module.strip_prefix(module).is_some_and(|rest| rest.starts_with("::"))
+            })
+            && (self.search.is_empty() || line.text.to_lowercase().contains(&self.search.to_lowercase()))
+    }
+}
+
+/// Kept lines matching `filter`, oldest first.
+pub fn recent(filter: &LogFilter) -> Vec<LogLine> {
+    let recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    recent.iter().filter(|line| filter.matches(line)).cloned().collect()
+}
+
+/// Modules of the kept lines, in alphabetical order.
+pub fn modules() -> Vec<&'static str> {
+    let recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    recent.iter().map(|line| line.module).collect::<BTreeSet<_>>().into_iter().collect()
+}
+
+/// Writes an error or a warning, whatever the level.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::LogLevel::Warn, module_path!(), format_args!($($arg)*))
+    };
+}
+
+/// Writes a message of normal operation, unless the level is `warn`.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::LogLevel::Info, module_path!(), format_args!($($arg)*))
+    };
+}
+
+/// Writes a detailed trace, at the `debug` level only.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::LogLevel::Debug, module_path!(), format_args!($($arg)*))
+    };
+}
+
+pub(crate) use {debug, info, warning};
+
+/// Current diagnostics settings, returned by `GET /api/debug`.
+#[derive(Debug, Serialize)]
+pub struct DebugStatus {
+    pub log_level: LogLevel,
+    pub raw_capture: bool,
+}
+
+/// Handle changing the diagnostics settings at runtime, recording each change in the
+/// audit log.
+#[derive(Clone)]
+pub struct Diagnostics {
+    capture: RawCapture,
+    audit: AuditLog,
+}
+
+impl Diagnostics {
+    pub fn new(capture: RawCapture, audit: AuditLog) -> Self {
+        Self { capture, audit }
+    }
+
+    /// Current log level and raw capture state.
+    pub fn status(&self) -> DebugStatus {
+        DebugStatus {
+            log_level: level(),
+            raw_capture: self.capture.is_enabled(),
+        }
+    }
+
+    /// Changes the log level, recording the change.
+    pub fn set_level(&self, new: LogLevel, source: AuditSource) {
+        if level() != new {
+            set_level(new);
+            self.audit.record(source, "log level changed", new.as_str());
+        }
+    }
+
+    /// Switches the raw capture on or off, recording the change.
+    pub fn set_raw_capture(&self, enabled: bool, source: AuditSource) {
+        if self.capture.is_enabled() != enabled {
+            self.capture.set_enabled(enabled);
+            let detail = if enabled { "on" } else { "off" };
+            self.audit.record(source, "raw capture switched", detail);
+        }
+    }
+}
+
+/// Parses an `on`/`off` switch payload.
+pub fn parse_switch(payload: &str) -> Option<bool> {
+    match payload.trim().to_ascii_lowercase().as_str() {
+        "on" | "1" | "true" => Some(true),
+        "off" | "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Changes the log level on `{base_topic}/debug/log_level` messages and switches the
+/// raw capture on `{base_topic}/debug/raw_capture` messages.
+pub fn follow(diagnostics: &Diagnostics, subscriptions: &Subscriptions, base_topic: &str) {
+    let handle = diagnostics.clone();
+    subscriptions.subscribe(format!("{}/debug/log_level", base_topic), move |topic, payload| {
+        match String::from_utf8_lossy(payload).parse() {
+            Ok(level) => handle.set_level(level, AuditSource::Mqtt),
+            Err(e) => warning!("Ignoring {} command: {:#}", topic, e),
+        }
+    });
+    let handle = diagnostics.clone();
+    subscriptions.subscribe(format!("{}/debug/raw_capture", base_topic), move |topic, payload| {
+        let command = String::from_utf8_lossy(payload);
+        match parse_switch(&command) {
+            Some(enabled) => handle.set_raw_capture(enabled, AuditSource::Mqtt),
+            None => warning!("Ignoring {:?} on {}", command, topic),
+        }
+    });
+}
@@ -1,183 +1,1130 @@
 //! Main entry point for the boilert application.
 //! Orchestrates sensor reading, MQTT publishing, and Slint UI updates.
 
+mod acquisition;
+mod alerts;
+mod audit;
+mod auth;
+mod billing;
+mod board;
+mod burst;
+mod chart;
+mod chart_export;
+mod cli;
+mod clock;
+mod commands;
 mod config;
+mod delta;
+mod discovery;
+mod display_lock;
+mod draws;
+mod efficiency;
+mod filter;
+mod flow;
+mod forecast;
+mod format;
+mod group;
+mod health;
+mod heater;
+mod heatup;
+mod history;
+mod hotplug;
+mod http;
+mod instance_lock;
+mod live_config;
+mod logging;
+mod maintenance;
+mod messages;
+mod metrics;
+mod mixing;
+mod mqtt;
+mod onboarding;
+mod outputs;
+mod preferences;
+mod pressure;
+mod remote;
+mod scenario;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod season;
+mod selftest;
 mod sensors;
+mod simulation;
+mod state;
+mod stats;
+mod supervisor;
+mod systemd;
+mod tank_sim;
+mod ui;
+#[cfg(feature = "update")]
+mod update;
+mod usb_export;
+mod water;
+mod wizard;
 
+use boilert::model::AuditSource;
+use chrono::Utc;
+use clap::Parser;
+use history::SensorHistory;
+use logging::{info, warning};
 use std::error::Error;
+use std::sync::Arc;
 use slint::ComponentHandle;
 use std::time::Duration;
-use tokio::time;
+use tokio::sync::Mutex;
 
 slint::include_modules!();
 
-// --- History Management ---
-// We store 24 hours of data with 15-minute resolution.
-const HISTORY_POINTS: usize = 96; // 24 hours * 4 points/hour
+/// How long the `power_loss` alert stays active after startup.
+const POWER_LOSS_ALERT_DURATION: Duration = Duration::from_secs(3600);
 
-/// Buffer to store historical temperature data for a single sensor.
-struct SensorHistory {
-    /// Circular-like buffer of temperature values.
-    points: Vec<f32>,
-    /// Last time a point was added to the history.
-    last_update: std::time::Instant,
-}
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = cli::Cli::parse();
+
+    // A freshly updated binary that keeps failing is replaced by the previous one;
+    // exiting lets systemd start it again. Counted before anything can fail, the
+    // configuration included, so that every failed start uses a boot attempt.
+    #[cfg(feature = "update")]
+    if cli.command.is_none() && !cli.list_sensors && update::check_pending_on_boot() {
+        return Err("Rolled back to the previous version".into());
+    }
+
+    // Without a configuration, guide the installer through the setup on the display
+    if cli.command.is_none() && !cli.list_sensors && !cli.headless && !cli.config.exists() {
+        onboarding::run(&cli.config)?;
+    }
+
+    // Load configuration from config.toml, or the file given with --config
+    let mut config = config::Config::load(&cli.config)?;
+    format::init(&config.format);
+    messages::init(&config.messages);
+    let log_level = match (cli.log_level, &cli.command) {
+        (Some(level), _) => level,
+        // Only the values on stdout, for scripts
+        (None, Some(cli::Command::Read { json: true })) => logging::LogLevel::Warn,
+        (None, _) => config.debug.log_level,
+    };
+    logging::set_level(log_level);
+    config.runtime.headless |= cli.headless;
+    for o in &config.env_overrides {
+        info!("Configuration: {} set by {}", o.key, o.variable);
+    }
 
-impl SensorHistory {
-    fn new(initial_val: f32) -> Self {
-        Self {
-            points: vec![initial_val; HISTORY_POINTS],
-            last_update: std::time::Instant::now(),
+    // Add the 1-Wire sensors missing from the configuration
+    if config.auto_discover {
+        let board = board::Board::from_config(&config.hardware);
+        if !board.is_simulated()
+            && let Err(e) = sensors::auto_discover(&mut config.sensors, &board)
+        {
+            warning!("1-Wire auto-discovery failed: {:#}", e);
         }
     }
 
-    fn add_point(&mut self, val: f32) {
-        self.points.remove(0);
-        self.points.push(val);
-        self.last_update = std::time::Instant::now();
+    let command = cli.command.or(cli.list_sensors.then_some(cli::Command::Sensors));
+    if let Some(command) = command {
+        return Ok(cli::run(command, &config, &cli.config)?);
     }
 
-    /// Maps the temperature data points to an SVG path string for Slint's Path element.
-    /// 
-    /// The X axis ranges from 0 to 95 (HISTORY_POINTS - 1).
-    /// The Y axis ranges from 0 (mapped to 100°C) to 100 (mapped to 0°C).
-    fn to_svg_path(&self) -> String {
-        let mut path = String::new();
-        for (i, &temp) in self.points.iter().enumerate() {
-            let x = i as f32;
-            // Map 100°C to 0 (top of the graph) and 0°C to 100 (bottom of the graph).
-            let y = (100.0 - temp).clamp(0.0, 100.0);
-            if i == 0 {
-                path.push_str(&format!("M {} {} ", x, y));
-            } else {
-                path.push_str(&format!("L {} {} ", x, y));
+    let runtime = build_runtime(config.runtime.profile)?;
+
+    // Only one instance drives the outputs and publishes under the base topic
+    let _instance_lock =
+        instance_lock::InstanceLock::acquire(std::path::Path::new(&config.instance_lock.path), cli.takeover)?;
+    if config.instance_lock.check_broker {
+        runtime.block_on(instance_lock::check_broker(&config.instance_lock, &config.mqtt, cli.takeover))?;
+    }
+
+    if config.runtime.headless {
+        return runtime.block_on(async {
+            let app = start(&config, &cli.config, None)?;
+            tokio::select! {
+                result = wait_for_shutdown() => result?,
+                _ = app.controls.wait_for_restart() => {}
             }
-        }
-        path
+            app.shutdown().await;
+            app.exit()
+        });
     }
-}
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
     // Initialize the Slint window
     let ui = AppWindow::new()?;
-    let ui_weak = ui.as_weak();
 
-    // Load configuration from config.toml
-    let config = config::Config::load()?;
-    
     // Set application version from Cargo.toml
     ui.set_app_version(env!("CARGO_PKG_VERSION").into());
-    
-    // MQTT Setup
-    let mut mqttoptions = rumqttc::MqttOptions::new("boilert", &config.mqtt.host, config.mqtt.port);
-    mqttoptions.set_keep_alive(Duration::from_secs(5));
 
-    let (client, mut eventloop) = rumqttc::AsyncClient::new(mqttoptions, 10);
-    
-    tokio::spawn(async move {
-        loop {
-            if let Err(e) = eventloop.poll().await {
-                eprintln!("MQTT connection error: {}", e);
-                tokio::time::sleep(Duration::from_secs(5)).await;
+    let app = {
+        let _guard = runtime.enter();
+        start(&config, &cli.config, Some(ui.as_weak()))?
+    };
+
+    // The Slint event loop owns the main thread, so the tokio runtime is driven
+    // from a dedicated thread. This also works for the current-thread runtime.
+    let handle = runtime.handle().clone();
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let driver = std::thread::spawn(move || {
+        runtime.block_on(async {
+            let _ = stop_rx.await;
+        })
+    });
+
+    // Start the Slint UI main loop
+    ui.run()?;
+
+    handle.block_on(app.shutdown());
+    let _ = stop_tx.send(());
+    let _ = driver.join();
+
+    app.exit()
+}
+
+/// Builds the tokio runtime matching the configured profile.
+fn build_runtime(profile: config::RuntimeProfile) -> std::io::Result<tokio::runtime::Runtime> {
+    let mut builder = match profile {
+        config::RuntimeProfile::Standard => tokio::runtime::Builder::new_multi_thread(),
+        config::RuntimeProfile::Lightweight => tokio::runtime::Builder::new_current_thread(),
+    };
+    builder.enable_all().build()
+}
+
+/// Waits for Ctrl-C or, on Unix, SIGTERM (sent by systemd on stop).
+async fn wait_for_shutdown() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = sigterm.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
+
+/// State kept by `main` for a clean exit.
+struct App {
+    state_path: std::path::PathBuf,
+    state_sources: state::StateSources,
+    clock_status: clock::ClockStatus,
+    availability: mqtt::Availability,
+    /// Availability on the other brokers.
+    broker_availability: Vec<mqtt::Availability>,
+    outputs: outputs::Outputs,
+    controls: commands::Controls,
+}
+
+impl App {
+    /// Saves the runtime state one last time, sets the outputs to their safe state and
+    /// announces that boilert goes offline, on a clean exit.
+    async fn shutdown(&self) {
+        if self.clock_status.is_sane() {
+            let mut final_state = self.state_sources.capture().await;
+            final_state.running = false;
+            if let Err(e) = state::save(&self.state_path, &final_state) {
+                warning!("Failed to save runtime state: {:#}", e);
             }
         }
-    });
+        self.outputs.to_safe_states();
+        self.availability.set_offline().await;
+        for availability in &self.broker_availability {
+            availability.set_offline().await;
+        }
+    }
 
-    // Initial UI setup
-    let mut initial_sensors = Vec::new();
-    for sensor in &config.sensors {
-        initial_sensors.push(SensorData {
-            name: sensor.name.clone().into(),
-            value: 0.0,
-            history_path: "".into(),
-        });
+    /// Result of `main` once shut down: a requested restart exits with an error, so that
+    /// systemd starts boilert again.
+    fn exit(&self) -> Result<(), Box<dyn Error>> {
+        if self.controls.restart_requested() {
+            return Err("Restart requested".into());
+        }
+        Ok(())
     }
-    ui.set_sensors(slint::ModelRc::from(initial_sensors.as_slice()));
+}
 
-    // Initialize history with current sensor values (read once)
-    let mut history: Vec<SensorHistory> = Vec::new();
+/// Spawns all background tasks. Must be called within the tokio runtime context.
+///
+/// `config_path` is the file `config` was loaded from, where the changes are saved.
+/// `ui_weak` is `None` in headless mode. Fails if the MQTT TLS settings cannot be loaded.
+fn start(
+    config: &config::Config,
+    config_path: &std::path::Path,
+    ui_weak: Option<slint::Weak<AppWindow>>,
+) -> anyhow::Result<App> {
+    let faults = simulation::Faults::from_config(&config.simulation);
+    let mut board = board::Board::from_config(&config.hardware)
+        .with_faults(faults.clone())
+        .with_capture(sensors::RawCapture::from_config(&config.debug));
+    if let Some(path) = config.simulation.scenario.as_deref()
+        && board.is_simulated()
+    {
+        match scenario::Scenario::load(std::path::Path::new(path), config.simulation.speed) {
+            Ok(scenario) => {
+                info!("Simulation: replaying {} at {}x", path, config.simulation.speed);
+                board = board.with_scenario(scenario);
+            }
+            Err(e) => warning!("Simulation: ignoring the scenario: {:#}", e),
+        }
+    }
+    if let Some(tank) = &config.simulation.tank {
+        if board.is_simulated() {
+            info!("Simulation: {} l tank model at {}x", tank.volume_l, config.simulation.speed);
+            board = board.with_tank(tank_sim::TankSim::new(tank, config.simulation.speed));
+        } else {
+            warning!("Simulation: the tank model only runs on the simulated board");
+        }
+    }
+    info!("Hardware: {}", board);
     for sensor in &config.sensors {
-        let val = sensors::read_temperature(&sensor.id).unwrap_or(20.0);
-        history.push(SensorHistory::new(val));
-    }
-
-    // Spawn the main sensor reading and UI update loop
-    let sensor_config = config.clone();
-    tokio::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(2));
-        let mut last_history_update = std::time::Instant::now();
-        let history_update_interval = Duration::from_secs(15 * 60); // 15 minutes
-
-        loop {
-            interval.tick().await;
-            
-            let mut temps = Vec::new();
-            for sensor in &sensor_config.sensors {
-                let temp = match sensors::read_temperature(&sensor.id) {
-                    Ok(temp) => temp,
-                    Err(e) => {
-                        eprintln!("Error reading sensor {}: {}", sensor.name, e);
-                        0.0
-                    }
-                };
-                temps.push(temp);
+        sensors::apply_resolution(sensor, &board);
+    }
+
+    // MQTT Setup, with the birth message published on every connection
+    let config_fingerprint = audit::config_fingerprint(config_path);
+    let info = mqtt::DeviceInfo::new(config_fingerprint.as_ref().ok().cloned(), &config.sensors);
+    let client_id = mqtt::client_id(&config.mqtt);
+    info!("MQTT client ID {}", client_id);
+    let (client, eventloop) =
+        mqtt::connect(&config.mqtt, &client_id, config.runtime.profile.mqtt_channel_capacity())?;
+    // Other brokers, each over a connection of its own
+    let mut brokers = Vec::new();
+    for broker in &config.mqtt.brokers {
+        let broker_config = broker.mqtt_config(&config.mqtt);
+        let (client, eventloop) = mqtt::connect(
+            &broker_config,
+            &mqtt::client_id(&broker_config),
+            config.runtime.profile.mqtt_channel_capacity(),
+        )
+        .map_err(|e| e.context(format!("[[mqtt.brokers]] {}", broker.name)))?;
+        info!("MQTT: also publishing to broker {} ({}:{})", broker.name, broker.host, broker.port);
+        let publisher = mqtt::Publisher::new(client.clone(), &broker_config).with_sensor_ids(&config.sensors);
+        let availability = mqtt::Availability::new(client, &broker_config).with_info(&info);
+        brokers.push((broker.name.clone(), Arc::new(Mutex::new(eventloop)), availability, publisher));
+    }
+    let publisher = mqtt::Publisher::new(client.clone(), &config.mqtt)
+        .with_sensor_ids(&config.sensors)
+        .with_mirrors(brokers.iter().map(|(_, _, _, publisher)| publisher.clone()).collect());
+    let alerts = alerts::Alerts::new(publisher.clone(), &config.mqtt.base_topic);
+    let subscriptions = mqtt::Subscriptions::new(client.clone());
+    let availability = mqtt::Availability::new(client.clone(), &config.mqtt).with_info(&info);
+    let failover = match &config.mqtt.failover {
+        Some(failover) => {
+            info!("MQTT: failover to {} after {} s without the main broker", failover.host, failover.after_s);
+            Some(mqtt::Failover::new(&config.mqtt, failover, &client_id, client.clone())?)
+        }
+        None => None,
+    };
+
+    // Sensors published by other devices
+    sensors::follow_topics(&config.sensors, &subscriptions);
 
-                let topic = format!("{}/{}", sensor_config.mqtt.base_topic, sensor.name);
-                let payload = temp.to_string();
-                let _ = client.publish(topic, rumqttc::QoS::AtLeastOnce, false, payload).await;
+    // Record the configuration edits made since the previous run
+    let audit = audit::AuditLog::open(&config.audit);
+    match config_fingerprint {
+        Ok(fingerprint) => audit.record_config(&fingerprint),
+        Err(e) => warning!("Failed to fingerprint the configuration: {:#}", e),
+    }
+
+    // On the simulated board, faults can be injected at runtime over MQTT
+    if board.is_simulated() {
+        let prefix = format!("{}/simulation/fault/", config.mqtt.base_topic);
+        let audit = audit.clone();
+        subscriptions.subscribe(format!("{}+", prefix), move |topic, payload| {
+            if let Some(sensor) = topic.strip_prefix(&prefix) {
+                match faults.handle_message(sensor, payload) {
+                    Ok(()) => audit.record(
+                        AuditSource::Mqtt,
+                        "simulation fault",
+                        format!("{} = {}", sensor, String::from_utf8_lossy(payload).trim()),
+                    ),
+                    Err(e) => warning!("Simulation: ignoring fault command for {}: {:#}", sensor, e),
+                }
             }
+        });
+    }
 
-            // Update history every 15 minutes
-            let now = std::time::Instant::now();
-            let update_history = now.duration_since(last_history_update) >= history_update_interval;
-            if update_history {
-                for (i, &temp) in temps.iter().enumerate() {
-                    if i < history.len() {
-                        history[i].add_point(temp);
-                    }
+    // The simulated heating element and draws can be driven over MQTT, for external control loops
+    if let Some(tank) = board.tank() {
+        let base_topic = &config.mqtt.base_topic;
+        for command in tank_sim::COMMANDS {
+            let (tank, audit) = (tank.clone(), audit.clone());
+            subscriptions.subscribe(format!("{}/simulation/{}", base_topic, command), move |topic, payload| {
+                let text = String::from_utf8_lossy(payload);
+                match tank.handle_message(command, payload) {
+                    Ok(()) => audit.record(AuditSource::Mqtt, "simulation", format!("{} = {}", command, text.trim())),
+                    Err(e) => warning!("Simulation: ignoring {:?} on {}: {:#}", text, topic, e),
                 }
-                last_history_update = now;
-            }
-
-            // Calculate the total thermal energy stored in the boiler (kWh).
-            // Formula: E = (m * cp * delta_T) / 3600
-            // Here: volume * energy_coefficient * (avg_temp - reference_temp) / 1000
-            let avg_temp: f32 = if temps.is_empty() { 0.0 } else { temps.iter().sum::<f32>() / temps.len() as f32 };
-            let delta_t = (avg_temp - sensor_config.boiler.reference_temp_c).max(0.0);
-            let energy_kwh = (sensor_config.boiler.volume_l * delta_t * sensor_config.boiler.energy_coefficient) / 1000.0;
-
-            // Publish the total energy to a dedicated MQTT topic
-            let energy_topic = format!("{}/energy", sensor_config.mqtt.base_topic);
-            let _ = client.publish(energy_topic, rumqttc::QoS::AtLeastOnce, false, energy_kwh.to_string()).await;
-
-            // Batch UI updates and send them to the main Slint thread.
-            // We recreate the sensors model with the latest data and history paths.
-            let _ = slint::invoke_from_event_loop({
-                let ui_weak = ui_weak.clone();
-                let temps = temps.clone();
-                let history_paths: Vec<String> = history.iter().map(|h| h.to_svg_path()).collect();
-                let sensor_names: Vec<String> = sensor_config.sensors.iter().map(|s| s.name.clone()).collect();
-                move || {
-                    if let Some(ui) = ui_weak.upgrade() {
-                        let mut sensor_data = Vec::new();
-                        for i in 0..temps.len() {
-                            sensor_data.push(SensorData {
-                                name: sensor_names[i].clone().into(),
-                                value: temps[i],
-                                history_path: history_paths[i].clone().into(),
-                            });
-                        }
-                        ui.set_sensors(slint::ModelRc::from(sensor_data.as_slice()));
-                        ui.set_energy_kwh(energy_kwh);
-                    }
+            });
+        }
+        supervisor::spawn_supervised("tank_sim", alerts.clone(), {
+            let tank = tank.clone();
+            let publisher = publisher.clone();
+            let base_topic = base_topic.clone();
+            move || tank_sim::publish_loop(tank.clone(), publisher.clone(), base_topic.clone())
+        });
+    }
+
+    // Restore the runtime state saved by the previous run
+    let state_path = std::path::PathBuf::from(&config.state.path);
+    let saved_state = match state::load(&state_path) {
+        Ok(saved) => saved.unwrap_or_default(),
+        Err(e) => {
+            warning!("Ignoring runtime state: {:#}", e);
+            state::RuntimeState::default()
+        }
+    };
+    alerts.restore_history(&saved_state.alert_history);
+    for alert in &saved_state.alerts {
+        alerts.raise(&alert.id, alert.message.clone());
+    }
+
+    // Heating element runtime statistics, from the state reported on MQTT or read from
+    // the contactor
+    let heater = heater::HeaterStats::new(&config.heater, saved_state.heater.clone());
+    if config.heater.feedback_line.is_some() {
+        supervisor::spawn_supervised("heater_feedback", alerts.clone(), {
+            let heater = heater.clone();
+            let heater_config = config.heater.clone();
+            let board = board.clone();
+            let alerts = alerts.clone();
+            move || heater::follow_feedback(heater.clone(), heater_config.clone(), board.clone(), alerts.clone())
+        });
+    }
+    if config.heater.is_enabled() {
+        heater::follow(&config.heater, &heater, &subscriptions);
+        heater::follow_meter_reset(&heater, &subscriptions, &config.mqtt.base_topic, audit.clone());
+        if config.heater.heatup.enabled {
+            heater::follow_heatup_reset(&heater, &subscriptions, &config.mqtt.base_topic, audit.clone());
+        }
+        supervisor::spawn_supervised("heater", alerts.clone(), {
+            let heater = heater.clone();
+            let heater_config = config.heater.clone();
+            let billing = config.billing.clone();
+            let publisher = publisher.clone();
+            let base_topic = config.mqtt.base_topic.clone();
+            let alerts = alerts.clone();
+            move || {
+                heater::publish_loop(
+                    heater.clone(),
+                    heater_config.clone(),
+                    billing.clone(),
+                    publisher.clone(),
+                    base_topic.clone(),
+                    alerts.clone(),
+                )
+            }
+        });
+    }
+
+    // Hot water flow and volume, from the pulses of the flow sensor
+    let flow = flow::FlowMeter::new(&config.flow, saved_state.flow.clone());
+    if let Some(line) = config.flow.line {
+        supervisor::spawn_supervised("flow", alerts.clone(), {
+            let flow = flow.clone();
+            let board = board.clone();
+            move || flow::count_pulses(flow.clone(), board.clone(), line)
+        });
+        supervisor::spawn_supervised("flow_publish", alerts.clone(), {
+            let flow = flow.clone();
+            let window = config.flow.window();
+            let publisher = publisher.clone();
+            let base_topic = config.mqtt.base_topic.clone();
+            move || flow::publish_loop(flow.clone(), window, publisher.clone(), base_topic.clone())
+        });
+    }
+
+    // Follow 1-Wire sensors being unplugged and plugged back in
+    let presence = hotplug::Presence::default();
+    let has_w1 = config.sensors.iter().any(|s| sensors::backend(s, &board) == sensors::SensorBackend::W1);
+    if let Some(interval) = config.timing.w1_rescan_interval()
+        && has_w1
+    {
+        supervisor::spawn_supervised("w1_rescan", alerts.clone(), {
+            let presence = presence.clone();
+            let board = board.clone();
+            let sensors = config.sensors.clone();
+            let publisher = publisher.clone();
+            let base_topic = config.mqtt.base_topic.clone();
+            move || {
+                hotplug::rescan_loop(
+                    presence.clone(),
+                    board.clone(),
+                    sensors.clone(),
+                    interval,
+                    publisher.clone(),
+                    base_topic.clone(),
+                )
+            }
+        });
+    }
+
+    // Check the system clock before anything gets time-stamped
+    let clock_status = clock::ClockStatus::default();
+    let clock_check = clock::check();
+    if let Err(reason) = &clock_check {
+        warning!("{}: time-stamped data is on hold until it is fixed", reason);
+    }
+    supervisor::spawn_supervised("clock", alerts.clone(), {
+        let clock_status = clock_status.clone();
+        let alerts = alerts.clone();
+        move || clock::monitor(clock_status.clone(), alerts.clone())
+    });
+
+    // Maintenance reminders, against the calendar and the heater and flow statistics
+    let maintenance = maintenance::Maintenance::new(
+        &config.maintenance,
+        &saved_state.maintenance,
+        heater.clone(),
+        flow.clone(),
+        audit.clone(),
+    );
+    if maintenance.is_enabled() {
+        maintenance::follow(&maintenance, &subscriptions, &config.mqtt.base_topic);
+        supervisor::spawn_supervised("maintenance", alerts.clone(), {
+            let maintenance = maintenance.clone();
+            let alerts = alerts.clone();
+            let publisher = publisher.clone();
+            let base_topic = config.mqtt.base_topic.clone();
+            let clock_status = clock_status.clone();
+            move || {
+                maintenance::check_loop(
+                    maintenance.clone(),
+                    alerts.clone(),
+                    publisher.clone(),
+                    base_topic.clone(),
+                    clock_status.clone(),
+                )
+            }
+        });
+    }
+
+    tokio::spawn(discovery::announce(config.clone(), publisher.clone()));
+
+    // Report an unclean shutdown of the previous run (power loss, crash)
+    let power_loss = saved_state.unclean_shutdown(clock_check.is_ok().then(Utc::now));
+    if let Some(event) = &power_loss {
+        // Rounded up to the minute, as the last save may be up to a minute old
+        let outage_duration =
+            event.outage_s.map(|s| format::duration(Duration::from_secs((s.max(0) as u64 / 60 + 1) * 60)));
+        let outage = outage_duration
+            .as_ref()
+            .map(|d| format!("offline for up to {}", d))
+            .unwrap_or_else(|| "outage duration unknown".to_string());
+        warning!("Power loss detected: the previous run did not exit cleanly ({})", outage);
+        if let Ok(payload) = serde_json::to_vec(event) {
+            let topic = format!("{}/events/power_loss", config.mqtt.base_topic);
+            let _ = publisher.try_publish(topic, rumqttc::QoS::AtLeastOnce, false, payload);
+        }
+        if config.state.notify_power_loss {
+            let message = messages::Message::new(format!("Power loss detected ({})", outage))
+                .with("duration", outage_duration.unwrap_or_default());
+            alerts.raise("power_loss", message);
+        }
+    }
+    if alerts.active().iter().any(|a| a.id == "power_loss") {
+        let alerts = alerts.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(POWER_LOSS_ALERT_DURATION).await;
+            alerts.clear("power_loss");
+        });
+    }
+
+    let eventloop = Arc::new(Mutex::new(eventloop));
+    let health = health::Health::new(config.cycle_interval());
+    let metrics = metrics::Metrics::new();
+    supervisor::spawn_supervised("mqtt", alerts.clone(), {
+        let health = health.clone();
+        let subscriptions = subscriptions.clone();
+        let availability = availability.clone();
+        let publisher = publisher.clone();
+        let offline = config.mqtt.offline.clone();
+        move || {
+            mqtt::run_event_loop(
+                eventloop.clone(),
+                health.clone(),
+                subscriptions.clone(),
+                availability.clone(),
+                publisher.clone(),
+                offline.clone(),
+                failover.clone(),
+            )
+        }
+    });
+    for (name, eventloop, availability, publisher) in &brokers {
+        let name = name.clone();
+        let eventloop = eventloop.clone();
+        let availability = availability.clone();
+        let publisher = publisher.clone();
+        let offline = config.mqtt.offline.clone();
+        supervisor::spawn_supervised("mqtt_broker", alerts.clone(), move || {
+            mqtt::run_broker_loop(
+                name.clone(),
+                eventloop.clone(),
+                availability.clone(),
+                publisher.clone(),
+                offline.clone(),
+            )
+        });
+    }
+
+    // High-rate sampling of a single sensor, on request
+    let bursts = burst::Bursts::new(
+        &config.burst,
+        &config.sensors,
+        board.clone(),
+        publisher.clone(),
+        &config.mqtt.base_topic,
+        audit.clone(),
+    );
+    burst::follow(&bursts, &subscriptions);
+
+    // Log level and raw capture, switched remotely to diagnose a device in the field
+    let diagnostics = logging::Diagnostics::new(board.capture().clone(), audit.clone());
+    logging::follow(&diagnostics, &subscriptions, &config.mqtt.base_topic);
+
+    // Relays, plugs and coils, set to their safe state before anything can switch them.
+    // The command receiver cannot be shared, so the driver is not restarted by the supervisor.
+    let (outputs, output_driver) = outputs::Outputs::new(
+        &config.outputs,
+        &board,
+        publisher.clone(),
+        &config.mqtt.base_topic,
+        audit.clone(),
+    );
+    tokio::spawn(output_driver.run());
+    outputs::follow(&outputs, &subscriptions, &config.mqtt.base_topic);
+
+    // Remote commands (immediate publication, reference temperature, restart)
+    let controls = commands::Controls::new(config.boiler.reference_temp_c);
+    commands::follow(&controls, &subscriptions, publisher.clone(), &config.mqtt.base_topic, audit.clone());
+    // Safe settings changed over MQTT, saved to the configuration file
+    let live_config = live_config::LiveConfig::new(config_path, config);
+    live_config::follow(
+        &live_config,
+        &controls,
+        &subscriptions,
+        publisher.clone(),
+        &config.mqtt.base_topic,
+        audit.clone(),
+    );
+    // Edits of the configuration file
+    let config_watcher = live_config::ConfigWatcher {
+        live_config: live_config.clone(),
+        controls: controls.clone(),
+        audit: audit.clone(),
+    };
+    supervisor::spawn_supervised("config_reload", alerts.clone(), move || config_watcher.clone().run());
+
+    // Latest acquisition results, shared with the HTTP API
+    let (snapshot_tx, snapshots) = tokio::sync::watch::channel(None);
+
+    // Weekly efficiency score and insights, from the acquisition results
+    let efficiency = efficiency::Efficiency::new(&config.efficiency, &config.billing, &saved_state.efficiency);
+    let efficiency_tracker = efficiency::EfficiencyTracker {
+        efficiency: efficiency.clone(),
+        snapshots: snapshots.clone(),
+        in_tank: config.sensors.iter().filter(|s| s.in_tank).map(|s| s.name.clone()).collect(),
+        heater: config.heater.is_enabled().then(|| heater.clone()),
+        flow: config.flow.line.is_some().then(|| flow.clone()),
+        publisher: publisher.clone(),
+        base_topic: config.mqtt.base_topic.clone(),
+        clock_status: clock_status.clone(),
+    };
+    supervisor::spawn_supervised("efficiency", alerts.clone(), move || efficiency_tracker.clone().run());
+
+    // Heating season of each day, and this season compared with the last one
+    let seasons = season::Seasons::new(&config.season, &saved_state.season);
+    let season_tracker = season::SeasonTracker {
+        seasons: seasons.clone(),
+        efficiency: efficiency.clone(),
+        billing: config.billing.clone(),
+        snapshots: snapshots.clone(),
+        publisher: publisher.clone(),
+        base_topic: config.mqtt.base_topic.clone(),
+        clock_status: clock_status.clone(),
+    };
+    supervisor::spawn_supervised("season", alerts.clone(), move || season_tracker.clone().run());
+
+    // Delivered temperature of the hot water draws, from the pipe sensors
+    if let Some(pipes) = &config.pipes {
+        let draw_tracker = draws::DrawTracker {
+            config: pipes.clone(),
+            snapshots: snapshots.clone(),
+            flow: config.flow.line.is_some().then(|| flow.clone()),
+            publisher: publisher.clone(),
+            base_topic: config.mqtt.base_topic.clone(),
+        };
+        supervisor::spawn_supervised("draws", alerts.clone(), move || draw_tracker.clone().run());
+    }
+
+    // Display preferences, kept apart from the deployed configuration
+    let preferences = preferences::Preferences::load(&config.preferences, audit.clone());
+
+    // Health reporting for external watchdogs
+    if let Some(path) = config.health.status_file.clone() {
+        supervisor::spawn_supervised("status_file", alerts.clone(), {
+            let health = health.clone();
+            let alerts = alerts.clone();
+            move || health::write_status_file(health.clone(), alerts.clone(), path.clone())
+        });
+    }
+    if let Some(listen) = config.http.listen.clone() {
+        let handler = http_handler(HttpServices {
+            auth: config.auth.clone(),
+            health: health.clone(),
+            alerts: alerts.clone(),
+            metrics: metrics.clone(),
+            snapshots: snapshots.clone(),
+            audit: audit.clone(),
+            bursts: bursts.clone(),
+            diagnostics: diagnostics.clone(),
+            outputs: outputs.clone(),
+            maintenance: maintenance.clone(),
+            efficiency: efficiency.clone(),
+            seasons: seasons.clone(),
+            preferences: preferences.clone(),
+        });
+        supervisor::spawn_supervised("http", alerts.clone(), move || {
+            let listen = listen.clone();
+            let handler = handler.clone();
+            async move {
+                if let Err(e) = http::serve(listen, handler).await {
+                    warning!("HTTP server error: {:#}", e);
                 }
+            }
+        });
+    }
+
+    // Self-monitoring metrics on MQTT and the diagnostics page
+    supervisor::spawn_supervised("metrics", alerts.clone(), {
+        let metrics = metrics.clone();
+        let publisher = publisher.clone();
+        let base_topic = config.mqtt.base_topic.clone();
+        let ui_weak = ui_weak.clone();
+        move || metrics::publish_loop(metrics.clone(), publisher.clone(), base_topic.clone(), ui_weak.clone())
+    });
+
+    // Restore the saved history, or initialize it with current sensor values (read once)
+    let history_period = config.timing.history_period();
+    let history_density = config.timing.history_density();
+    let restore = |name: &str, initial: &dyn Fn() -> f32| match saved_state.history_for(name) {
+        Some(points) if !points.is_empty() => {
+            let mut restored = SensorHistory::from_points(points, history_period).with_density(history_density);
+            if power_loss.is_some() {
+                restored.mark_gap();
+            }
+            restored
+        }
+        _ => SensorHistory::new(initial(), Utc::now(), history_period).with_density(history_density),
+    };
+    let mut history: Vec<SensorHistory> = config
+        .sensors
+        .iter()
+        .map(|sensor| restore(&sensor.name, &|| sensors::open(sensor, &board).read().unwrap_or(20.0)))
+        .collect();
+    // Virtual sensors start from the difference of the latest values
+    for delta in &config.deltas {
+        let latest = |name: &str| {
+            let index = config.sensors.iter().position(|s| s.name == name)?;
+            history[index].points().back().map(|p| p.value)
+        };
+        let initial = latest(&delta.plus).unwrap_or(0.0) - latest(&delta.minus).unwrap_or(0.0);
+        let restored = restore(&delta.name, &|| initial);
+        history.push(restored);
+    }
+    let history = Arc::new(Mutex::new(history));
+    let sensor_names = config.sensor_names();
+
+    // Save the runtime state periodically
+    let state_sources = state::StateSources {
+        names: sensor_names.clone(),
+        history: history.clone(),
+        alerts: alerts.clone(),
+        heater: heater.clone(),
+        flow: flow.clone(),
+        maintenance: maintenance.clone(),
+        efficiency: efficiency.clone(),
+        seasons: seasons.clone(),
+    };
+    supervisor::spawn_supervised("state", alerts.clone(), {
+        let path = config.state.path.clone();
+        let interval = Duration::from_secs(config.state.save_interval_s.max(1));
+        let state_sources = state_sources.clone();
+        let clock_status = clock_status.clone();
+        move || state::persist_loop(path.clone(), interval, state_sources.clone(), clock_status.clone())
+    });
+
+    // Minimum, maximum and average of every sensor at the end of each hour and day
+    if config.stats.hourly || config.stats.daily {
+        let stats_tracker = stats::StatsTracker {
+            config: config.stats.clone(),
+            history: history.clone(),
+            names: sensor_names.clone(),
+            snapshots: snapshots.clone(),
+            publisher: publisher.clone(),
+            base_topic: config.mqtt.base_topic.clone(),
+            clock_status: clock_status.clone(),
+        };
+        supervisor::spawn_supervised("stats", alerts.clone(), move || stats_tracker.clone().run());
+    }
+
+    // Chart images, every day and on request
+    if let Some(export) = config.chart.export.clone() {
+        let requests = Arc::new(tokio::sync::Notify::new());
+        subscriptions.subscribe(format!("{}/chart/export", config.mqtt.base_topic), {
+            let requests = requests.clone();
+            move |_, _| requests.notify_one()
+        });
+        supervisor::spawn_supervised("chart_export", alerts.clone(), {
+            let history = history.clone();
+            let names = sensor_names.clone();
+            let publisher = publisher.clone();
+            let base_topic = config.mqtt.base_topic.clone();
+            move || {
+                chart_export::export_loop(
+                    export.clone(),
+                    history.clone(),
+                    names.clone(),
+                    requests.clone(),
+                    publisher.clone(),
+                    base_topic.clone(),
+                )
+            }
+        });
+    }
+
+    // Refresh the UI from the latest acquisition results, independently of the sensors
+    let (ui_snapshot, ui_snapshots) = tokio::sync::watch::channel(ui::UiSnapshot::default());
+    if let Some(ui_weak) = ui_weak.clone() {
+        let refresh = ui::UiRefresh {
+            ui_weak: ui_weak.clone(),
+            snapshots: ui_snapshots,
+            remotes: remote::follow(&config.remotes, &subscriptions),
+            sensor_names: sensor_names.clone(),
+            group_names: config.groups.iter().map(|g| g.name.clone()).collect(),
+            alerts: alerts.clone(),
+            clock_status: clock_status.clone(),
+            metrics: metrics.clone(),
+            audit: audit.clone(),
+            health: health.clone(),
+            flow: config.flow.line.is_some().then(|| flow.clone()),
+            preferences: preferences.subscribe(),
+            history_period_min: config.timing.history_period_min,
+            interval: config.timing.ui_refresh_interval(config.runtime.profile),
+        };
+        supervisor::spawn_supervised("ui", alerts.clone(), move || refresh.clone().run());
+
+        // Sensor mapping wizard. The command receiver cannot be shared, so the task is
+        // not restarted by the supervisor.
+        let (commands_tx, commands) = tokio::sync::mpsc::channel(8);
+        let (view, views) = tokio::sync::watch::channel(wizard::MappingWizard::initial_view(config, &board));
+        if let Some(ui) = ui_weak.upgrade() {
+            ui::connect_wizard(&ui, commands_tx);
+        }
+        let wizard = wizard::MappingWizard {
+            board: board.clone(),
+            config_path: config_path.to_path_buf(),
+            commands,
+            view,
+            audit: audit.clone(),
+        };
+        tokio::spawn(wizard.run());
+        tokio::spawn(ui::show_wizard(ui_weak.clone(), views));
+
+        // PIN lock of the controls
+        let lock = display_lock::DisplayLock::new(&config.display_lock, audit.clone());
+        if let Some(ui) = ui_weak.upgrade() {
+            ui::connect_lock(&ui, &lock);
+        }
+        tokio::spawn(ui::show_lock(ui_weak.clone(), lock.clone()));
+        if lock.is_enabled() {
+            supervisor::spawn_supervised("display_lock", alerts.clone(), move || lock.clone().relock_loop());
+        }
+
+        // On a restart command, `main` only gets control back once the event loop ends
+        tokio::spawn({
+            let controls = controls.clone();
+            async move {
+                controls.wait_for_restart().await;
+                let _ = slint::invoke_from_event_loop(|| {
+                    let _ = slint::quit_event_loop();
+                });
+            }
+        });
+        // Likewise on a stop, so that the exit is clean: state saved, outputs safe, offline published
+        tokio::spawn(async {
+            if let Err(e) = wait_for_shutdown().await {
+                warning!("Cannot wait for the stop signals: {}", e);
+                return;
+            }
+            let _ = slint::invoke_from_event_loop(|| {
+                let _ = slint::quit_event_loop();
+            });
+        });
+
+        // Maintenance page
+        if maintenance.is_enabled() {
+            if let Some(ui) = ui_weak.upgrade() {
+                ui::connect_maintenance(&ui, &maintenance);
+            }
+            tokio::spawn(ui::show_maintenance(ui_weak.clone(), maintenance.clone()));
+        }
+
+        // Efficiency page
+        tokio::spawn(ui::show_efficiency(ui_weak.clone(), efficiency.clone()));
+        tokio::spawn(ui::show_season(ui_weak.clone(), seasons.clone()));
+
+        // Display preferences page, and the brightness schedule when the backlight can be set
+        let backlight = preferences::backlight();
+        if let Some(ui) = ui_weak.upgrade() {
+            ui::connect_preferences(&ui, &preferences);
+        }
+        tokio::spawn(ui::show_preferences(ui_weak.clone(), preferences.clone(), backlight.is_some()));
+        if let Some(backlight) = backlight {
+            supervisor::spawn_supervised("brightness", alerts.clone(), move || {
+                preferences.clone().brightness_loop(backlight.clone())
             });
         }
+
+        // Log viewer of the diagnostics page
+        let log_viewer = ui::LogViewer::new();
+        if let Some(ui) = ui_weak.upgrade() {
+            ui::connect_logs(&ui, &log_viewer);
+        }
+        tokio::spawn(ui::show_logs(ui_weak.clone(), log_viewer));
+
+        // Burst sampling controls of the diagnostics page
+        if let Some(ui) = ui_weak.upgrade() {
+            ui::connect_burst(&ui, &bursts);
+        }
+        tokio::spawn(ui::show_burst(ui_weak.clone(), bursts.clone()));
+
+        // Export to a USB stick, for the technicians without network access
+        let usb_export = usb_export::UsbExport::new(
+            &config.usb_export,
+            alerts.clone(),
+            audit.clone(),
+            sensor_names.clone(),
+            history.clone(),
+            seasons.clone(),
+        );
+        if let Some(ui) = ui_weak.upgrade() {
+            ui::connect_usb_export(&ui, &usb_export);
+        }
+        tokio::spawn(ui::show_usb_export(ui_weak, usb_export.clone()));
+        supervisor::spawn_supervised("usb_export", alerts.clone(), move || usb_export.clone().detect_loop());
+    }
+
+    // Spawn the main sensor reading loop under supervision
+    let acquisition = acquisition::Acquisition {
+        config: config.clone(),
+        board,
+        publisher: publisher.clone(),
+        ui_snapshot,
+        snapshot: snapshot_tx,
+        history: history.clone(),
+        alerts: alerts.clone(),
+        clock_status: clock_status.clone(),
+        health: health.clone(),
+        metrics: metrics.clone(),
+        heater: heater.clone(),
+        presence,
+        controls: controls.clone(),
+        settings: live_config.subscribe(),
+        outputs: outputs.clone(),
+    };
+    // Run the self-test first, then start acquisition unless the policy says to abort
+    let policy = config.selftest.policy;
+    tokio::spawn({
+        let config = config.clone();
+        let health = health.clone();
+        let alerts = alerts.clone();
+        async move {
+            if policy != selftest::SelfTestPolicy::Off {
+                let report = selftest::run(&config, &acquisition.board, &health).await;
+                if !selftest::conclude(&report, policy, &alerts, &acquisition.publisher, &config.mqtt.base_topic) {
+                    warning!("Aborting after failed self-test (policy = \"abort\")");
+                    // Give the report a chance to reach the broker
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    std::process::exit(1);
+                }
+            }
+            supervisor::spawn_supervised("acquisition", alerts.clone(), move || acquisition.clone().run());
+        }
     });
 
-    // Start the Slint UI main loop
-    ui.run()?;
+    // Tell systemd we are up, and keep its watchdog fed while acquisition is alive
+    systemd::notify("READY=1");
+    if let Some(interval) = systemd::watchdog_interval() {
+        supervisor::spawn_supervised("watchdog", alerts.clone(), {
+            let health = health.clone();
+            move || systemd::watchdog_loop(interval, health.clone())
+        });
+    }
+
+    // End the probation of a freshly installed version once it is healthy
+    #[cfg(feature = "update")]
+    tokio::spawn(update::confirm_when_healthy(health.clone()));
+
+    Ok(App {
+        state_path,
+        state_sources,
+        clock_status,
+        availability,
+        broker_availability: brokers.into_iter().map(|(_, _, availability, _)| availability).collect(),
+        outputs,
+        controls,
+    })
+}
+
+/// Services the embedded HTTP server answers from.
+struct HttpServices {
+    auth: auth::AuthConfig,
+    health: health::Health,
+    alerts: alerts::Alerts,
+    metrics: metrics::Metrics,
+    snapshots: tokio::sync::watch::Receiver<Option<boilert::model::Snapshot>>,
+    audit: audit::AuditLog,
+    bursts: burst::Bursts,
+    diagnostics: logging::Diagnostics,
+    outputs: outputs::Outputs,
+    maintenance: maintenance::Maintenance,
+    efficiency: efficiency::Efficiency,
+    seasons: season::Seasons,
+    preferences: preferences::Preferences,
+}
 
-    Ok(())
+/// Builds the request handler of the embedded HTTP server.
+///
+/// `/healthz` stays open for watchdogs; the other endpoints require the viewer role.
+fn http_handler(services: HttpServices) -> http::Handler {
+    let HttpServices {
+        auth,
+        health,
+        alerts,
+        metrics,
+        snapshots,
+        audit,
+        bursts,
+        diagnostics,
+        outputs,
+        maintenance,
+        efficiency,
+        seasons,
+        preferences,
+    } = services;
+    Arc::new(move |request: http::Request| {
+        if request.path != "/healthz"
+            && let Err(denied) = auth.authorize(&request, auth::Role::Viewer)
+        {
+            return denied;
+        }
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/healthz") => health::handle_healthz(&health, &alerts),
+            ("GET", "/metrics") => {
+                let mut body = metrics.snapshot().to_prometheus();
+                if let Some(snapshot) = snapshots.borrow().as_ref() {
+                    body.push_str(&metrics::snapshot_to_prometheus(snapshot));
+                }
+                http::Response::text(200, body)
+            }
+            ("GET", "/api/snapshot") => match snapshots.borrow().as_ref() {
+                Some(snapshot) => http::Response::json(200, snapshot),
+                None => http::Response::text(503, "No acquisition cycle completed yet\n"),
+            },
+            ("GET", "/api/audit") => http::Response::json(200, &audit.recent()),
+            ("GET", "/api/burst") => http::Response::json(200, &bursts.status()),
+            ("POST", path) if path.starts_with("/api/burst/") => {
+                if let Err(denied) = auth.authorize(&request, auth::Role::Operator) {
+                    return denied;
+                }
+                let sensor = &path["/api/burst/".len()..];
+                let minutes = match request.query_param("minutes").map(str::parse) {
+                    None => burst::DEFAULT_MINUTES,
+                    Some(Ok(minutes)) => minutes,
+                    Some(Err(_)) => return http::Response::text(400, "invalid minutes\n"),
+                };
+                match bursts.start(sensor, minutes, AuditSource::Rest) {
+                    Ok(status) => http::Response::json(200, &status),
+                    Err(e) => http::Response::text(400, format!("{:#}\n", e)),
+                }
+            }
+            ("DELETE", "/api/burst") => {
+                if let Err(denied) = auth.authorize(&request, auth::Role::Operator) {
+                    return denied;
+                }
+                if bursts.stop(AuditSource::Rest) {
+                    http::Response::text(200, "stopped\n")
+                } else {
+                    http::Response::text(400, "no burst running\n")
+                }
+            }
+            ("GET", "/api/debug") => http::Response::json(200, &diagnostics.status()),
+            ("POST", "/api/debug") => {
+                if let Err(denied) = auth.authorize(&request, auth::Role::Operator) {
+                    return denied;
+                }
+                let level = request.query_param("log_level").map(str::parse::<logging::LogLevel>).transpose();
+                let raw_capture = request.query_param("raw_capture").map(|v| logging::parse_switch(v).ok_or(v));
+                match (level, raw_capture.transpose()) {
+                    (Ok(level), Ok(raw_capture)) => {
+                        if let Some(level) = level {
+                            diagnostics.set_level(level, AuditSource::Rest);
+                        }
+                        if let Some(enabled) = raw_capture {
+                            diagnostics.set_raw_capture(enabled, AuditSource::Rest);
+                        }
+                        http::Response::json(200, &diagnostics.status())
+                    }
+                    (Err(e), _) => http::Response::text(400, format!("{:#}\n", e)),
+                    (_, Err(value)) => http::Response::text(400, format!("invalid raw_capture {:?}\n", value)),
+                }
+            }
+            ("GET", "/api/outputs") => http::Response::json(200, &outputs.status()),
+            ("POST", path) if path.starts_with("/api/outputs/") => {
+                if let Err(denied) = auth.authorize(&request, auth::Role::Operator) {
+                    return denied;
+                }
+                let name = &path["/api/outputs/".len()..];
+                let Some(on) = request.query_param("state").and_then(logging::parse_switch) else {
+                    return http::Response::text(400, "state must be on or off\n");
+                };
+                match outputs.switch(name, on, AuditSource::Rest) {
+                    Ok(()) => http::Response::text(202, "switching\n"),
+                    Err(e) => http::Response::text(400, format!("{:#}\n", e)),
+                }
+            }
+            ("GET", "/api/efficiency") => http::Response::json(200, &efficiency.report()),
+            ("GET", "/api/seasons") => http::Response::json(200, &seasons.report()),
+            ("GET", "/api/maintenance") => http::Response::json(200, &maintenance.status()),
+            ("POST", path) if path.starts_with("/api/maintenance/") => {
+                if let Err(denied) = auth.authorize(&request, auth::Role::Operator) {
+                    return denied;
+                }
+                let name = &path["/api/maintenance/".len()..];
+                let Some(action) = request.query_param("action") else {
+                    return http::Response::text(400, "action must be done or snooze\n");
+                };
+                match maintenance.apply(name, action, AuditSource::Rest) {
+                    Ok(()) => http::Response::text(200, "ok\n"),
+                    Err(e) => http::Response::text(400, format!("{:#}\n", e)),
+                }
+            }
+            ("GET", "/api/preferences") => http::Response::json(200, &preferences.get()),
+            ("POST", path) if path.starts_with("/api/preferences/") => {
+                if let Err(denied) = auth.authorize(&request, auth::Role::Operator) {
+                    return denied;
+                }
+                let name = &path["/api/preferences/".len()..];
+                let Some(value) = request.query_param("value") else {
+                    return http::Response::text(400, "missing value\n");
+                };
+                match preferences.set(name, value, AuditSource::Rest) {
+                    Ok(()) => http::Response::json(200, &preferences.get()),
+                    Err(e) => http::Response::text(400, format!("{:#}\n", e)),
+                }
+            }
+            _ => http::Response::not_found(),
+        }
+    })
 }
@@ -0,0 +1,376 @@
+//! Maintenance reminders.
+//! Each `[[maintenance]]` task (anode inspection, descaling, filter cleaning) comes due
+//! after a number of months, of heating hours or of litres of hot water since it was
+//! last done, whichever runs out first. A due task raises an alert, is highlighted on
+//! the maintenance page and is published on `{base_topic}/maintenance/{name}`. It can be
+//! marked done, which starts a new interval, or snoozed for a few days, from the
+//! display, over MQTT or over HTTP.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use boilert::model::AuditSource;
+use chrono::{DateTime, Months, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, watch};
+use tokio::time;
+
+use crate::alerts::Alerts;
+use crate::audit::AuditLog;
+use crate::clock::ClockStatus;
+use crate::flow::FlowMeter;
+use crate::heater::HeaterStats;
+use crate::logging::{info, warning};
+use crate::messages::Message;
+use crate::mqtt::{Publisher, Subscriptions};
+
+/// Interval between two checks of the reminders.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A maintenance task (`[[maintenance]]`).
+#[derive(Debug, Deserialize, Clone)]
+pub struct MaintenanceConfig {
+    /// Name of the task, used in its topics and alert (e.g., "anode").
+    pub name: String,
+    /// Text shown on the display and in the alert (default: the name).
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Months between two occurrences.
+    #[serde(default)]
+    pub every_months: Option<u32>,
+    /// Hours of heating between two occurrences, from the heater statistics.
+    #[serde(default)]
+    pub every_heating_hours: Option<f64>,
+    /// Litres of hot water drawn between two occurrences, from the flow meter.
+    #[serde(default)]
+    pub every_litres: Option<f64>,
+    /// Time a snoozed reminder stays quiet, in days.
+    #[serde(default = "default_snooze_days")]
+    pub snooze_days: u32,
+}
+
+fn default_snooze_days() -> u32 {
+    7
+}
+
+impl MaintenanceConfig {
+    /// Checks the name and the intervals.
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() || self.name.contains(['/', '+', '#']) {
+            bail!("Maintenance {:?}: the name must not be empty nor contain /, + or #", self.name);
+        }
+        if self.every_months.is_none() && self.every_heating_hours.is_none() && self.every_litres.is_none() {
+            bail!("Maintenance {}: every_months, every_heating_hours or every_litres is required", self.name);
+        }
+        if self.every_months == Some(0)
+            || self.every_heating_hours.is_some_and(|hours| hours <= 0.0)
+            || self.every_litres.is_some_and(|litres| litres <= 0.0)
+        {
+            bail!("Maintenance {}: the intervals must be greater than 0", self.name);
+        }
+        if self.snooze_days == 0 {
+            bail!("Maintenance {}: snooze_days must be greater than 0", self.name);
+        }
+        Ok(())
+    }
+
+    fn label(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Last occurrence of a task, persisted with the runtime state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceRecord {
+    pub name: String,
+    /// Time the task was last done, or its reminder first configured.
+    pub done_at: DateTime<Utc>,
+    /// Heater on-time at `done_at`, in seconds.
+    pub heater_on_time_s: f64,
+    /// Hot water volume at `done_at`, in litres.
+    pub volume_l: f64,
+    /// The reminder stays quiet until then.
+    #[serde(default)]
+    pub snoozed_until: Option<DateTime<Utc>>,
+}
+
+/// Interval of a task that runs out first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Limit {
+    #[default]
+    Months,
+    HeatingHours,
+    Litres,
+}
+
+/// State of a task, published on `{base_topic}/maintenance/{name}` and returned by
+/// `GET /api/maintenance`. The remaining amounts are rounded, negative once overdue.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TaskStatus {
+    pub name: String,
+    pub label: String,
+    pub done_at: DateTime<Utc>,
+    /// Elapsed fraction of the interval that runs out first, 1 or more once due.
+    pub progress: f64,
+    /// `true` once an interval ran out, unless the reminder is snoozed.
+    pub due: bool,
+    pub snoozed_until: Option<DateTime<Utc>>,
+    /// Days left with `every_months`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days_left: Option<f64>,
+    /// Heating hours left with `every_heating_hours`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heating_hours_left: Option<f64>,
+    /// Litres left with `every_litres`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub litres_left: Option<f64>,
+    #[serde(skip)]
+    pub limit: Limit,
+}
+
+impl TaskStatus {
+    /// Alert raised while the task is due.
+    fn alert_id(&self) -> String {
+        format!("maintenance_{}", self.name)
+    }
+}
+
+/// Computes the state of a task at `now`, from the current heater on-time and volume.
+fn status(
+    config: &MaintenanceConfig,
+    record: &MaintenanceRecord,
+    now: DateTime<Utc>,
+    on_time_s: f64,
+    volume_l: f64,
+) -> TaskStatus {
+    // Elapsed and length of each configured interval, in days, heating hours or litres
+    let days = config.every_months.map(|months| {
+        let due_at = record.done_at.checked_add_months(Months::new(months)).unwrap_or(DateTime::<Utc>::MAX_UTC);
+        let days = |duration: chrono::Duration| duration.num_seconds() as f64 / 86400.0;
+        (days(now - record.done_at), days(due_at - record.done_at))
+    });
+    let hours = config.every_heating_hours.map(|hours| ((on_time_s - record.heater_on_time_s) / 3600.0, hours));
+    let litres = config.every_litres.map(|litres| (volume_l - record.volume_l, litres));
+    let (limit, progress) = [(Limit::Months, days), (Limit::HeatingHours, hours), (Limit::Litres, litres)]
+        .into_iter()
+        .filter_map(|(limit, interval)| interval.map(|(elapsed, every)| (limit, elapsed.max(0.0) / every)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or_default();
+    let left = |interval: Option<(f64, f64)>| interval.map(|(elapsed, every)| (every - elapsed).round());
+    let progress = (progress * 100.0).round() / 100.0;
+    let snoozed_until = record.snoozed_until.filter(|&until| until > now);
+    TaskStatus {
+        name: config.name.clone(),
+        label: config.label().to_string(),
+        done_at: record.done_at,
+        progress,
+        due: progress >= 1.0 && snoozed_until.is_none(),
+        snoozed_until,
+        days_left: left(days),
+        heating_hours_left: left(hours),
+        litres_left: left(litres),
+        limit,
+    }
+}
+
+/// Handle on the maintenance tasks.
+///
+/// Cloning a `Maintenance` handle is cheap; all clones share the same tasks.
+#[derive(Clone)]
+pub struct Maintenance {
+    configs: Arc<Vec<MaintenanceConfig>>,
+    records: Arc<Mutex<Vec<MaintenanceRecord>>>,
+    heater: HeaterStats,
+    flow: FlowMeter,
+    audit: AuditLog,
+    /// Wakes the check loop after a task was done or snoozed.
+    changed: Arc<Notify>,
+    statuses: watch::Sender<Vec<TaskStatus>>,
+}
+
+impl Maintenance {
+    /// Follows the configured tasks from their saved records. The intervals of new tasks
+    /// start at the first check with a trustworthy clock.
+    pub fn new(
+        configs: &[MaintenanceConfig],
+        saved: &[MaintenanceRecord],
+        heater: HeaterStats,
+        flow: FlowMeter,
+        audit: AuditLog,
+    ) -> Self {
+        let records = saved.iter().filter(|record| configs.iter().any(|c| c.name == record.name)).cloned().collect();
+        Self {
+            configs: Arc::new(configs.to_vec()),
+            records: Arc::new(Mutex::new(records)),
+            heater,
+            flow,
+            audit,
+            changed: Arc::new(Notify::new()),
+            statuses: watch::channel(Vec::new()).0,
+        }
+    }
+
+    /// `true` if at least one task is configured.
+    pub fn is_enabled(&self) -> bool {
+        !self.configs.is_empty()
+    }
+
+    /// Copy of the records, for persistence.
+    pub fn records(&self) -> Vec<MaintenanceRecord> {
+        self.lock().clone()
+    }
+
+    /// State of the tasks at the last check.
+    pub fn status(&self) -> Vec<TaskStatus> {
+        self.statuses.borrow().clone()
+    }
+
+    /// Receiver following the state of the tasks.
+    pub fn subscribe(&self) -> watch::Receiver<Vec<TaskStatus>> {
+        self.statuses.subscribe()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<MaintenanceRecord>> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record of a task done now.
+    fn done_now(&self, name: &str) -> MaintenanceRecord {
+        MaintenanceRecord {
+            name: name.to_string(),
+            done_at: Utc::now(),
+            heater_on_time_s: self.heater.totals().on_time_s,
+            volume_l: self.flow.volume_l(),
+            snoozed_until: None,
+        }
+    }
+
+    fn config(&self, name: &str) -> Result<&MaintenanceConfig> {
+        match self.configs.iter().find(|config| config.name == name) {
+            Some(config) => Ok(config),
+            None => bail!("Unknown maintenance task {}", name),
+        }
+    }
+
+    /// Marks the task `name` done, starting a new interval.
+    pub fn complete(&self, name: &str, source: AuditSource) -> Result<()> {
+        let config = self.config(name)?;
+        let record = self.done_now(name);
+        {
+            let mut records = self.lock();
+            records.retain(|record| record.name != name);
+            records.push(record);
+        }
+        info!("Maintenance {} done", name);
+        self.audit.record(source, "maintenance done", config.label());
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Silences the reminder of the task `name` for `snooze_days`.
+    pub fn snooze(&self, name: &str, source: AuditSource) -> Result<()> {
+        let config = self.config(name)?;
+        let until = Utc::now() + chrono::Duration::days(config.snooze_days.into());
+        {
+            let mut records = self.lock();
+            if !records.iter().any(|record| record.name == name) {
+                records.push(self.done_now(name));
+            }
+            if let Some(record) = records.iter_mut().find(|record| record.name == name) {
+                record.snoozed_until = Some(until);
+            }
+        }
+        info!("Maintenance {} snoozed for {} days", name, config.snooze_days);
+        self.audit.record(source, "maintenance snoozed", format!("{} {} days", config.label(), config.snooze_days));
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Runs an action on the task `name`: `done` or `snooze`.
+    pub fn apply(&self, name: &str, action: &str, source: AuditSource) -> Result<()> {
+        match action.trim().to_ascii_lowercase().as_str() {
+            "done" => self.complete(name, source),
+            "snooze" => self.snooze(name, source),
+            other => bail!("unknown action {:?} (expected done or snooze)", other),
+        }
+    }
+
+    /// Starts the interval of the new tasks, then updates the state of all of them.
+    fn refresh(&self, now: DateTime<Utc>) -> Vec<TaskStatus> {
+        let on_time_s = self.heater.totals().on_time_s;
+        let volume_l = self.flow.volume_l();
+        let statuses: Vec<TaskStatus> = {
+            let mut records = self.lock();
+            for config in self.configs.iter() {
+                if !records.iter().any(|record| record.name == config.name) {
+                    records.push(self.done_now(&config.name));
+                }
+            }
+            self.configs
+                .iter()
+                .filter_map(|config| {
+                    let record = records.iter().find(|record| record.name == config.name)?;
+                    Some(status(config, record, now, on_time_s, volume_l))
+                })
+                .collect()
+        };
+        self.statuses.send_if_modified(|shown| {
+            let changed = *shown != statuses;
+            *shown = statuses.clone();
+            changed
+        });
+        statuses
+    }
+}
+
+/// Checks the tasks every minute and right after an action, raising the alert of the due
+/// ones and publishing their state (retained) when it changes. Nothing is checked until
+/// the clock can be trusted.
+pub async fn check_loop(
+    maintenance: Maintenance,
+    alerts: Alerts,
+    publisher: Publisher,
+    base_topic: String,
+    clock_status: ClockStatus,
+) {
+    let mut published: Vec<TaskStatus> = Vec::new();
+    loop {
+        if clock_status.is_sane() {
+            let statuses = maintenance.refresh(Utc::now());
+            for task in &statuses {
+                let id = task.alert_id();
+                let raised = alerts.active().iter().any(|alert| alert.id == id);
+                if task.due && !raised {
+                    alerts.raise(&id, Message::new(format!("Maintenance due: {}", task.label)));
+                } else if !task.due && raised {
+                    alerts.clear(&id);
+                }
+                if published.contains(task) {
+                    continue;
+                }
+                if let Ok(payload) = serde_json::to_vec(task) {
+                    let topic = format!("{}/maintenance/{}", base_topic, task.name);
+                    let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, payload).await;
+                }
+            }
+            published = statuses;
+        }
+        let _ = time::timeout(CHECK_INTERVAL, maintenance.changed.notified()).await;
+    }
+}
+
+/// Marks the tasks done or snoozes them on `{base_topic}/maintenance/{name}/set`
+/// messages (`done`/`snooze`).
+pub fn follow(maintenance: &Maintenance, subscriptions: &Subscriptions, base_topic: &str) {
+    let maintenance = maintenance.clone();
+    let prefix = format!("{}/maintenance/", base_topic);
+    subscriptions.subscribe(format!("{}+/set", prefix), move |topic, payload| {
+        let Some(name) = topic.strip_prefix(&prefix).and_then(|rest| rest.strip_suffix("/set")) else {
+            return;
+        };
+        if let Err(e) = maintenance.apply(name, &String::from_utf8_lossy(payload), AuditSource::Mqtt) {
+            warning!("Ignoring {} command: {:#}", topic, e);
+        }
+    });
+}
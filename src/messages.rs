@@ -0,0 +1,138 @@
+//! Alert message templates.
+//! Each alert comes with a built-in English text and the values it is about (sensor,
+//! value, threshold, duration). The `[messages]` section can replace the text of any
+//! alert with a template in the household's language, e.g.
+//! `"{{sensor}} à {{value}} (seuil {{threshold}})"`. The rendered text is what is logged,
+//! published on MQTT and shown in the UI.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use anyhow::{Result, bail};
+use serde::Deserialize;
+
+/// Variables a template can use; `id` and `message` (the built-in text) are always set.
+const VARIABLES: [&str; 6] = ["id", "message", "sensor", "value", "threshold", "duration"];
+
+/// Templates from the `[messages]` section.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MessagesConfig {
+    /// Language of the templates in use (e.g., "fr"); built-in texts if absent.
+    pub language: Option<String>,
+    /// Templates by language, then by alert ID. An ID ending with `*` matches every
+    /// alert starting with the rest (e.g., "delta_*").
+    pub templates: HashMap<String, HashMap<String, String>>,
+}
+
+impl MessagesConfig {
+    /// Checks that the language has templates and that they only use known variables.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(language) = &self.language
+            && !self.templates.contains_key(language)
+        {
+            bail!("[messages] no templates for language {:?}", language);
+        }
+        for (language, templates) in &self.templates {
+            for (id, template) in templates {
+                if let Some(unknown) = placeholders(template).find(|name| !VARIABLES.contains(name)) {
+                    bail!(
+                        "[messages.templates.{}] {}: unknown variable {{{{{}}}}} (expected one of {})",
+                        language,
+                        id,
+                        unknown,
+                        VARIABLES.join(", ")
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Template of the alert `id` in the selected language: the one for the exact ID,
+    /// or else the one with the longest matching prefix.
+    fn template(&self, id: &str) -> Option<&str> {
+        let templates = self.templates.get(self.language.as_ref()?)?;
+        if let Some(template) = templates.get(id) {
+            return Some(template);
+        }
+        templates
+            .iter()
+            .filter_map(|(key, template)| Some((key.strip_suffix('*')?, template)))
+            .filter(|(prefix, _)| id.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, template)| template.as_str())
+    }
+}
+
+/// Names of the `{{name}}` placeholders of `template`.
+fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template.split("{{").skip(1).filter_map(|part| Some(part.split_once("}}")?.0.trim()))
+}
+
+static TEMPLATES: OnceLock<MessagesConfig> = OnceLock::new();
+
+/// Sets the templates of the process. Only the first call has an effect; until then,
+/// the built-in texts are used.
+pub fn init(config: &MessagesConfig) {
+    let _ = TEMPLATES.set(config.clone());
+}
+
+/// Text of an alert: the built-in message and the values it is about.
+#[derive(Debug, Clone, Default)]
+pub struct Message {
+    text: String,
+    variables: Vec<(&'static str, String)>,
+}
+
+impl Message {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            variables: Vec::new(),
+        }
+    }
+
+    /// Sets a template variable (`sensor`, `value`, `threshold` or `duration`).
+    pub fn with(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.variables.push((name, value.into()));
+        self
+    }
+
+    /// Renders the message of the alert `id` with its template, if one is configured.
+    /// Variables the alert does not provide are left empty.
+    pub fn render(&self, id: &str) -> String {
+        let Some(template) = TEMPLATES.get().and_then(|t| t.template(id)) else {
+            return self.text.clone();
+        };
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}") else {
+                break;
+            };
+            out.push_str(&rest[..start]);
+            let value = match rest[start + 2..start + end].trim() {
+                "id" => id,
+                "message" => &self.text,
+                name => self.variables.iter().find(|(n, _)| *n == name).map_or("", |(_, v)| v),
+            };
+            out.push_str(value);
+            rest = &rest[start + end + 2..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Self::new(text)
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        Self::new(text)
+    }
+}
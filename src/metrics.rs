@@ -0,0 +1,239 @@
+//! Self-monitoring metrics of the boilert application.
+//! Exposed on the diagnostics page, on MQTT `$sys` topics and in Prometheus format,
+//! along with the latest readings.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use boilert::model::Snapshot;
+
+/// Interval between two publications of the metrics on MQTT and in the UI.
+const METRICS_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct MetricsState {
+    loop_duration: Duration,
+    /// Last read latency per sensor name, in configuration order.
+    read_latency: Vec<(String, Duration)>,
+    /// Values outside the plausible range per sensor name, in order of first occurrence.
+    implausible_values: Vec<(String, u64)>,
+    publish_failures: u64,
+}
+
+/// Shared metrics registry, updated by the background tasks.
+#[derive(Clone)]
+pub struct Metrics {
+    state: Arc<Mutex<MetricsState>>,
+    /// Number of UI updates queued to the Slint event loop but not yet applied.
+    ui_queue_depth: Arc<AtomicI64>,
+    started: Instant,
+}
+
+/// Point-in-time copy of all metrics.
+#[derive(Debug, Clone)]
+pub struct MetricsSnapshot {
+    pub uptime: Duration,
+    pub loop_duration: Duration,
+    pub read_latency: Vec<(String, Duration)>,
+    pub implausible_values: Vec<(String, u64)>,
+    pub publish_failures: u64,
+    pub ui_queue_depth: i64,
+    /// Resident memory of the process, if available (Linux only).
+    pub memory_rss_bytes: Option<u64>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MetricsState::default())),
+            ui_queue_depth: Arc::new(AtomicI64::new(0)),
+            started: Instant::now(),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MetricsState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records the duration of a complete acquisition cycle.
+    pub fn record_loop_duration(&self, duration: Duration) {
+        self.state().loop_duration = duration;
+    }
+
+    /// Records the time taken to read one sensor.
+    pub fn record_read_latency(&self, sensor: &str, latency: Duration) {
+        let mut state = self.state();
+        match state.read_latency.iter_mut().find(|(name, _)| name == sensor) {
+            Some(entry) => entry.1 = latency,
+            None => state.read_latency.push((sensor.to_string(), latency)),
+        }
+    }
+
+    /// Counts a value of `sensor` outside its plausible range.
+    pub fn record_implausible_value(&self, sensor: &str) {
+        let mut state = self.state();
+        match state.implausible_values.iter_mut().find(|(name, _)| name == sensor) {
+            Some(entry) => entry.1 += 1,
+            None => state.implausible_values.push((sensor.to_string(), 1)),
+        }
+    }
+
+    /// Counts an MQTT publish that could not be queued.
+    pub fn record_publish_failure(&self) {
+        self.state().publish_failures += 1;
+    }
+
+    /// Marks a UI update as queued to the event loop.
+    pub fn ui_update_queued(&self) {
+        self.ui_queue_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Marks a queued UI update as applied.
+    pub fn ui_update_applied(&self) {
+        self.ui_queue_depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let state = self.state();
+        MetricsSnapshot {
+            uptime: self.started.elapsed(),
+            loop_duration: state.loop_duration,
+            read_latency: state.read_latency.clone(),
+            implausible_values: state.implausible_values.clone(),
+            publish_failures: state.publish_failures,
+            ui_queue_depth: self.ui_queue_depth.load(Ordering::Relaxed),
+            memory_rss_bytes: memory_rss_bytes(),
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE boilert_uptime_seconds gauge");
+        let _ = writeln!(out, "boilert_uptime_seconds {}", self.uptime.as_secs());
+        let _ = writeln!(out, "# TYPE boilert_loop_duration_seconds gauge");
+        let _ = writeln!(out, "boilert_loop_duration_seconds {}", self.loop_duration.as_secs_f64());
+        let _ = writeln!(out, "# TYPE boilert_sensor_read_latency_seconds gauge");
+        for (name, latency) in &self.read_latency {
+            let _ = writeln!(
+                out,
+                "boilert_sensor_read_latency_seconds{{sensor=\"{}\"}} {}",
+                name,
+                latency.as_secs_f64()
+            );
+        }
+        if !self.implausible_values.is_empty() {
+            let _ = writeln!(out, "# TYPE boilert_implausible_values_total counter");
+            for (name, count) in &self.implausible_values {
+                let _ = writeln!(out, "boilert_implausible_values_total{{sensor=\"{}\"}} {}", name, count);
+            }
+        }
+        let _ = writeln!(out, "# TYPE boilert_mqtt_publish_failures_total counter");
+        let _ = writeln!(out, "boilert_mqtt_publish_failures_total {}", self.publish_failures);
+        let _ = writeln!(out, "# TYPE boilert_ui_queue_depth gauge");
+        let _ = writeln!(out, "boilert_ui_queue_depth {}", self.ui_queue_depth);
+        if let Some(rss) = self.memory_rss_bytes {
+            let _ = writeln!(out, "# TYPE boilert_memory_rss_bytes gauge");
+            let _ = writeln!(out, "boilert_memory_rss_bytes {}", rss);
+        }
+        out
+    }
+
+    /// Returns the metrics as `(name, value)` pairs, used for the `$sys` topics and
+    /// the diagnostics page.
+    pub fn items(&self) -> Vec<(String, String)> {
+        let mut items = vec![
+            ("uptime_s".to_string(), self.uptime.as_secs().to_string()),
+            ("loop_duration_ms".to_string(), self.loop_duration.as_millis().to_string()),
+            ("publish_failures".to_string(), self.publish_failures.to_string()),
+            ("ui_queue_depth".to_string(), self.ui_queue_depth.to_string()),
+        ];
+        if let Some(rss) = self.memory_rss_bytes {
+            items.push(("memory_rss_kb".to_string(), (rss / 1024).to_string()));
+        }
+        for (name, latency) in &self.read_latency {
+            items.push((format!("read_latency_ms/{}", name), latency.as_millis().to_string()));
+        }
+        for (name, count) in &self.implausible_values {
+            items.push((format!("implausible_values/{}", name), count.to_string()));
+        }
+        items
+    }
+}
+
+/// Renders the readings and the stored energy of an acquisition cycle in the
+/// Prometheus text exposition format. Invalid sensors are left out.
+pub fn snapshot_to_prometheus(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# TYPE boilert_temperature_celsius gauge");
+    for reading in snapshot.readings.iter().filter(|r| r.status.is_valid()) {
+        let _ = writeln!(
+            out,
+            "boilert_temperature_celsius{{sensor=\"{}\"}} {}",
+            reading.sensor, reading.value
+        );
+    }
+    let _ = writeln!(out, "# TYPE boilert_energy_kwh gauge");
+    let _ = writeln!(out, "boilert_energy_kwh {}", snapshot.energy.energy_kwh);
+    if let Some(pressure_bar) = snapshot.pressure_bar {
+        let _ = writeln!(out, "# TYPE boilert_pressure_bar gauge");
+        let _ = writeln!(out, "boilert_pressure_bar {}", pressure_bar);
+    }
+    out
+}
+
+/// Reads the resident set size of the current process from `/proc/self/status`.
+fn memory_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Periodically publishes the metrics to `{base_topic}/$sys/...` and to the
+/// diagnostics page.
+pub async fn publish_loop(
+    metrics: Metrics,
+    publisher: crate::mqtt::Publisher,
+    base_topic: String,
+    ui_weak: Option<slint::Weak<crate::AppWindow>>,
+) {
+    let mut interval = tokio::time::interval(METRICS_INTERVAL);
+    loop {
+        interval.tick().await;
+        let items = metrics.snapshot().items();
+
+        for (name, value) in &items {
+            let topic = format!("{}/$sys/{}", base_topic, name);
+            if publisher
+                .publish(topic, rumqttc::QoS::AtMostOnce, false, value.clone())
+                .await
+                .is_err()
+            {
+                metrics.record_publish_failure();
+            }
+        }
+
+        let Some(ui_weak) = ui_weak.clone() else {
+            continue;
+        };
+        let _ = slint::invoke_from_event_loop({
+            move || {
+                if let Some(ui) = ui_weak.upgrade() {
+                    let rows: Vec<crate::DiagnosticItem> = items
+                        .into_iter()
+                        .map(|(label, value)| crate::DiagnosticItem {
+                            label: label.into(),
+                            value: value.into(),
+                        })
+                        .collect();
+                    ui.set_diagnostics(slint::ModelRc::from(rows.as_slice()));
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,158 @@
+//! Thermostatic mixing valve monitoring.
+//! A sensor on the outlet of the mixing valve is watched for two failures: water hot
+//! enough to scald (immediate `mixing_scald` alert) and an outlet drifting away from
+//! the valve setpoint for too long (`mixing_deviation` alert), e.g., a scaled-up or
+//! stuck cartridge.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use boilert::model::Reading;
+use serde::Deserialize;
+
+use crate::alerts::Alerts;
+use crate::format::{self, Unit};
+use crate::messages::Message;
+
+/// Mixing valve settings from the `[mixing_valve]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MixingValveConfig {
+    /// Name of the sensor on the valve outlet.
+    pub sensor: String,
+    /// Outlet temperature set on the valve, in °C.
+    pub setpoint_c: f32,
+    /// Allowed deviation from the setpoint, in °C.
+    pub tolerance_c: f32,
+    /// Time outside the tolerance before the deviation alert, in minutes.
+    pub deviation_min: f32,
+    /// Raise the scald alert above this outlet temperature, in °C.
+    pub scald_above_c: f32,
+}
+
+impl Default for MixingValveConfig {
+    fn default() -> Self {
+        Self {
+            sensor: String::new(),
+            setpoint_c: 45.0,
+            tolerance_c: 3.0,
+            deviation_min: 10.0,
+            scald_above_c: 50.0,
+        }
+    }
+}
+
+impl MixingValveConfig {
+    /// Checks the settings against the configured sensor names.
+    pub fn validate(&self, sensor_names: &[String]) -> Result<()> {
+        if !sensor_names.contains(&self.sensor) {
+            bail!("[mixing_valve] unknown sensor {:?}", self.sensor);
+        }
+        if self.tolerance_c <= 0.0 {
+            bail!("[mixing_valve] tolerance_c must be positive");
+        }
+        if self.deviation_min < 0.0 {
+            bail!("[mixing_valve] deviation_min must not be negative");
+        }
+        Ok(())
+    }
+
+    fn deviation_delay(&self) -> Duration {
+        Duration::from_secs_f32(self.deviation_min * 60.0)
+    }
+}
+
+/// State of the valve outlet, as shown on the dashboard.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MixingStatus {
+    /// Outlet temperature, in °C.
+    pub value_c: f32,
+    /// `false` when the outlet sensor is invalid.
+    pub valid: bool,
+    pub setpoint_c: f32,
+    /// Outside the tolerance for longer than `deviation_min`.
+    pub deviating: bool,
+    /// Above the scald threshold.
+    pub scalding: bool,
+}
+
+/// Watches the outlet of the mixing valve across acquisition cycles.
+pub struct MixingValveMonitor {
+    config: MixingValveConfig,
+    /// Start of the current excursion outside the tolerance.
+    outside_since: Option<Instant>,
+    status: MixingStatus,
+}
+
+impl MixingValveMonitor {
+    pub fn new(config: &MixingValveConfig) -> Self {
+        Self {
+            config: config.clone(),
+            outside_since: None,
+            status: MixingStatus {
+                setpoint_c: config.setpoint_c,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Changes the setpoint and thresholds, keeping the excursion in progress.
+    pub fn set_config(&mut self, config: &MixingValveConfig) {
+        self.config = config.clone();
+        self.status.setpoint_c = config.setpoint_c;
+    }
+
+    /// Checks the outlet reading of this cycle and raises or clears the alerts.
+    /// Invalid readings leave the alerts as they are.
+    pub fn update(&mut self, readings: &[Reading], alerts: &Alerts) -> MixingStatus {
+        let config = &self.config;
+        let Some(reading) = readings.iter().find(|r| r.sensor == config.sensor) else {
+            return self.status;
+        };
+        self.status.value_c = reading.value;
+        self.status.valid = reading.status.is_valid();
+        if !self.status.valid {
+            return self.status;
+        }
+
+        let value = reading.value;
+        self.status.scalding = value > config.scald_above_c;
+        let text = |t| format::text(Unit::Celsius, t);
+        if self.status.scalding {
+            let message = Message::new(format!(
+                "Mixed hot water at {}, above {}: risk of scalding",
+                text(value),
+                text(config.scald_above_c)
+            ))
+            .with("sensor", &config.sensor)
+            .with("value", text(value))
+            .with("threshold", text(config.scald_above_c));
+            alerts.raise("mixing_scald", message);
+        } else {
+            alerts.clear("mixing_scald");
+        }
+
+        if (value - config.setpoint_c).abs() > config.tolerance_c {
+            let since = *self.outside_since.get_or_insert_with(Instant::now);
+            self.status.deviating = since.elapsed() >= config.deviation_delay();
+        } else {
+            self.outside_since = None;
+            self.status.deviating = false;
+        }
+        if self.status.deviating {
+            let message = Message::new(format!(
+                "Mixing valve outlet at {}, setpoint {}: check the valve",
+                text(value),
+                text(config.setpoint_c)
+            ))
+            .with("sensor", &config.sensor)
+            .with("value", text(value))
+            .with("threshold", text(config.setpoint_c))
+            .with("duration", format::duration(config.deviation_delay()));
+            alerts.raise("mixing_deviation", message);
+        } else {
+            alerts.clear("mixing_deviation");
+        }
+        self.status
+    }
+}
@@ -0,0 +1,147 @@
+//! Public data model of boilert.
+//!
+//! These types define the JSON schema of the values produced by the application.
+//! External frontends can depend on the `boilert` library to deserialize them.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Outcome of the last read of a sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorStatus {
+    /// The value was read successfully.
+    Ok,
+    /// The read failed; the value is the last valid one, held for a grace period.
+    Stale,
+    /// The read failed for longer than the grace period; the value is not meaningful.
+    Error,
+    /// The value was read but is outside the plausible range of the sensor; it is
+    /// shown for inspection only.
+    Suspect,
+}
+
+impl SensorStatus {
+    /// Returns `true` if the value can be used (published, stored, computed with).
+    pub fn is_valid(self) -> bool {
+        matches!(self, SensorStatus::Ok | SensorStatus::Stale)
+    }
+}
+
+/// A temperature reading of one sensor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reading {
+    /// Configured name of the sensor (e.g., "Top").
+    pub sensor: String,
+    /// Temperature in Celsius.
+    pub value: f32,
+    /// Relative humidity in percent, for sensors measuring it (e.g., SHT31).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub humidity: Option<f32>,
+    /// Atmospheric pressure in hPa, for sensors measuring it (e.g., BME280).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pressure_hpa: Option<f32>,
+    /// Outcome of the read.
+    pub status: SensorStatus,
+    /// Time of the read.
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Value of a sensor as published on MQTT with `payload = "json"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensorPayload {
+    /// Value, rounded for display; `null` when the read failed for longer than the grace period.
+    pub value: Option<f32>,
+    /// Unit symbol (e.g., "°C").
+    pub unit: String,
+    /// Time of the read.
+    pub ts: DateTime<Utc>,
+    /// Hardware ID of the sensor (absent for virtual sensors and the boiler pressure).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_id: Option<String>,
+    pub status: SensorStatus,
+}
+
+/// Thermal energy stored in the boiler.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnergyReport {
+    /// Energy above the reference temperature, in kWh.
+    pub energy_kwh: f32,
+    /// Average temperature of all sensors, in Celsius.
+    pub average_temp_c: f32,
+    /// Cold water reference temperature, in Celsius.
+    pub reference_temp_c: f32,
+    /// Volume of the boiler, in liters.
+    pub volume_l: f32,
+}
+
+/// A single active alert.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    /// Stable identifier of the alert (e.g., "task_acquisition").
+    pub id: String,
+    /// Human-readable description shown in the UI and published on MQTT.
+    pub message: String,
+}
+
+/// Complete state of the application after an acquisition cycle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Time of the acquisition cycle.
+    pub timestamp: DateTime<Utc>,
+    /// Readings of all configured sensors, in configuration order.
+    pub readings: Vec<Reading>,
+    /// Energy stored in the boiler.
+    pub energy: EnergyReport,
+    /// Boiler pressure in bar, if a transducer is configured and reads correctly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pressure_bar: Option<f32>,
+    /// Active alerts, oldest first.
+    pub alerts: Vec<Alert>,
+}
+
+/// Origin of a recorded action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditSource {
+    /// Touch screen.
+    Ui,
+    /// MQTT command topic.
+    Mqtt,
+    /// HTTP API.
+    Rest,
+    /// Scheduled action.
+    Schedule,
+    /// Command line (`boilert <subcommand>`).
+    Cli,
+    /// Edit of `config.toml`.
+    Config,
+    /// Scripting hook (`[scripting]`).
+    Script,
+}
+
+impl AuditSource {
+    /// Name of the source, as serialized.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditSource::Ui => "ui",
+            AuditSource::Mqtt => "mqtt",
+            AuditSource::Rest => "rest",
+            AuditSource::Schedule => "schedule",
+            AuditSource::Cli => "cli",
+            AuditSource::Config => "config",
+            AuditSource::Script => "script",
+        }
+    }
+}
+
+/// An entry of the audit log: a control action or a configuration change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub source: AuditSource,
+    /// What was done (e.g., "update installed").
+    pub action: String,
+    /// Parameters of the action.
+    pub detail: String,
+}
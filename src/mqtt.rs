@@ -0,0 +1,1424 @@
+//! MQTT connection handling.
+//! Builds the client options from the configuration and drives the event loop, over
+//! MQTT 3.1.1 or MQTT 5 (`protocol` in `[mqtt]`).
+//! Messages published while the broker is unreachable are buffered and sent once the
+//! connection is back, and reconnection attempts back off exponentially.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use rumqttc::v5::mqttbytes::v5::PublishProperties;
+use rumqttc::{
+    AsyncClient, ConnectReturnCode, ConnectionError, Event, EventLoop, LastWill, MqttOptions, Packet, QoS,
+    TlsConfiguration, Transport, v5,
+};
+use rand::Rng;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{WebPkiSupportedAlgorithms, ring};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::config::{MqttConfig, SensorConfig};
+use crate::health::Health;
+use crate::logging::{debug, info, warning};
+
+/// Builds the MQTT client options for the configured broker.
+///
+/// # Errors
+/// Returns an error if TLS is enabled and a certificate or key file cannot be loaded.
+pub fn options(config: &MqttConfig, client_id: &str) -> Result<MqttOptions> {
+    let mut mqttoptions = MqttOptions::new(client_id, &config.host, config.port);
+    mqttoptions.set_keep_alive(Duration::from_secs(config.keep_alive_s));
+    mqttoptions.set_clean_session(config.clean_session);
+    if let Some(username) = &config.username {
+        mqttoptions.set_credentials(username, config.password.as_deref().unwrap_or_default());
+    }
+    if let Some(transport) = transport(config)? {
+        mqttoptions.set_transport(transport);
+    }
+    Ok(mqttoptions)
+}
+
+/// TLS transport of the connection, if enabled.
+fn transport(config: &MqttConfig) -> Result<Option<Transport>> {
+    if !config.tls.enabled {
+        return Ok(None);
+    }
+    if config.tls.insecure_skip_verify {
+        warning!("MQTT: the broker certificate is not verified (insecure_skip_verify)");
+    }
+    let tls = config.tls.client_config()?;
+    Ok(Some(Transport::tls_with_config(TlsConfiguration::Rustls(Arc::new(tls)))))
+}
+
+/// Client ID of boilert on the broker: `client_id` in `[mqtt]`, or `boilert-{hostname}`
+/// so that instances on different hosts do not take over each other's connection.
+pub fn client_id(config: &MqttConfig) -> String {
+    if let Some(client_id) = &config.client_id {
+        return client_id.clone();
+    }
+    let hostname: String = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .unwrap_or_default()
+        .trim()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    if hostname.is_empty() {
+        "boilert".to_string()
+    } else {
+        format!("boilert-{}", hostname)
+    }
+}
+
+/// Creates the client of boilert and its connection, in the configured protocol
+/// version, with `offline` on the availability topic as last will.
+///
+/// Without a clean session, an MQTT 5 broker keeps the session for
+/// `buffer_max_age_s` of `[mqtt.offline]`, as older messages are not worth delivering.
+///
+/// # Errors
+/// Returns an error if TLS is enabled and a certificate or key file cannot be loaded.
+pub fn connect(config: &MqttConfig, client_id: &str, capacity: usize) -> Result<(Client, Connection)> {
+    Ok(match connect_options(config, client_id)? {
+        ConnectOptions::V3(options) => {
+            let (client, eventloop) = AsyncClient::new(*options, capacity);
+            (Client::V3(client), Connection::V3(Box::new(eventloop)))
+        }
+        ConnectOptions::V5(options) => {
+            let (client, eventloop) = v5::AsyncClient::new(*options, capacity);
+            (Client::V5(client), Connection::V5(Box::new(eventloop)))
+        }
+    })
+}
+
+/// Options of a connection in either protocol version.
+#[derive(Clone)]
+pub enum ConnectOptions {
+    V3(Box<MqttOptions>),
+    V5(Box<v5::MqttOptions>),
+}
+
+impl ConnectOptions {
+    fn broker_address(&self) -> (String, u16) {
+        match self {
+            ConnectOptions::V3(options) => options.broker_address(),
+            ConnectOptions::V5(options) => options.broker_address(),
+        }
+    }
+}
+
+/// Options of the connection of boilert to the broker of `config`, as made by [`connect`].
+///
+/// # Errors
+/// Returns an error if TLS is enabled and a certificate or key file cannot be loaded.
+pub fn connect_options(config: &MqttConfig, client_id: &str) -> Result<ConnectOptions> {
+    match config.protocol {
+        Protocol::V3 => {
+            let mut mqttoptions = options(config, client_id)?;
+            mqttoptions.set_last_will(LastWill::new(status_topic(config), "offline", QoS::AtLeastOnce, true));
+            Ok(ConnectOptions::V3(Box::new(mqttoptions)))
+        }
+        Protocol::V5 => {
+            let mut mqttoptions = v5::MqttOptions::new(client_id, &config.host, config.port);
+            mqttoptions.set_keep_alive(Duration::from_secs(config.keep_alive_s));
+            mqttoptions.set_clean_start(config.clean_session);
+            if !config.clean_session {
+                let mut properties = v5::mqttbytes::v5::ConnectProperties::new();
+                properties.session_expiry_interval =
+                    Some(u32::try_from(config.offline.buffer_max_age_s).unwrap_or(u32::MAX));
+                mqttoptions.set_connect_properties(properties);
+            }
+            if let Some(username) = &config.username {
+                mqttoptions.set_credentials(username, config.password.as_deref().unwrap_or_default());
+            }
+            if let Some(transport) = transport(config)? {
+                mqttoptions.set_transport(transport);
+            }
+            let will = v5::mqttbytes::v5::LastWill::new(
+                status_topic(config),
+                "offline",
+                v5_qos(QoS::AtLeastOnce),
+                true,
+                None,
+            );
+            mqttoptions.set_last_will(will);
+            Ok(ConnectOptions::V5(Box::new(mqttoptions)))
+        }
+    }
+}
+
+/// Another broker, from a `[[mqtt.brokers]]` section: the messages published under the
+/// base topic are also sent to it, under its own base topic and over a connection of its
+/// own, so that a broker out of reach does not hold back the others.
+#[derive(Debug, Deserialize, Clone)]
+pub struct BrokerConfig {
+    /// Name of the broker, in the logs.
+    pub name: String,
+    /// Hostname or IP of the broker.
+    pub host: String,
+    /// Port of the broker (usually 1883, 8883 with TLS).
+    pub port: u16,
+    /// Base topic on this broker (default: that of `[mqtt]`).
+    #[serde(default)]
+    pub base_topic: Option<String>,
+    /// MQTT protocol version, "3.1.1" or "5".
+    #[serde(default)]
+    pub protocol: Protocol,
+    /// Client ID on this broker (default: that of `[mqtt]`).
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// User name and password on this broker (anonymous if absent).
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// TLS connection to this broker.
+    #[serde(default)]
+    pub tls: MqttTlsConfig,
+}
+
+impl BrokerConfig {
+    /// Settings of the connection to this broker: its own, the rest from `[mqtt]`.
+    /// Discovery, the legacy topics and the failover stay with the main broker.
+    pub fn mqtt_config(&self, main: &MqttConfig) -> MqttConfig {
+        MqttConfig {
+            host: self.host.clone(),
+            port: self.port,
+            base_topic: self.base_topic.clone().unwrap_or_else(|| main.base_topic.clone()),
+            protocol: self.protocol,
+            client_id: self.client_id.clone().or_else(|| main.client_id.clone()),
+            username: self.username.clone(),
+            password: self.password.clone(),
+            tls: self.tls.clone(),
+            legacy: None,
+            discovery: None,
+            brokers: Vec::new(),
+            failover: None,
+            ..main.clone()
+        }
+    }
+}
+
+/// Secondary broker, from the `[mqtt.failover]` section: boilert moves its connection
+/// there when the main broker stays unreachable, and comes back once the main broker
+/// answers again.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FailoverConfig {
+    /// Hostname or IP of the secondary broker.
+    pub host: String,
+    /// Port of the secondary broker (default: that of `[mqtt]`).
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// User name and password on the secondary broker (default: those of `[mqtt]`).
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Time the broker in use stays unreachable before switching to the other, in seconds.
+    #[serde(default = "default_failover_after_s")]
+    pub after_s: u64,
+    /// Interval between two checks of the main broker while on the secondary, in seconds.
+    #[serde(default = "default_failover_check_s")]
+    pub check_interval_s: u64,
+}
+
+fn default_failover_after_s() -> u64 {
+    60
+}
+
+fn default_failover_check_s() -> u64 {
+    60
+}
+
+impl FailoverConfig {
+    /// Settings of the connection to the secondary broker: its own, the rest from `[mqtt]`.
+    pub fn mqtt_config(&self, main: &MqttConfig) -> MqttConfig {
+        let (username, password) = match &self.username {
+            Some(username) => (Some(username.clone()), self.password.clone()),
+            None => (main.username.clone(), main.password.clone()),
+        };
+        MqttConfig {
+            host: self.host.clone(),
+            port: self.port.unwrap_or(main.port),
+            username,
+            password,
+            failover: None,
+            ..main.clone()
+        }
+    }
+
+    /// Checks the host and the delays.
+    pub fn validate(&self) -> Result<()> {
+        if self.host.trim().is_empty() {
+            bail!("[mqtt.failover] host must not be empty");
+        }
+        if self.after_s == 0 {
+            bail!("[mqtt.failover] after_s must be at least 1");
+        }
+        if self.check_interval_s < 5 {
+            bail!("[mqtt.failover] check_interval_s must be at least 5");
+        }
+        Ok(())
+    }
+}
+
+/// MQTT protocol version (`protocol` in `[mqtt]`).
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// MQTT 3.1.1, supported by every broker.
+    #[default]
+    #[serde(rename = "3.1.1")]
+    V3,
+    /// MQTT 5, adding user properties and message expiry (see `[mqtt.v5]`).
+    #[serde(rename = "5")]
+    V5,
+}
+
+/// Properties of the messages published over MQTT 5, from the `[mqtt.v5]` section.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct V5Config {
+    /// Time after which the broker drops a sample of a sensor not yet delivered, in
+    /// seconds (default: never).
+    pub message_expiry_s: Option<u32>,
+    /// User properties attached to every message, in addition to `firmware_version` and,
+    /// on the topics of a sensor, `sensor_id` (e.g., `site = "home"`).
+    pub user_properties: BTreeMap<String, String>,
+}
+
+impl V5Config {
+    /// Checks the expiry and that the section is only set with MQTT 5.
+    pub fn validate(&self, protocol: Protocol) -> Result<()> {
+        if self.message_expiry_s == Some(0) {
+            bail!("[mqtt.v5] message_expiry_s must be greater than 0");
+        }
+        if protocol != Protocol::V5 && (self.message_expiry_s.is_some() || !self.user_properties.is_empty()) {
+            bail!("[mqtt.v5] requires protocol = \"5\" in [mqtt]");
+        }
+        Ok(())
+    }
+}
+
+/// QoS of an MQTT 5 message.
+fn v5_qos(qos: QoS) -> v5::mqttbytes::QoS {
+    match qos {
+        QoS::AtMostOnce => v5::mqttbytes::QoS::AtMostOnce,
+        QoS::AtLeastOnce => v5::mqttbytes::QoS::AtLeastOnce,
+        QoS::ExactlyOnce => v5::mqttbytes::QoS::ExactlyOnce,
+    }
+}
+
+/// Client handle of either protocol version.
+///
+/// Cloning a `Client` is cheap; all clones share the same connection.
+#[derive(Clone)]
+pub enum Client {
+    V3(AsyncClient),
+    V5(v5::AsyncClient),
+}
+
+impl Client {
+    /// Publishes a message, waiting for room in the request queue. The properties are
+    /// only sent over MQTT 5.
+    async fn publish(
+        &self,
+        topic: String,
+        qos: QoS,
+        retain: bool,
+        payload: Vec<u8>,
+        properties: PublishProperties,
+    ) -> Result<()> {
+        match self {
+            Client::V3(client) => client.publish(topic, qos, retain, payload).await?,
+            Client::V5(client) => {
+                client.publish_with_properties(topic, v5_qos(qos), retain, payload, properties).await?
+            }
+        }
+        Ok(())
+    }
+
+    /// Publishes a message without waiting; fails if the request queue is full.
+    fn try_publish(
+        &self,
+        topic: String,
+        qos: QoS,
+        retain: bool,
+        payload: Vec<u8>,
+        properties: PublishProperties,
+    ) -> Result<()> {
+        match self {
+            Client::V3(client) => client.try_publish(topic, qos, retain, payload)?,
+            Client::V5(client) => client.try_publish_with_properties(topic, v5_qos(qos), retain, payload, properties)?,
+        }
+        Ok(())
+    }
+
+    fn try_subscribe(&self, filter: String) -> Result<()> {
+        match self {
+            Client::V3(client) => client.try_subscribe(filter, QoS::AtLeastOnce)?,
+            Client::V5(client) => client.try_subscribe(filter, v5_qos(QoS::AtLeastOnce))?,
+        }
+        Ok(())
+    }
+}
+
+/// Event loop of either protocol version, driven by [`run_event_loop`].
+pub enum Connection {
+    V3(Box<EventLoop>),
+    V5(Box<v5::EventLoop>),
+}
+
+/// What the event loop reports to boilert.
+enum Received {
+    /// The broker accepted the connection.
+    Connected,
+    /// A message on a subscribed topic.
+    Message(String, Vec<u8>),
+    Other,
+}
+
+impl Connection {
+    /// Host and port of the broker the connection goes to.
+    fn broker_address(&self) -> (String, u16) {
+        match self {
+            Connection::V3(eventloop) => eventloop.mqtt_options.broker_address(),
+            Connection::V5(eventloop) => eventloop.options.broker_address(),
+        }
+    }
+
+    /// Drops the current connection; the next poll connects with `options`. The messages
+    /// not acknowledged yet are sent again on the new connection.
+    fn reconnect_with(&mut self, options: &ConnectOptions) {
+        match (self, options) {
+            (Connection::V3(eventloop), ConnectOptions::V3(options)) => {
+                eventloop.mqtt_options = (**options).clone();
+                eventloop.clean();
+            }
+            (Connection::V5(eventloop), ConnectOptions::V5(options)) => {
+                eventloop.options = (**options).clone();
+                eventloop.clean();
+            }
+            _ => {}
+        }
+    }
+
+    /// Sends the pending requests and waits for the next packet, connecting first if needed.
+    async fn poll(&mut self) -> Result<Received> {
+        Ok(match self {
+            Connection::V3(eventloop) => match eventloop.poll().await? {
+                Event::Incoming(Packet::ConnAck(_)) => Received::Connected,
+                Event::Incoming(Packet::Publish(publish)) => Received::Message(publish.topic, publish.payload.to_vec()),
+                _ => Received::Other,
+            },
+            Connection::V5(eventloop) => match eventloop.poll().await? {
+                v5::Event::Incoming(v5::mqttbytes::v5::Packet::ConnAck(_)) => Received::Connected,
+                v5::Event::Incoming(v5::mqttbytes::v5::Packet::Publish(publish)) => {
+                    Received::Message(String::from_utf8_lossy(&publish.topic).into_owned(), publish.payload.to_vec())
+                }
+                _ => Received::Other,
+            },
+        })
+    }
+}
+
+/// TLS settings of the broker connection, from the `[mqtt.tls]` section.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MqttTlsConfig {
+    /// Connect over TLS (brokers usually listen for TLS on port 8883).
+    pub enabled: bool,
+    /// PEM file of the CA certificates the broker certificate is checked against
+    /// (default: the CAs of the system).
+    pub ca_path: Option<String>,
+    /// PEM certificate and private key presented to brokers that authenticate clients
+    /// by certificate.
+    pub client_cert_path: Option<String>,
+    pub client_key_path: Option<String>,
+    /// Accept any broker certificate: the connection is encrypted but the broker is not
+    /// authenticated. For testing only.
+    pub insecure_skip_verify: bool,
+}
+
+impl MqttTlsConfig {
+    /// Checks the settings and, if TLS is enabled, that the certificate files load.
+    pub fn validate(&self) -> Result<()> {
+        if self.client_cert_path.is_some() != self.client_key_path.is_some() {
+            bail!("[mqtt.tls] client_cert_path and client_key_path must be set together");
+        }
+        if self.enabled {
+            self.client_config()?;
+        }
+        Ok(())
+    }
+
+    /// Builds the TLS client configuration from the certificate files.
+    fn client_config(&self) -> Result<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        match &self.ca_path {
+            Some(path) => {
+                for cert in read_certs(path)? {
+                    roots
+                        .add(cert)
+                        .map_err(|e| anyhow!("[mqtt.tls] invalid CA certificate in {}: {}", path, e))?;
+                }
+            }
+            None => {
+                let native = rustls_native_certs::load_native_certs()
+                    .context("[mqtt.tls] failed to load the CA certificates of the system")?;
+                // Like browsers, skip the system certificates that cannot be parsed
+                roots.add_parsable_certificates(native);
+            }
+        }
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+        let mut config = match (&self.client_cert_path, &self.client_key_path) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(read_certs(cert)?, read_key(key)?)
+                .context("[mqtt.tls] invalid client certificate or key")?,
+            _ => builder.with_no_client_auth(),
+        };
+        if self.insecure_skip_verify {
+            let algorithms = ring::default_provider().signature_verification_algorithms;
+            config.dangerous().set_certificate_verifier(Arc::new(AcceptAnyCertificate(algorithms)));
+        }
+        Ok(config)
+    }
+}
+
+/// Reads the certificates of a PEM file.
+fn read_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = std::fs::File::open(path).with_context(|| format!("[mqtt.tls] failed to open {}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("[mqtt.tls] failed to read {}", path))?;
+    if certs.is_empty() {
+        bail!("[mqtt.tls] no certificate in {}", path);
+    }
+    Ok(certs)
+}
+
+/// Reads the first private key (PKCS#8, RSA or EC) of a PEM file.
+fn read_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = std::fs::File::open(path).with_context(|| format!("[mqtt.tls] failed to open {}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("[mqtt.tls] failed to read {}", path))?
+        .with_context(|| format!("[mqtt.tls] no private key in {}", path))
+}
+
+/// Verifier accepting any broker certificate (`insecure_skip_verify`). The handshake
+/// signatures are still checked against the certificate presented.
+#[derive(Debug)]
+struct AcceptAnyCertificate(WebPkiSupportedAlgorithms);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_schemes()
+    }
+}
+
+/// Birth message of boilert, published (retained) on `{base_topic}/info` on every
+/// connection, so that remote monitoring can tell which version and configuration a
+/// device runs.
+#[derive(Debug, Serialize)]
+pub struct DeviceInfo {
+    pub version: &'static str,
+    /// Fingerprint of `config.toml`, as in the audit log.
+    pub config_hash: Option<String>,
+    pub sensors: Vec<SensorInfo>,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Sensor listed in the birth message.
+#[derive(Debug, Serialize)]
+pub struct SensorInfo {
+    pub name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub id: String,
+}
+
+impl DeviceInfo {
+    pub fn new(config_hash: Option<String>, sensors: &[SensorConfig]) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            config_hash,
+            sensors: sensors
+                .iter()
+                .map(|sensor| SensorInfo {
+                    name: sensor.name.clone(),
+                    id: sensor.id.clone(),
+                })
+                .collect(),
+            started_at: Utc::now(),
+        }
+    }
+}
+
+/// Availability of boilert on `{base_topic}/status`: `online` (retained) on every
+/// connection, `offline` published by the broker as the last will when the connection
+/// is lost, or by boilert itself on a clean exit. The birth message, if any, is
+/// published with `online`.
+#[derive(Clone)]
+pub struct Availability {
+    client: Client,
+    topic: String,
+    info_topic: String,
+    info: Option<Arc<Vec<u8>>>,
+}
+
+impl Availability {
+    pub fn new(client: Client, config: &MqttConfig) -> Self {
+        Self {
+            client,
+            topic: status_topic(config),
+            info_topic: format!("{}/info", config.base_topic),
+            info: None,
+        }
+    }
+
+    /// Publishes `info` on `{base_topic}/info` on every connection.
+    pub fn with_info(mut self, info: &DeviceInfo) -> Self {
+        match serde_json::to_vec(info) {
+            Ok(payload) => self.info = Some(Arc::new(payload)),
+            Err(e) => warning!("MQTT: failed to encode the birth message: {}", e),
+        }
+        self
+    }
+
+    fn set_online(&self) {
+        if let Some(info) = &self.info {
+            let info = info.to_vec();
+            let _ = self.client.try_publish(self.info_topic.clone(), QoS::AtLeastOnce, true, info, Default::default());
+        }
+        let online = b"online".to_vec();
+        let _ = self.client.try_publish(self.topic.clone(), QoS::AtLeastOnce, true, online, Default::default());
+    }
+
+    /// Publishes `offline` before a clean exit, without waiting for the broker to notice.
+    pub async fn set_offline(&self) {
+        let offline = b"offline".to_vec();
+        if self.client.publish(self.topic.clone(), QoS::AtLeastOnce, true, offline, Default::default()).await.is_ok() {
+            // Give the event loop a chance to send it
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+}
+
+/// Availability topic of boilert.
+pub fn status_topic(config: &MqttConfig) -> String {
+    format!("{}/status", config.base_topic)
+}
+
+/// Retain flags of the values published each cycle, from the `[mqtt.retain]` section.
+/// A retained value is handed by the broker to every new subscriber, so a dashboard that
+/// reconnects shows the latest value at once instead of waiting for the next cycle.
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(default)]
+pub struct RetainConfig {
+    /// Temperatures and other quantities of the sensors, virtual sensors included.
+    pub sensors: bool,
+    /// Minimum, maximum and average of the sensor groups.
+    pub groups: bool,
+    /// Energy stored in the tank.
+    pub energy: bool,
+    /// Boiler pressure.
+    pub pressure: bool,
+    /// Acquisition time of the cycle.
+    pub timestamp: bool,
+    /// State document of the cycle (JSON payload format).
+    pub state: bool,
+}
+
+/// Buffering of the messages published while the broker is unreachable, and delays
+/// between reconnection attempts, from the `[mqtt.offline]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct OfflineConfig {
+    /// Messages kept while the broker is unreachable, the oldest dropped first
+    /// (0 disables the buffer).
+    pub buffer_messages: usize,
+    /// Age after which a buffered message is dropped, in seconds.
+    pub buffer_max_age_s: u64,
+    /// Delay before the first reconnection attempt, in seconds, doubled after each failure.
+    pub reconnect_min_s: f32,
+    /// Upper bound of the reconnection delay, in seconds.
+    pub reconnect_max_s: f32,
+}
+
+impl Default for OfflineConfig {
+    fn default() -> Self {
+        Self {
+            buffer_messages: 1000,
+            buffer_max_age_s: 3600,
+            reconnect_min_s: 1.0,
+            reconnect_max_s: 60.0,
+        }
+    }
+}
+
+impl OfflineConfig {
+    /// Checks the reconnection delays.
+    pub fn validate(&self) -> Result<()> {
+        if !(self.reconnect_min_s > 0.0 && self.reconnect_max_s >= self.reconnect_min_s) {
+            bail!("[mqtt.offline] reconnect_min_s must be greater than 0 and not exceed reconnect_max_s");
+        }
+        Ok(())
+    }
+}
+
+/// Format of the sensor values published on MQTT (`payload` in `[mqtt]`).
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    /// The bare number, or `unavailable`.
+    #[default]
+    Plain,
+    /// A JSON document with the unit, time, sensor ID and status
+    /// ([`boilert::model::SensorPayload`]), and a state document of each cycle.
+    Json,
+}
+
+/// Legacy topic scheme, from the `[mqtt.legacy]` section.
+///
+/// During the transition period, every value published under the base topic is
+/// also published under the legacy scheme, so existing automations keep working
+/// while they are moved to the new topics.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LegacyTopicsConfig {
+    /// Base topic of the legacy scheme (e.g., "boiler").
+    pub base_topic: String,
+    /// Last day the legacy topics are published, as "YYYY-MM-DD" (default: forever).
+    #[serde(default)]
+    pub until: Option<NaiveDate>,
+    /// Legacy name of topics that were renamed, keyed by the new name relative to the
+    /// base topic (e.g., `"heater/energy_kwh" = "heater_energy"`).
+    #[serde(default)]
+    pub rename: HashMap<String, String>,
+}
+
+impl LegacyTopicsConfig {
+    /// Returns `true` while the transition period lasts.
+    pub fn is_active(&self) -> bool {
+        self.until.is_none_or(|until| Local::now().date_naive() <= until)
+    }
+
+    /// Legacy topic of a topic relative to the new base topic.
+    fn topic(&self, suffix: &str) -> String {
+        let suffix = self.rename.get(suffix).map(String::as_str).unwrap_or(suffix);
+        format!("{}/{}", self.base_topic, suffix)
+    }
+}
+
+/// QoS level of a `qos` setting.
+///
+/// # Errors
+/// Returns an error if the level is not 0, 1 or 2.
+pub fn qos_level(level: u8) -> Result<QoS> {
+    match level {
+        0 => Ok(QoS::AtMostOnce),
+        1 => Ok(QoS::AtLeastOnce),
+        2 => Ok(QoS::ExactlyOnce),
+        _ => bail!("QoS must be 0, 1 or 2, not {}", level),
+    }
+}
+
+/// QoS chosen in the configuration instead of the one of the message.
+#[derive(Debug, Default)]
+struct QosPolicy {
+    /// `qos` of `[mqtt]`.
+    default: Option<QoS>,
+    /// `[mqtt.qos_topics]`, the most specific filters first.
+    topics: Vec<(String, QoS)>,
+}
+
+impl QosPolicy {
+    fn new(config: &MqttConfig) -> Self {
+        let mut topics: Vec<(String, QoS)> = config
+            .qos_topics
+            .iter()
+            .filter_map(|(filter, &level)| Some((filter.clone(), qos_level(level).ok()?)))
+            .collect();
+        // Exact topics first, then the filters with the fewest wildcards, the longest first
+        topics.sort_by_key(|(filter, _)| {
+            (filter.ends_with('#'), filter.split('/').filter(|&level| level == "+").count(), usize::MAX - filter.len())
+        });
+        Self {
+            default: config.qos.and_then(|level| qos_level(level).ok()),
+            topics,
+        }
+    }
+
+    /// QoS of a message on `suffix` (topic relative to the base topic, if under it).
+    fn qos(&self, suffix: Option<&str>, requested: QoS) -> QoS {
+        suffix
+            .and_then(|suffix| self.topics.iter().find(|(filter, _)| topic_matches(filter, suffix)))
+            .map(|&(_, qos)| qos)
+            .or(self.default)
+            .unwrap_or(requested)
+    }
+}
+
+/// A message published while the broker was unreachable.
+struct Queued {
+    topic: String,
+    qos: QoS,
+    retain: bool,
+    payload: Vec<u8>,
+    at: Instant,
+}
+
+/// Messages waiting for the connection to the broker.
+#[derive(Default)]
+struct Outbox {
+    /// Set when the broker accepts the connection, cleared on a connection error.
+    connected: bool,
+    /// The buffered messages are being sent: new messages queue behind them, so that an
+    /// old retained value never replaces a newer one.
+    flushing: bool,
+    queue: VecDeque<Queued>,
+    /// Messages dropped since the connection was lost (QoS 0, buffer full or too old).
+    dropped: usize,
+}
+
+impl Outbox {
+    /// Drops the messages older than `max_age`.
+    fn expire(&mut self, max_age: Duration) {
+        while self.queue.front().is_some_and(|message| message.at.elapsed() > max_age) {
+            self.queue.pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+/// Properties of the messages published over MQTT 5.
+#[derive(Debug, Clone, Default)]
+struct Properties {
+    /// `firmware_version` and `[mqtt.v5] user_properties`, sent with every message.
+    common: Vec<(String, String)>,
+    /// ID of each sensor, keyed by name, sent as `sensor_id` with its samples.
+    sensor_ids: HashMap<String, String>,
+    message_expiry_s: Option<u32>,
+}
+
+impl Properties {
+    fn new(config: &MqttConfig) -> Self {
+        let mut common = vec![("firmware_version".to_string(), env!("CARGO_PKG_VERSION").to_string())];
+        common.extend(config.v5.user_properties.iter().map(|(name, value)| (name.clone(), value.clone())));
+        Self {
+            common,
+            sensor_ids: HashMap::new(),
+            message_expiry_s: config.v5.message_expiry_s,
+        }
+    }
+
+    /// ID of the sensor whose sample is published on `suffix` (topic relative to the
+    /// base topic, if under it): samples go to `{base_topic}/{sensor}` and the topics below.
+    fn sensor_id(&self, suffix: Option<&str>) -> Option<&String> {
+        self.sensor_ids.get(suffix?.split('/').next()?)
+    }
+
+    /// Seconds left before a sample on `suffix` published `age` ago expires, if it does.
+    fn expiry(&self, suffix: Option<&str>, age: Duration) -> Option<u64> {
+        self.sensor_id(suffix)?;
+        Some(u64::from(self.message_expiry_s?).saturating_sub(age.as_secs()))
+    }
+
+    /// Properties of a message on `suffix` published `age` ago.
+    fn get(&self, suffix: Option<&str>, age: Duration) -> PublishProperties {
+        let mut user_properties = self.common.clone();
+        if let Some(id) = self.sensor_id(suffix) {
+            user_properties.push(("sensor_id".to_string(), id.clone()));
+        }
+        PublishProperties {
+            user_properties,
+            message_expiry_interval: self.expiry(suffix, age).map(|seconds| seconds.max(1) as u32),
+            ..Default::default()
+        }
+    }
+}
+
+/// Publishing handle that applies the configured QoS, mirrors the values to the legacy
+/// topics during the transition period and buffers the messages while the broker is
+/// unreachable. Over MQTT 5, the messages carry the user properties and the samples
+/// expire after `[mqtt.v5] message_expiry_s`.
+///
+/// Cloning a `Publisher` is cheap; all clones share the same client and buffer.
+#[derive(Clone)]
+pub struct Publisher {
+    client: Client,
+    base_topic: String,
+    /// Publishers of the other brokers, which get a copy of the messages under the base topic.
+    mirrors: Arc<Vec<Publisher>>,
+    legacy: Option<Arc<LegacyTopicsConfig>>,
+    qos: Arc<QosPolicy>,
+    properties: Arc<Properties>,
+    outbox: Arc<std::sync::Mutex<Outbox>>,
+    /// Size of the buffer, in messages.
+    capacity: usize,
+    max_age: Duration,
+}
+
+impl Publisher {
+    pub fn new(client: Client, config: &MqttConfig) -> Self {
+        let legacy = config.legacy.clone().filter(|legacy| {
+            if !legacy.is_active() {
+                warning!("MQTT: the legacy topics under {} are no longer published", legacy.base_topic);
+            }
+            legacy.is_active()
+        });
+        Self {
+            client,
+            base_topic: config.base_topic.clone(),
+            mirrors: Arc::new(Vec::new()),
+            legacy: legacy.map(Arc::new),
+            qos: Arc::new(QosPolicy::new(config)),
+            properties: Arc::new(Properties::new(config)),
+            outbox: Arc::new(std::sync::Mutex::new(Outbox::default())),
+            capacity: config.offline.buffer_messages,
+            max_age: Duration::from_secs(config.offline.buffer_max_age_s),
+        }
+    }
+
+    /// Sends the ID of the sensors as the `sensor_id` property of their samples.
+    pub fn with_sensor_ids(mut self, sensors: &[SensorConfig]) -> Self {
+        let properties = Arc::make_mut(&mut self.properties);
+        properties.sensor_ids = sensors
+            .iter()
+            .filter(|sensor| !sensor.id.is_empty())
+            .map(|sensor| (sensor.name.clone(), sensor.id.clone()))
+            .collect();
+        self
+    }
+
+    /// Also publishes the messages under the base topic with `mirrors`, the publishers
+    /// of the other brokers.
+    pub fn with_mirrors(mut self, mirrors: Vec<Publisher>) -> Self {
+        self.mirrors = Arc::new(mirrors);
+        self
+    }
+
+    /// Publishes a message, waiting for room in the request queue. `qos` is used unless
+    /// the configuration sets another one for the topic.
+    pub async fn publish(
+        &self,
+        topic: impl Into<String>,
+        qos: QoS,
+        retain: bool,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<()> {
+        let topic = topic.into();
+        let payload = payload.into();
+        let qos = self.qos.qos(self.suffix(&topic), qos);
+        self.mirror(&topic, qos, retain, &payload);
+        let legacy = self.legacy_topic(&topic);
+        self.send(topic, qos, retain, payload.clone()).await?;
+        self.copy_to_legacy(legacy, qos, retain, payload);
+        Ok(())
+    }
+
+    /// Publishes a message without waiting; fails if the request queue is full. `qos` is
+    /// used unless the configuration sets another one for the topic.
+    pub fn try_publish(
+        &self,
+        topic: impl Into<String>,
+        qos: QoS,
+        retain: bool,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<()> {
+        let topic = topic.into();
+        let payload = payload.into();
+        let qos = self.qos.qos(self.suffix(&topic), qos);
+        self.mirror(&topic, qos, retain, &payload);
+        let legacy = self.legacy_topic(&topic);
+        self.try_send(topic, qos, retain, payload.clone())?;
+        self.copy_to_legacy(legacy, qos, retain, payload);
+        Ok(())
+    }
+
+    /// Sends the copy of a message under its `legacy` topic, if any. The copy is lost when
+    /// the request queue is full: it must not hold back the current topics.
+    fn copy_to_legacy(&self, legacy: Option<String>, qos: QoS, retain: bool, payload: Vec<u8>) {
+        if let Some(legacy) = legacy
+            && self.try_send(legacy, qos, retain, payload).is_err()
+        {
+            self.outbox().dropped += 1;
+        }
+    }
+
+    /// Publishes a command to another device without waiting. Unlike [`Self::try_publish`],
+    /// the command is never buffered: it fails while the broker is unreachable rather than
+    /// reaching the device long after it was given.
+    pub fn try_publish_now(
+        &self,
+        topic: impl Into<String>,
+        qos: QoS,
+        retain: bool,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<()> {
+        let topic = topic.into();
+        if !self.outbox().connected {
+            bail!("the MQTT broker is unreachable");
+        }
+        let properties = self.properties(&topic, Duration::ZERO);
+        self.client.try_publish(topic, qos, retain, payload.into(), properties)
+    }
+
+    /// Sends a copy of a message under the base topic to the other brokers, under their
+    /// own base topic. A broker that cannot keep up loses the copy, it must not hold
+    /// back the others.
+    fn mirror(&self, topic: &str, qos: QoS, retain: bool, payload: &[u8]) {
+        let Some(suffix) = self.suffix(topic) else {
+            return;
+        };
+        for mirror in self.mirrors.iter() {
+            let topic = format!("{}/{}", mirror.base_topic, suffix);
+            if mirror.try_send(topic, qos, retain, payload.to_vec()).is_err() {
+                mirror.outbox().dropped += 1;
+            }
+        }
+    }
+
+    async fn send(&self, topic: String, qos: QoS, retain: bool, payload: Vec<u8>) -> Result<()> {
+        let properties = self.properties(&topic, Duration::ZERO);
+        match self.hold(topic, qos, retain, payload) {
+            Some(message) => self.client.publish(message.topic, qos, retain, message.payload, properties).await,
+            None => Ok(()),
+        }
+    }
+
+    fn try_send(&self, topic: String, qos: QoS, retain: bool, payload: Vec<u8>) -> Result<()> {
+        let properties = self.properties(&topic, Duration::ZERO);
+        match self.hold(topic, qos, retain, payload) {
+            Some(message) => self.client.try_publish(message.topic, qos, retain, message.payload, properties),
+            None => Ok(()),
+        }
+    }
+
+    /// MQTT 5 properties of a message on `topic` published `age` ago.
+    fn properties(&self, topic: &str, age: Duration) -> PublishProperties {
+        self.properties.get(self.suffix(topic), age)
+    }
+
+    fn outbox(&self) -> std::sync::MutexGuard<'_, Outbox> {
+        self.outbox.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Buffers a message if the broker is unreachable or buffered messages are still
+    /// being sent. Returns it if it can be sent right away.
+    ///
+    /// While the broker is unreachable, QoS 0 messages are dropped, as the broker would
+    /// be allowed to do.
+    fn hold(&self, topic: String, qos: QoS, retain: bool, payload: Vec<u8>) -> Option<Queued> {
+        let message = Queued {
+            topic,
+            qos,
+            retain,
+            payload,
+            at: Instant::now(),
+        };
+        let mut outbox = self.outbox();
+        if outbox.connected && !outbox.flushing {
+            return Some(message);
+        }
+        if !outbox.connected && (qos == QoS::AtMostOnce || self.capacity == 0) {
+            outbox.dropped += 1;
+            return None;
+        }
+        outbox.expire(self.max_age);
+        if outbox.queue.len() >= self.capacity.max(1) {
+            outbox.queue.pop_front();
+            outbox.dropped += 1;
+        }
+        outbox.queue.push_back(message);
+        None
+    }
+
+    /// Records the state of the connection. Once it is back, the buffered messages are
+    /// sent in the order they were published.
+    pub fn set_connected(&self, connected: bool) {
+        let mut outbox = self.outbox();
+        outbox.connected = connected;
+        if connected && !outbox.flushing {
+            outbox.flushing = true;
+            tokio::spawn(self.clone().flush());
+        }
+    }
+
+    /// Sends the buffered messages, until the buffer is empty or the connection is lost.
+    async fn flush(self) {
+        let mut sent = 0;
+        loop {
+            let message = {
+                let mut outbox = self.outbox();
+                outbox.expire(self.max_age);
+                let next = if outbox.connected { outbox.queue.pop_front() } else { None };
+                let Some(message) = next else {
+                    // Once lost again, the next connection takes over
+                    outbox.flushing = false;
+                    if outbox.connected && (sent > 0 || outbox.dropped > 0) {
+                        info!("MQTT: {} buffered messages sent, {} dropped", sent, outbox.dropped);
+                        outbox.dropped = 0;
+                    }
+                    return;
+                };
+                message
+            };
+            let age = message.at.elapsed();
+            if self.properties.expiry(self.suffix(&message.topic), age) == Some(0) {
+                // The broker would drop the sample anyway
+                self.outbox().dropped += 1;
+                continue;
+            }
+            let properties = self.properties(&message.topic, age);
+            let result = self.client.publish(message.topic, message.qos, message.retain, message.payload, properties);
+            if result.await.is_err() {
+                self.outbox().flushing = false;
+                return;
+            }
+            sent += 1;
+        }
+    }
+
+    /// Topic relative to the base topic, if it is under it.
+    fn suffix<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        topic.strip_prefix(&self.base_topic)?.strip_prefix('/')
+    }
+
+    /// Legacy counterpart of `topic`, if it is under the base topic and the transition
+    /// period has not ended.
+    fn legacy_topic(&self, topic: &str) -> Option<String> {
+        let legacy = self.legacy.as_ref().filter(|legacy| legacy.is_active())?;
+        let suffix = self.suffix(topic)?;
+        Some(legacy.topic(suffix)).filter(|legacy| legacy != topic)
+    }
+}
+
+/// Callback receiving the topic and payload of a message.
+pub type MessageHandler = Arc<dyn Fn(&str, &[u8]) + Send + Sync>;
+
+/// Registry of subscribed topic filters and their handlers.
+///
+/// Subscriptions are re-sent on every (re)connection, and incoming messages are
+/// dispatched to the handlers of all matching filters by the event loop.
+#[derive(Clone)]
+pub struct Subscriptions {
+    client: Client,
+    handlers: Arc<std::sync::Mutex<Vec<(String, MessageHandler)>>>,
+}
+
+impl Subscriptions {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            handlers: Arc::new(std::sync::Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Subscribes to `filter` (MQTT wildcards allowed) and calls `handler` for every
+    /// matching message.
+    pub fn subscribe(&self, filter: impl Into<String>, handler: impl Fn(&str, &[u8]) + Send + Sync + 'static) {
+        let filter = filter.into();
+        let _ = self.client.try_subscribe(filter.clone());
+        self.handlers().push((filter, Arc::new(handler)));
+    }
+
+    fn handlers(&self) -> std::sync::MutexGuard<'_, Vec<(String, MessageHandler)>> {
+        self.handlers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Re-sends all subscriptions, after a (re)connection with a clean session.
+    fn resubscribe(&self) {
+        for (filter, _) in self.handlers().iter() {
+            let _ = self.client.try_subscribe(filter.clone());
+        }
+    }
+
+    /// Calls the handlers of all filters matching `topic`.
+    fn dispatch(&self, topic: &str, payload: &[u8]) {
+        debug!("MQTT: received {} ({} bytes)", topic, payload.len());
+        // Handlers are called outside of the lock so they may subscribe in turn
+        let handlers: Vec<MessageHandler> = self
+            .handlers()
+            .iter()
+            .filter(|(filter, _)| topic_matches(filter, topic))
+            .map(|(_, handler)| handler.clone())
+            .collect();
+        for handler in handlers {
+            handler(topic, payload);
+        }
+    }
+}
+
+/// Returns `true` if `topic` matches the MQTT topic `filter` (`+` and `#` wildcards).
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for filter_level in filter.split('/') {
+        match (filter_level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (f, Some(t)) if f == t => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+/// Delay before reconnecting to a broker.
+///
+/// After an error, the next attempt waits twice as long as the previous one, from
+/// `reconnect_min_s` up to `reconnect_max_s`, minus a random part of up to half the
+/// delay so that devices cut off together do not all come back at once.
+struct Backoff {
+    min: Duration,
+    max: Duration,
+    delay: Duration,
+}
+
+impl Backoff {
+    fn new(offline: &OfflineConfig) -> Self {
+        let min = Duration::from_secs_f32(offline.reconnect_min_s);
+        Self {
+            min,
+            max: Duration::from_secs_f32(offline.reconnect_max_s),
+            delay: min,
+        }
+    }
+
+    /// Starts over from the shortest delay, once connected.
+    fn reset(&mut self) {
+        self.delay = self.min;
+    }
+
+    /// Delay before the next attempt.
+    fn next(&mut self) -> Duration {
+        let wait = self.delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+        self.delay = (self.delay * 2).min(self.max);
+        wait
+    }
+}
+
+/// Connections to the main broker and to the secondary one of `[mqtt.failover]`, with
+/// the same client.
+#[derive(Clone)]
+pub struct Failover {
+    config: FailoverConfig,
+    main: ConnectOptions,
+    secondary: ConnectOptions,
+    client: Client,
+    /// Topic telling which broker is in use (retained).
+    topic: String,
+}
+
+impl Failover {
+    /// Builds the options of both connections of `client`.
+    ///
+    /// # Errors
+    /// Returns an error if TLS is enabled and a certificate or key file cannot be loaded.
+    pub fn new(config: &MqttConfig, failover: &FailoverConfig, client_id: &str, client: Client) -> Result<Self> {
+        Ok(Self {
+            config: failover.clone(),
+            main: connect_options(config, client_id)?,
+            secondary: connect_options(&failover.mqtt_config(config), client_id)?,
+            client,
+            topic: format!("{}/broker", config.base_topic),
+        })
+    }
+
+    /// Publishes the broker in use on the broker itself.
+    fn announce(&self, on_secondary: bool) {
+        let role = if on_secondary { "secondary" } else { "primary" };
+        let _ = self.client.try_publish(self.topic.clone(), QoS::AtLeastOnce, true, role.into(), Default::default());
+    }
+}
+
+/// Returns `true` if something accepts TCP connections at `address`.
+async fn is_reachable((host, port): (String, u16)) -> bool {
+    let connect = tokio::net::TcpStream::connect((host.as_str(), port));
+    matches!(tokio::time::timeout(Duration::from_secs(5), connect).await, Ok(Ok(_)))
+}
+
+/// Polls the event loop forever, reconnecting after errors, reporting the
+/// connection state to `health`, the publisher and on the availability topic, and
+/// dispatching incoming messages.
+///
+/// With `failover`, the connection moves to the other broker once the broker in use
+/// stayed unreachable for `after_s`; while on the secondary broker, the main one is
+/// checked every `check_interval_s` and used again as soon as it accepts connections.
+///
+/// The event loop is shared so that a restarted MQTT task keeps the same
+/// connection state and the client handle stays valid.
+pub async fn run_event_loop(
+    eventloop: Arc<Mutex<Connection>>,
+    health: Health,
+    subscriptions: Subscriptions,
+    availability: Availability,
+    publisher: Publisher,
+    offline: OfflineConfig,
+    failover: Option<Failover>,
+) {
+    let mut backoff = Backoff::new(&offline);
+    let mut eventloop = eventloop.lock().await;
+    // A restarted task finds the connection where the previous one left it
+    let mut on_secondary = failover.as_ref().is_some_and(|f| eventloop.broker_address() != f.main.broker_address());
+    let mut unreachable_since: Option<Instant> = None;
+    let mut next_check = Instant::now();
+    let mut main_check: Option<tokio::task::JoinHandle<bool>> = None;
+    loop {
+        match eventloop.poll().await {
+            Ok(Received::Connected) => {
+                health.set_mqtt_connected(true);
+                backoff.reset();
+                unreachable_since = None;
+                subscriptions.resubscribe();
+                // Replaces the `offline` left by the last will of a previous connection
+                availability.set_online();
+                publisher.set_connected(true);
+                if let Some(failover) = &failover {
+                    failover.announce(on_secondary);
+                    let (host, port) = eventloop.broker_address();
+                    health.set_mqtt_failover(on_secondary.then(|| format!("{}:{}", host, port)));
+                }
+            }
+            Ok(Received::Message(topic, payload)) => subscriptions.dispatch(&topic, &payload),
+            Ok(Received::Other) => {}
+            Err(e) => {
+                health.set_mqtt_connected(false);
+                publisher.set_connected(false);
+                let since = *unreachable_since.get_or_insert_with(Instant::now);
+                if let Some(failover) = &failover
+                    && since.elapsed() >= Duration::from_secs(failover.config.after_s)
+                {
+                    on_secondary = !on_secondary;
+                    let options = if on_secondary { &failover.secondary } else { &failover.main };
+                    let (host, port) = options.broker_address();
+                    let after_s = failover.config.after_s;
+                    warning!("MQTT: broker unreachable for {} s, switching to {}:{}", after_s, host, port);
+                    eventloop.reconnect_with(options);
+                    unreachable_since = None;
+                    next_check = Instant::now() + Duration::from_secs(failover.config.check_interval_s);
+                    backoff.reset();
+                    continue;
+                }
+                let wait = backoff.next();
+                warning!("MQTT connection error: {}, retrying in {:.1} s", e, wait.as_secs_f32());
+                tokio::time::sleep(wait).await;
+            }
+        }
+
+        // Back to the main broker once it accepts connections again; the check runs
+        // aside, the poll returns at least once per keep-alive interval
+        let Some(failover) = failover.as_ref().filter(|_| on_secondary && unreachable_since.is_none()) else {
+            continue;
+        };
+        match main_check.take() {
+            Some(check) if check.is_finished() => {
+                if check.await.unwrap_or(false) {
+                    let (host, port) = failover.main.broker_address();
+                    info!("MQTT: main broker {}:{} reachable again, switching back", host, port);
+                    on_secondary = false;
+                    eventloop.reconnect_with(&failover.main);
+                }
+                next_check = Instant::now() + Duration::from_secs(failover.config.check_interval_s);
+            }
+            Some(check) => main_check = Some(check),
+            None if Instant::now() >= next_check => {
+                main_check = Some(tokio::spawn(is_reachable(failover.main.broker_address())));
+            }
+            None => {}
+        }
+    }
+}
+
+/// Polls the event loop of another broker forever, like `run_event_loop`. Nothing is
+/// subscribed on these brokers: they only receive the published values.
+pub async fn run_broker_loop(
+    name: String,
+    eventloop: Arc<Mutex<Connection>>,
+    availability: Availability,
+    publisher: Publisher,
+    offline: OfflineConfig,
+) {
+    let mut backoff = Backoff::new(&offline);
+    let mut eventloop = eventloop.lock().await;
+    loop {
+        match eventloop.poll().await {
+            Ok(Received::Connected) => {
+                info!("MQTT: connected to broker {}", name);
+                backoff.reset();
+                availability.set_online();
+                publisher.set_connected(true);
+            }
+            Ok(Received::Message(..) | Received::Other) => {}
+            Err(e) => {
+                let wait = backoff.next();
+                warning!("MQTT connection error on broker {}: {}, retrying in {:.1} s", name, e, wait.as_secs_f32());
+                publisher.set_connected(false);
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+}
+
+/// Returns an actionable hint for a connection error, if one is known.
+pub fn troubleshooting_hint(error: &ConnectionError) -> Option<&'static str> {
+    match error {
+        ConnectionError::ConnectionRefused(ConnectReturnCode::BadUserNamePassword)
+        | ConnectionError::ConnectionRefused(ConnectReturnCode::NotAuthorized) => {
+            Some("the broker rejected the credentials: check the username/password and the broker ACLs")
+        }
+        ConnectionError::ConnectionRefused(ConnectReturnCode::BadClientId) => {
+            Some("the broker rejected the client ID: another client may be using it")
+        }
+        ConnectionError::ConnectionRefused(ConnectReturnCode::RefusedProtocolVersion) => {
+            Some("the broker does not support MQTT 3.1.1")
+        }
+        ConnectionError::ConnectionRefused(_) => Some("the broker is not accepting connections right now"),
+        ConnectionError::Tls(_) => Some(
+            "TLS handshake failed: check the port (8883 for TLS), the CA certificate and that the host name matches the broker certificate",
+        ),
+        ConnectionError::Io(e) => match e.kind() {
+            std::io::ErrorKind::ConnectionRefused => {
+                Some("nothing is listening on this host/port: check `host`, `port` and that the broker is running")
+            }
+            std::io::ErrorKind::TimedOut => Some("the broker did not answer: check the network and firewall"),
+            _ if e.to_string().contains("lookup") || e.to_string().contains("resolve") => {
+                Some("the host name could not be resolved: check `host` and DNS")
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
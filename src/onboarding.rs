@@ -0,0 +1,511 @@
+//! First-run setup on the touchscreen.
+//! When `config.toml` does not exist, boilert shows a guided setup instead of the
+//! dashboard: the probes found on the 1-Wire bus are named, the tank volume and the MQTT
+//! broker are entered (and the connection tested), then `config.toml` is written and
+//! the application starts normally. No SSH access is needed for a first installation.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use boilert::model::AuditSource;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet};
+use slint::ComponentHandle;
+use tokio::sync::{mpsc, watch};
+
+use crate::audit::{AuditConfig, AuditLog};
+use crate::board::{Board, HardwareConfig};
+use crate::logging::{info, warning};
+use crate::mqtt;
+use crate::sensors;
+use crate::{SetupFieldData, SetupState, SetupWindow};
+
+/// Probes offered by the setup; more can be added to `config.toml` afterwards.
+const MAX_SENSORS: usize = 6;
+/// Longest value typed in a field.
+const MAX_FIELD_LEN: usize = 64;
+/// Time given to the broker to accept the test connection.
+const MQTT_TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Action requested from the setup window.
+#[derive(Debug, Clone)]
+pub enum SetupCommand {
+    /// Look for the probes on the 1-Wire bus again.
+    Discover,
+    /// Select the field receiving the keyboard input.
+    Select(usize),
+    Key(String),
+    Backspace,
+    Previous,
+    Next,
+    /// Try to connect to the broker.
+    TestMqtt,
+    /// Write `config.toml`.
+    Save,
+}
+
+/// Steps of the setup, in order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Step {
+    #[default]
+    Sensors,
+    Tank,
+    Mqtt,
+    Summary,
+}
+
+impl Step {
+    const ALL: [Step; 4] = [Step::Sensors, Step::Tank, Step::Mqtt, Step::Summary];
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|&s| s == self).unwrap_or(0)
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Step::Sensors => "Sondes",
+            Step::Tank => "Ballon",
+            Step::Mqtt => "MQTT",
+            Step::Summary => "Résumé",
+        }
+    }
+}
+
+/// What the setup window shows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SetupView {
+    pub step: usize,
+    pub title: String,
+    /// Label and value of each field of the step.
+    pub fields: Vec<(String, String)>,
+    pub selected: Option<usize>,
+    pub editable: bool,
+    pub status: String,
+}
+
+/// Values entered so far, as typed.
+#[derive(Debug, Clone)]
+struct Answers {
+    /// 1-Wire ID and name of each probe.
+    sensors: Vec<(String, String)>,
+    volume_l: String,
+    reference_temp_c: String,
+    host: String,
+    port: String,
+    base_topic: String,
+}
+
+impl Default for Answers {
+    fn default() -> Self {
+        Self {
+            sensors: Vec::new(),
+            volume_l: "200".to_string(),
+            reference_temp_c: "15".to_string(),
+            host: String::new(),
+            port: "1883".to_string(),
+            base_topic: "boilert/sensors".to_string(),
+        }
+    }
+}
+
+impl Answers {
+    /// Label and value of the fields of `step`.
+    fn fields(&self, step: Step, mqtt_tested: bool) -> Vec<(String, String)> {
+        let field = |label: &str, value: &str| (label.to_string(), value.to_string());
+        match step {
+            Step::Sensors => self.sensors.iter().map(|(id, name)| field(id, name)).collect(),
+            Step::Tank => vec![
+                field("Volume du ballon (l)", &self.volume_l),
+                field("Eau froide (°C)", &self.reference_temp_c),
+            ],
+            Step::Mqtt => vec![
+                field("Serveur", &self.host),
+                field("Port", &self.port),
+                field("Topic de base", &self.base_topic),
+            ],
+            Step::Summary => {
+                let names: Vec<&str> = self.sensors.iter().map(|(_, name)| name.as_str()).collect();
+                vec![
+                    field("Sondes", &if names.is_empty() { "aucune".to_string() } else { names.join(", ") }),
+                    field("Ballon", &format!("{} l, eau froide {} °C", self.volume_l, self.reference_temp_c)),
+                    field("MQTT", &format!("{}:{}, {}", self.host, self.port, self.base_topic)),
+                    field("Connexion", if mqtt_tested { "testée" } else { "non testée" }),
+                ]
+            }
+        }
+    }
+
+    /// Editable value of the field `index` of `step`.
+    fn field_mut(&mut self, step: Step, index: usize) -> Option<&mut String> {
+        match (step, index) {
+            (Step::Sensors, i) => self.sensors.get_mut(i).map(|(_, name)| name),
+            (Step::Tank, 0) => Some(&mut self.volume_l),
+            (Step::Tank, 1) => Some(&mut self.reference_temp_c),
+            (Step::Mqtt, 0) => Some(&mut self.host),
+            (Step::Mqtt, 1) => Some(&mut self.port),
+            (Step::Mqtt, 2) => Some(&mut self.base_topic),
+            _ => None,
+        }
+    }
+
+    /// Checks the values of `step`; the error is shown to the user.
+    fn check(&self, step: Step) -> Result<(), String> {
+        match step {
+            Step::Sensors => {
+                for (i, (_, name)) in self.sensors.iter().enumerate() {
+                    if name.is_empty() {
+                        return Err("Chaque sonde doit avoir un nom".to_string());
+                    }
+                    if self.sensors[..i].iter().any(|(_, other)| other == name) {
+                        return Err(format!("Le nom {} est utilisé deux fois", name));
+                    }
+                }
+            }
+            Step::Tank => {
+                if !self.volume_l.parse::<f64>().is_ok_and(|v| v > 0.0) {
+                    return Err("Entrez le volume du ballon en litres".to_string());
+                }
+                if self.reference_temp_c.parse::<f64>().is_err() {
+                    return Err("Entrez la température de l'eau froide".to_string());
+                }
+            }
+            Step::Mqtt => {
+                if self.host.is_empty() {
+                    return Err("Entrez l'adresse du serveur MQTT".to_string());
+                }
+                if !self.port.parse::<u16>().is_ok_and(|p| p > 0) {
+                    return Err("Le port doit être un nombre entre 1 et 65535".to_string());
+                }
+                if self.base_topic.is_empty() || self.base_topic.contains(['+', '#']) {
+                    return Err("Le topic de base ne doit pas être vide ni contenir + ou #".to_string());
+                }
+            }
+            Step::Summary => {}
+        }
+        Ok(())
+    }
+
+    /// Content of `config.toml`.
+    fn config_toml(&self) -> String {
+        let mut doc = toml_edit::DocumentMut::new();
+        doc["mqtt"] = toml_edit::table();
+        doc["mqtt"]["host"] = toml_edit::value(self.host.as_str());
+        doc["mqtt"]["port"] = toml_edit::value(self.port.parse::<i64>().unwrap_or(1883));
+        doc["mqtt"]["base_topic"] = toml_edit::value(self.base_topic.as_str());
+        doc["boiler"] = toml_edit::table();
+        doc["boiler"]["volume_l"] = toml_edit::value(self.volume_l.parse::<f64>().unwrap_or(200.0));
+        doc["boiler"]["reference_temp_c"] = toml_edit::value(self.reference_temp_c.parse::<f64>().unwrap_or(15.0));
+        doc["boiler"]["energy_coefficient"] = toml_edit::value(1.162);
+        let mut sensors = toml_edit::ArrayOfTables::new();
+        for (id, name) in &self.sensors {
+            let mut table = toml_edit::Table::new();
+            table["name"] = toml_edit::value(name.as_str());
+            table["id"] = toml_edit::value(id.as_str());
+            sensors.push(table);
+        }
+        doc["sensors"] = toml_edit::Item::ArrayOfTables(sensors);
+        format!("# Written by the boilert setup; see README.md for the other settings.\n\n{}", doc)
+    }
+}
+
+/// The setup task: handles the window commands and publishes the view.
+struct Setup {
+    /// Configuration file written at the end.
+    path: PathBuf,
+    board: Board,
+    step: Step,
+    answers: Answers,
+    selected: Option<usize>,
+    status: String,
+    mqtt_tested: bool,
+    view: watch::Sender<SetupView>,
+}
+
+impl Setup {
+    /// Handles the commands until `config.toml` is written (returns `true`) or the
+    /// window is closed.
+    async fn run(mut self, mut commands: mpsc::Receiver<SetupCommand>) -> bool {
+        self.discover().await;
+        self.publish();
+        while let Some(command) = commands.recv().await {
+            match command {
+                SetupCommand::Discover => self.discover().await,
+                SetupCommand::Select(index) => {
+                    self.selected = self.answers.field_mut(self.step, index).is_some().then_some(index);
+                }
+                SetupCommand::Key(text) => {
+                    if let Some(value) = self.selected.and_then(|i| self.answers.field_mut(self.step, i))
+                        && value.chars().count() < MAX_FIELD_LEN
+                    {
+                        value.push_str(&text);
+                    }
+                }
+                SetupCommand::Backspace => {
+                    if let Some(value) = self.selected.and_then(|i| self.answers.field_mut(self.step, i)) {
+                        value.pop();
+                    }
+                }
+                SetupCommand::Previous => self.go_to(self.step.index().saturating_sub(1)),
+                SetupCommand::Next => match self.answers.check(self.step) {
+                    Ok(()) => self.go_to(self.step.index() + 1),
+                    Err(message) => self.status = message,
+                },
+                SetupCommand::TestMqtt => self.test_mqtt().await,
+                SetupCommand::Save => {
+                    if self.save() {
+                        return true;
+                    }
+                }
+            }
+            self.publish();
+        }
+        false
+    }
+
+    /// Lists the probes on the bus, keeping the names already entered.
+    async fn discover(&mut self) {
+        let board = self.board.clone();
+        let found = tokio::task::spawn_blocking(move || sensors::discover_w1(&board))
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+        let ids = match found {
+            Ok(ids) => ids,
+            Err(e) => {
+                warning!("Setup: 1-Wire discovery failed: {:#}", e);
+                Vec::new()
+            }
+        };
+        let previous = std::mem::take(&mut self.answers.sensors);
+        self.answers.sensors = ids
+            .iter()
+            .take(MAX_SENSORS)
+            .enumerate()
+            .map(|(i, id)| {
+                let name = previous
+                    .iter()
+                    .find(|(p, _)| p == id)
+                    .map_or_else(|| format!("S{}", i + 1), |(_, n)| n.clone());
+                (id.clone(), name)
+            })
+            .collect();
+        self.selected = None;
+        self.status = match ids.len() {
+            0 => "Aucune sonde détectée : vérifiez le câblage puis touchez Rechercher".to_string(),
+            n if n > MAX_SENSORS => format!(
+                "{} sondes détectées, les {} premières sont proposées ; touchez une ligne pour la renommer",
+                n, MAX_SENSORS
+            ),
+            n => format!("{} sonde(s) détectée(s) ; touchez une ligne pour la renommer", n),
+        };
+    }
+
+    fn go_to(&mut self, index: usize) {
+        self.step = Step::ALL[index.min(Step::ALL.len() - 1)];
+        self.selected = None;
+        self.status = match self.step {
+            Step::Sensors => "Touchez une ligne pour renommer la sonde".to_string(),
+            Step::Tank => "Volume du ballon et température de l'eau froide qui l'alimente".to_string(),
+            Step::Mqtt => "Serveur MQTT de la domotique ; touchez Tester pour vérifier la connexion".to_string(),
+            Step::Summary => "Touchez Enregistrer pour écrire config.toml et démarrer".to_string(),
+        };
+    }
+
+    /// Connects to the broker and reports the outcome.
+    async fn test_mqtt(&mut self) {
+        if let Err(message) = self.answers.check(Step::Mqtt) {
+            self.status = message;
+            return;
+        }
+        self.status = "Connexion...".to_string();
+        self.publish();
+        let port = self.answers.port.parse().unwrap_or(1883);
+        self.mqtt_tested = false;
+        self.status = match test_connection(&self.answers.host, port).await {
+            Ok(()) => {
+                self.mqtt_tested = true;
+                "Connexion réussie".to_string()
+            }
+            Err(e) => {
+                warning!("Setup: MQTT test failed: {:#}", e);
+                format!("Échec de la connexion : {:#}", e)
+            }
+        };
+    }
+
+    /// Writes `config.toml`; returns `true` on success.
+    fn save(&mut self) -> bool {
+        if let Some(message) = [Step::Sensors, Step::Tank, Step::Mqtt]
+            .into_iter()
+            .find_map(|step| self.answers.check(step).err())
+        {
+            self.status = message;
+            return false;
+        }
+        let path = self.path.as_path();
+        match crate::state::write_atomic(path, self.answers.config_toml().as_bytes()) {
+            Ok(()) => {
+                let answers = &self.answers;
+                AuditLog::open(&AuditConfig::default()).record(
+                    AuditSource::Ui,
+                    "setup completed",
+                    format!("{} sensor(s), broker {}:{}", answers.sensors.len(), answers.host, answers.port),
+                );
+                info!("Setup: {} written", path.display());
+                true
+            }
+            Err(e) => {
+                warning!("Setup: failed to write {}: {:#}", path.display(), e);
+                self.status = "Échec de l'écriture de config.toml".to_string();
+                false
+            }
+        }
+    }
+
+    fn publish(&self) {
+        let _ = self.view.send(SetupView {
+            step: self.step.index(),
+            title: format!("{} ({}/{})", self.step.title(), self.step.index() + 1, Step::ALL.len()),
+            fields: self.answers.fields(self.step, self.mqtt_tested),
+            selected: self.selected,
+            editable: self.step != Step::Summary,
+            status: self.status.clone(),
+        });
+    }
+}
+
+/// Connects to the broker and waits for it to accept the connection.
+async fn test_connection(host: &str, port: u16) -> Result<()> {
+    let options = MqttOptions::new(format!("boilert-setup-{}", std::process::id()), host, port);
+    let (_client, mut eventloop) = AsyncClient::new(options, 10);
+    let connect = async {
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => return Ok(()),
+                Ok(_) => {}
+                Err(e) => match mqtt::troubleshooting_hint(&e) {
+                    Some(hint) => bail!("{}", hint),
+                    None => return Err(e.into()),
+                },
+            }
+        }
+    };
+    tokio::time::timeout(MQTT_TEST_TIMEOUT, connect)
+        .await
+        .context("the broker did not answer")?
+}
+
+/// Shows the setup window until `config.toml` is written.
+///
+/// # Errors
+/// Returns an error if the window cannot be created or is closed before the end of
+/// the setup.
+pub fn run(path: &Path) -> Result<()> {
+    println!("No {}: starting the setup on the display", path.display());
+    let ui = SetupWindow::new()?;
+    let (commands_tx, commands) = mpsc::channel(16);
+    connect(&ui, commands_tx);
+    let (view, views) = watch::channel(SetupView::default());
+    let setup = Setup {
+        path: path.to_path_buf(),
+        board: Board::from_config(&HardwareConfig::default()),
+        step: Step::Sensors,
+        answers: Answers::default(),
+        selected: None,
+        status: String::new(),
+        mqtt_tested: false,
+        view,
+    };
+
+    // The setup runs on its own runtime, the window owns the main thread
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let ui_weak = ui.as_weak();
+    let driver = std::thread::spawn(move || {
+        runtime.block_on(async move {
+            tokio::spawn(show(ui_weak, views));
+            let saved = setup.run(commands).await;
+            if saved {
+                let _ = slint::invoke_from_event_loop(|| {
+                    let _ = slint::quit_event_loop();
+                });
+            }
+            saved
+        })
+    });
+    ui.run()?;
+    // Closing the window drops the command channel, which ends the setup
+    drop(ui);
+    let saved = driver.join().unwrap_or(false);
+    if !saved {
+        bail!("Setup not completed: {} was not written", path.display());
+    }
+    Ok(())
+}
+
+/// Forwards the actions of the setup window to the setup task.
+fn connect(ui: &SetupWindow, commands: mpsc::Sender<SetupCommand>) {
+    let send = move |command| {
+        let _ = commands.try_send(command);
+    };
+    ui.on_discover({
+        let send = send.clone();
+        move || send(SetupCommand::Discover)
+    });
+    ui.on_select({
+        let send = send.clone();
+        move |index| send(SetupCommand::Select(index.max(0) as usize))
+    });
+    ui.on_key({
+        let send = send.clone();
+        move |text| send(SetupCommand::Key(text.to_string()))
+    });
+    ui.on_backspace({
+        let send = send.clone();
+        move || send(SetupCommand::Backspace)
+    });
+    ui.on_previous({
+        let send = send.clone();
+        move || send(SetupCommand::Previous)
+    });
+    ui.on_next({
+        let send = send.clone();
+        move || send(SetupCommand::Next)
+    });
+    ui.on_test_mqtt({
+        let send = send.clone();
+        move || send(SetupCommand::TestMqtt)
+    });
+    ui.on_save(move || send(SetupCommand::Save));
+}
+
+/// Pushes the view to the window whenever it changes.
+async fn show(ui_weak: slint::Weak<SetupWindow>, mut views: watch::Receiver<SetupView>) {
+    loop {
+        let view = views.borrow_and_update().clone();
+        let _ = slint::invoke_from_event_loop({
+            let ui_weak = ui_weak.clone();
+            move || {
+                if let Some(ui) = ui_weak.upgrade() {
+                    let fields: Vec<SetupFieldData> = view
+                        .fields
+                        .iter()
+                        .map(|(label, value)| SetupFieldData {
+                            label: label.as_str().into(),
+                            value: value.as_str().into(),
+                        })
+                        .collect();
+                    ui.set_state(SetupState {
+                        title: view.title.into(),
+                        step: view.step as i32,
+                        fields: slint::ModelRc::new(slint::VecModel::from(fields)),
+                        selected: view.selected.map_or(-1, |i| i as i32),
+                        editable: view.editable,
+                        status: view.status.into(),
+                    });
+                }
+            }
+        });
+        if views.changed().await.is_err() {
+            return;
+        }
+    }
+}
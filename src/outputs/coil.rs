@@ -0,0 +1,23 @@
+//! Modbus backend: a coil of a Modbus RTU device (relay module, controller input).
+
+use anyhow::Result;
+
+use super::Output;
+use crate::sensors::{ModbusConfig, write_coil};
+
+/// Coil of a Modbus RTU device, on a serial bus shared with the Modbus sensors.
+pub struct CoilOutput {
+    config: ModbusConfig,
+}
+
+impl CoilOutput {
+    pub fn new(config: &ModbusConfig) -> Self {
+        Self { config: config.clone() }
+    }
+}
+
+impl Output for CoilOutput {
+    fn set(&self, on: bool) -> Result<()> {
+        write_coil(&self.config, on)
+    }
+}
@@ -0,0 +1,24 @@
+//! Dry-run backend: logs the switches instead of driving hardware.
+
+use anyhow::Result;
+
+use super::Output;
+use crate::logging::info;
+
+/// Output that only logs its switches.
+pub struct DryRunOutput {
+    name: String,
+}
+
+impl DryRunOutput {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string() }
+    }
+}
+
+impl Output for DryRunOutput {
+    fn set(&self, on: bool) -> Result<()> {
+        info!("Output {}: {} (dry run)", self.name, if on { "on" } else { "off" });
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+//! GPIO backend: a relay driven by a line of the board's GPIO chip.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
+
+use super::{Output, OutputConfig};
+use crate::board::Board;
+
+/// Relay on a GPIO line. The line is requested on the first switch and kept, so the
+/// relay holds its state between switches.
+pub struct GpioOutput {
+    chip: PathBuf,
+    line: u32,
+    active_low: bool,
+    handle: Mutex<Option<LineHandle>>,
+}
+
+impl GpioOutput {
+    pub fn new(board: &Board, output: &OutputConfig) -> Self {
+        Self {
+            chip: board.gpio_chip().to_path_buf(),
+            line: output.line.unwrap_or_default(),
+            active_low: output.active_low,
+            handle: Mutex::new(None),
+        }
+    }
+
+    fn request(&self, on: bool) -> Result<LineHandle> {
+        let mut chip = Chip::new(&self.chip).with_context(|| format!("Failed to open {}", self.chip.display()))?;
+        let flags = if self.active_low {
+            LineRequestFlags::OUTPUT | LineRequestFlags::ACTIVE_LOW
+        } else {
+            LineRequestFlags::OUTPUT
+        };
+        chip.get_line(self.line)
+            .and_then(|line| line.request(flags, u8::from(on), "boilert-output"))
+            .with_context(|| format!("Failed to request GPIO line {}", self.line))
+    }
+}
+
+impl Output for GpioOutput {
+    fn set(&self, on: bool) -> Result<()> {
+        let mut handle = self.handle.lock().unwrap_or_else(|e| e.into_inner());
+        match handle.as_ref() {
+            Some(line) => line
+                .set_value(u8::from(on))
+                .with_context(|| format!("Failed to set GPIO line {}", self.line)),
+            // The line starts at the requested level
+            None => {
+                *handle = Some(self.request(on)?);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! Output backends: relays, smart plugs and coils switched by boilert.
+//!
+//! Every configured output is driven through an [`Output`] backend, selected per
+//! output in `config.toml` like the sensor backends, so the logic deciding what to
+//! switch does not depend on the hardware. Outputs are set to their safe state at
+//! startup and on a clean exit, and can be switched over MQTT and HTTP.
+
+mod coil;
+mod dry_run;
+mod gpio;
+mod plug;
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, bail};
+use boilert::model::AuditSource;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::audit::AuditLog;
+use crate::board::Board;
+use crate::logging::{self, info, warning};
+use crate::mqtt::{Publisher, Subscriptions};
+use crate::sensors::ModbusConfig;
+
+pub use plug::PlugConfig;
+
+/// Something boilert can switch on and off.
+pub trait Output: Send + Sync {
+    /// Switches the output on or off. May block until the hardware confirms.
+    fn set(&self, on: bool) -> Result<()>;
+}
+
+/// Backend of an output.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputBackend {
+    /// Relay on a GPIO line of the board (see `line` and `active_low`).
+    Gpio,
+    /// Smart plug or relay module switched by MQTT messages (see `mqtt`).
+    Mqtt,
+    /// Coil of a Modbus RTU device (see `modbus`, `register` is the coil address).
+    Modbus,
+    /// Only logs the switches, to try a configuration without touching the hardware.
+    Log,
+}
+
+/// Configuration of an output (`[[outputs]]`).
+#[derive(Debug, Deserialize, Clone)]
+pub struct OutputConfig {
+    /// Name of the output, used in its topics (e.g., "heater").
+    pub name: String,
+    pub backend: OutputBackend,
+    /// GPIO line of a `gpio` output, on the board's GPIO chip.
+    #[serde(default)]
+    pub line: Option<u32>,
+    /// The relay closes when the line is low.
+    #[serde(default)]
+    pub active_low: bool,
+    /// Topic and payloads of an `mqtt` output.
+    #[serde(default)]
+    pub mqtt: PlugConfig,
+    /// Serial settings and coil of a `modbus` output.
+    #[serde(default)]
+    pub modbus: ModbusConfig,
+    /// State at startup and on a clean exit (off unless set).
+    #[serde(default)]
+    pub safe_on: bool,
+}
+
+impl OutputConfig {
+    /// Checks the name and the settings of the backend.
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() || self.name.contains(['/', '+', '#']) {
+            bail!("Output {:?}: the name must not be empty nor contain /, + or #", self.name);
+        }
+        match self.backend {
+            OutputBackend::Gpio if self.line.is_none() => bail!("Output {}: line is required", self.name),
+            OutputBackend::Mqtt
+                if self.mqtt.command_topic.is_empty() || self.mqtt.command_topic.contains(['+', '#']) =>
+            {
+                bail!("Output {}: [outputs.mqtt] command_topic is required and cannot contain wildcards", self.name)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Creates the backend driving `output`. On the simulated board, GPIO outputs only log.
+fn open(output: &OutputConfig, board: &Board, publisher: &Publisher) -> Box<dyn Output> {
+    match output.backend {
+        OutputBackend::Gpio if board.is_simulated() => Box::new(dry_run::DryRunOutput::new(&output.name)),
+        OutputBackend::Gpio => Box::new(gpio::GpioOutput::new(board, output)),
+        OutputBackend::Mqtt => Box::new(plug::PlugOutput::new(&output.mqtt, publisher.clone())),
+        OutputBackend::Modbus => Box::new(coil::CoilOutput::new(&output.modbus)),
+        OutputBackend::Log => Box::new(dry_run::DryRunOutput::new(&output.name)),
+    }
+}
+
+/// State of an output, returned by `GET /api/outputs`.
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputStatus {
+    pub name: String,
+    /// Last state applied (`None` until the first switch succeeded).
+    pub on: Option<bool>,
+}
+
+/// An output and its last applied state.
+struct Channel {
+    name: String,
+    safe_on: bool,
+    backend: Box<dyn Output>,
+    on: Mutex<Option<bool>>,
+}
+
+/// A switch request; `source` is `None` for the safe states, which are not audited.
+struct Command {
+    index: usize,
+    on: bool,
+    source: Option<AuditSource>,
+}
+
+/// Handle switching the outputs. Switches are queued and applied one after the other
+/// by [`OutputDriver::run`], so a slow bus never blocks the caller.
+///
+/// Cloning an `Outputs` handle is cheap; all clones share the same outputs.
+#[derive(Clone)]
+pub struct Outputs {
+    channels: Arc<Vec<Channel>>,
+    commands: mpsc::Sender<Command>,
+}
+
+/// Task applying the switches requested through [`Outputs`].
+pub struct OutputDriver {
+    channels: Arc<Vec<Channel>>,
+    commands: mpsc::Receiver<Command>,
+    publisher: Publisher,
+    base_topic: String,
+    audit: AuditLog,
+}
+
+impl Outputs {
+    /// Opens the backends of the configured outputs.
+    pub fn new(
+        configs: &[OutputConfig],
+        board: &Board,
+        publisher: Publisher,
+        base_topic: &str,
+        audit: AuditLog,
+    ) -> (Self, OutputDriver) {
+        let channels: Arc<Vec<Channel>> = Arc::new(
+            configs
+                .iter()
+                .map(|config| Channel {
+                    name: config.name.clone(),
+                    safe_on: config.safe_on,
+                    backend: open(config, board, &publisher),
+                    on: Mutex::new(None),
+                })
+                .collect(),
+        );
+        let (commands, receiver) = mpsc::channel(16);
+        let driver = OutputDriver {
+            channels: channels.clone(),
+            commands: receiver,
+            publisher,
+            base_topic: base_topic.to_string(),
+            audit,
+        };
+        (Self { channels, commands }, driver)
+    }
+
+    /// Current state of every output.
+    pub fn status(&self) -> Vec<OutputStatus> {
+        self.channels
+            .iter()
+            .map(|channel| OutputStatus {
+                name: channel.name.clone(),
+                on: *channel.on.lock().unwrap_or_else(|e| e.into_inner()),
+            })
+            .collect()
+    }
+
+    /// Queues a switch of the output `name`.
+    ///
+    /// # Errors
+    /// Returns an error if the output is unknown or too many switches are pending.
+    pub fn switch(&self, name: &str, on: bool, source: AuditSource) -> Result<()> {
+        let Some(index) = self.channels.iter().position(|channel| channel.name == name) else {
+            bail!("Unknown output {}", name);
+        };
+        if self.commands.try_send(Command { index, on, source: Some(source) }).is_err() {
+            bail!("Too many switches pending");
+        }
+        Ok(())
+    }
+
+    /// Sets every output to its safe state right away, on a clean exit.
+    pub fn to_safe_states(&self) {
+        for channel in self.channels.iter() {
+            if let Err(e) = channel.backend.set(channel.safe_on) {
+                warning!("Output {}: {:#}", channel.name, e);
+            }
+        }
+    }
+}
+
+impl OutputDriver {
+    /// Sets the outputs to their safe state, then applies the switches as they come.
+    pub async fn run(mut self) {
+        for index in 0..self.channels.len() {
+            let on = self.channels[index].safe_on;
+            self.apply(Command { index, on, source: None }).await;
+        }
+        while let Some(command) = self.commands.recv().await {
+            self.apply(command).await;
+        }
+    }
+
+    /// Switches the output off the async runtime, then publishes its state.
+    async fn apply(&self, command: Command) {
+        let Command { index, on, source } = command;
+        let channels = self.channels.clone();
+        let result = tokio::task::spawn_blocking(move || channels[index].backend.set(on))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result);
+        let channel = &self.channels[index];
+        if let Err(e) = result {
+            warning!("Output {}: {:#}", channel.name, e);
+            return;
+        }
+        *channel.on.lock().unwrap_or_else(|e| e.into_inner()) = Some(on);
+        let state = if on { "on" } else { "off" };
+        let topic = format!("{}/outputs/{}", self.base_topic, channel.name);
+        let _ = self.publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, state).await;
+        if let Some(source) = source {
+            info!("Output {} switched {}", channel.name, state);
+            self.audit.record(source, "output switched", format!("{} {}", channel.name, state));
+        }
+    }
+}
+
+/// Switches the outputs on `{base_topic}/outputs/{name}/set` messages (`on`/`off`).
+pub fn follow(outputs: &Outputs, subscriptions: &Subscriptions, base_topic: &str) {
+    let outputs = outputs.clone();
+    let prefix = format!("{}/outputs/", base_topic);
+    subscriptions.subscribe(format!("{}+/set", prefix), move |topic, payload| {
+        let Some(name) = topic.strip_prefix(&prefix).and_then(|rest| rest.strip_suffix("/set")) else {
+            return;
+        };
+        let command = String::from_utf8_lossy(payload);
+        let result = match logging::parse_switch(&command) {
+            Some(on) => outputs.switch(name, on, AuditSource::Mqtt),
+            None => Err(anyhow::anyhow!("invalid state {:?}", command)),
+        };
+        if let Err(e) = result {
+            warning!("Ignoring {} command: {:#}", topic, e);
+        }
+    });
+}
@@ -0,0 +1,56 @@
+//! MQTT backend: a smart plug or relay module switched by MQTT messages (Tasmota,
+//! Shelly, Zigbee2MQTT...).
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use super::Output;
+use crate::mqtt::Publisher;
+
+/// Command topic and payloads of an MQTT output (`[outputs.mqtt]`).
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PlugConfig {
+    /// Topic the device listens to (e.g., "cmnd/boiler-plug/POWER").
+    pub command_topic: String,
+    pub payload_on: String,
+    pub payload_off: String,
+    /// Publish the command retained, so the device gets it back after a restart.
+    pub retain: bool,
+}
+
+impl Default for PlugConfig {
+    fn default() -> Self {
+        Self {
+            command_topic: String::new(),
+            payload_on: "ON".to_string(),
+            payload_off: "OFF".to_string(),
+            retain: false,
+        }
+    }
+}
+
+/// Device switched by publishing its command topic.
+pub struct PlugOutput {
+    config: PlugConfig,
+    publisher: Publisher,
+}
+
+impl PlugOutput {
+    pub fn new(config: &PlugConfig, publisher: Publisher) -> Self {
+        Self {
+            config: config.clone(),
+            publisher,
+        }
+    }
+}
+
+impl Output for PlugOutput {
+    fn set(&self, on: bool) -> Result<()> {
+        let c = &self.config;
+        let payload = if on { &c.payload_on } else { &c.payload_off };
+        self.publisher
+            .try_publish_now(c.command_topic.clone(), rumqttc::QoS::AtLeastOnce, c.retain, payload.clone())
+            .with_context(|| format!("Failed to publish {}", c.command_topic))
+    }
+}
@@ -0,0 +1,317 @@
+//! Display preferences chosen on the device.
+//! Purely cosmetic choices (theme, temperature unit, chart range, backlight schedule) are
+//! saved to their own writable file rather than to `config.toml`, so they survive restarts
+//! and a configuration redeployed by a fleet management tool does not wipe them. There is
+//! no page order to keep: the pages are reached from fixed buttons, not browsed in sequence.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use boilert::model::AuditSource;
+use chrono::{Local, NaiveTime};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::audit::AuditLog;
+use crate::format::Unit;
+use crate::logging::{info, warning};
+use crate::state;
+
+/// Interval between two checks of the brightness schedule.
+const BRIGHTNESS_INTERVAL: Duration = Duration::from_secs(60);
+/// Directory of the backlight devices.
+const BACKLIGHT_DIR: &str = "/sys/class/backlight";
+
+/// Settings from the `[preferences]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PreferencesConfig {
+    /// Path of the JSON file holding the display preferences.
+    pub path: String,
+}
+
+impl Default for PreferencesConfig {
+    fn default() -> Self {
+        Self {
+            path: "boilert-preferences.json".to_string(),
+        }
+    }
+}
+
+/// Colours of the pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Light text on a black background.
+    #[default]
+    Dark,
+    /// Dark text on a white background, easier to read in a bright room.
+    Light,
+}
+
+impl FromStr for Theme {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "dark" => Ok(Theme::Dark),
+            "light" => Ok(Theme::Light),
+            other => bail!("unknown theme {:?} (expected dark or light)", other),
+        }
+    }
+}
+
+/// Unit of the temperatures shown on the display.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    /// Unit the values are formatted with.
+    pub fn unit(self) -> Unit {
+        match self {
+            TemperatureUnit::Celsius => Unit::Celsius,
+            TemperatureUnit::Fahrenheit => Unit::Fahrenheit,
+        }
+    }
+
+    /// Converts a temperature in °C to this unit.
+    pub fn convert(self, celsius: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+}
+
+impl FromStr for TemperatureUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "celsius" | "c" => Ok(TemperatureUnit::Celsius),
+            "fahrenheit" | "f" => Ok(TemperatureUnit::Fahrenheit),
+            other => bail!("unknown temperature unit {:?} (expected celsius or fahrenheit)", other),
+        }
+    }
+}
+
+/// Backlight brightness by time of day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrightnessSchedule {
+    /// Brightness during the day, in percent of the maximum.
+    pub day_percent: u8,
+    /// Brightness at night, in percent of the maximum.
+    pub night_percent: u8,
+    /// Local time the night starts ("HH:MM").
+    pub night_start: String,
+    /// Local time the night ends ("HH:MM").
+    pub night_end: String,
+}
+
+impl Default for BrightnessSchedule {
+    fn default() -> Self {
+        Self {
+            day_percent: 100,
+            night_percent: 100,
+            night_start: "22:00".to_string(),
+            night_end: "07:00".to_string(),
+        }
+    }
+}
+
+impl BrightnessSchedule {
+    /// Checks the brightness levels and the times.
+    fn validate(&self) -> Result<()> {
+        // The display must stay readable: a black screen looks like a dead device
+        if !(1..=100).contains(&self.day_percent) || !(1..=100).contains(&self.night_percent) {
+            bail!("brightness must be between 1 and 100 %");
+        }
+        parse_time(&self.night_start)?;
+        parse_time(&self.night_end)?;
+        Ok(())
+    }
+
+    /// Brightness at the local time `now`, in percent.
+    pub fn percent_at(&self, now: NaiveTime) -> u8 {
+        let (Ok(start), Ok(end)) = (parse_time(&self.night_start), parse_time(&self.night_end)) else {
+            return self.day_percent;
+        };
+        // The night usually spans midnight
+        let night = if start <= end {
+            start <= now && now < end
+        } else {
+            now >= start || now < end
+        };
+        if night { self.night_percent } else { self.day_percent }
+    }
+}
+
+/// Parses a local time of the brightness schedule.
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M").with_context(|| format!("invalid time {:?} (expected HH:MM)", time))
+}
+
+/// Display preferences, as saved to the preferences file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiPreferences {
+    pub theme: Theme,
+    /// Unit of the temperatures shown; MQTT, the HTTP API and the exports always use °C.
+    pub temperature_unit: TemperatureUnit,
+    /// Hours of history shown on the charts (`None`: the whole history).
+    pub chart_hours: Option<u32>,
+    pub brightness: BrightnessSchedule,
+}
+
+impl UiPreferences {
+    /// Changes the preference `name` to `value`.
+    ///
+    /// # Errors
+    /// Returns an error for an unknown preference or an invalid value.
+    fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let value = value.trim();
+        match name {
+            "theme" => self.theme = value.parse()?,
+            "temperature_unit" => self.temperature_unit = value.parse()?,
+            "chart_hours" => {
+                self.chart_hours = match value {
+                    "all" => None,
+                    hours => Some(hours.parse().ok().filter(|&h| h > 0).context("chart_hours must be all or hours")?),
+                }
+            }
+            "day_percent" => self.brightness.day_percent = value.parse().context("invalid day_percent")?,
+            "night_percent" => self.brightness.night_percent = value.parse().context("invalid night_percent")?,
+            "night_start" => self.brightness.night_start = value.to_string(),
+            "night_end" => self.brightness.night_end = value.to_string(),
+            other => bail!("unknown preference {:?}", other),
+        }
+        self.brightness.validate()
+    }
+}
+
+/// Shared display preferences.
+///
+/// Cloning a `Preferences` handle is cheap; all clones share the same preferences.
+#[derive(Clone)]
+pub struct Preferences {
+    path: PathBuf,
+    audit: AuditLog,
+    current: watch::Sender<UiPreferences>,
+}
+
+impl Preferences {
+    /// Loads the preferences saved to `config.path`, or the defaults on the first start.
+    pub fn load(config: &PreferencesConfig, audit: AuditLog) -> Self {
+        let path = PathBuf::from(&config.path);
+        let saved = match load(&path) {
+            Ok(saved) => saved.unwrap_or_default(),
+            Err(e) => {
+                warning!("{:#}, using the default display preferences", e);
+                UiPreferences::default()
+            }
+        };
+        Self {
+            path,
+            audit,
+            current: watch::channel(saved).0,
+        }
+    }
+
+    /// Current preferences.
+    pub fn get(&self) -> UiPreferences {
+        self.current.borrow().clone()
+    }
+
+    /// Follows the changes of the preferences.
+    pub fn subscribe(&self) -> watch::Receiver<UiPreferences> {
+        self.current.subscribe()
+    }
+
+    /// Changes the preference `name` to `value` and saves the preferences.
+    ///
+    /// # Errors
+    /// Returns an error for an unknown preference, an invalid value, or if the
+    /// preferences cannot be saved.
+    pub fn set(&self, name: &str, value: &str, source: AuditSource) -> Result<()> {
+        let mut preferences = self.get();
+        preferences.set(name, value)?;
+        if preferences == self.get() {
+            return Ok(());
+        }
+        let content = serde_json::to_vec_pretty(&preferences)?;
+        state::write_atomic(&self.path, &content)?;
+        self.audit.record(source, "preference", format!("{} = {}", name, value.trim()));
+        self.current.send_replace(preferences);
+        Ok(())
+    }
+
+    /// Sets the backlight following the brightness schedule, every minute and whenever
+    /// the preferences change.
+    pub async fn brightness_loop(self, backlight: PathBuf) {
+        let mut preferences = self.subscribe();
+        let mut ticker = time::interval(BRIGHTNESS_INTERVAL);
+        let mut applied = None;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = preferences.changed() => {}
+            }
+            let percent = preferences.borrow_and_update().brightness.percent_at(Local::now().time());
+            if applied == Some(percent) {
+                continue;
+            }
+            match set_brightness(&backlight, percent) {
+                Ok(()) => {
+                    info!("Backlight set to {} %", percent);
+                    applied = Some(percent);
+                }
+                Err(e) => warning!("Failed to set the backlight: {:#}", e),
+            }
+        }
+    }
+}
+
+/// Loads the preferences from `path`.
+///
+/// Returns `Ok(None)` if the file does not exist yet.
+fn load(path: &Path) -> Result<Option<UiPreferences>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read preferences file {}", path.display()))?;
+    let preferences: UiPreferences = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse preferences file {}", path.display()))?;
+    preferences.brightness.validate()?;
+    Ok(Some(preferences))
+}
+
+/// First backlight device of the display, if it can be controlled.
+pub fn backlight() -> Option<PathBuf> {
+    let mut devices: Vec<PathBuf> = std::fs::read_dir(BACKLIGHT_DIR)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    devices.sort();
+    devices.into_iter().next()
+}
+
+/// Sets the brightness of `backlight` to `percent` of its maximum.
+fn set_brightness(backlight: &Path, percent: u8) -> Result<()> {
+    let max: u64 = std::fs::read_to_string(backlight.join("max_brightness"))?
+        .trim()
+        .parse()
+        .context("invalid max_brightness")?;
+    let value = (max * u64::from(percent)).div_ceil(100);
+    std::fs::write(backlight.join("brightness"), value.to_string())
+        .with_context(|| format!("Failed to write {}", backlight.join("brightness").display()))
+}
@@ -0,0 +1,177 @@
+//! Boiler pressure transducer.
+//! A 4-20 mA transducer drives a shunt resistor read by an ADC (MCP3008 on SPI or
+//! ADS1115 on I2C). The loop current is converted linearly to a pressure; currents
+//! outside the NAMUR NE 43 range mean a broken loop or a failed transducer.
+
+use anyhow::{Result, bail};
+use boilert::model::SensorStatus;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::alerts::Alerts;
+use crate::board::Board;
+use crate::format::{self, Unit};
+use crate::messages::Message;
+use crate::sensors::{Mcp3008, ads1115};
+use crate::simulation::Faults;
+
+/// Loop currents below this, in mA, mean a broken loop.
+const MIN_LOOP_MA: f32 = 3.6;
+/// Loop currents above this, in mA, mean a short circuit or a failed transducer.
+const MAX_LOOP_MA: f32 = 21.0;
+/// Fault injection ID of the transducer on the simulated board.
+const SIMULATION_ID: &str = "pressure";
+
+/// ADC reading the shunt voltage.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PressureAdc {
+    /// 10-bit MCP3008 on SPI (see `bus`, `chip_select` and `vref`).
+    #[default]
+    Mcp3008,
+    /// 16-bit ADS1115 on I2C (see `bus` and `address`).
+    Ads1115,
+}
+
+/// Pressure transducer settings from the `[pressure]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PressureConfig {
+    pub adc: PressureAdc,
+    /// SPI or I2C bus (default: the board's bus).
+    pub bus: Option<u8>,
+    /// MCP3008: SPI chip select.
+    pub chip_select: u8,
+    /// ADS1115: I2C address.
+    pub address: u8,
+    /// ADC input of the shunt.
+    pub channel: u8,
+    /// Shunt resistor, in ohms (150 Ω gives 0.6-3.0 V).
+    pub shunt_ohm: f32,
+    /// MCP3008: reference voltage, in V.
+    pub vref: f32,
+    /// ADC samples averaged per read.
+    pub samples: u32,
+    /// Pressure at 4 mA, in bar.
+    pub zero_bar: f32,
+    /// Pressure at 20 mA, in bar.
+    pub full_scale_bar: f32,
+    /// Raise the `pressure_low` alert below this pressure, in bar.
+    pub alarm_below_bar: Option<f32>,
+}
+
+impl Default for PressureConfig {
+    fn default() -> Self {
+        Self {
+            adc: PressureAdc::Mcp3008,
+            bus: None,
+            chip_select: 0,
+            address: ads1115::DEFAULT_ADDRESS,
+            channel: 0,
+            shunt_ohm: 150.0,
+            vref: 3.3,
+            samples: 16,
+            zero_bar: 0.0,
+            full_scale_bar: 10.0,
+            alarm_below_bar: None,
+        }
+    }
+}
+
+impl PressureConfig {
+    /// Checks the settings that would make every read fail.
+    pub fn validate(&self) -> Result<()> {
+        if self.shunt_ohm <= 0.0 {
+            bail!("[pressure] shunt_ohm must be positive");
+        }
+        if self.full_scale_bar == self.zero_bar {
+            bail!("[pressure] full_scale_bar must differ from zero_bar");
+        }
+        Ok(())
+    }
+
+    /// Converts a loop current, in mA, to a pressure in bar.
+    fn pressure_bar(&self, current_ma: f32) -> f32 {
+        self.zero_bar + (current_ma - 4.0) / 16.0 * (self.full_scale_bar - self.zero_bar)
+    }
+
+    /// `true` if `value` is below the alarm threshold.
+    pub fn is_low(&self, value: f32) -> bool {
+        self.alarm_below_bar.is_some_and(|threshold| value < threshold)
+    }
+
+    /// Raises or clears the low-pressure alert. Invalid readings leave the alert as it is.
+    pub fn check_alert(&self, value: f32, status: SensorStatus, alerts: &Alerts) {
+        let Some(threshold) = self.alarm_below_bar else {
+            return;
+        };
+        if status == SensorStatus::Error {
+            return;
+        }
+        if value < threshold {
+            let (value, threshold) = (format::text(Unit::Bar, value), format::text(Unit::Bar, threshold));
+            let message = Message::new(format!("Boiler pressure is {}, below {}", value, threshold))
+                .with("value", value)
+                .with("threshold", threshold);
+            alerts.raise("pressure_low", message);
+        } else {
+            alerts.clear("pressure_low");
+        }
+    }
+}
+
+enum Source {
+    Mcp3008(Mcp3008),
+    Ads1115(ads1115::Ads1115),
+    Simulated(Faults),
+}
+
+/// The pressure transducer of the boiler.
+pub struct PressureSensor {
+    config: PressureConfig,
+    source: Source,
+}
+
+impl PressureSensor {
+    pub fn new(config: &PressureConfig, board: &Board) -> Self {
+        let source = if board.is_simulated() {
+            Source::Simulated(board.faults().clone())
+        } else {
+            match config.adc {
+                PressureAdc::Mcp3008 => Source::Mcp3008(Mcp3008::new(board.spi_device(config.bus, config.chip_select))),
+                PressureAdc::Ads1115 => {
+                    Source::Ads1115(ads1115::Ads1115::new(board.i2c_device(config.bus), config.address))
+                }
+            }
+        };
+        Self {
+            config: config.clone(),
+            source,
+        }
+    }
+
+    /// Reads the pressure, in bar.
+    ///
+    /// # Errors
+    /// Returns an error if the ADC cannot be read or the loop current is out of range.
+    pub fn read_bar(&self) -> Result<f32> {
+        let config = &self.config;
+        let volts = match &self.source {
+            Source::Mcp3008(adc) => adc.read_ratio(config.channel, config.samples)? * config.vref,
+            Source::Ads1115(adc) => adc.read_voltage(config.channel, config.samples)?,
+            Source::Simulated(faults) => {
+                // Around 1.5 bar, subject to the faults injected for `pressure`
+                return faults.apply(SIMULATION_ID, || {
+                    let bar: f32 = rand::thread_rng().gen_range(1.45..1.55);
+                    (bar * 100.0).round() / 100.0
+                });
+            }
+        };
+        let current_ma = volts / config.shunt_ohm * 1000.0;
+        if !(MIN_LOOP_MA..=MAX_LOOP_MA).contains(&current_ma) {
+            bail!("Pressure loop current {:.1} mA out of range: transducer disconnected or failed", current_ma);
+        }
+        // Round to 2 decimal places
+        Ok((config.pressure_bar(current_ma) * 100.0).round() / 100.0)
+    }
+}
@@ -0,0 +1,112 @@
+//! Aggregation of other boilert instances.
+//! Follows the MQTT topics published by remote instances so that their tanks can be
+//! shown on this display.
+
+use std::time::{Duration, Instant};
+
+use boilert::model::SensorPayload;
+use serde::Deserialize;
+use tokio::sync::watch;
+
+use crate::mqtt::Subscriptions;
+
+/// A remote boilert instance to follow.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RemoteConfig {
+    /// Name shown in the UI (e.g., "Immeuble B").
+    pub name: String,
+    /// `base_topic` configured on the remote instance.
+    pub base_topic: String,
+}
+
+/// Topics directly under a base topic that do not carry a sensor temperature.
+const NON_SENSOR_TOPICS: [&str; 3] = ["timestamp", "selftest", "state"];
+
+/// A remote tank is shown as offline when nothing was received for this long.
+const STALE_AFTER: Duration = Duration::from_secs(60);
+
+/// Latest values received from a remote instance.
+#[derive(Debug, Clone)]
+pub struct RemoteTank {
+    pub name: String,
+    /// Sensor names and temperatures, in the order they were first received.
+    pub sensors: Vec<(String, f32)>,
+    /// Energy stored in the remote boiler, in kWh.
+    pub energy_kwh: Option<f32>,
+    /// Pressure of the remote boiler, in bar, if it has a transducer.
+    pub pressure_bar: Option<f32>,
+    last_update: Option<Instant>,
+}
+
+impl RemoteTank {
+    /// Returns `true` if values were received recently.
+    pub fn is_online(&self) -> bool {
+        self.last_update.is_some_and(|t| t.elapsed() < STALE_AFTER)
+    }
+
+    fn update(&mut self, topic: &str, value: f32) {
+        if topic == "energy" {
+            self.energy_kwh = Some(value);
+        } else if topic == "pressure" {
+            self.pressure_bar = Some(value);
+        } else {
+            match self.sensors.iter_mut().find(|(name, _)| name == topic) {
+                Some(sensor) => sensor.1 = value,
+                None => self.sensors.push((topic.to_string(), value)),
+            }
+        }
+        self.last_update = Some(Instant::now());
+    }
+}
+
+/// Subscribes to the topics of the configured remote instances.
+///
+/// The returned channel holds one `RemoteTank` per remote, in configuration order,
+/// and is updated on every message received.
+pub fn follow(remotes: &[RemoteConfig], subscriptions: &Subscriptions) -> watch::Receiver<Vec<RemoteTank>> {
+    let tanks: Vec<RemoteTank> = remotes
+        .iter()
+        .map(|remote| RemoteTank {
+            name: remote.name.clone(),
+            sensors: Vec::new(),
+            energy_kwh: None,
+            pressure_bar: None,
+            last_update: None,
+        })
+        .collect();
+    let (sender, receiver) = watch::channel(tanks);
+
+    for (index, remote) in remotes.iter().enumerate() {
+        let prefix = format!("{}/", remote.base_topic);
+        let sender = sender.clone();
+        subscriptions.subscribe(format!("{}+", prefix), move |topic, payload| {
+            let Some(topic) = topic.strip_prefix(&prefix) else {
+                return;
+            };
+            if NON_SENSOR_TOPICS.contains(&topic) {
+                return;
+            }
+            // The last will of a remote that died: no need to wait for its values to go stale
+            if topic == "status" {
+                if payload == b"offline" {
+                    sender.send_modify(|tanks| tanks[index].last_update = None);
+                }
+                return;
+            }
+            let Some(value) = parse_value(payload) else {
+                return;
+            };
+            sender.send_modify(|tanks| tanks[index].update(topic, value));
+        });
+    }
+    receiver
+}
+
+/// Value published by a remote instance, whatever its payload format (`None` if invalid).
+fn parse_value(payload: &[u8]) -> Option<f32> {
+    let text = std::str::from_utf8(payload).ok()?.trim();
+    text.parse().ok().or_else(|| {
+        let payload: SensorPayload = serde_json::from_str(text).ok()?;
+        payload.value.filter(|_| payload.status.is_valid())
+    })
+}
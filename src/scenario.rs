@@ -0,0 +1,217 @@
+//! Temperature scenarios for the simulated board.
+//! Instead of random values, the simulated sensors replay a scenario: temperature
+//! profiles built from ramps, heating cycles and hot water draws (TOML), or keyframes
+//! recorded on a real installation (CSV). Time can be accelerated to go through a day
+//! in minutes.
+//!
+//! TOML scenarios start from an initial temperature per sensor and apply timed events
+//! in order, with a passive heat loss towards the ambient temperature in between. CSV
+//! scenarios give the temperatures of each sensor at given minutes, linearly
+//! interpolated. Both restart from the beginning once they are over.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+/// Sensor key matching every sensor.
+const ALL_SENSORS: &str = "*";
+
+/// Effect of a scenario event on the temperature of its sensors.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Effect {
+    /// Moves linearly to `to_c` over the event.
+    Ramp { to_c: f32 },
+    /// Heating cycle: rises at `rate_c_per_h`.
+    Heat { rate_c_per_h: f32 },
+    /// Hot water draw: cold water lowers the temperature by `drop_c` over the event.
+    Draw { drop_c: f32 },
+}
+
+/// A timed event of a TOML scenario.
+#[derive(Debug, Deserialize, Clone)]
+struct Event {
+    /// Start, in minutes from the beginning of the scenario.
+    at_min: f32,
+    duration_min: f32,
+    /// IDs of the affected sensors (default: all).
+    #[serde(default)]
+    sensors: Vec<String>,
+    #[serde(flatten)]
+    effect: Effect,
+}
+
+impl Event {
+    fn applies_to(&self, sensor_id: &str) -> bool {
+        self.sensors.is_empty() || self.sensors.iter().any(|s| s == sensor_id)
+    }
+
+    fn end_min(&self) -> f32 {
+        self.at_min + self.duration_min
+    }
+}
+
+/// A scenario file in the TOML format.
+#[derive(Debug, Deserialize)]
+struct EventScenario {
+    /// The scenario restarts after this, in minutes (default: end of the last event).
+    length_min: Option<f32>,
+    #[serde(default = "default_ambient")]
+    ambient_c: f32,
+    /// Passive heat loss between events, in °C per hour at 40 °C above the ambient
+    /// (proportional to the difference with the ambient).
+    #[serde(default)]
+    cooling_c_per_h: f32,
+    /// Temperature of each sensor at the start, by ID (`"*"` for the others).
+    initial: HashMap<String, f32>,
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+fn default_ambient() -> f32 {
+    20.0
+}
+
+impl EventScenario {
+    /// Passive cooling of `value` over `minutes`.
+    fn cool(&self, value: f32, minutes: f32) -> f32 {
+        if minutes <= 0.0 || self.cooling_c_per_h <= 0.0 {
+            return value;
+        }
+        let rate_per_min = self.cooling_c_per_h / 40.0 / 60.0;
+        self.ambient_c + (value - self.ambient_c) * (-rate_per_min * minutes).exp()
+    }
+
+    fn temperature(&self, sensor_id: &str, minute: f32) -> Option<f32> {
+        let mut value = *self.initial.get(sensor_id).or_else(|| self.initial.get(ALL_SENSORS))?;
+        let mut at = 0.0;
+        for event in self.events.iter().filter(|e| e.applies_to(sensor_id) && e.at_min <= minute) {
+            value = self.cool(value, event.at_min - at);
+            let elapsed = (minute.min(event.end_min()) - event.at_min).max(0.0);
+            let progress = if event.duration_min > 0.0 { elapsed / event.duration_min } else { 1.0 };
+            value = match event.effect {
+                Effect::Ramp { to_c } => value + (to_c - value) * progress,
+                Effect::Heat { rate_c_per_h } => value + rate_c_per_h * elapsed / 60.0,
+                Effect::Draw { drop_c } => value - drop_c * progress,
+            };
+            at = event.at_min + elapsed;
+        }
+        Some(self.cool(value, minute - at))
+    }
+}
+
+/// Keyframes of a CSV scenario: per sensor ID, `(minute, °C)` in time order.
+#[derive(Debug)]
+struct KeyframeScenario {
+    sensors: HashMap<String, Vec<(f32, f32)>>,
+}
+
+impl KeyframeScenario {
+    /// Parses `minute,<sensor id>,<sensor id>...` rows; empty cells are skipped.
+    fn parse(content: &str) -> Result<Self> {
+        let mut lines = content.lines().filter(|l| !l.trim().is_empty() && !l.starts_with('#'));
+        let header: Vec<String> = lines
+            .next()
+            .context("Empty scenario")?
+            .split(',')
+            .map(|h| h.trim().to_string())
+            .collect();
+        let mut sensors: HashMap<String, Vec<(f32, f32)>> = HashMap::new();
+        for (row, line) in lines.enumerate() {
+            let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+            let minute: f32 = cells[0].parse().with_context(|| format!("Row {}: invalid minute", row + 2))?;
+            for (id, cell) in header.iter().zip(&cells).skip(1) {
+                if cell.is_empty() {
+                    continue;
+                }
+                let value = cell.parse().with_context(|| format!("Row {}: invalid value for {}", row + 2, id))?;
+                let frames = sensors.entry(id.clone()).or_default();
+                if frames.last().is_some_and(|&(last, _)| last > minute) {
+                    bail!("Row {}: the minutes must be in increasing order", row + 2);
+                }
+                frames.push((minute, value));
+            }
+        }
+        Ok(Self { sensors })
+    }
+
+    fn length_min(&self) -> f32 {
+        self.sensors.values().filter_map(|f| f.last()).map(|&(minute, _)| minute).fold(0.0, f32::max)
+    }
+
+    fn temperature(&self, sensor_id: &str, minute: f32) -> Option<f32> {
+        let frames = self.sensors.get(sensor_id).or_else(|| self.sensors.get(ALL_SENSORS))?;
+        let after = frames.iter().position(|&(m, _)| m > minute);
+        Some(match after {
+            Some(0) => frames[0].1,
+            None => frames.last()?.1,
+            Some(i) => {
+                let ((m0, v0), (m1, v1)) = (frames[i - 1], frames[i]);
+                v0 + (v1 - v0) * (minute - m0) / (m1 - m0)
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Profile {
+    Events(EventScenario),
+    Keyframes(KeyframeScenario),
+}
+
+/// A loaded scenario, played from the time it was loaded.
+#[derive(Debug)]
+pub struct Scenario {
+    profile: Profile,
+    length_min: f32,
+    /// Scenario seconds per real second.
+    speed: f32,
+    started: Instant,
+}
+
+impl Scenario {
+    /// Loads a scenario file: CSV keyframes if its extension is `.csv`, TOML events otherwise.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn load(path: &Path, speed: f32) -> Result<Self> {
+        let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let profile = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("csv")) {
+            Profile::Keyframes(KeyframeScenario::parse(&content)?)
+        } else {
+            Profile::Events(toml::from_str(&content).with_context(|| format!("Invalid scenario {}", path.display()))?)
+        };
+        let length_min = match &profile {
+            Profile::Events(scenario) => scenario
+                .length_min
+                .unwrap_or_else(|| scenario.events.iter().map(Event::end_min).fold(0.0, f32::max)),
+            Profile::Keyframes(scenario) => scenario.length_min(),
+        };
+        Ok(Self {
+            profile,
+            length_min,
+            speed: speed.max(0.0),
+            started: Instant::now(),
+        })
+    }
+
+    /// Current time in the scenario, in minutes, wrapped to its length.
+    fn minute(&self) -> f32 {
+        let minute = self.started.elapsed().as_secs_f32() * self.speed / 60.0;
+        if self.length_min > 0.0 { minute % self.length_min } else { minute }
+    }
+
+    /// Current temperature of `sensor_id`, rounded to 2 decimal places, or `None` if
+    /// the scenario does not cover the sensor.
+    pub fn temperature(&self, sensor_id: &str) -> Option<f32> {
+        let minute = self.minute();
+        let value = match &self.profile {
+            Profile::Events(scenario) => scenario.temperature(sensor_id, minute),
+            Profile::Keyframes(scenario) => scenario.temperature(sensor_id, minute),
+        }?;
+        Some((value * 100.0).round() / 100.0)
+    }
+}
@@ -0,0 +1,182 @@
+//! Embedded scripting hooks (Rhai), enabled with the `scripting` feature.
+//!
+//! A user script can define functions called by boilert:
+//! - `on_cycle(readings, energy_kwh)` after every acquisition cycle, with the readings
+//!   as a map (sensor name -> temperature);
+//! - `on_alert(id, message, raised)` when an alert is raised or cleared;
+//! - `on_heater(on)` when the heater switches on or off.
+//!
+//! From these functions, the script can:
+//! - publish custom MQTT messages with `publish(topic, payload)`, where `topic` is
+//!   relative to the base topic (useful for derived values);
+//! - switch a configured output with `set_output(name, on)`, for bespoke control rules;
+//! - log messages with `print(...)`.
+//!
+//! Scripts are sandboxed: Rhai has no file or network access, and the number of
+//! operations per call and the size of strings, arrays and maps are bounded. The
+//! script is reloaded when its file changes.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::{Result, anyhow};
+use boilert::model::AuditSource;
+use rhai::{AST, CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope};
+
+use crate::alerts::Alert;
+use crate::logging::{info, warning};
+use crate::outputs::Outputs;
+
+/// Maximum number of operations a single hook call may execute.
+const MAX_OPERATIONS: u64 = 100_000;
+/// Maximum length of a string built by the script, in bytes.
+const MAX_STRING_SIZE: usize = 4096;
+/// Maximum number of items of an array or a map built by the script.
+const MAX_COLLECTION_SIZE: usize = 1000;
+
+/// A message the script asked to publish, relative to the base topic.
+#[derive(Debug, Clone)]
+pub struct ScriptPublish {
+    pub topic: String,
+    pub payload: String,
+}
+
+/// A loaded user script with its engine and global state.
+pub struct ScriptHooks {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    modified: Option<SystemTime>,
+    published: Arc<Mutex<Vec<ScriptPublish>>>,
+    /// Alerts active at the last call of `on_alert`.
+    alerts: Vec<String>,
+    /// Heater state at the last call of `on_heater`.
+    heater_on: Option<bool>,
+}
+
+impl ScriptHooks {
+    /// Compiles the script at `path` and runs its top-level statements once. The script
+    /// switches `outputs`.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or the script fails to compile or run.
+    pub fn load(path: &str, outputs: Outputs) -> Result<Self> {
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(32);
+        engine.set_max_string_size(MAX_STRING_SIZE);
+        engine.set_max_array_size(MAX_COLLECTION_SIZE);
+        engine.set_max_map_size(MAX_COLLECTION_SIZE);
+        engine.on_print(|msg| info!("[script] {}", msg));
+        {
+            let published = published.clone();
+            engine.register_fn("publish", move |topic: &str, payload: Dynamic| {
+                published
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(ScriptPublish {
+                        topic: topic.to_string(),
+                        payload: payload.to_string(),
+                    });
+            });
+        }
+        // Only the changes are queued: a rule calls it on every cycle
+        engine.register_fn("set_output", move |name: &str, on: bool| -> Result<(), Box<EvalAltResult>> {
+            if outputs.status().iter().any(|output| output.name == name && output.on == Some(on)) {
+                return Ok(());
+            }
+            outputs.switch(name, on, AuditSource::Script).map_err(|e| format!("{:#}", e).into())
+        });
+
+        let mut hooks = Self {
+            path: PathBuf::from(path),
+            engine,
+            ast: AST::empty(),
+            scope: Scope::new(),
+            modified: None,
+            published,
+            alerts: Vec::new(),
+            heater_on: None,
+        };
+        hooks.compile()?;
+        Ok(hooks)
+    }
+
+    fn compile(&mut self) -> Result<()> {
+        self.modified = std::fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        let ast = self
+            .engine
+            .compile_file(self.path.clone())
+            .map_err(|e| anyhow!("Failed to compile {}: {}", self.path.display(), e))?;
+        let mut scope = Scope::new();
+        self.engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| anyhow!("Failed to run {}: {}", self.path.display(), e))?;
+        self.ast = ast;
+        self.scope = scope;
+        Ok(())
+    }
+
+    /// Recompiles the script if its file changed since the last load.
+    /// On error, the previous version is kept.
+    pub fn reload_if_changed(&mut self) {
+        let modified = std::fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified != self.modified {
+            match self.compile() {
+                Ok(()) => info!("Reloaded script {}", self.path.display()),
+                Err(e) => warning!("{:#}", e),
+            }
+        }
+    }
+
+    /// Calls the `on_cycle` hook, if defined.
+    pub fn on_cycle(&mut self, readings: &[(String, f32)], energy_kwh: f32) {
+        let mut map = Map::new();
+        for (name, value) in readings {
+            map.insert(name.as_str().into(), Dynamic::from_float(*value as rhai::FLOAT));
+        }
+        self.call("on_cycle", (map, energy_kwh as rhai::FLOAT));
+    }
+
+    /// Calls the `on_alert` hook, if defined, for every alert raised or cleared since the
+    /// last call.
+    pub fn on_alerts(&mut self, active: &[Alert]) {
+        let previous = std::mem::replace(&mut self.alerts, active.iter().map(|a| a.id.clone()).collect());
+        for alert in active.iter().filter(|a| !previous.contains(&a.id)) {
+            self.call("on_alert", (alert.id.clone(), alert.message.clone(), true));
+        }
+        for id in previous {
+            if !active.iter().any(|a| a.id == id) {
+                self.call("on_alert", (id, String::new(), false));
+            }
+        }
+    }
+
+    /// Calls the `on_heater` hook, if defined, when the heater state changed since the
+    /// last call.
+    pub fn on_heater(&mut self, on: bool) {
+        if self.heater_on.replace(on) != Some(on) {
+            self.call("on_heater", (on,));
+        }
+    }
+
+    /// Returns the messages published by the hooks since the last call.
+    pub fn take_published(&mut self) -> Vec<ScriptPublish> {
+        std::mem::take(&mut *self.published.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Calls the function `name` of the script, if defined, and logs its errors.
+    fn call(&mut self, name: &str, args: impl FuncArgs) {
+        if !self.ast.iter_functions().any(|f| f.name == name) {
+            return;
+        }
+        // The top-level statements only run when the script is loaded
+        let options = CallFnOptions::new().eval_ast(false);
+        if let Err(e) = self.engine.call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, name, args) {
+            warning!("Script error in {}: {}", name, e);
+        }
+    }
+}
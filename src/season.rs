@@ -0,0 +1,393 @@
+//! Heating season detection and seasonal statistics.
+//! Every statistics day is classified as a heating-season or a summer day, from its mean
+//! outdoor temperature when an outdoor sensor is configured, or else from the calendar.
+//! The energy balance of the day, taken from the efficiency records, is added to the
+//! totals of its season, so that this winter can be compared with the last one; the
+//! comparison is shown on the efficiency page and published on `{base_topic}/season`.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result, bail};
+use boilert::model::Snapshot;
+use chrono::{Datelike, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::billing::BillingConfig;
+use crate::clock::ClockStatus;
+use crate::efficiency::Efficiency;
+use crate::logging::info;
+use crate::mqtt::Publisher;
+
+/// Seasons kept: the last four years.
+const KEPT_SEASONS: usize = 8;
+/// Heating seasons run across the new year: from this month on, a day belongs to the
+/// season starting that year.
+const SEASON_YEAR_START_MONTH: u32 = 7;
+
+/// Season settings (`[season]`).
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SeasonConfig {
+    /// Name of the outdoor temperature sensor (calendar only if absent).
+    pub outdoor_sensor: Option<String>,
+    /// Mean outdoor temperature of a day below which it belongs to the heating season, in °C.
+    pub heating_below_c: f32,
+    /// First day of the heating season in the calendar ("MM-DD").
+    pub heating_start: String,
+    /// Last day of the heating season in the calendar ("MM-DD").
+    pub heating_end: String,
+}
+
+impl Default for SeasonConfig {
+    fn default() -> Self {
+        Self {
+            outdoor_sensor: None,
+            heating_below_c: 15.0,
+            heating_start: "10-01".to_string(),
+            heating_end: "04-30".to_string(),
+        }
+    }
+}
+
+impl SeasonConfig {
+    /// Checks the calendar and the outdoor sensor against the configured sensor names.
+    pub fn validate(&self, sensor_names: &[String]) -> Result<()> {
+        if let Some(sensor) = &self.outdoor_sensor
+            && !sensor_names.contains(sensor)
+        {
+            bail!("[season] unknown outdoor_sensor {:?}", sensor);
+        }
+        if !self.heating_below_c.is_finite() {
+            bail!("[season] heating_below_c must be a temperature");
+        }
+        month_day(&self.heating_start)?;
+        month_day(&self.heating_end)?;
+        Ok(())
+    }
+
+    /// Season of `date` in the calendar.
+    fn calendar_kind(&self, date: NaiveDate) -> SeasonKind {
+        let (Ok(start), Ok(end)) = (month_day(&self.heating_start), month_day(&self.heating_end)) else {
+            return SeasonKind::Summer;
+        };
+        let day = (date.month(), date.day());
+        // The heating season usually spans the new year
+        let heating = if start <= end { start <= day && day <= end } else { day >= start || day <= end };
+        if heating { SeasonKind::Heating } else { SeasonKind::Summer }
+    }
+
+    /// Season of `date`, whose outdoor temperatures averaged `outdoor_mean_c`.
+    fn kind(&self, date: NaiveDate, outdoor_mean_c: Option<f64>) -> SeasonKind {
+        match outdoor_mean_c {
+            Some(mean) if self.outdoor_sensor.is_some() => {
+                if mean < f64::from(self.heating_below_c) { SeasonKind::Heating } else { SeasonKind::Summer }
+            }
+            _ => self.calendar_kind(date),
+        }
+    }
+}
+
+/// Parses a "MM-DD" day of the calendar.
+fn month_day(day: &str) -> Result<(u32, u32)> {
+    let parsed = day.trim().split_once('-').and_then(|(month, day)| {
+        let (month, day) = (month.parse().ok()?, day.parse().ok()?);
+        // Checked against a leap year, so that 02-29 is accepted
+        NaiveDate::from_ymd_opt(2024, month, day).map(|_| (month, day))
+    });
+    parsed.with_context(|| format!("[season] {:?} is not a MM-DD day", day))
+}
+
+/// Kind of season.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeasonKind {
+    Heating,
+    Summer,
+}
+
+impl SeasonKind {
+    /// Name of the kind, as serialized.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SeasonKind::Heating => "heating",
+            SeasonKind::Summer => "summer",
+        }
+    }
+}
+
+/// Totals of a season.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SeasonTotals {
+    pub kind: SeasonKind,
+    /// Year the season started.
+    pub year: i32,
+    pub first_day: NaiveDate,
+    pub last_day: NaiveDate,
+    /// Days accounted.
+    pub days: u32,
+    /// Energy stored by heating, in kWh.
+    pub heated_kwh: f64,
+    /// Energy drawn as hot water, in kWh.
+    pub drawn_kwh: f64,
+    /// Energy lost while idle, in kWh.
+    pub standby_loss_kwh: f64,
+    /// Sum of the daily mean outdoor temperatures, and the number of days it holds.
+    pub outdoor_sum_c: f64,
+    pub outdoor_days: u32,
+}
+
+impl SeasonTotals {
+    /// Season name: the years of a heating season ("2025-2026"), the year of a summer.
+    pub fn label(&self) -> String {
+        match self.kind {
+            SeasonKind::Heating => format!("{}-{}", self.year, self.year + 1),
+            SeasonKind::Summer => self.year.to_string(),
+        }
+    }
+
+    /// Mean outdoor temperature of the season, in °C.
+    pub fn outdoor_mean_c(&self) -> Option<f64> {
+        (self.outdoor_days > 0).then(|| self.outdoor_sum_c / f64::from(self.outdoor_days))
+    }
+
+    /// Heating energy per day, in kWh.
+    pub fn heated_kwh_per_day(&self) -> Option<f64> {
+        (self.days > 0).then(|| self.heated_kwh / f64::from(self.days))
+    }
+}
+
+/// Year the season of kind `kind` holding `date` started.
+fn season_year(kind: SeasonKind, date: NaiveDate) -> i32 {
+    match kind {
+        SeasonKind::Heating if date.month() < SEASON_YEAR_START_MONTH => date.year() - 1,
+        _ => date.year(),
+    }
+}
+
+/// Persisted season records: the totals of the seasons and the outdoor temperatures of
+/// the day in progress.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SeasonRecords {
+    /// Statistics day of the outdoor readings.
+    pub day: Option<NaiveDate>,
+    pub outdoor_sum_c: f64,
+    pub outdoor_samples: u64,
+    /// Seasons, oldest first.
+    pub seasons: Vec<SeasonTotals>,
+}
+
+impl SeasonRecords {
+    /// Mean outdoor temperature of the day in progress.
+    fn outdoor_mean_c(&self) -> Option<f64> {
+        (self.outdoor_samples > 0).then(|| self.outdoor_sum_c / self.outdoor_samples as f64)
+    }
+}
+
+/// Current season and its comparison with the previous one of the same kind, published
+/// on `{base_topic}/season`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SeasonReport {
+    /// Season of the day in progress.
+    pub today: SeasonKind,
+    /// Totals of the current season, up to yesterday.
+    pub current: Option<SeasonSummary>,
+    /// Totals of the same season a year before.
+    pub previous: Option<SeasonSummary>,
+    /// Change of the heating energy per day since the previous season, in percent.
+    pub heated_change_percent: Option<i32>,
+}
+
+/// Totals of a season, as reported.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SeasonSummary {
+    pub season: String,
+    pub days: u32,
+    pub heated_kwh: f64,
+    pub heated_kwh_per_day: Option<f64>,
+    pub drawn_kwh: f64,
+    pub standby_loss_kwh: f64,
+    pub outdoor_mean_c: Option<f64>,
+}
+
+impl From<&SeasonTotals> for SeasonSummary {
+    fn from(totals: &SeasonTotals) -> Self {
+        Self {
+            season: totals.label(),
+            days: totals.days,
+            heated_kwh: totals.heated_kwh,
+            heated_kwh_per_day: totals.heated_kwh_per_day(),
+            drawn_kwh: totals.drawn_kwh,
+            standby_loss_kwh: totals.standby_loss_kwh,
+            outdoor_mean_c: totals.outdoor_mean_c(),
+        }
+    }
+}
+
+/// Shared season records and report.
+///
+/// Cloning a `Seasons` handle is cheap; all clones share the same records.
+#[derive(Clone)]
+pub struct Seasons {
+    config: SeasonConfig,
+    records: Arc<Mutex<SeasonRecords>>,
+    report: watch::Sender<SeasonReport>,
+}
+
+impl Seasons {
+    /// Creates the handle, starting from the records saved in the runtime state.
+    pub fn new(config: &SeasonConfig, saved: &SeasonRecords) -> Self {
+        let seasons = Self {
+            config: config.clone(),
+            records: Arc::new(Mutex::new(saved.clone())),
+            report: watch::channel(SeasonReport {
+                today: SeasonKind::Summer,
+                current: None,
+                previous: None,
+                heated_change_percent: None,
+            })
+            .0,
+        };
+        let today = saved.day.unwrap_or_else(|| Local::now().date_naive());
+        seasons.report.send_replace(seasons.compute(today));
+        seasons
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SeasonRecords> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records, for the runtime state.
+    pub fn records(&self) -> SeasonRecords {
+        self.lock().clone()
+    }
+
+    /// Totals of the seasons kept, oldest first.
+    pub fn totals(&self) -> Vec<SeasonTotals> {
+        self.lock().seasons.clone()
+    }
+
+    /// Latest report.
+    pub fn report(&self) -> SeasonReport {
+        self.report.borrow().clone()
+    }
+
+    /// Follows the reports.
+    pub fn subscribe(&self) -> watch::Receiver<SeasonReport> {
+        self.report.subscribe()
+    }
+
+    /// Adds the completed day `date` to the totals of its season.
+    fn archive(&self, date: NaiveDate, efficiency: &Efficiency) {
+        let mut records = self.lock();
+        let outdoor_mean_c = records.outdoor_mean_c();
+        let kind = self.config.kind(date, outdoor_mean_c);
+        let Some(day) = efficiency.days().into_iter().find(|day| day.date == date) else {
+            return;
+        };
+        let year = season_year(kind, date);
+        let position = records.seasons.iter().position(|s| s.kind == kind && s.year == year);
+        let season = match position {
+            Some(position) => &mut records.seasons[position],
+            None => {
+                records.seasons.push(SeasonTotals {
+                    kind,
+                    year,
+                    first_day: date,
+                    last_day: date,
+                    days: 0,
+                    heated_kwh: 0.0,
+                    drawn_kwh: 0.0,
+                    standby_loss_kwh: 0.0,
+                    outdoor_sum_c: 0.0,
+                    outdoor_days: 0,
+                });
+                let skip = records.seasons.len().saturating_sub(KEPT_SEASONS);
+                records.seasons.drain(..skip);
+                let last = records.seasons.len() - 1;
+                info!("Season: {} {} started on {}", kind.as_str(), records.seasons[last].label(), date);
+                &mut records.seasons[last]
+            }
+        };
+        season.last_day = season.last_day.max(date);
+        season.days += 1;
+        season.heated_kwh += day.heated_kwh;
+        season.drawn_kwh += day.drawn_kwh;
+        season.standby_loss_kwh += day.standby_loss_kwh;
+        if let Some(mean) = outdoor_mean_c {
+            season.outdoor_sum_c += mean;
+            season.outdoor_days += 1;
+        }
+    }
+
+    /// Computes the report of the statistics day `today`.
+    fn compute(&self, today: NaiveDate) -> SeasonReport {
+        let records = self.lock();
+        let kind = self.config.kind(today, records.outdoor_mean_c());
+        let year = season_year(kind, today);
+        let find = |year: i32| records.seasons.iter().find(|s| s.kind == kind && s.year == year);
+        let (current, previous) = (find(year), find(year - 1));
+        let heated_change_percent = current
+            .and_then(SeasonTotals::heated_kwh_per_day)
+            .zip(previous.and_then(SeasonTotals::heated_kwh_per_day))
+            .filter(|&(_, before)| before > 0.0)
+            .map(|(now, before)| ((now / before - 1.0) * 100.0).round() as i32);
+        SeasonReport {
+            today: kind,
+            current: current.map(SeasonSummary::from),
+            previous: previous.map(SeasonSummary::from),
+            heated_change_percent,
+        }
+    }
+}
+
+/// Task classifying the statistics days and keeping the season totals.
+#[derive(Clone)]
+pub struct SeasonTracker {
+    pub seasons: Seasons,
+    pub efficiency: Efficiency,
+    pub billing: BillingConfig,
+    pub snapshots: watch::Receiver<Option<Snapshot>>,
+    pub publisher: Publisher,
+    pub base_topic: String,
+    pub clock_status: ClockStatus,
+}
+
+impl SeasonTracker {
+    /// Averages the outdoor temperature of each day and archives the days as they end,
+    /// publishing the report whenever it changes; only while the clock is sane.
+    pub async fn run(self) {
+        let Self { seasons, efficiency, billing, mut snapshots, publisher, base_topic, clock_status } = self;
+        while snapshots.changed().await.is_ok() {
+            let Some(snapshot) = snapshots.borrow_and_update().clone() else {
+                continue;
+            };
+            if !clock_status.is_sane() {
+                continue;
+            }
+            let today = billing.day(snapshot.timestamp.with_timezone(&Local));
+            let completed = seasons.lock().day.replace(today).filter(|&day| day != today);
+            if let Some(day) = completed {
+                seasons.archive(day, &efficiency);
+                let mut records = seasons.lock();
+                records.outdoor_sum_c = 0.0;
+                records.outdoor_samples = 0;
+            }
+            if let Some(sensor) = &seasons.config.outdoor_sensor
+                && let Some(reading) = snapshot.readings.iter().find(|r| &r.sensor == sensor && r.status.is_valid())
+            {
+                let mut records = seasons.lock();
+                records.outdoor_sum_c += f64::from(reading.value);
+                records.outdoor_samples += 1;
+            }
+
+            let report = seasons.compute(today);
+            if seasons.report.send_if_modified(|current| std::mem::replace(current, report.clone()) != report)
+                && let Ok(payload) = serde_json::to_vec(&report)
+            {
+                let topic = format!("{}/season", base_topic);
+                let _ = publisher.publish(topic, rumqttc::QoS::AtLeastOnce, true, payload).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Startup self-test.
+//! Checks sensors, MQTT connectivity and the data store before acquisition starts.
+
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::Alerts;
+use crate::board::Board;
+use crate::config::Config;
+use crate::format::{self, Unit};
+use crate::health::Health;
+use crate::logging::{info, warning};
+use crate::mqtt::Publisher;
+use crate::sensors;
+
+/// What to do when the self-test fails.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SelfTestPolicy {
+    /// Skip the self-test.
+    Off,
+    /// Report the failures, raise a `selftest` alert and keep running.
+    #[default]
+    Degrade,
+    /// Report the failures and exit, letting the service manager retry.
+    Abort,
+}
+
+/// Self-test settings.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SelfTestConfig {
+    /// Behavior on failure.
+    pub policy: SelfTestPolicy,
+    /// Time allowed to connect to the MQTT broker, in seconds.
+    pub mqtt_timeout_s: u64,
+    /// Minimum free space on the data store, in megabytes.
+    pub min_free_mb: u64,
+}
+
+impl Default for SelfTestConfig {
+    fn default() -> Self {
+        Self {
+            policy: SelfTestPolicy::Degrade,
+            mqtt_timeout_s: 10,
+            min_free_mb: 50,
+        }
+    }
+}
+
+/// Result of a single check.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// Result of the self-test, printed and published on `{base_topic}/selftest`.
+#[derive(Debug, Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+impl SelfTestReport {
+    fn push(&mut self, name: impl Into<String>, result: Result<String, String>) {
+        let (passed, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        self.passed &= passed;
+        self.checks.push(Check {
+            name: name.into(),
+            passed,
+            detail,
+        });
+    }
+
+    /// Names of the failed checks.
+    pub fn failures(&self) -> Vec<&str> {
+        self.checks.iter().filter(|c| !c.passed).map(|c| c.name.as_str()).collect()
+    }
+}
+
+/// Runs all checks. MQTT connectivity is observed through `health`, so the MQTT
+/// event loop must already be running.
+pub async fn run(config: &Config, board: &Board, health: &Health) -> SelfTestReport {
+    let mut report = SelfTestReport {
+        passed: true,
+        checks: Vec::new(),
+    };
+
+    for sensor in &config.sensors {
+        // Sensor reads block, keep them off the runtime threads
+        let backend = sensors::open(sensor, board);
+        let result = tokio::task::spawn_blocking(move || backend.read())
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r.map(|t| format::text(Unit::Celsius, t)).map_err(|e| e.to_string()));
+        report.push(format!("sensor {}", sensor.name), result);
+    }
+
+    let timeout = Duration::from_secs(config.selftest.mqtt_timeout_s);
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !health.is_mqtt_connected() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    let mqtt = if health.is_mqtt_connected() {
+        Ok(format!("connected to {}:{}", config.mqtt.host, config.mqtt.port))
+    } else {
+        Err(format!(
+            "no connection to {}:{} within {}s",
+            config.mqtt.host,
+            config.mqtt.port,
+            timeout.as_secs()
+        ))
+    };
+    report.push("mqtt", mqtt);
+
+    let state_dir = Path::new(&config.state.path)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    report.push("data store", check_writable(state_dir));
+    report.push("disk space", check_free_space(state_dir, config.selftest.min_free_mb));
+
+    report
+}
+
+/// Prints the report, publishes it and applies the configured policy.
+///
+/// Returns `false` if the application must abort.
+pub fn conclude(
+    report: &SelfTestReport,
+    policy: SelfTestPolicy,
+    alerts: &Alerts,
+    publisher: &Publisher,
+    base_topic: &str,
+) -> bool {
+    if report.passed {
+        info!("Self-test passed");
+    } else {
+        warning!("Self-test FAILED");
+    }
+    for check in &report.checks {
+        if check.passed {
+            info!("Self-test check {}: {}", check.name, check.detail);
+        } else {
+            warning!("Self-test check {} failed: {}", check.name, check.detail);
+        }
+    }
+
+    if let Ok(payload) = serde_json::to_vec(report) {
+        let topic = format!("{}/selftest", base_topic);
+        let _ = publisher.try_publish(topic, rumqttc::QoS::AtLeastOnce, true, payload);
+    }
+
+    if report.passed {
+        alerts.clear("selftest");
+        return true;
+    }
+    alerts.raise("selftest", format!("Self-test failed: {}", report.failures().join(", ")));
+    policy != SelfTestPolicy::Abort
+}
+
+fn check_writable(dir: &Path) -> Result<String, String> {
+    let probe = dir.join(".boilert-selftest");
+    crate::state::write_atomic(&probe, b"ok").map_err(|e| format!("{} is not writable: {:#}", dir.display(), e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(format!("{} is writable", dir.display()))
+}
+
+fn check_free_space(dir: &Path, min_free_mb: u64) -> Result<String, String> {
+    let free_mb = free_space(dir).map_err(|e| format!("cannot read free space: {}", e))? / (1024 * 1024);
+    if free_mb < min_free_mb {
+        Err(format!("{} MB free, at least {} MB required", free_mb, min_free_mb))
+    } else {
+        Ok(format!("{} MB free", free_mb))
+    }
+}
+
+/// Free space available to unprivileged users on the filesystem of `dir`, in bytes.
+fn free_space(dir: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is a valid NUL-terminated string and `stat` a valid output buffer.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
@@ -0,0 +1,74 @@
+//! ADS1115 16-bit I2C ADC, read in single-shot mode on its single-ended inputs.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use linux_embedded_hal::I2cdev;
+
+/// Address with the ADDR pin tied to ground.
+pub const DEFAULT_ADDRESS: u8 = 0x48;
+
+const REG_CONVERSION: u8 = 0x00;
+const REG_CONFIG: u8 = 0x01;
+/// Start a single conversion (OS), ±4.096 V range (PGA = 001), single-shot mode,
+/// 128 samples/s, comparator disabled. The input multiplexer bits are added per channel.
+const CONFIG_SINGLE_SHOT: u16 = 0x8000 | (0b001 << 9) | (1 << 8) | (0b100 << 5) | 0b11;
+/// Full scale of the ±4.096 V range.
+const FULL_SCALE_V: f32 = 4.096;
+/// Duration of a conversion at 128 samples/s, with margin.
+const CONVERSION_TIME: Duration = Duration::from_millis(9);
+
+/// ADS1115 on an I2C bus. The bus is opened on the first read and reopened after an error.
+pub struct Ads1115 {
+    device: PathBuf,
+    address: u8,
+    bus: Mutex<Option<I2cdev>>,
+}
+
+impl Ads1115 {
+    pub fn new(device: PathBuf, address: u8) -> Self {
+        Self {
+            device,
+            address,
+            bus: Mutex::new(None),
+        }
+    }
+
+    /// Reads the average voltage of `channel` (AIN0 to AIN3 against ground) over
+    /// `samples` conversions, in volts.
+    pub fn read_voltage(&self, channel: u8, samples: u32) -> Result<f32> {
+        if channel > 3 {
+            bail!("Invalid ADS1115 channel {} (0 to 3)", channel);
+        }
+        let mut state = self.bus.lock().unwrap_or_else(|e| e.into_inner());
+        let mut bus = match state.take() {
+            Some(bus) => bus,
+            None => I2cdev::new(&self.device).with_context(|| format!("Failed to open {}", self.device.display()))?,
+        };
+        let samples = samples.max(1);
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            sum += convert(&mut bus, self.address, channel).with_context(|| {
+                format!("Failed to read ADS1115 0x{:02x} on {}", self.address, self.device.display())
+            })?;
+        }
+        *state = Some(bus);
+        Ok(sum / samples as f32)
+    }
+}
+
+/// Runs one single-shot conversion and returns the voltage.
+fn convert(bus: &mut I2cdev, address: u8, channel: u8) -> Result<f32> {
+    // MUX = 1xx: AINx against ground
+    let config = CONFIG_SINGLE_SHOT | ((0b100 | channel as u16) << 12);
+    let [high, low] = config.to_be_bytes();
+    bus.write(address, &[REG_CONFIG, high, low])?;
+    thread::sleep(CONVERSION_TIME);
+    let mut data = [0u8; 2];
+    bus.write_read(address, &[REG_CONVERSION], &mut data)?;
+    Ok(i16::from_be_bytes(data) as f32 * FULL_SCALE_V / 32768.0)
+}
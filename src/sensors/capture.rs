@@ -0,0 +1,162 @@
+//! Raw data of failed reads ("raw capture"), for the analysis of intermittent faults.
+//!
+//! Backends attach the data they received (the `w1_slave` text, I2C or Modbus bytes,
+//! ADC codes) to their errors as a [`RawRead`]. When the capture is enabled, every
+//! failed read carrying raw data is appended to a ring file: once the file reaches its
+//! size limit, it is renamed with a `.1` suffix (replacing the previous one) and a
+//! new file is started. The capture can be switched on and off at runtime.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::logging::{LogLevel, warning};
+
+/// Capture file used when the capture is switched on at runtime without `raw_capture`.
+const DEFAULT_CAPTURE_FILE: &str = "raw-reads.log";
+
+/// Debug settings from the `[debug]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct DebugConfig {
+    /// Log level at startup.
+    pub log_level: LogLevel,
+    /// File receiving the raw data of failed sensor reads (disabled at startup if absent).
+    pub raw_capture: Option<String>,
+    /// Size of the capture file before it is rotated, in KiB.
+    pub raw_capture_kb: u64,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            log_level: LogLevel::Info,
+            raw_capture: None,
+            raw_capture_kb: 256,
+        }
+    }
+}
+
+/// Error of a read, with the raw data it was decoded from.
+#[derive(Debug)]
+pub struct RawRead {
+    message: String,
+    raw: String,
+}
+
+impl RawRead {
+    pub fn new(message: impl Into<String>, raw: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            raw: raw.into(),
+        }
+    }
+}
+
+impl fmt::Display for RawRead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for RawRead {}
+
+/// Formats bytes as space-separated hexadecimal, like the `w1_slave` file.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Capture file, opened on the first record.
+#[derive(Debug)]
+struct RingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: Option<(File, u64)>,
+}
+
+impl RingFile {
+    fn append(&mut self, record: &str) -> std::io::Result<()> {
+        let (mut file, mut size) = match self.file.take() {
+            Some(open) => open,
+            None => {
+                let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+                let size = file.metadata()?.len();
+                (file, size)
+            }
+        };
+        if size > 0 && size + record.len() as u64 > self.max_bytes {
+            drop(file);
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(".1");
+            fs::rename(&self.path, rotated)?;
+            file = File::create(&self.path)?;
+            size = 0;
+        }
+        file.write_all(record.as_bytes())?;
+        self.file = Some((file, size + record.len() as u64));
+        Ok(())
+    }
+}
+
+/// Shared raw capture; disabled by default.
+///
+/// Cloning a `RawCapture` handle is cheap; all clones write to the same file and share
+/// the on/off switch.
+#[derive(Debug, Clone, Default)]
+pub struct RawCapture {
+    ring: Option<Arc<Mutex<RingFile>>>,
+    enabled: Arc<AtomicBool>,
+}
+
+impl RawCapture {
+    /// Creates the capture, enabled if `raw_capture` is set.
+    pub fn from_config(config: &DebugConfig) -> Self {
+        let path = config.raw_capture.as_deref().unwrap_or(DEFAULT_CAPTURE_FILE);
+        Self {
+            ring: Some(Arc::new(Mutex::new(RingFile {
+                path: PathBuf::from(path),
+                max_bytes: config.raw_capture_kb.max(1) * 1024,
+                file: None,
+            }))),
+            enabled: Arc::new(AtomicBool::new(config.raw_capture.is_some())),
+        }
+    }
+
+    /// Returns `true` if failed reads are captured.
+    pub fn is_enabled(&self) -> bool {
+        self.ring.is_some() && self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Switches the capture on or off (no effect on a capture not created from the
+    /// configuration).
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Appends the raw data of a failed read of `sensor`, if the error carries any.
+    pub fn record(&self, sensor: &str, error: &anyhow::Error) {
+        let Some(ring) = self.ring.as_ref().filter(|_| self.is_enabled()) else {
+            return;
+        };
+        let Some(raw) = error.chain().find_map(|cause| cause.downcast_ref::<RawRead>()) else {
+            return;
+        };
+        let record = format!(
+            "--- {} {}: {:#}\n{}\n",
+            Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            sensor,
+            error,
+            raw.raw.trim_end()
+        );
+        let mut ring = ring.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = ring.append(&record) {
+            warning!("Failed to write the raw capture to {}: {}", ring.path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Dummy backend: simulated readings for development workstations.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use rand::Rng;
+
+use super::TemperatureSensor;
+use crate::board::Board;
+use crate::scenario::Scenario;
+use crate::simulation::Faults;
+
+/// Simulated sensor replaying the board's scenario, or returning random values between
+/// 20.0 and 30.0 °C without one, subject to the faults injected for its ID (see
+/// [`crate::simulation`]).
+pub struct DummySensor {
+    id: String,
+    faults: Faults,
+    scenario: Option<Arc<Scenario>>,
+}
+
+impl DummySensor {
+    pub fn new(board: &Board, id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            faults: board.faults().clone(),
+            scenario: board.scenario().cloned(),
+        }
+    }
+}
+
+impl TemperatureSensor for DummySensor {
+    fn read(&self) -> Result<f32> {
+        self.faults.apply(&self.id, || {
+            if let Some(temp) = self.scenario.as_ref().and_then(|s| s.temperature(&self.id)) {
+                return temp;
+            }
+            let temp: f32 = rand::thread_rng().gen_range(20.0..30.0);
+            // Round to 2 decimal places
+            (temp * 100.0).round() / 100.0
+        })
+    }
+}
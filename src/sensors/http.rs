@@ -0,0 +1,96 @@
+//! HTTP backend: temperatures polled from the REST API of another device (ESPHome,
+//! Tasmota, or anything answering a number or a JSON document).
+//!
+//! The URL is fetched at most once per `poll_s`; reads in between return the last
+//! value, so a slow node is not queried on every acquisition cycle.
+
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use super::TemperatureSensor;
+use super::topic::parse_value;
+use crate::config::SensorConfig;
+
+/// Largest response accepted.
+const MAX_RESPONSE_BYTES: u64 = 64 * 1024;
+
+/// URL of an `http` sensor.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct HttpSensorConfig {
+    /// URL returning the temperature (e.g., "http://esphome-node.local/sensor/boiler_top").
+    pub url: String,
+    /// JSON pointer to the temperature in a JSON response (e.g., "/value" for ESPHome);
+    /// without it, the response is the number itself.
+    pub json_pointer: Option<String>,
+    /// Minimum time between two requests, in seconds.
+    pub poll_s: u64,
+    /// Maximum wait for the response, in milliseconds.
+    pub timeout_ms: u64,
+}
+
+impl Default for HttpSensorConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            json_pointer: None,
+            poll_s: 30,
+            timeout_ms: 3000,
+        }
+    }
+}
+
+/// Temperature polled over HTTP.
+pub struct HttpSensor {
+    config: HttpSensorConfig,
+    agent: ureq::Agent,
+    /// Last value fetched and when.
+    last: Mutex<Option<(f32, Instant)>>,
+}
+
+impl HttpSensor {
+    pub fn new(sensor: &SensorConfig) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_millis(sensor.http.timeout_ms.max(1)))
+            .user_agent(concat!("boilert/", env!("CARGO_PKG_VERSION")))
+            .build();
+        Self {
+            config: sensor.http.clone(),
+            agent,
+            last: Mutex::new(None),
+        }
+    }
+
+    fn fetch(&self) -> Result<f32> {
+        let url = &self.config.url;
+        let mut body = Vec::new();
+        self.agent
+            .get(url)
+            .call()
+            .with_context(|| format!("Request to {} failed", url))?
+            .into_reader()
+            .take(MAX_RESPONSE_BYTES)
+            .read_to_end(&mut body)
+            .with_context(|| format!("Failed to read the response of {}", url))?;
+        parse_value(&body, self.config.json_pointer.as_deref()).with_context(|| format!("Invalid response from {}", url))
+    }
+}
+
+impl TemperatureSensor for HttpSensor {
+    fn read(&self) -> Result<f32> {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((value, fetched)) = *last
+            && fetched.elapsed() < Duration::from_secs(self.config.poll_s)
+        {
+            return Ok(value);
+        }
+        // Failures are not cached: the next cycle tries again
+        let value = (self.fetch()? * 100.0).round() / 100.0;
+        *last = Some((value, Instant::now()));
+        Ok(value)
+    }
+}
@@ -0,0 +1,247 @@
+//! I2C backend: SHT31 and BME280 temperature/humidity sensors read through `/dev/i2c-<n>`.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+use linux_embedded_hal::I2cdev;
+use serde::Deserialize;
+
+use super::capture::{RawRead, hex};
+use super::{Measurement, TemperatureSensor};
+use crate::board::Board;
+use crate::config::SensorConfig;
+
+/// Supported I2C sensor chips.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum I2cChip {
+    /// Sensirion SHT3x: temperature and humidity.
+    #[default]
+    Sht31,
+    /// Bosch BME280: temperature, humidity and pressure.
+    Bme280,
+}
+
+impl I2cChip {
+    /// Address of the chip with its address pin tied low.
+    fn default_address(self) -> u8 {
+        match self {
+            I2cChip::Sht31 => 0x44,
+            I2cChip::Bme280 => 0x76,
+        }
+    }
+}
+
+/// Single-shot measurement, high repeatability, without clock stretching.
+const SHT31_MEASURE: [u8; 2] = [0x24, 0x00];
+/// Maximum duration of an SHT31 high-repeatability measurement.
+const SHT31_MEASURE_TIME: Duration = Duration::from_millis(16);
+
+const BME280_CHIP_ID: u8 = 0x60;
+const BME280_REG_CHIP_ID: u8 = 0xD0;
+const BME280_REG_CALIB_TP: u8 = 0x88;
+const BME280_REG_CALIB_H: u8 = 0xE1;
+const BME280_REG_CTRL_HUM: u8 = 0xF2;
+const BME280_REG_CTRL_MEAS: u8 = 0xF4;
+const BME280_REG_DATA: u8 = 0xF7;
+/// Forced mode with 1x oversampling of temperature and pressure (osrs_t = osrs_p = 001, mode = 01).
+const BME280_FORCED_MEASURE: u8 = 0x25;
+/// Maximum duration of a forced measurement with 1x oversampling.
+const BME280_MEASURE_TIME: Duration = Duration::from_millis(10);
+
+/// Sensor on an I2C bus. The bus is opened on the first read and reopened after an
+/// error, so a sensor connected after startup is picked up.
+pub struct I2cSensor {
+    chip: I2cChip,
+    device: PathBuf,
+    address: u8,
+    state: Mutex<Option<Connection>>,
+}
+
+/// Open bus and, for the BME280, the calibration read from the chip.
+struct Connection {
+    bus: I2cdev,
+    calibration: Option<Bme280Calibration>,
+}
+
+impl I2cSensor {
+    /// Creates the backend of `sensor`, on its bus or the board's default one.
+    pub fn new(board: &Board, sensor: &SensorConfig) -> Self {
+        let chip = sensor.chip.unwrap_or_default();
+        Self {
+            chip,
+            device: board.i2c_device(sensor.bus),
+            address: sensor.address.unwrap_or_else(|| chip.default_address()),
+            state: Mutex::new(None),
+        }
+    }
+
+    fn connect(&self) -> Result<Connection> {
+        let mut bus = I2cdev::new(&self.device)
+            .with_context(|| format!("Failed to open {}", self.device.display()))?;
+        let calibration = match self.chip {
+            I2cChip::Sht31 => None,
+            I2cChip::Bme280 => Some(Bme280Calibration::read(&mut bus, self.address)?),
+        };
+        Ok(Connection { bus, calibration })
+    }
+
+    fn measure(&self, connection: &mut Connection) -> Result<Measurement> {
+        match &connection.calibration {
+            Some(calibration) => measure_bme280(&mut connection.bus, self.address, calibration),
+            None => measure_sht31(&mut connection.bus, self.address),
+        }
+    }
+}
+
+impl TemperatureSensor for I2cSensor {
+    fn read(&self) -> Result<f32> {
+        Ok(self.read_measurement()?.temperature)
+    }
+
+    fn read_measurement(&self) -> Result<Measurement> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut connection = match state.take() {
+            Some(connection) => connection,
+            None => self.connect()?,
+        };
+        let result = self.measure(&mut connection);
+        // After an error, start afresh on the next read (e.g., the sensor was reconnected)
+        if result.is_ok() {
+            *state = Some(connection);
+        }
+        result.with_context(|| {
+            format!("Failed to read {:?} at 0x{:02x} on {}", self.chip, self.address, self.device.display())
+        })
+    }
+}
+
+fn measure_sht31(bus: &mut I2cdev, address: u8) -> Result<Measurement> {
+    bus.write(address, &SHT31_MEASURE)?;
+    thread::sleep(SHT31_MEASURE_TIME);
+    let mut data = [0u8; 6];
+    bus.read(address, &mut data)?;
+    if crc8(&data[0..2]) != data[2] || crc8(&data[3..5]) != data[5] {
+        return Err(RawRead::new("CRC check failed", hex(&data)).into());
+    }
+    let raw_t = f32::from(u16::from_be_bytes([data[0], data[1]]));
+    let raw_rh = f32::from(u16::from_be_bytes([data[3], data[4]]));
+    Ok(Measurement {
+        temperature: round2(-45.0 + 175.0 * raw_t / 65535.0),
+        humidity: Some(round2(100.0 * raw_rh / 65535.0)),
+        pressure_hpa: None,
+    })
+}
+
+/// CRC-8 of the SHT3x (polynomial 0x31, initial value 0xFF).
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0xFF, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 { (crc << 1) ^ 0x31 } else { crc << 1 }
+        })
+    })
+}
+
+/// Factory calibration of a BME280, read from its non-volatile memory.
+struct Bme280Calibration {
+    t: [f64; 3],
+    p: [f64; 9],
+    h: [f64; 6],
+}
+
+impl Bme280Calibration {
+    fn read(bus: &mut I2cdev, address: u8) -> Result<Self> {
+        let mut id = [0u8; 1];
+        bus.write_read(address, &[BME280_REG_CHIP_ID], &mut id)?;
+        if id[0] != BME280_CHIP_ID {
+            return Err(RawRead::new(format!("unexpected chip ID 0x{:02x}, not a BME280", id[0]), hex(&id)).into());
+        }
+        let mut tp = [0u8; 26];
+        bus.write_read(address, &[BME280_REG_CALIB_TP], &mut tp)?;
+        let mut h = [0u8; 7];
+        bus.write_read(address, &[BME280_REG_CALIB_H], &mut h)?;
+
+        let unsigned = |i: usize| f64::from(u16::from_le_bytes([tp[i], tp[i + 1]]));
+        let signed = |i: usize| f64::from(i16::from_le_bytes([tp[i], tp[i + 1]]));
+        Ok(Self {
+            t: [unsigned(0), signed(2), signed(4)],
+            p: [
+                unsigned(6),
+                signed(8),
+                signed(10),
+                signed(12),
+                signed(14),
+                signed(16),
+                signed(18),
+                signed(20),
+                signed(22),
+            ],
+            h: [
+                f64::from(tp[25]),
+                f64::from(i16::from_le_bytes([h[0], h[1]])),
+                f64::from(h[2]),
+                // 12-bit signed values sharing the nibbles of 0xE5
+                f64::from((i16::from(h[3] as i8) << 4) | i16::from(h[4] & 0x0F)),
+                f64::from((i16::from(h[5] as i8) << 4) | i16::from(h[4] >> 4)),
+                f64::from(h[6] as i8),
+            ],
+        })
+    }
+}
+
+/// Runs a forced measurement and applies the compensation formulas of the datasheet.
+fn measure_bme280(bus: &mut I2cdev, address: u8, cal: &Bme280Calibration) -> Result<Measurement> {
+    // The humidity setting only takes effect after a write to ctrl_meas
+    bus.write(address, &[BME280_REG_CTRL_HUM, 0x01])?;
+    bus.write(address, &[BME280_REG_CTRL_MEAS, BME280_FORCED_MEASURE])?;
+    thread::sleep(BME280_MEASURE_TIME);
+    let mut data = [0u8; 8];
+    bus.write_read(address, &[BME280_REG_DATA], &mut data)?;
+    let adc_p = f64::from((u32::from(data[0]) << 12) | (u32::from(data[1]) << 4) | (u32::from(data[2]) >> 4));
+    let adc_t = f64::from((u32::from(data[3]) << 12) | (u32::from(data[4]) << 4) | (u32::from(data[5]) >> 4));
+    let adc_h = f64::from(u16::from_be_bytes([data[6], data[7]]));
+
+    let [t1, t2, t3] = cal.t;
+    let var1 = (adc_t / 16384.0 - t1 / 1024.0) * t2;
+    let var2 = (adc_t / 131072.0 - t1 / 8192.0).powi(2) * t3;
+    let t_fine = var1 + var2;
+    let temperature = t_fine / 5120.0;
+
+    let [p1, p2, p3, p4, p5, p6, p7, p8, p9] = cal.p;
+    let mut var1 = t_fine / 2.0 - 64000.0;
+    let mut var2 = var1 * var1 * p6 / 32768.0;
+    var2 += var1 * p5 * 2.0;
+    var2 = var2 / 4.0 + p4 * 65536.0;
+    var1 = (p3 * var1 * var1 / 524288.0 + p2 * var1) / 524288.0;
+    var1 = (1.0 + var1 / 32768.0) * p1;
+    let pressure_hpa = if var1 == 0.0 {
+        None
+    } else {
+        let mut p = 1048576.0 - adc_p;
+        p = (p - var2 / 4096.0) * 6250.0 / var1;
+        let var1 = p9 * p * p / 2147483648.0;
+        let var2 = p * p8 / 32768.0;
+        Some(round2(((p + (var1 + var2 + p7) / 16.0) / 100.0) as f32))
+    };
+
+    let [h1, h2, h3, h4, h5, h6] = cal.h;
+    let mut h = t_fine - 76800.0;
+    h = (adc_h - (h4 * 64.0 + h5 / 16384.0 * h))
+        * (h2 / 65536.0 * (1.0 + h6 / 67108864.0 * h * (1.0 + h3 / 67108864.0 * h)));
+    h *= 1.0 - h1 * h / 524288.0;
+
+    Ok(Measurement {
+        temperature: round2(temperature as f32),
+        humidity: Some(round2(h.clamp(0.0, 100.0) as f32)),
+        pressure_hpa,
+    })
+}
+
+/// Rounds to 2 decimal places, like the other backends.
+fn round2(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0
+}
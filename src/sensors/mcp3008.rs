@@ -0,0 +1,184 @@
+//! Analog backend: PT1000, NTC and other resistive probes read through an MCP3008
+//! SPI ADC (`/dev/spidev<bus>.<cs>`).
+//!
+//! Each probe forms a voltage divider with a fixed resistor: reference voltage, fixed
+//! resistor, ADC input, probe, ground. The probe resistance is derived from the ADC
+//! code, then converted to a temperature.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result, bail};
+use linux_embedded_hal::spidev::{SpiModeFlags, Spidev, SpidevOptions, SpidevTransfer};
+use serde::Deserialize;
+
+use super::TemperatureSensor;
+use super::capture::RawRead;
+use crate::board::Board;
+use crate::config::SensorConfig;
+
+/// Full scale of the 10-bit ADC.
+const ADC_MAX: f32 = 1023.0;
+/// SPI clock; the MCP3008 supports 1.35 MHz at 3.3 V.
+const SPI_SPEED_HZ: u32 = 1_000_000;
+/// 0 °C in Kelvin.
+const ZERO_CELSIUS_K: f32 = 273.15;
+
+/// Conversion of the probe resistance to a temperature.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Conversion {
+    /// `R = r0 * (1 + alpha * T)`, for platinum probes (PT100, PT1000).
+    #[default]
+    Linear,
+    /// `1/T = a + b ln(R) + c ln(R)^3` (T in Kelvin), for NTC thermistors.
+    SteinhartHart,
+}
+
+/// Divider and conversion parameters of an analog sensor (`[sensors.analog]`).
+/// The defaults match a PT1000 with a 1 kΩ divider resistor.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct AnalogConfig {
+    /// Fixed resistor of the voltage divider, in ohms.
+    pub divider_ohm: f32,
+    /// Number of ADC samples averaged per read.
+    pub samples: u32,
+    pub conversion: Conversion,
+    /// Linear: resistance at 0 °C, in ohms.
+    pub r0_ohm: f32,
+    /// Linear: temperature coefficient, per °C.
+    pub alpha: f32,
+    /// Steinhart-Hart: coefficient A.
+    pub a: f32,
+    /// Steinhart-Hart: coefficient B.
+    pub b: f32,
+    /// Steinhart-Hart: coefficient C.
+    pub c: f32,
+}
+
+impl Default for AnalogConfig {
+    fn default() -> Self {
+        Self {
+            divider_ohm: 1000.0,
+            samples: 16,
+            conversion: Conversion::Linear,
+            r0_ohm: 1000.0,
+            alpha: 0.00385,
+            // Common 10 kΩ NTC thermistor
+            a: 1.009_249_5e-3,
+            b: 2.378_405e-4,
+            c: 2.019_202e-7,
+        }
+    }
+}
+
+impl AnalogConfig {
+    /// Converts a resistance, in ohms, to a temperature in Celsius.
+    fn temperature(&self, resistance: f32) -> f32 {
+        match self.conversion {
+            Conversion::Linear => (resistance / self.r0_ohm - 1.0) / self.alpha,
+            Conversion::SteinhartHart => {
+                let ln_r = resistance.ln();
+                1.0 / (self.a + self.b * ln_r + self.c * ln_r.powi(3)) - ZERO_CELSIUS_K
+            }
+        }
+    }
+}
+
+/// MCP3008 ADC on an SPI chip select. The device is opened on the first read and
+/// reopened after an error.
+pub struct Mcp3008 {
+    device: PathBuf,
+    /// Opened on the first read, reopened after an error.
+    spi: Mutex<Option<Spidev>>,
+}
+
+impl Mcp3008 {
+    pub fn new(device: PathBuf) -> Self {
+        Self {
+            device,
+            spi: Mutex::new(None),
+        }
+    }
+
+    fn open(&self) -> Result<Spidev> {
+        let mut spi = Spidev::open(&self.device).with_context(|| format!("Failed to open {}", self.device.display()))?;
+        let options = SpidevOptions::new()
+            .bits_per_word(8)
+            .max_speed_hz(SPI_SPEED_HZ)
+            .mode(SpiModeFlags::SPI_MODE_0)
+            .build();
+        spi.configure(&options)?;
+        Ok(spi)
+    }
+
+    /// Reads the average of `samples` codes of `channel` (0 to 1023).
+    pub fn read_code(&self, channel: u8, samples: u32) -> Result<f32> {
+        if channel > 7 {
+            bail!("Invalid MCP3008 channel {} (0 to 7)", channel);
+        }
+        let mut state = self.spi.lock().unwrap_or_else(|e| e.into_inner());
+        let spi = match state.take() {
+            Some(spi) => spi,
+            None => self.open()?,
+        };
+        let code = sample(&spi, channel, samples.max(1))
+            .with_context(|| format!("Failed to read channel {} on {}", channel, self.device.display()))?;
+        *state = Some(spi);
+        Ok(code)
+    }
+
+    /// Reads `channel` as a fraction of the reference voltage (0 to 1).
+    pub fn read_ratio(&self, channel: u8, samples: u32) -> Result<f32> {
+        Ok(self.read_code(channel, samples)? / ADC_MAX)
+    }
+}
+
+/// Reads the average ADC code of a channel.
+fn sample(spi: &Spidev, channel: u8, samples: u32) -> Result<f32> {
+    let mut sum = 0u32;
+    for _ in 0..samples {
+        // Start bit, single-ended mode and channel, then 10 bits clocked out
+        let tx = [0x01, (0x08 | channel) << 4, 0x00];
+        let mut rx = [0u8; 3];
+        spi.transfer(&mut SpidevTransfer::read_write(&tx, &mut rx))?;
+        sum += (u32::from(rx[1] & 0x03) << 8) | u32::from(rx[2]);
+    }
+    Ok(sum as f32 / samples as f32)
+}
+
+/// Resistive probe on a channel of an MCP3008.
+pub struct Mcp3008Sensor {
+    adc: Mcp3008,
+    channel: u8,
+    analog: AnalogConfig,
+}
+
+impl Mcp3008Sensor {
+    /// Creates the backend of `sensor`, on its SPI bus or the board's default one.
+    pub fn new(board: &Board, sensor: &SensorConfig) -> Self {
+        Self {
+            adc: Mcp3008::new(board.spi_device(sensor.bus, sensor.chip_select.unwrap_or(0))),
+            channel: sensor.channel.unwrap_or(0),
+            analog: sensor.analog.clone(),
+        }
+    }
+}
+
+impl TemperatureSensor for Mcp3008Sensor {
+    fn read(&self) -> Result<f32> {
+        let code = self.adc.read_code(self.channel, self.analog.samples)?;
+
+        // A code at either end of the scale means an open or shorted probe
+        if !(1.0..=ADC_MAX - 1.0).contains(&code) {
+            let message =
+                format!("Channel {} out of range (code {:.0}): probe disconnected or shorted", self.channel, code);
+            return Err(RawRead::new(message, format!("code={:.2} samples={}", code, self.analog.samples)).into());
+        }
+        let resistance = self.analog.divider_ohm * code / (ADC_MAX - code);
+        let temp = self.analog.temperature(resistance);
+        // Round to 2 decimal places
+        Ok((temp * 100.0).round() / 100.0)
+    }
+}
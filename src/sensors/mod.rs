@@ -0,0 +1,215 @@
+//! Temperature sensor backends and ADC drivers.
+//!
+//! Every configured sensor is read through a [`TemperatureSensor`] backend, selected
+//! per sensor in `config.toml` so that sensor technologies can be mixed in one
+//! installation. Without a `backend` setting, sensors are simulated on the
+//! `simulated` board and read from the 1-Wire bus otherwise.
+
+pub mod ads1115;
+mod capture;
+mod dummy;
+mod http;
+mod i2c;
+mod mcp3008;
+mod modbus;
+mod remote_w1;
+mod tank;
+mod topic;
+mod w1;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::board::Board;
+use crate::config::SensorConfig;
+use crate::logging::{info, warning};
+
+pub use capture::{DebugConfig, RawCapture};
+pub use http::HttpSensorConfig;
+pub use i2c::I2cChip;
+pub use mcp3008::{AnalogConfig, Mcp3008};
+pub use modbus::{ModbusConfig, write_coil};
+pub use remote_w1::{RemoteW1Config, serve_w1_agent};
+pub use tank::TankProbeConfig;
+pub use topic::{TopicSensorConfig, follow_topics};
+pub use w1::{W1Sensor, discover_w1};
+
+/// Everything measured by a sensor in one read.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Measurement {
+    /// Temperature, in Celsius.
+    pub temperature: f32,
+    /// Relative humidity, in percent.
+    pub humidity: Option<f32>,
+    /// Atmospheric pressure, in hPa.
+    pub pressure_hpa: Option<f32>,
+}
+
+/// A source of temperature readings.
+pub trait TemperatureSensor: Send + Sync {
+    /// Reads the current temperature, in Celsius.
+    fn read(&self) -> Result<f32>;
+
+    /// Reads the temperature along with the other quantities the sensor measures.
+    fn read_measurement(&self) -> Result<Measurement> {
+        Ok(Measurement {
+            temperature: self.read()?,
+            ..Default::default()
+        })
+    }
+}
+
+/// Backend of a sensor.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorBackend {
+    /// DS18B20 and compatible sensors on the 1-Wire bus (`id` is the device ID).
+    W1,
+    /// Random values, for development (`id` is only used to inject faults).
+    Dummy,
+    /// SHT31 or BME280 on an I2C bus (see `chip`, `bus` and `address`).
+    I2c,
+    /// Resistive probe (PT1000, NTC) on an MCP3008 SPI ADC (see `bus`, `chip_select`,
+    /// `channel` and `analog`).
+    Mcp3008,
+    /// Register of a Modbus RTU device on a serial bus (see `modbus`).
+    Modbus,
+    /// DS18B20 on the 1-Wire bus of another machine (see `remote_w1`).
+    #[serde(rename = "remote-w1")]
+    RemoteW1,
+    /// Value published on an MQTT topic by another device (see `mqtt`).
+    Mqtt,
+    /// Value polled from the REST API of another device (see `http`).
+    Http,
+    /// Layer of the simulated tank (see `tank` and `[simulation.tank]`).
+    Tank,
+}
+
+/// Returns the backend of `sensor`: the configured one, or the board's default.
+pub fn backend(sensor: &SensorConfig, board: &Board) -> SensorBackend {
+    sensor.backend.unwrap_or(if board.is_simulated() {
+        SensorBackend::Dummy
+    } else {
+        SensorBackend::W1
+    })
+}
+
+/// Creates the backend reading `sensor`, with its calibration applied.
+pub fn open(sensor: &SensorConfig, board: &Board) -> Box<dyn TemperatureSensor> {
+    let raw: Box<dyn TemperatureSensor> = match backend(sensor, board) {
+        SensorBackend::W1 => Box::new(W1Sensor::new(board, &sensor.id)),
+        SensorBackend::Dummy => Box::new(dummy::DummySensor::new(board, &sensor.id)),
+        SensorBackend::I2c => Box::new(i2c::I2cSensor::new(board, sensor)),
+        SensorBackend::Mcp3008 => Box::new(mcp3008::Mcp3008Sensor::new(board, sensor)),
+        SensorBackend::Modbus => Box::new(modbus::ModbusSensor::new(sensor)),
+        SensorBackend::RemoteW1 => Box::new(remote_w1::RemoteW1Sensor::new(sensor)),
+        SensorBackend::Mqtt => Box::new(topic::TopicSensor::new(sensor)),
+        SensorBackend::Http => Box::new(http::HttpSensor::new(sensor)),
+        SensorBackend::Tank => Box::new(tank::TankSensor::new(board, sensor)),
+    };
+    // Wrapped even while the capture is off, as it can be switched on at runtime
+    let raw: Box<dyn TemperatureSensor> = Box::new(Captured {
+        raw,
+        label: format!("{} ({})", sensor.name, sensor.id),
+        capture: board.capture().clone(),
+    });
+    if sensor.offset_c == 0.0 && sensor.scale == 1.0 {
+        return raw;
+    }
+    Box::new(Calibrated {
+        raw,
+        offset_c: sensor.offset_c,
+        scale: sensor.scale,
+    })
+}
+
+/// Applies the calibration of a sensor to the temperatures of its backend.
+struct Calibrated {
+    raw: Box<dyn TemperatureSensor>,
+    offset_c: f32,
+    scale: f32,
+}
+
+impl Calibrated {
+    fn apply(&self, temperature: f32) -> f32 {
+        temperature * self.scale + self.offset_c
+    }
+}
+
+impl TemperatureSensor for Calibrated {
+    fn read(&self) -> Result<f32> {
+        self.raw.read().map(|t| self.apply(t))
+    }
+
+    fn read_measurement(&self) -> Result<Measurement> {
+        let measurement = self.raw.read_measurement()?;
+        Ok(Measurement {
+            temperature: self.apply(measurement.temperature),
+            ..measurement
+        })
+    }
+}
+
+/// Writes the configured resolution to `sensor` if it is a 1-Wire sensor with a
+/// `resolution_bits` setting. Failures are logged.
+pub fn apply_resolution(sensor: &SensorConfig, board: &Board) {
+    let Some(bits) = sensor.resolution_bits else {
+        return;
+    };
+    if board.is_simulated() || backend(sensor, board) != SensorBackend::W1 {
+        return;
+    }
+    match w1::set_resolution(board, &sensor.id, bits) {
+        Ok(()) => info!("Sensor {}: resolution set to {} bits", sensor.name, bits),
+        Err(e) => warning!("Sensor {}: cannot set the resolution: {:#}", sensor.name, e),
+    }
+}
+
+/// Writes the raw data of the failed reads of a backend to the raw capture.
+struct Captured {
+    raw: Box<dyn TemperatureSensor>,
+    label: String,
+    capture: RawCapture,
+}
+
+impl Captured {
+    fn check<T>(&self, result: Result<T>) -> Result<T> {
+        if let Err(e) = &result {
+            self.capture.record(&self.label, e);
+        }
+        result
+    }
+}
+
+impl TemperatureSensor for Captured {
+    fn read(&self) -> Result<f32> {
+        self.check(self.raw.read())
+    }
+
+    fn read_measurement(&self) -> Result<Measurement> {
+        self.check(self.raw.read_measurement())
+    }
+}
+
+/// Appends a sensor for every 1-Wire device found on the bus but missing from
+/// `sensors`, named after its position ("S3" for the third sensor).
+///
+/// Returns the number of sensors added.
+///
+/// # Errors
+/// Returns an error if the 1-Wire devices directory cannot be read.
+pub fn auto_discover(sensors: &mut Vec<SensorConfig>, board: &Board) -> Result<usize> {
+    let before = sensors.len();
+    for id in discover_w1(board)? {
+        if sensors.iter().any(|s| s.id == id) {
+            continue;
+        }
+        let name = (sensors.len() + 1..)
+            .map(|n| format!("S{}", n))
+            .find(|name| !sensors.iter().any(|s| &s.name == name))
+            .unwrap_or_default();
+        info!("Discovered 1-Wire sensor {} as {}", id, name);
+        sensors.push(SensorConfig::w1(name, id));
+    }
+    Ok(sensors.len() - before)
+}
@@ -0,0 +1,282 @@
+//! Modbus RTU backend: temperatures exposed as registers by other devices (heat
+//! pumps, controllers) on an RS-485 serial bus.
+//!
+//! Sensors and output coils on the same serial port share one connection, so their
+//! transactions never interleave on the bus.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use serialport::{ClearBuffer, SerialPort};
+
+use super::TemperatureSensor;
+use super::capture::{RawRead, hex};
+use crate::config::SensorConfig;
+
+/// Silence kept after each transaction; covers the 3.5 character inter-frame delay
+/// down to 9600 baud.
+const INTER_FRAME_DELAY: Duration = Duration::from_millis(5);
+
+/// Open serial ports, by device path.
+type SharedPort = Arc<Mutex<Option<Box<dyn SerialPort>>>>;
+static PORTS: OnceLock<Mutex<HashMap<String, SharedPort>>> = OnceLock::new();
+
+/// Returns the shared connection of the serial port at `path`.
+fn shared_port(path: &str) -> SharedPort {
+    let ports = PORTS.get_or_init(Default::default);
+    let mut ports = ports.lock().unwrap_or_else(|e| e.into_inner());
+    ports.entry(path.to_string()).or_default().clone()
+}
+
+/// Parity of the serial line.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    #[default]
+    None,
+    Even,
+    Odd,
+}
+
+/// Register table read by the sensor.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterKind {
+    /// Holding registers (function 0x03).
+    #[default]
+    Holding,
+    /// Input registers (function 0x04).
+    Input,
+}
+
+/// Encoding of the value in the registers. 32-bit values span two registers, high
+/// word first.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DataType {
+    U16,
+    #[default]
+    I16,
+    U32,
+    I32,
+    F32,
+}
+
+impl DataType {
+    fn registers(self) -> u16 {
+        match self {
+            DataType::U16 | DataType::I16 => 1,
+            DataType::U32 | DataType::I32 | DataType::F32 => 2,
+        }
+    }
+
+    fn decode(self, data: &[u8]) -> f32 {
+        match self {
+            DataType::U16 => f32::from(u16::from_be_bytes([data[0], data[1]])),
+            DataType::I16 => f32::from(i16::from_be_bytes([data[0], data[1]])),
+            DataType::U32 => u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as f32,
+            DataType::I32 => i32::from_be_bytes([data[0], data[1], data[2], data[3]]) as f32,
+            DataType::F32 => f32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+        }
+    }
+}
+
+/// Serial settings and register of a Modbus sensor (`[sensors.modbus]`).
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ModbusConfig {
+    /// Serial device of the RS-485 adapter.
+    pub port: String,
+    pub baud_rate: u32,
+    pub parity: Parity,
+    /// 1 or 2.
+    pub stop_bits: u8,
+    /// Address of the device on the bus (1 to 247).
+    pub slave: u8,
+    /// Address of the (first) register, zero-based as sent on the wire.
+    pub register: u16,
+    pub function: RegisterKind,
+    pub data_type: DataType,
+    /// The temperature is `raw * scale + offset` (e.g., `scale = 0.1` for tenths of °C).
+    pub scale: f32,
+    pub offset: f32,
+    /// Maximum wait for the response, in milliseconds.
+    pub timeout_ms: u64,
+}
+
+impl Default for ModbusConfig {
+    fn default() -> Self {
+        Self {
+            port: "/dev/ttyUSB0".to_string(),
+            baud_rate: 9600,
+            parity: Parity::None,
+            stop_bits: 1,
+            slave: 1,
+            register: 0,
+            function: RegisterKind::Holding,
+            data_type: DataType::I16,
+            scale: 1.0,
+            offset: 0.0,
+            timeout_ms: 1000,
+        }
+    }
+}
+
+/// Register of a Modbus RTU device.
+pub struct ModbusSensor {
+    config: ModbusConfig,
+    port: SharedPort,
+}
+
+impl ModbusSensor {
+    pub fn new(sensor: &SensorConfig) -> Self {
+        Self {
+            config: sensor.modbus.clone(),
+            port: shared_port(&sensor.modbus.port),
+        }
+    }
+
+    /// Reads the registers of the value; returns their bytes.
+    fn transaction(&self, port: &mut dyn SerialPort) -> Result<Vec<u8>> {
+        let c = &self.config;
+        let function = match c.function {
+            RegisterKind::Holding => 0x03,
+            RegisterKind::Input => 0x04,
+        };
+        let count = c.data_type.registers();
+        let mut request = vec![c.slave, function];
+        request.extend_from_slice(&c.register.to_be_bytes());
+        request.extend_from_slice(&count.to_be_bytes());
+        request.extend_from_slice(&crc16(&request).to_le_bytes());
+
+        port.clear(ClearBuffer::Input)?;
+        port.write_all(&request)?;
+
+        // Slave, function and byte count (or exception code)
+        let mut response = vec![0u8; 3];
+        port.read_exact(&mut response).context("No response")?;
+        if response[0] != c.slave || response[1] & 0x7F != function {
+            let message = format!("Unexpected response from slave {} (function 0x{:02x})", response[0], response[1]);
+            return Err(RawRead::new(message, hex(&response)).into());
+        }
+        if response[1] & 0x80 != 0 {
+            bail!("Slave {} returned exception {}", c.slave, response[2]);
+        }
+        let byte_count = usize::from(response[2]);
+        if byte_count != 2 * usize::from(count) {
+            return Err(RawRead::new(format!("Unexpected response length {}", byte_count), hex(&response)).into());
+        }
+        response.resize(3 + byte_count + 2, 0);
+        port.read_exact(&mut response[3..]).context("Truncated response")?;
+        let (frame, crc) = response.split_at(3 + byte_count);
+        if crc16(frame).to_le_bytes() != crc {
+            return Err(RawRead::new("CRC check failed", hex(&response)).into());
+        }
+        Ok(frame[3..].to_vec())
+    }
+}
+
+impl TemperatureSensor for ModbusSensor {
+    fn read(&self) -> Result<f32> {
+        let c = &self.config;
+        let result = exchange(c, &self.port, |port| self.transaction(port));
+        let data = result.with_context(|| {
+            format!("Failed to read register {} of slave {} on {}", c.register, c.slave, c.port)
+        })?;
+        let temp = c.data_type.decode(&data) * c.scale + c.offset;
+        // Round to 2 decimal places
+        Ok((temp * 100.0).round() / 100.0)
+    }
+}
+
+/// Opens the serial port, or applies the settings of this device to the port already
+/// opened for another device on the bus.
+fn configure(c: &ModbusConfig, port: Option<Box<dyn SerialPort>>) -> Result<Box<dyn SerialPort>> {
+    let parity = match c.parity {
+        Parity::None => serialport::Parity::None,
+        Parity::Even => serialport::Parity::Even,
+        Parity::Odd => serialport::Parity::Odd,
+    };
+    let stop_bits = match c.stop_bits {
+        1 => serialport::StopBits::One,
+        2 => serialport::StopBits::Two,
+        n => bail!("Invalid number of stop bits {} (1 or 2)", n),
+    };
+    let timeout = Duration::from_millis(c.timeout_ms);
+    match port {
+        Some(mut port) => {
+            port.set_baud_rate(c.baud_rate)?;
+            port.set_parity(parity)?;
+            port.set_stop_bits(stop_bits)?;
+            port.set_timeout(timeout)?;
+            Ok(port)
+        }
+        None => serialport::new(c.port.as_str(), c.baud_rate)
+            .data_bits(serialport::DataBits::Eight)
+            .parity(parity)
+            .stop_bits(stop_bits)
+            .timeout(timeout)
+            .open()
+            .with_context(|| format!("Failed to open {}", c.port)),
+    }
+}
+
+/// Runs a transaction on the shared port, configured for the device of `c`.
+fn exchange<T>(
+    c: &ModbusConfig,
+    shared: &SharedPort,
+    transaction: impl FnOnce(&mut dyn SerialPort) -> Result<T>,
+) -> Result<T> {
+    // Held for the whole transaction: other devices on the bus wait their turn
+    let mut shared = shared.lock().unwrap_or_else(|e| e.into_inner());
+    let mut port = configure(c, shared.take())?;
+    let result = transaction(port.as_mut());
+    thread::sleep(INTER_FRAME_DELAY);
+    // After an error, reopen the port on the next transaction (e.g., the adapter was replugged)
+    if result.is_ok() {
+        *shared = Some(port);
+    }
+    result
+}
+
+/// Switches the coil at `register` of the device of `c` (function 0x05).
+///
+/// # Errors
+/// Returns an error if the port cannot be opened or the device does not confirm the write.
+pub fn write_coil(c: &ModbusConfig, on: bool) -> Result<()> {
+    let value: u16 = if on { 0xFF00 } else { 0x0000 };
+    let mut request = vec![c.slave, 0x05];
+    request.extend_from_slice(&c.register.to_be_bytes());
+    request.extend_from_slice(&value.to_be_bytes());
+    request.extend_from_slice(&crc16(&request).to_le_bytes());
+    exchange(c, &shared_port(&c.port), |port| {
+        port.clear(ClearBuffer::Input)?;
+        port.write_all(&request)?;
+        // The device echoes the request; an exception response is 5 bytes long
+        let mut response = vec![0u8; 5];
+        port.read_exact(&mut response).context("No response")?;
+        if response[1] & 0x80 != 0 {
+            bail!("Slave {} returned exception {}", c.slave, response[2]);
+        }
+        response.resize(request.len(), 0);
+        port.read_exact(&mut response[5..]).context("Truncated response")?;
+        if response != request {
+            bail!("Unexpected response {}", hex(&response));
+        }
+        Ok(())
+    })
+    .with_context(|| format!("Failed to write coil {} of slave {} on {}", c.register, c.slave, c.port))
+}
+
+/// CRC-16 of Modbus RTU frames (polynomial 0xA001, initial value 0xFFFF).
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte), |crc, _| {
+            if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 }
+        })
+    })
+}
@@ -0,0 +1,191 @@
+//! Remote 1-Wire backend: DS18B20 sensors wired to another machine (e.g., a Pi next
+//! to the boiler while the display sits elsewhere).
+//!
+//! The `w1_slave` file of the sensor is fetched from the remote machine, either from
+//! the agent started there with `boilert w1-agent` or over SSH, and decoded as for a
+//! local sensor.
+//!
+//! Agent protocol, one TCP connection per read: the client sends the device ID
+//! followed by a newline; the agent answers with the content of its `w1_slave` file,
+//! or with `ERR <message>`, and closes the connection.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::time::Duration;
+use std::{fs, thread};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use super::TemperatureSensor;
+use super::capture::RawRead;
+use crate::board::Board;
+use crate::config::SensorConfig;
+use crate::logging::warning;
+
+/// Default TCP port of the agent.
+const DEFAULT_AGENT_PORT: u16 = 7070;
+
+/// Longest request accepted by the agent.
+const MAX_REQUEST: u64 = 64;
+
+/// Way the `w1_slave` file is fetched from the remote machine.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteTransport {
+    /// `boilert w1-agent` running on the remote machine.
+    #[default]
+    Agent,
+    /// `cat` over SSH, with key authentication.
+    Ssh,
+}
+
+/// Remote machine of a `remote-w1` sensor.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RemoteW1Config {
+    pub transport: RemoteTransport,
+    /// Host name or address; `user@host` for SSH.
+    pub host: String,
+    /// TCP port of the agent.
+    pub port: u16,
+    /// 1-Wire devices directory on the remote machine (SSH only; the agent uses its
+    /// own `[hardware]` setting).
+    pub w1_dir: String,
+    /// Maximum wait for the connection and for the reply, in milliseconds.
+    pub timeout_ms: u64,
+}
+
+impl Default for RemoteW1Config {
+    fn default() -> Self {
+        Self {
+            transport: RemoteTransport::Agent,
+            host: String::new(),
+            port: DEFAULT_AGENT_PORT,
+            w1_dir: "/sys/bus/w1/devices".to_string(),
+            timeout_ms: 3000,
+        }
+    }
+}
+
+/// Returns `true` if `id` can be used as the name of a 1-Wire device directory:
+/// hexadecimal digits and dashes only, so that no other path can be read.
+fn is_device_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 32 && id.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+}
+
+/// DS18B20 (and compatible) sensor attached to another machine.
+pub struct RemoteW1Sensor {
+    config: RemoteW1Config,
+    id: String,
+}
+
+impl RemoteW1Sensor {
+    pub fn new(sensor: &SensorConfig) -> Self {
+        Self {
+            config: sensor.remote_w1.clone(),
+            id: sensor.id.clone(),
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(self.config.timeout_ms.max(1))
+    }
+
+    /// Asks the agent for the `w1_slave` content of the sensor.
+    fn fetch_from_agent(&self) -> Result<String> {
+        let c = &self.config;
+        let address = (c.host.as_str(), c.port)
+            .to_socket_addrs()
+            .with_context(|| format!("Cannot resolve {}", c.host))?
+            .next()
+            .with_context(|| format!("No address for {}", c.host))?;
+        let mut stream = TcpStream::connect_timeout(&address, self.timeout())
+            .with_context(|| format!("Cannot connect to the agent at {}:{}", c.host, c.port))?;
+        stream.set_read_timeout(Some(self.timeout()))?;
+        stream.set_write_timeout(Some(self.timeout()))?;
+        stream.write_all(format!("{}\n", self.id).as_bytes())?;
+        let mut content = String::new();
+        stream.read_to_string(&mut content).context("No reply from the agent")?;
+        if let Some(message) = content.strip_prefix("ERR ") {
+            bail!("Agent at {}: {}", c.host, message.trim());
+        }
+        Ok(content)
+    }
+
+    /// Reads the `w1_slave` file of the sensor with `cat` over SSH.
+    fn fetch_over_ssh(&self) -> Result<String> {
+        let c = &self.config;
+        if !is_device_id(&self.id) {
+            bail!("Invalid 1-Wire device ID {:?}", self.id);
+        }
+        let path = format!("{}/{}/w1_slave", c.w1_dir.trim_end_matches('/'), self.id);
+        let connect_timeout = self.timeout().as_secs().max(1);
+        let output = Command::new("ssh")
+            .args(["-o", "BatchMode=yes", "-o"])
+            .arg(format!("ConnectTimeout={}", connect_timeout))
+            .args([c.host.as_str(), "cat", path.as_str()])
+            .output()
+            .context("Failed to run ssh")?;
+        if !output.status.success() {
+            bail!("ssh {}: {}", c.host, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl TemperatureSensor for RemoteW1Sensor {
+    fn read(&self) -> Result<f32> {
+        let content = match self.config.transport {
+            RemoteTransport::Agent => self.fetch_from_agent(),
+            RemoteTransport::Ssh => self.fetch_over_ssh(),
+        }
+        .with_context(|| format!("Failed to read remote sensor {}", self.id))?;
+
+        super::w1::parse(&content)
+            .map_err(|message| RawRead::new(format!("{} for sensor {}", message, self.id), content).into())
+    }
+}
+
+/// Serves the `w1_slave` files of the local 1-Wire bus on `listen` (e.g., "0.0.0.0:7070")
+/// until the process is stopped. Each connection is handled on its own thread.
+///
+/// # Errors
+/// Returns an error if the address cannot be bound.
+pub fn serve_w1_agent(board: &Board, listen: &str) -> Result<()> {
+    let listener = TcpListener::bind(listen).with_context(|| format!("Cannot listen on {}", listen))?;
+    println!("1-Wire agent serving {} on {}", board.w1_dir().display(), listen);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warning!("1-Wire agent: connection failed: {}", e);
+                continue;
+            }
+        };
+        let board = board.clone();
+        thread::spawn(move || {
+            if let Err(e) = answer(&board, stream) {
+                warning!("1-Wire agent: {:#}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Answers one request of a client.
+fn answer(board: &Board, stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request = String::new();
+    BufReader::new(&stream).take(MAX_REQUEST).read_line(&mut request)?;
+    let id = request.trim();
+    let reply = if !is_device_id(id) {
+        format!("ERR invalid device ID {:?}\n", id)
+    } else {
+        fs::read_to_string(board.w1_dir().join(id).join("w1_slave"))
+            .unwrap_or_else(|e| format!("ERR cannot read sensor {}: {}\n", id, e))
+    };
+    (&stream).write_all(reply.as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,53 @@
+//! Tank backend: temperatures of the simulated tank (see [`crate::tank_sim`]), for
+//! the simulated board.
+
+use anyhow::{Result, bail};
+use serde::Deserialize;
+
+use super::TemperatureSensor;
+use crate::board::Board;
+use crate::config::SensorConfig;
+use crate::simulation::Faults;
+use crate::tank_sim::TankSim;
+
+/// Position of a `tank` sensor.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TankProbeConfig {
+    /// Height in the tank, from 0.0 (bottom) to 1.0 (top).
+    pub height: f32,
+}
+
+impl Default for TankProbeConfig {
+    fn default() -> Self {
+        Self { height: 0.5 }
+    }
+}
+
+/// Probe in the simulated tank, subject to the faults injected for its ID.
+pub struct TankSensor {
+    id: String,
+    height: f32,
+    faults: Faults,
+    sim: Option<TankSim>,
+}
+
+impl TankSensor {
+    pub fn new(board: &Board, sensor: &SensorConfig) -> Self {
+        Self {
+            id: sensor.id.clone(),
+            height: sensor.tank.height,
+            faults: board.faults().clone(),
+            sim: board.tank().cloned(),
+        }
+    }
+}
+
+impl TemperatureSensor for TankSensor {
+    fn read(&self) -> Result<f32> {
+        let Some(sim) = &self.sim else {
+            bail!("No simulated tank: set [simulation.tank] on the simulated board");
+        };
+        self.faults.apply(&self.id, || sim.temperature(self.height))
+    }
+}
@@ -0,0 +1,124 @@
+//! MQTT backend: temperatures published by other devices (an outdoor sensor, a
+//! weather station) on the broker, read as if they were local sensors.
+//!
+//! Each `mqtt` sensor subscribes to its topic; a read returns the last value received,
+//! or an error once it is older than `max_age_s`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use super::TemperatureSensor;
+use super::capture::RawRead;
+use crate::config::SensorConfig;
+use crate::logging::warning;
+use crate::mqtt::Subscriptions;
+
+/// Last value received for a sensor and when it arrived.
+type Slot = Arc<Mutex<Option<(f32, Instant)>>>;
+
+/// Last values of the `mqtt` sensors, by sensor name.
+static SLOTS: OnceLock<Mutex<HashMap<String, Slot>>> = OnceLock::new();
+
+fn slot(sensor: &str) -> Slot {
+    let slots = SLOTS.get_or_init(Default::default);
+    let mut slots = slots.lock().unwrap_or_else(|e| e.into_inner());
+    slots.entry(sensor.to_string()).or_default().clone()
+}
+
+/// Topic of an `mqtt` sensor.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TopicSensorConfig {
+    /// Topic carrying the temperature (no wildcards).
+    pub topic: String,
+    /// JSON pointer to the temperature in a JSON payload (e.g., "/DS18B20/Temperature"
+    /// for Tasmota); without it, the payload is the number itself.
+    pub json_pointer: Option<String>,
+    /// A value older than this is a read error, in seconds.
+    pub max_age_s: u64,
+}
+
+impl Default for TopicSensorConfig {
+    fn default() -> Self {
+        Self {
+            topic: String::new(),
+            json_pointer: None,
+            max_age_s: 600,
+        }
+    }
+}
+
+/// Decodes a temperature from `payload`: the number at `json_pointer` in a JSON
+/// document, or the whole payload as a number.
+pub(super) fn parse_value(payload: &[u8], json_pointer: Option<&str>) -> Result<f32> {
+    let text = std::str::from_utf8(payload).context("Payload is not UTF-8")?.trim();
+    let Some(pointer) = json_pointer else {
+        return text.parse().map_err(|_| RawRead::new("Not a number", text).into());
+    };
+    let document: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| RawRead::new(format!("Invalid JSON: {}", e), text))?;
+    let value = match document.pointer(pointer) {
+        Some(serde_json::Value::Number(number)) => number.as_f64(),
+        // Some devices send numbers as strings
+        Some(serde_json::Value::String(string)) => string.trim().parse().ok(),
+        _ => None,
+    };
+    match value {
+        Some(value) => Ok(value as f32),
+        None => Err(RawRead::new(format!("No number at {}", pointer), text).into()),
+    }
+}
+
+/// Temperature received on an MQTT topic.
+pub struct TopicSensor {
+    topic: String,
+    max_age: Duration,
+    latest: Slot,
+}
+
+impl TopicSensor {
+    pub fn new(sensor: &SensorConfig) -> Self {
+        Self {
+            topic: sensor.mqtt.topic.clone(),
+            max_age: Duration::from_secs(sensor.mqtt.max_age_s),
+            latest: slot(&sensor.name),
+        }
+    }
+}
+
+impl TemperatureSensor for TopicSensor {
+    fn read(&self) -> Result<f32> {
+        let latest = *self.latest.lock().unwrap_or_else(|e| e.into_inner());
+        match latest {
+            None => bail!("Nothing received on {}", self.topic),
+            Some((_, received)) if received.elapsed() > self.max_age => {
+                bail!("Nothing received on {} for {} s", self.topic, received.elapsed().as_secs())
+            }
+            Some((value, _)) => Ok((value * 100.0).round() / 100.0),
+        }
+    }
+}
+
+/// Subscribes to the topics of the `mqtt` sensors among `sensors`.
+pub fn follow_topics(sensors: &[SensorConfig], subscriptions: &Subscriptions) {
+    for sensor in sensors.iter().filter(|s| s.backend == Some(super::SensorBackend::Mqtt)) {
+        let latest = slot(&sensor.name);
+        let (name, pointer) = (sensor.name.clone(), sensor.mqtt.json_pointer.clone());
+        subscriptions.subscribe(sensor.mqtt.topic.clone(), move |topic, payload| {
+            match parse_value(payload, pointer.as_deref()) {
+                Ok(value) => *latest.lock().unwrap_or_else(|e| e.into_inner()) = Some((value, Instant::now())),
+                Err(e) => warning!(
+                    "Sensor {}: ignoring {:?} on {}: {:#}",
+                    name,
+                    String::from_utf8_lossy(payload),
+                    topic,
+                    e
+                ),
+            }
+        });
+    }
+}
@@ -0,0 +1,85 @@
+//! 1-Wire backend: DS18B20 and compatible sensors read through sysfs.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+use super::TemperatureSensor;
+use super::capture::RawRead;
+use crate::board::Board;
+
+/// DS18B20 (and compatible) sensor read through the Linux 1-Wire sysfs interface.
+pub struct W1Sensor {
+    /// Path of the `w1_slave` file of the device.
+    path: PathBuf,
+    id: String,
+}
+
+impl W1Sensor {
+    /// Creates the backend of the 1-Wire device `id` (e.g., "28-000000000001").
+    pub fn new(board: &Board, id: &str) -> Self {
+        Self {
+            path: board.w1_dir().join(id).join("w1_slave"),
+            id: id.to_string(),
+        }
+    }
+}
+
+impl TemperatureSensor for W1Sensor {
+    /// Reads `<w1_dir>/<id>/w1_slave`, returning the temperature rounded to 2 decimal places.
+    fn read(&self) -> Result<f32> {
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read sensor {}", self.id))?;
+
+        parse(&content).map_err(|message| RawRead::new(format!("{} for sensor {}", message, self.id), content).into())
+    }
+}
+
+/// Decodes the content of a `w1_slave` file.
+pub(super) fn parse(content: &str) -> Result<f32, &'static str> {
+    // The w1_slave file contains two lines.
+    // Line 1: 72 01 4b 46 7f ff 0e 10 57 : crc=57 YES (YES indicates valid data)
+    // Line 2: 72 01 4b 46 7f ff 0e 10 57 t=23125 (t is temperature in millidegrees)
+    if !content.contains("YES") {
+        return Err("CRC check failed");
+    }
+
+    let pos = content.find("t=").ok_or("Temperature not found")?;
+    let temp_milli = content[pos + 2..].trim().parse::<f32>().map_err(|_| "Invalid temperature")?;
+    let temp = temp_milli / 1000.0;
+    // Round to 2 decimal places
+    Ok((temp * 100.0).round() / 100.0)
+}
+
+/// Sets the conversion resolution of the 1-Wire sensor `id`, from 9 bits (0.5 °C, 94 ms
+/// conversion) to 12 bits (0.0625 °C, 750 ms). The setting is lost when the sensor is
+/// powered off.
+///
+/// # Errors
+/// Returns an error if the `resolution` attribute cannot be written (kernel before 5.10,
+/// sensor with a fixed resolution such as the DS18S20, or missing write access).
+pub fn set_resolution(board: &Board, id: &str, bits: u8) -> Result<()> {
+    let path = board.w1_dir().join(id).join("resolution");
+    fs::write(&path, bits.to_string()).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// 1-Wire family codes of the supported temperature sensors
+/// (DS18S20, DS1822, DS18B20, MAX31850).
+const TEMPERATURE_FAMILIES: [&str; 4] = ["10-", "22-", "28-", "3b-"];
+
+/// Lists the IDs of the temperature sensors present on the 1-Wire bus, sorted.
+///
+/// # Errors
+/// Returns an error if the 1-Wire devices directory cannot be read
+/// (e.g., the `w1-gpio` overlay is not enabled).
+pub fn discover_w1(board: &Board) -> Result<Vec<String>> {
+    let entries = fs::read_dir(board.w1_dir())
+        .with_context(|| format!("Failed to read {}", board.w1_dir().display()))?;
+    let mut ids: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| TEMPERATURE_FAMILIES.iter().any(|family| name.starts_with(family)))
+        .collect();
+    ids.sort();
+    Ok(ids)
+}
@@ -0,0 +1,194 @@
+//! Fault injection for the simulated board.
+//! Lets error handling, alerts and UI error states be exercised without hardware.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow, bail};
+use serde::Deserialize;
+
+use crate::logging::info;
+use crate::tank_sim::TankSimConfig;
+
+/// Delay of a simulated read timeout, close to a stalled 1-Wire conversion.
+const TIMEOUT_DELAY: Duration = Duration::from_secs(1);
+
+/// Sensor key matching every sensor.
+const ALL_SENSORS: &str = "*";
+
+/// A fault affecting the reads of a simulated sensor.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FaultKind {
+    /// The read fails the CRC check.
+    Crc,
+    /// The read hangs, then fails.
+    Timeout,
+    /// The sensor keeps returning the same value.
+    Stuck,
+    /// The sensor disappeared from the bus.
+    Missing,
+}
+
+impl FromStr for FaultKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "crc" => Ok(FaultKind::Crc),
+            "timeout" => Ok(FaultKind::Timeout),
+            "stuck" => Ok(FaultKind::Stuck),
+            "missing" => Ok(FaultKind::Missing),
+            _ => bail!("unknown fault {:?} (expected crc, timeout, stuck or missing)", s),
+        }
+    }
+}
+
+/// A fault injected from the configuration.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FaultConfig {
+    /// 1-Wire ID of the affected sensor, or `"*"` for all sensors.
+    pub sensor: String,
+    /// Kind of fault.
+    pub kind: FaultKind,
+    /// Probability that a read is affected (1.0: every read).
+    #[serde(default = "default_probability")]
+    pub probability: f32,
+}
+
+fn default_probability() -> f32 {
+    1.0
+}
+
+/// Simulation settings (only used with the `simulated` board).
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SimulationConfig {
+    /// Faults active from startup.
+    pub faults: Vec<FaultConfig>,
+    /// Scenario file replayed by the simulated sensors (`.toml` or `.csv`, see
+    /// [`crate::scenario`]); random values if absent.
+    pub scenario: Option<String>,
+    /// Time acceleration of the scenario and of the tank model (60: one minute of
+    /// simulation per second).
+    pub speed: f32,
+    /// Physical model of the tank, read by the `tank` sensors.
+    pub tank: Option<TankSimConfig>,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            faults: Vec::new(),
+            scenario: None,
+            speed: 1.0,
+            tank: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ActiveFault {
+    kind: FaultKind,
+    probability: f32,
+    /// Values returned by stuck sensors, captured on their first affected read.
+    stuck_values: HashMap<String, f32>,
+}
+
+/// Shared set of injected faults, keyed by sensor ID.
+#[derive(Debug, Clone, Default)]
+pub struct Faults(Arc<Mutex<HashMap<String, ActiveFault>>>);
+
+impl Faults {
+    /// Creates the fault set configured in `[simulation]`.
+    pub fn from_config(config: &SimulationConfig) -> Self {
+        let faults = Self::default();
+        for fault in &config.faults {
+            faults.set(&fault.sensor, fault.kind, fault.probability);
+        }
+        faults
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, ActiveFault>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Injects a fault on `sensor` (`"*"` for all sensors), replacing any previous one.
+    pub fn set(&self, sensor: &str, kind: FaultKind, probability: f32) {
+        info!("Simulation: injecting {:?} fault on {} (p = {})", kind, sensor, probability);
+        self.lock().insert(
+            sensor.to_string(),
+            ActiveFault {
+                kind,
+                probability: probability.clamp(0.0, 1.0),
+                stuck_values: HashMap::new(),
+            },
+        );
+    }
+
+    /// Removes the fault of `sensor`.
+    pub fn clear(&self, sensor: &str) {
+        if self.lock().remove(sensor).is_some() {
+            info!("Simulation: cleared fault on {}", sensor);
+        }
+    }
+
+    /// Applies the fault of `sensor_id`, if any, to a simulated read.
+    ///
+    /// `read` produces the fault-free value.
+    pub fn apply(&self, sensor_id: &str, read: impl FnOnce() -> f32) -> Result<f32> {
+        use rand::Rng;
+
+        let value = read();
+        let kind = {
+            let mut faults = self.lock();
+            let key = if faults.contains_key(sensor_id) { sensor_id } else { ALL_SENSORS };
+            let Some(fault) = faults.get_mut(key) else {
+                return Ok(value);
+            };
+            if !rand::thread_rng().gen_bool(fault.probability as f64) {
+                return Ok(value);
+            }
+            if fault.kind == FaultKind::Stuck {
+                return Ok(*fault.stuck_values.entry(sensor_id.to_string()).or_insert(value));
+            }
+            fault.kind
+        };
+
+        // Fail outside of the lock, a timeout blocks the caller for a while
+        match kind {
+            FaultKind::Timeout => {
+                std::thread::sleep(TIMEOUT_DELAY);
+                Err(anyhow!("Timed out reading sensor {}", sensor_id))
+            }
+            FaultKind::Missing => Err(anyhow!("Failed to read sensor {}: no such device", sensor_id)),
+            FaultKind::Crc => Err(anyhow!("CRC check failed for sensor {}", sensor_id)),
+            FaultKind::Stuck => unreachable!("stuck sensors return a value"),
+        }
+    }
+
+    /// Handles a message on `{base_topic}/simulation/fault/{sensor}`.
+    ///
+    /// The payload is `<kind> [probability]`, or `none` (or empty) to clear the fault.
+    ///
+    /// # Errors
+    /// Returns an error if the payload is invalid; the faults are then left unchanged.
+    pub fn handle_message(&self, sensor: &str, payload: &[u8]) -> Result<()> {
+        let payload = String::from_utf8_lossy(payload);
+        let mut words = payload.split_whitespace();
+        match words.next() {
+            None | Some("none") => self.clear(sensor),
+            Some(kind) => {
+                let kind = kind.parse::<FaultKind>()?;
+                let probability = match words.next() {
+                    Some(p) => p.parse().map_err(|_| anyhow!("invalid probability {:?}", p))?,
+                    None => 1.0,
+                };
+                self.set(sensor, kind, probability);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+//! Crash-safe persistence of the runtime state.
+//! Periodically saves what must survive a restart or a power blip (history, active
+//! alerts and their history, heater and flow totals, maintenance records, efficiency
+//! and season records) to a JSON file, and restores it at startup.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::alerts::{Alert, AlertEvent, Alerts};
+use crate::clock::ClockStatus;
+use crate::efficiency::{DailyEfficiency, Efficiency};
+use crate::flow::{FlowMeter, FlowTotals};
+use crate::heater::{HeaterStats, HeaterTotals};
+use crate::history::{HistoryPoint, SensorHistory};
+use crate::logging::warning;
+use crate::maintenance::{Maintenance, MaintenanceRecord};
+use crate::season::{SeasonRecords, Seasons};
+
+/// History of one sensor, identified by its configured name.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SensorHistoryState {
+    pub name: String,
+    pub points: Vec<HistoryPoint>,
+}
+
+/// Everything persisted across restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RuntimeState {
+    /// Time of the last save.
+    pub saved_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub history: Vec<SensorHistoryState>,
+    #[serde(default)]
+    pub alerts: Vec<Alert>,
+    /// Alerts raised and cleared, oldest first.
+    #[serde(default)]
+    pub alert_history: Vec<AlertEvent>,
+    /// Heating element runtime totals.
+    #[serde(default)]
+    pub heater: HeaterTotals,
+    /// Hot water volume counted by the flow meter.
+    #[serde(default)]
+    pub flow: FlowTotals,
+    /// Last occurrence of the maintenance tasks.
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceRecord>,
+    /// Daily energy balances of the efficiency score.
+    #[serde(default)]
+    pub efficiency: Vec<DailyEfficiency>,
+    /// Totals of the heating seasons and summers.
+    #[serde(default)]
+    pub season: SeasonRecords,
+    /// `true` while the application runs; cleared by the final save on a clean exit.
+    /// Finding it set at startup means the previous run ended abruptly (power loss, crash).
+    #[serde(default)]
+    pub running: bool,
+}
+
+/// An unclean shutdown of the previous run, detected at startup.
+#[derive(Debug, Serialize)]
+pub struct PowerLossEvent {
+    /// Last save of the previous run, when it was still running.
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Startup time, if the clock can be trusted (e.g., thanks to a battery-backed RTC).
+    pub restarted_at: Option<DateTime<Utc>>,
+    /// Upper bound of the outage, in seconds, when both times are known.
+    pub outage_s: Option<i64>,
+}
+
+impl RuntimeState {
+    /// Returns the power-loss event if the previous run did not exit cleanly.
+    ///
+    /// `now` is `None` when the clock cannot be trusted yet.
+    pub fn unclean_shutdown(&self, now: Option<DateTime<Utc>>) -> Option<PowerLossEvent> {
+        if !self.running {
+            return None;
+        }
+        let outage_s = now.zip(self.saved_at).map(|(now, saved_at)| (now - saved_at).num_seconds());
+        Some(PowerLossEvent {
+            last_seen: self.saved_at,
+            restarted_at: now,
+            outage_s,
+        })
+    }
+
+    /// Returns the saved points of the sensor named `name`, if any.
+    pub fn history_for(&self, name: &str) -> Option<&[HistoryPoint]> {
+        self.history
+            .iter()
+            .find(|h| h.name == name)
+            .map(|h| h.points.as_slice())
+    }
+}
+
+/// Loads the runtime state from `path`.
+///
+/// Returns `Ok(None)` if the file does not exist yet (first start).
+///
+/// # Errors
+/// Returns an error if the file exists but cannot be read or parsed.
+pub fn load(path: &Path) -> Result<Option<RuntimeState>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read state file {}", path.display()))?;
+    let state = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse state file {}", path.display()))?;
+    Ok(Some(state))
+}
+
+/// Writes `content` to `path` atomically: the data is written and flushed to a
+/// temporary file which then replaces the target, so a crash or power loss never
+/// leaves a truncated file behind.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp).with_context(|| format!("Failed to create {}", tmp.display()))?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to rename {}", tmp.display()))?;
+    // Persist the rename itself
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty())
+        && let Ok(dir) = File::open(dir)
+    {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Handles to everything the runtime state is collected from.
+#[derive(Clone)]
+pub struct StateSources {
+    /// Names of the histories, in order.
+    pub names: Vec<String>,
+    pub history: Arc<Mutex<Vec<SensorHistory>>>,
+    pub alerts: Alerts,
+    pub heater: HeaterStats,
+    pub flow: FlowMeter,
+    pub maintenance: Maintenance,
+    pub efficiency: Efficiency,
+    pub seasons: Seasons,
+}
+
+impl StateSources {
+    /// Collects the current runtime state.
+    pub async fn capture(&self) -> RuntimeState {
+        let history = self.history.lock().await;
+        RuntimeState {
+            saved_at: Some(Utc::now()),
+            history: self
+                .names
+                .iter()
+                .zip(history.iter())
+                .map(|(name, h)| SensorHistoryState {
+                    name: name.clone(),
+                    points: h.points().iter().copied().collect(),
+                })
+                .collect(),
+            alerts: self.alerts.active(),
+            alert_history: self.alerts.history(),
+            heater: self.heater.totals(),
+            flow: self.flow.totals(),
+            maintenance: self.maintenance.records(),
+            efficiency: self.efficiency.days(),
+            season: self.seasons.records(),
+            running: true,
+        }
+    }
+}
+
+/// Saves the runtime state to `path`.
+pub fn save(path: &Path, state: &RuntimeState) -> Result<()> {
+    let content = serde_json::to_vec(state)?;
+    write_atomic(path, &content)
+}
+
+/// Saves the runtime state every `interval`, once the clock can be trusted.
+pub async fn persist_loop(path: String, interval: Duration, sources: StateSources, clock_status: ClockStatus) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if !clock_status.is_sane() {
+            continue;
+        }
+        let state = sources.capture().await;
+        if let Err(e) = save(Path::new(&path), &state) {
+            warning!("Failed to save runtime state: {:#}", e);
+        }
+    }
+}
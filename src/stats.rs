@@ -0,0 +1,219 @@
+//! Hourly and daily statistics.
+//! At the end of every hour and every day (local time), the lowest, highest and average
+//! temperature of each sensor over the period are computed from the in-memory history,
+//! with the change of the stored energy, and published under `{base_topic}/stats/`, so
+//! that dashboards do not have to aggregate the values themselves.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+use boilert::model::Snapshot;
+use chrono::{DateTime, TimeDelta, Utc};
+use rumqttc::QoS;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, watch};
+
+use crate::clock::{ClockStatus, next_boundary};
+use crate::format::{self, Unit};
+use crate::history::SensorHistory;
+use crate::mqtt::Publisher;
+
+/// Settings from the `[stats]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct StatsConfig {
+    /// Publish the statistics of every hour.
+    pub hourly: bool,
+    /// Publish the statistics of every day.
+    pub daily: bool,
+    /// Publish them with the retain flag.
+    pub retain: bool,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            hourly: true,
+            daily: true,
+            retain: false,
+        }
+    }
+}
+
+/// Period the statistics cover.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Hourly,
+    Daily,
+}
+
+impl Period {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Period::Hourly => "hourly",
+            Period::Daily => "daily",
+        }
+    }
+
+    fn duration(self) -> TimeDelta {
+        match self {
+            Period::Hourly => TimeDelta::hours(1),
+            Period::Daily => TimeDelta::days(1),
+        }
+    }
+}
+
+/// Values of a sensor over a period.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SensorStats {
+    pub min: f32,
+    pub max: f32,
+    /// Average weighted by the time each value of the history stood.
+    pub avg: f32,
+}
+
+/// Statistics of a period, published on `{base_topic}/stats/{period}`.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsReport {
+    pub period: Period,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Sensors with history points in the period.
+    pub sensors: BTreeMap<String, SensorStats>,
+    /// Change of the stored energy over the period, or since startup when it started
+    /// during the period.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub energy_delta_kwh: Option<f32>,
+}
+
+/// Values of `history` from `from` (included) to `to` (excluded), `None` without a point.
+fn sensor_stats(history: &SensorHistory, from: DateTime<Utc>, to: DateTime<Utc>) -> Option<SensorStats> {
+    let points: Vec<_> = history.points().iter().filter(|p| p.timestamp >= from && p.timestamp < to).collect();
+    let first = points.first()?;
+    let (mut min, mut max) = (first.value, first.value);
+    let (mut sum, mut weight) = (0.0, 0.0);
+    for (i, point) in points.iter().enumerate() {
+        min = min.min(point.value);
+        max = max.max(point.value);
+        // A value stands until the next point, or the end of the period
+        let until = points.get(i + 1).map_or(to, |next| next.timestamp);
+        let seconds = (until - point.timestamp).num_seconds().max(1) as f32;
+        sum += point.value * seconds;
+        weight += seconds;
+    }
+    Some(SensorStats {
+        min,
+        max,
+        avg: format::round(Unit::Celsius, sum / weight),
+    })
+}
+
+/// Stored energy at `at`: the last sample before it, or the first one after it when the
+/// samples start later.
+fn energy_at(samples: &VecDeque<(DateTime<Utc>, f32)>, at: DateTime<Utc>) -> Option<f32> {
+    samples.iter().rev().find(|(time, _)| *time <= at).or(samples.front()).map(|&(_, energy)| energy)
+}
+
+/// Task publishing the statistics at the end of every hour and day.
+#[derive(Clone)]
+pub struct StatsTracker {
+    pub config: StatsConfig,
+    /// History of the physical and virtual sensors, in the order of `names`.
+    pub history: Arc<Mutex<Vec<SensorHistory>>>,
+    pub names: Vec<String>,
+    pub snapshots: watch::Receiver<Option<Snapshot>>,
+    pub publisher: Publisher,
+    pub base_topic: String,
+    pub clock_status: ClockStatus,
+}
+
+impl StatsTracker {
+    /// Samples the stored energy and publishes the statistics of each period as it ends.
+    pub async fn run(self) {
+        let Self {
+            config,
+            history,
+            names,
+            mut snapshots,
+            publisher,
+            base_topic,
+            clock_status,
+        } = self;
+        // Stored energy, one sample per minute over the last day
+        let mut energy: VecDeque<(DateTime<Utc>, f32)> = VecDeque::new();
+        let mut end = next_boundary(Utc::now(), Period::Hourly.duration());
+        loop {
+            let wait = (end - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                changed = snapshots.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    if let Some(snapshot) = snapshots.borrow_and_update().as_ref()
+                        && energy.back().is_none_or(|&(time, _)| snapshot.timestamp - time >= TimeDelta::minutes(1))
+                    {
+                        energy.push_back((snapshot.timestamp, snapshot.energy.energy_kwh));
+                    }
+                    continue;
+                }
+                _ = tokio::time::sleep(wait) => {}
+            }
+
+            let to = end;
+            end = next_boundary(Utc::now().max(to), Period::Hourly.duration());
+            while energy.front().is_some_and(|&(time, _)| to - time > Period::Daily.duration() + TimeDelta::hours(1)) {
+                energy.pop_front();
+            }
+            // Periods dated by a wrong clock would be meaningless
+            if !clock_status.is_sane() {
+                continue;
+            }
+            let end_of_day = next_boundary(to - TimeDelta::seconds(1), Period::Daily.duration()) == to;
+            let periods = [(Period::Hourly, config.hourly), (Period::Daily, config.daily && end_of_day)];
+            for (period, _) in periods.into_iter().filter(|&(_, enabled)| enabled) {
+                let from = to - period.duration();
+                let sensors = {
+                    let history = history.lock().await;
+                    names
+                        .iter()
+                        .zip(history.iter())
+                        .filter_map(|(name, history)| Some((name.clone(), sensor_stats(history, from, to)?)))
+                        .collect()
+                };
+                let energy_delta_kwh = energy_at(&energy, to)
+                    .zip(energy_at(&energy, from))
+                    .map(|(end, start)| format::round(Unit::KilowattHour, end - start));
+                let report = StatsReport {
+                    period,
+                    from,
+                    to,
+                    sensors,
+                    energy_delta_kwh,
+                };
+                publish(&publisher, &base_topic, config.retain, &report).await;
+            }
+        }
+    }
+}
+
+/// Publishes `report` as a JSON document on `{base_topic}/stats/{period}`, and each of
+/// its values on a topic of its own below it.
+async fn publish(publisher: &Publisher, base_topic: &str, retain: bool, report: &StatsReport) {
+    let topic = format!("{}/stats/{}", base_topic, report.period.as_str());
+    let mut messages = Vec::new();
+    for (name, stats) in &report.sensors {
+        for (quantity, value) in [("min", stats.min), ("max", stats.max), ("avg", stats.avg)] {
+            messages.push((format!("{}/{}/{}", topic, name, quantity), format::data(Unit::Celsius, value)));
+        }
+    }
+    if let Some(delta) = report.energy_delta_kwh {
+        messages.push((format!("{}/energy_delta", topic), format::data(Unit::KilowattHour, delta)));
+    }
+    if let Ok(document) = serde_json::to_string(report) {
+        messages.push((topic, document));
+    }
+    for (topic, payload) in messages {
+        let _ = publisher.publish(topic, QoS::AtLeastOnce, retain, payload).await;
+    }
+}
@@ -0,0 +1,73 @@
+//! Supervision of long-running background tasks.
+//! Restarts tasks that panic or exit, with exponential backoff.
+
+use std::any::Any;
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::alerts::Alerts;
+use crate::format;
+use crate::messages::Message;
+
+/// Delay before the first restart of a failed task.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound for the restart delay.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A task running longer than this is considered healthy again:
+/// its alert is cleared and the backoff is reset.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Spawns a task under supervision.
+///
+/// `factory` is called to create a fresh future for every (re)start. Any state
+/// that must survive a restart has to live outside the future (e.g., in an `Arc`).
+/// When the task terminates, the supervisor logs it, raises the `task_<name>`
+/// alert and restarts it after a backoff delay.
+pub fn spawn_supervised<F, Fut>(name: &'static str, alerts: Alerts, mut factory: F) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let alert_id = format!("task_{}", name);
+        let mut backoff = INITIAL_BACKOFF;
+
+        loop {
+            let mut handle = tokio::spawn(factory());
+            let result = match time::timeout(STABLE_AFTER, &mut handle).await {
+                Ok(result) => result,
+                Err(_) => {
+                    // The task survived the stability window: it is healthy again.
+                    alerts.clear(&alert_id);
+                    backoff = INITIAL_BACKOFF;
+                    handle.await
+                }
+            };
+
+            let reason = match result {
+                Ok(()) => "exited unexpectedly".to_string(),
+                Err(e) if e.is_panic() => format!("panicked: {}", panic_message(e.into_panic())),
+                Err(e) => format!("was cancelled: {}", e),
+            };
+            let message = Message::new(format!("Task {} {}, restarting in {}s", name, reason, backoff.as_secs()))
+                .with("duration", format::duration(backoff));
+            alerts.raise(&alert_id, message);
+
+            time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    })
+}
+
+/// Extracts a readable message from a panic payload.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
@@ -0,0 +1,63 @@
+//! systemd service integration.
+//! Implements the `sd_notify` protocol (readiness and watchdog keep-alive) without
+//! linking libsystemd.
+
+use std::time::Duration;
+
+use crate::health::Health;
+
+/// Sends a state string (e.g., "READY=1") to the service manager.
+///
+/// Returns `false` if not running under systemd with `Type=notify` or if sending failed.
+pub fn notify(state: &str) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::net::UnixDatagram;
+
+        let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+            return false;
+        };
+        let Ok(socket) = UnixDatagram::unbound() else {
+            return false;
+        };
+        let path = path.to_string_lossy().into_owned();
+        // Names starting with '@' live in the Linux abstract socket namespace
+        #[cfg(target_os = "linux")]
+        if let Some(name) = path.strip_prefix('@') {
+            use std::os::linux::net::SocketAddrExt;
+            return std::os::unix::net::SocketAddr::from_abstract_name(name)
+                .and_then(|addr| socket.send_to_addr(state.as_bytes(), &addr))
+                .is_ok();
+        }
+        socket.send_to(state.as_bytes(), path).is_ok()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = state;
+        false
+    }
+}
+
+/// Returns the keep-alive interval requested by `WatchdogSec=` (half the timeout),
+/// or `None` if the watchdog is not enabled for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+/// Pings the systemd watchdog as long as the acquisition loop is alive, so that
+/// systemd restarts boilert if it hangs.
+pub async fn watchdog_loop(interval: Duration, health: Health) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if health.is_alive() {
+            notify("WATCHDOG=1");
+        }
+    }
+}
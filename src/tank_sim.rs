@@ -0,0 +1,457 @@
+//! Physical simulation of the boiler for the simulated board.
+//! The tank is split into horizontal layers of water exchanging heat with their
+//! neighbours and losing heat to the room; the heating element warms its layer under
+//! the control of a thermostat, and hot water draws push cold water in at the bottom.
+//! Sensors with the `tank` backend read the layer at their height, so the control
+//! logic can be tuned and regression-tested without hardware, in real time (optionally
+//! accelerated) or with `boilert simulate`.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow, bail};
+use serde::Deserialize;
+
+use crate::logging::info;
+use crate::mqtt::Publisher;
+
+/// Specific heat of water, in J/(kg·K); a litre is taken as a kilogram.
+const WATER_HEAT_CAPACITY: f64 = 4186.0;
+
+/// Longest step of the integration, in simulated seconds.
+const MAX_STEP_S: f64 = 5.0;
+
+/// Longest time simulated at once in real time; after a suspend, the tank resumes
+/// instead of catching up.
+const MAX_CATCH_UP: Duration = Duration::from_secs(3600);
+
+/// Commands accepted on `{base_topic}/simulation/<command>`.
+pub const COMMANDS: [&str; 2] = ["heater/set", "draw"];
+
+/// Interval between two checks of the element state for publication.
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A hot water draw repeated every `length_min`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DrawConfig {
+    /// Start, in minutes from the beginning of the cycle.
+    pub at_min: f32,
+    /// Volume drawn, in litres.
+    pub litres: f32,
+    /// Flow rate, in litres per minute.
+    #[serde(default = "default_flow")]
+    pub flow_l_min: f32,
+}
+
+fn default_flow() -> f32 {
+    8.0
+}
+
+/// Tank model from the `[simulation.tank]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TankSimConfig {
+    /// Number of horizontal layers the tank is split into.
+    pub layers: usize,
+    /// Water volume, in litres.
+    pub volume_l: f32,
+    /// Temperature of the whole tank at the start, in °C.
+    pub initial_c: f32,
+    /// Room temperature, in °C.
+    pub ambient_c: f32,
+    /// Temperature of the cold water entering during draws, in °C.
+    pub inlet_c: f32,
+    /// Heat loss of the whole tank through its insulation, in W per K above the ambient.
+    pub loss_w_per_k: f32,
+    /// Heat exchanged between two adjacent layers, in W per K of difference.
+    pub conduction_w_per_k: f32,
+    /// Power of the heating element, in W.
+    pub element_power_w: f32,
+    /// Height of the element, from 0.0 (bottom) to 1.0 (top).
+    pub element_height: f32,
+    /// Thermostat setpoint, in °C: the element stops once its probe reaches it.
+    pub setpoint_c: f32,
+    /// The element starts again once the probe is this far below the setpoint, in °C.
+    pub hysteresis_c: f32,
+    /// Height of the thermostat probe, from 0.0 (bottom) to 1.0 (top).
+    pub thermostat_height: f32,
+    /// Draws of the day.
+    pub draws: Vec<DrawConfig>,
+    /// The draws repeat after this, in minutes.
+    pub length_min: f32,
+}
+
+impl Default for TankSimConfig {
+    fn default() -> Self {
+        Self {
+            layers: 10,
+            volume_l: 200.0,
+            initial_c: 45.0,
+            ambient_c: 18.0,
+            inlet_c: 12.0,
+            loss_w_per_k: 1.5,
+            conduction_w_per_k: 2.0,
+            element_power_w: 3000.0,
+            element_height: 0.15,
+            setpoint_c: 60.0,
+            hysteresis_c: 6.0,
+            thermostat_height: 0.3,
+            draws: Vec::new(),
+            length_min: 1440.0,
+        }
+    }
+}
+
+impl TankSimConfig {
+    /// Checks the tank model.
+    pub fn validate(&self) -> Result<()> {
+        if !(2..=100).contains(&self.layers) {
+            bail!("[simulation.tank] layers must be between 2 and 100");
+        }
+        if self.volume_l <= 0.0 || self.length_min <= 0.0 {
+            bail!("[simulation.tank] volume_l and length_min must be positive");
+        }
+        if self.loss_w_per_k < 0.0 || self.conduction_w_per_k < 0.0 || self.element_power_w < 0.0 {
+            bail!("[simulation.tank] loss_w_per_k, conduction_w_per_k and element_power_w must not be negative");
+        }
+        if !(0.0..=1.0).contains(&self.element_height) || !(0.0..=1.0).contains(&self.thermostat_height) {
+            bail!("[simulation.tank] element_height and thermostat_height must be between 0.0 and 1.0");
+        }
+        if self.hysteresis_c < 0.0 {
+            bail!("[simulation.tank] hysteresis_c must not be negative");
+        }
+        if self.draws.iter().any(|d| d.litres <= 0.0 || d.flow_l_min <= 0.0 || d.at_min < 0.0) {
+            bail!("[simulation.tank] draws need a positive volume and flow and a start not below 0");
+        }
+        Ok(())
+    }
+
+    /// Index of the layer at `height`.
+    fn layer(&self, height: f32) -> usize {
+        ((height.clamp(0.0, 1.0) * self.layers as f32) as usize).min(self.layers - 1)
+    }
+}
+
+/// Control of the heating element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ElementMode {
+    /// Switched by the thermostat of the model.
+    #[default]
+    Auto,
+    /// Forced on or off, for an external control loop.
+    Forced(bool),
+}
+
+/// A draw in progress.
+#[derive(Debug, Clone, Copy)]
+struct ActiveDraw {
+    remaining_l: f64,
+    flow_l_s: f64,
+}
+
+/// Totals of a simulation run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimTotals {
+    /// Simulated time, in seconds.
+    pub elapsed_s: f64,
+    /// Time the element was on, in seconds.
+    pub element_on_s: f64,
+    /// Off-to-on switches of the element.
+    pub element_cycles: u64,
+    /// Energy put in by the element, in kWh.
+    pub energy_kwh: f64,
+    /// Volume drawn, in litres.
+    pub drawn_l: f64,
+    /// Coldest water delivered during a draw, in °C.
+    pub coldest_outlet_c: Option<f32>,
+}
+
+/// State of the simulated tank.
+#[derive(Debug)]
+struct Tank {
+    config: TankSimConfig,
+    /// Layer temperatures, from the bottom, in °C.
+    temps: Vec<f64>,
+    mode: ElementMode,
+    element_on: bool,
+    draws: Vec<ActiveDraw>,
+    /// Index of the next scheduled draw in the current cycle.
+    next_draw: usize,
+    /// Start of the current cycle of draws, in simulated seconds.
+    cycle_start_s: f64,
+    totals: SimTotals,
+}
+
+impl Tank {
+    fn new(config: &TankSimConfig) -> Self {
+        let mut config = config.clone();
+        config.draws.sort_by(|a, b| a.at_min.total_cmp(&b.at_min));
+        Self {
+            temps: vec![config.initial_c as f64; config.layers],
+            config,
+            mode: ElementMode::Auto,
+            element_on: false,
+            draws: Vec::new(),
+            next_draw: 0,
+            cycle_start_s: 0.0,
+            totals: SimTotals::default(),
+        }
+    }
+
+    /// Runs the model for `seconds` of simulated time.
+    fn advance(&mut self, seconds: f64) {
+        let mut left = seconds;
+        while left > 0.0 {
+            let dt = left.min(MAX_STEP_S);
+            self.step(dt);
+            left -= dt;
+        }
+    }
+
+    fn step(&mut self, dt: f64) {
+        self.schedule_draws();
+        self.switch_element();
+
+        let c = &self.config;
+        let layers = self.temps.len();
+        let layer_l = c.volume_l as f64 / layers as f64;
+        let layer_heat_capacity = layer_l * WATER_HEAT_CAPACITY;
+
+        // Losses through the insulation, shared evenly between the layers
+        let loss = c.loss_w_per_k as f64 / layers as f64;
+        for t in &mut self.temps {
+            *t -= dt * loss * (*t - c.ambient_c as f64) / layer_heat_capacity;
+        }
+        // Conduction between neighbours
+        let exchanged: Vec<f64> = self
+            .temps
+            .windows(2)
+            .map(|pair| dt * c.conduction_w_per_k as f64 * (pair[1] - pair[0]) / layer_heat_capacity)
+            .collect();
+        for (i, delta) in exchanged.into_iter().enumerate() {
+            self.temps[i] += delta;
+            self.temps[i + 1] -= delta;
+        }
+        if self.element_on {
+            let element = c.layer(c.element_height);
+            self.temps[element] += dt * c.element_power_w as f64 / layer_heat_capacity;
+            self.totals.element_on_s += dt;
+            self.totals.energy_kwh += dt * c.element_power_w as f64 / 3_600_000.0;
+        }
+
+        // Draws: hot water leaves at the top, cold water comes in at the bottom
+        let mut drawn = 0.0;
+        for draw in &mut self.draws {
+            let litres = (draw.flow_l_s * dt).min(draw.remaining_l);
+            draw.remaining_l -= litres;
+            drawn += litres;
+        }
+        self.draws.retain(|d| d.remaining_l > 1e-9);
+        if drawn > 0.0 {
+            let outlet = self.temps[layers - 1] as f32;
+            let coldest = self.totals.coldest_outlet_c.get_or_insert(outlet);
+            *coldest = coldest.min(outlet);
+            self.totals.drawn_l += drawn;
+            // Plug flow, one layer at most per step
+            let fraction = (drawn / layer_l).min(1.0);
+            for i in (1..layers).rev() {
+                self.temps[i] += fraction * (self.temps[i - 1] - self.temps[i]);
+            }
+            self.temps[0] += fraction * (c.inlet_c as f64 - self.temps[0]);
+        }
+
+        // Buoyancy: warmer water below a colder layer rises and mixes with it
+        for _ in 0..layers {
+            let mut stable = true;
+            for i in 0..layers - 1 {
+                if self.temps[i] > self.temps[i + 1] + 1e-6 {
+                    let mixed = (self.temps[i] + self.temps[i + 1]) / 2.0;
+                    self.temps[i] = mixed;
+                    self.temps[i + 1] = mixed;
+                    stable = false;
+                }
+            }
+            if stable {
+                break;
+            }
+        }
+
+        self.totals.elapsed_s += dt;
+    }
+
+    /// Starts the scheduled draws that are due.
+    fn schedule_draws(&mut self) {
+        let c = &self.config;
+        let length_s = c.length_min as f64 * 60.0;
+        if self.totals.elapsed_s - self.cycle_start_s >= length_s {
+            self.cycle_start_s += length_s;
+            self.next_draw = 0;
+        }
+        let in_cycle_s = self.totals.elapsed_s - self.cycle_start_s;
+        while let Some(draw) = c.draws.get(self.next_draw)
+            && draw.at_min as f64 * 60.0 <= in_cycle_s
+        {
+            self.draws.push(ActiveDraw {
+                remaining_l: draw.litres as f64,
+                flow_l_s: draw.flow_l_min as f64 / 60.0,
+            });
+            self.next_draw += 1;
+        }
+    }
+
+    /// Applies the thermostat, or the forced state of the element.
+    fn switch_element(&mut self) {
+        let c = &self.config;
+        let on = match self.mode {
+            ElementMode::Forced(on) => on,
+            ElementMode::Auto => {
+                let probe = self.temps[c.layer(c.thermostat_height)] as f32;
+                if self.element_on {
+                    probe < c.setpoint_c
+                } else {
+                    probe <= c.setpoint_c - c.hysteresis_c
+                }
+            }
+        };
+        if on && !self.element_on {
+            self.totals.element_cycles += 1;
+        }
+        self.element_on = on;
+    }
+
+    fn temperature(&self, height: f32) -> f32 {
+        let value = self.temps[self.config.layer(height)] as f32;
+        (value * 100.0).round() / 100.0
+    }
+}
+
+/// Shared simulated tank, running in real time scaled by `speed`.
+#[derive(Debug, Clone)]
+pub struct TankSim {
+    tank: Arc<Mutex<Tank>>,
+    /// Simulated seconds per real second.
+    speed: f32,
+    synced: Arc<Mutex<Instant>>,
+}
+
+impl TankSim {
+    pub fn new(config: &TankSimConfig, speed: f32) -> Self {
+        Self {
+            tank: Arc::new(Mutex::new(Tank::new(config))),
+            speed: speed.max(0.0),
+            synced: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Locks the tank, after running it up to the current time.
+    fn lock(&self) -> MutexGuard<'_, Tank> {
+        let mut tank = self.tank.lock().unwrap_or_else(|e| e.into_inner());
+        let mut synced = self.synced.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = synced.elapsed().min(MAX_CATCH_UP);
+        *synced = Instant::now();
+        tank.advance(elapsed.as_secs_f64() * self.speed as f64);
+        tank
+    }
+
+    /// Current temperature of the layer at `height`, rounded to 2 decimal places.
+    pub fn temperature(&self, height: f32) -> f32 {
+        self.lock().temperature(height)
+    }
+
+    /// `true` while the heating element is on.
+    pub fn element_on(&self) -> bool {
+        self.lock().element_on
+    }
+
+    /// Hands the element over to the thermostat, or forces its state.
+    pub fn set_mode(&self, mode: ElementMode) {
+        info!("Simulation: heating element {:?}", mode);
+        self.lock().mode = mode;
+    }
+
+    /// Starts a draw of `litres` at `flow_l_min`.
+    pub fn start_draw(&self, litres: f32, flow_l_min: f32) {
+        info!("Simulation: drawing {} l at {} l/min", litres, flow_l_min);
+        self.lock().draws.push(ActiveDraw {
+            remaining_l: litres as f64,
+            flow_l_s: flow_l_min as f64 / 60.0,
+        });
+    }
+
+    /// Handles a message on `{base_topic}/simulation/<command>`:
+    /// - `heater/set`: `auto` (thermostat), `on` or `off`;
+    /// - `draw`: `<litres> [flow in l/min]`.
+    ///
+    /// # Errors
+    /// Returns an error if the command or the payload is invalid.
+    pub fn handle_message(&self, command: &str, payload: &[u8]) -> Result<()> {
+        let payload = String::from_utf8_lossy(payload);
+        match command {
+            "heater/set" => {
+                let mode = match payload.trim() {
+                    "auto" => ElementMode::Auto,
+                    "on" => ElementMode::Forced(true),
+                    "off" => ElementMode::Forced(false),
+                    other => bail!("unknown mode {:?} (expected auto, on or off)", other),
+                };
+                self.set_mode(mode);
+            }
+            "draw" => {
+                let (litres, flow_l_min) = parse_draw(&payload)?;
+                self.start_draw(litres, flow_l_min);
+            }
+            _ => bail!("unknown command {:?}", command),
+        }
+        Ok(())
+    }
+}
+
+/// Parses `<litres> [flow in l/min]`.
+fn parse_draw(payload: &str) -> Result<(f32, f32)> {
+    let mut words = payload.split_whitespace().map(|word| {
+        word.parse::<f32>().ok().filter(|v| *v > 0.0).ok_or_else(|| anyhow!("invalid number {:?}", word))
+    });
+    let litres = words.next().ok_or_else(|| anyhow!("missing volume"))??;
+    let flow_l_min = words.next().transpose()?.unwrap_or_else(default_flow);
+    Ok((litres, flow_l_min))
+}
+
+/// Runs `config` for `duration` of simulated time, without waiting, calling `sample`
+/// every `every` with the simulated time, the state of the element and the temperature
+/// at a height. Returns the totals of the run.
+pub fn run_offline(
+    config: &TankSimConfig,
+    duration: Duration,
+    every: Duration,
+    mut sample: impl FnMut(Duration, bool, &dyn Fn(f32) -> f32),
+) -> SimTotals {
+    let mut tank = Tank::new(config);
+    let every_s = every.as_secs_f64().max(1.0);
+    let mut at_s = 0.0;
+    while at_s <= duration.as_secs_f64() {
+        tank.advance(at_s - tank.totals.elapsed_s);
+        sample(Duration::from_secs_f64(at_s), tank.element_on, &|height| tank.temperature(height));
+        at_s += every_s;
+    }
+    tank.totals
+}
+
+/// Publishes the state of the simulated element (`on`/`off`, retained) on
+/// `{base_topic}/simulation/heater` whenever it changes, so that `[heater] state_topic`
+/// can follow it.
+pub async fn publish_loop(sim: TankSim, publisher: Publisher, base_topic: String) {
+    let topic = format!("{}/simulation/heater", base_topic);
+    let mut published = None;
+    let mut interval = tokio::time::interval(PUBLISH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let on = sim.element_on();
+        if published == Some(on) {
+            continue;
+        }
+        let state = if on { "on" } else { "off" };
+        if publisher.publish(topic.clone(), rumqttc::QoS::AtLeastOnce, true, state).await.is_ok() {
+            published = Some(on);
+        }
+    }
+}
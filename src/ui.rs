@@ -0,0 +1,888 @@
+//! Bridge between the application tasks and the Slint UI.
+//! The UI is refreshed on its own cadence from the latest acquisition snapshot,
+//! so slow sensor reads never freeze the display.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use boilert::model::{AuditEntry, AuditSource};
+use chrono::Local;
+use tokio::sync::{Notify, mpsc, watch};
+use tokio::time;
+
+use crate::alerts::Alerts;
+use crate::audit::AuditLog;
+use crate::burst::{BurstStatus, Bursts};
+use crate::chart::{CHART_WIDTH, ChartRange};
+use crate::clock::ClockStatus;
+use crate::display_lock::DisplayLock;
+use crate::efficiency::{Efficiency, EfficiencyReport, Insight};
+use crate::flow::FlowMeter;
+use crate::format::{self, Unit};
+use crate::group::GroupStats;
+use crate::health::Health;
+use crate::logging::{self, LogFilter, LogLevel, LogLine, warning};
+use crate::maintenance::{Limit, Maintenance, TaskStatus};
+use crate::metrics::Metrics;
+use crate::mixing::MixingStatus;
+use crate::preferences::{Preferences, TemperatureUnit, Theme, UiPreferences};
+use crate::remote::RemoteTank;
+use crate::season::{SeasonKind, SeasonReport, Seasons};
+use crate::usb_export::{UsbExport, UsbStatus};
+use crate::wizard::{WizardCommand, WizardView};
+use crate::{
+    AppWindow, AuditItem, BurstState, DashboardState, EfficiencyState, GroupData, LockState, LogItem, LogState,
+    MaintenanceItem, MaintenanceState, PreferencesState, RemoteTankData, SensorData, UsbExportState,
+    WizardPositionData, WizardState,
+};
+
+/// Number of audit log entries shown on the audit page.
+const AUDIT_ENTRIES_SHOWN: usize = 14;
+
+/// Values of the last acquisition cycle, as shown on the dashboard.
+#[derive(Debug, Clone, Default)]
+pub struct UiSnapshot {
+    /// Temperatures, in the order of the configured sensors.
+    pub temps: Vec<f32>,
+    /// `false` for the sensors marked invalid after failing for too long.
+    pub valid: Vec<bool>,
+    /// Chart path of each sensor.
+    pub history_paths: Vec<String>,
+    /// Dashed forecast path of each sensor (empty without a forecast).
+    pub forecast_paths: Vec<String>,
+    /// Width of the chart coordinate space, history and forecast.
+    pub chart_width: f32,
+    /// Y range of the chart of each sensor.
+    pub chart_ranges: Vec<ChartRange>,
+    /// Energy stored in the boiler, in kWh.
+    pub energy_kwh: f32,
+    /// Boiler pressure, in bar (`None` without a transducer).
+    pub pressure_bar: Option<f32>,
+    /// `false` when the transducer failed for too long.
+    pub pressure_valid: bool,
+    /// `true` below the low-pressure alarm threshold.
+    pub pressure_low: bool,
+    /// Mixing valve outlet (`None` without `[mixing_valve]`).
+    pub mixing: Option<MixingStatus>,
+    /// Values of each sensor group (`None` without a valid sensor).
+    pub groups: Vec<Option<GroupStats>>,
+}
+
+/// Handles used by the UI refresh task.
+#[derive(Clone)]
+pub struct UiRefresh {
+    pub ui_weak: slint::Weak<AppWindow>,
+    /// Latest acquisition results.
+    pub snapshots: watch::Receiver<UiSnapshot>,
+    /// Tanks of the followed remote instances.
+    pub remotes: watch::Receiver<Vec<RemoteTank>>,
+    pub sensor_names: Vec<String>,
+    pub group_names: Vec<String>,
+    pub alerts: Alerts,
+    pub clock_status: ClockStatus,
+    pub metrics: Metrics,
+    pub audit: AuditLog,
+    /// MQTT connection, for the failover indicator.
+    pub health: Health,
+    /// Hot water flow meter, if configured.
+    pub flow: Option<FlowMeter>,
+    /// Display preferences: temperature unit and chart range.
+    pub preferences: watch::Receiver<UiPreferences>,
+    /// Interval between two history points, in minutes.
+    pub history_period_min: u32,
+    pub interval: Duration,
+}
+
+/// What is shown besides the snapshots: when it changes, the UI is refreshed.
+#[derive(Clone, PartialEq)]
+struct Status {
+    alert_text: String,
+    clock_sane: bool,
+    remotes_online: Vec<bool>,
+    audit_version: u64,
+    /// Flow in l/min and volume in litres, rounded as shown.
+    flow: Option<(f32, f32)>,
+    mqtt_failover: bool,
+}
+
+impl UiRefresh {
+    /// Pushes the latest snapshot, the remote tanks, the audit log, the flow, the active
+    /// alert, the clock warning and the broker failover to the UI every `interval`, skipping
+    /// refreshes when nothing changed.
+    pub async fn run(self) {
+        let UiRefresh {
+            ui_weak,
+            mut snapshots,
+            mut remotes,
+            sensor_names,
+            group_names,
+            alerts,
+            clock_status,
+            metrics,
+            audit,
+            health,
+            flow,
+            mut preferences,
+            history_period_min,
+            interval,
+        } = self;
+
+        let mut interval = time::interval(interval);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+        let mut shown: Option<Status> = None;
+        // Force a first refresh after a restart of this task
+        snapshots.mark_changed();
+
+        loop {
+            interval.tick().await;
+            let status = Status {
+                // Show the most recent active alert, if any
+                alert_text: alerts.active().last().map(|a| a.message.clone()).unwrap_or_default(),
+                clock_sane: clock_status.is_sane(),
+                remotes_online: remotes.borrow().iter().map(|t| t.is_online()).collect(),
+                audit_version: audit.version(),
+                flow: flow.as_ref().map(|f| {
+                    let rate = format::round(Unit::LitrePerMinute, f.rate_l_min());
+                    (rate, format::round(Unit::Litre, f.volume_l() as f32))
+                }),
+                mqtt_failover: health.mqtt_failover().is_some(),
+            };
+            let new_data = snapshots.has_changed().unwrap_or(false)
+                || remotes.has_changed().unwrap_or(false)
+                || preferences.has_changed().unwrap_or(false);
+            if !new_data && shown.as_ref() == Some(&status) {
+                continue;
+            }
+            let snapshot = snapshots.borrow_and_update().clone();
+            let remote_tanks = remotes.borrow_and_update().clone();
+            let (unit, chart_hours) = {
+                let preferences = preferences.borrow_and_update();
+                (preferences.temperature_unit, preferences.chart_hours)
+            };
+            // Only the latest hours of the history are shown with a chart range
+            let chart_start = chart_hours.map_or(0.0, |hours| {
+                (CHART_WIDTH - hours as f32 * 60.0 / history_period_min as f32).max(0.0)
+            });
+            shown = Some(status.clone());
+            let audit_entries = audit.recent();
+
+            metrics.ui_update_queued();
+            let _ = slint::invoke_from_event_loop({
+                let ui_weak = ui_weak.clone();
+                let metrics = metrics.clone();
+                let sensors: Vec<SensorData> = sensor_names
+                    .iter()
+                    .zip(snapshot.temps.iter().zip(snapshot.history_paths))
+                    .zip(snapshot.chart_ranges)
+                    .zip(snapshot.forecast_paths)
+                    .enumerate()
+                    .map(|(i, (((name, (&value, path)), range), forecast))| SensorData {
+                        name: name.as_str().into(),
+                        text: temperature(unit, value).into(),
+                        valid: snapshot.valid.get(i).copied().unwrap_or(true),
+                        history_path: path.into(),
+                        forecast_path: forecast.into(),
+                        chart_width: snapshot.chart_width,
+                        chart_start,
+                        chart_min: unit.convert(range.bottom),
+                        chart_max: unit.convert(range.top),
+                    })
+                    .collect();
+                let groups: Vec<GroupData> =
+                    group_names.iter().zip(&snapshot.groups).map(|group| group_data(group, unit)).collect();
+                move || {
+                    // Models are created on the UI thread: `ModelRc` is not `Send`
+                    if let Some(ui) = ui_weak.upgrade() {
+                        let remotes: Vec<RemoteTankData> =
+                            remote_tanks.iter().map(|tank| remote_tank_data(tank, unit)).collect();
+                        let audit: Vec<AuditItem> = audit_entries
+                            .iter()
+                            .rev()
+                            .take(AUDIT_ENTRIES_SHOWN)
+                            .map(audit_item)
+                            .collect();
+                        ui.set_dashboard(DashboardState {
+                            sensors: slint::ModelRc::new(slint::VecModel::from(sensors)),
+                            groups: slint::ModelRc::new(slint::VecModel::from(groups)),
+                            energy: format::text(Unit::KilowattHour, snapshot.energy_kwh).into(),
+                            alert_text: status.alert_text.into(),
+                            clock_warning: !status.clock_sane,
+                            mqtt_failover: status.mqtt_failover,
+                            remotes: slint::ModelRc::new(slint::VecModel::from(remotes)),
+                            audit: slint::ModelRc::new(slint::VecModel::from(audit)),
+                            has_pressure: snapshot.pressure_bar.is_some(),
+                            pressure: format::text(Unit::Bar, snapshot.pressure_bar.unwrap_or_default()).into(),
+                            pressure_valid: snapshot.pressure_valid,
+                            pressure_low: snapshot.pressure_low,
+                            has_mixing: snapshot.mixing.is_some(),
+                            mixing: temperature(unit, snapshot.mixing.map_or(0.0, |m| m.value_c)).into(),
+                            mixing_setpoint: temperature(unit, snapshot.mixing.map_or(0.0, |m| m.setpoint_c)).into(),
+                            mixing_valid: snapshot.mixing.is_some_and(|m| m.valid),
+                            mixing_deviating: snapshot.mixing.is_some_and(|m| m.deviating),
+                            mixing_scalding: snapshot.mixing.is_some_and(|m| m.scalding),
+                            has_flow: status.flow.is_some(),
+                            flow_rate: status.flow.map_or(0.0, |(rate, _)| rate),
+                            flow: status
+                                .flow
+                                .map(|(rate, volume)| {
+                                    format!(
+                                        "{}  ·  {}",
+                                        format::text(Unit::LitrePerMinute, rate),
+                                        format::text(Unit::Litre, volume)
+                                    )
+                                })
+                                .unwrap_or_default()
+                                .into(),
+                        });
+                    }
+                    metrics.ui_update_applied();
+                }
+            });
+        }
+    }
+}
+
+/// Formats a temperature in °C in the unit of the display preferences.
+fn temperature(unit: TemperatureUnit, celsius: f32) -> String {
+    format::text(unit.unit(), unit.convert(celsius))
+}
+
+/// Converts the values of a sensor group to their UI representation.
+fn group_data((name, stats): (&String, &Option<GroupStats>), unit: TemperatureUnit) -> GroupData {
+    let text = |value: fn(&GroupStats) -> f32| match stats {
+        Some(stats) => temperature(unit, value(stats)).into(),
+        None => "--".into(),
+    };
+    GroupData {
+        name: name.as_str().into(),
+        min: text(|s| s.min),
+        max: text(|s| s.max),
+        average: text(|s| s.average),
+        valid: stats.is_some(),
+    }
+}
+
+/// Converts a remote tank to its UI representation.
+fn remote_tank_data(tank: &RemoteTank, unit: TemperatureUnit) -> RemoteTankData {
+    let sensors: Vec<SensorData> = tank
+        .sensors
+        .iter()
+        .map(|(name, value)| SensorData {
+            name: name.as_str().into(),
+            text: temperature(unit, *value).into(),
+            valid: true,
+            history_path: Default::default(),
+            forecast_path: Default::default(),
+            chart_width: 0.0,
+            chart_start: 0.0,
+            chart_min: 0.0,
+            chart_max: 0.0,
+        })
+        .collect();
+    RemoteTankData {
+        name: tank.name.as_str().into(),
+        energy: tank
+            .energy_kwh
+            .map(|e| format::text(Unit::KilowattHour, e))
+            .unwrap_or_else(|| "--".to_string())
+            .into(),
+        pressure: tank.pressure_bar.map(|p| format::text(Unit::Bar, p)).unwrap_or_default().into(),
+        online: tank.is_online(),
+        sensors: slint::ModelRc::new(slint::VecModel::from(sensors)),
+    }
+}
+
+/// Converts an audit log entry to its UI representation.
+fn audit_item(entry: &AuditEntry) -> AuditItem {
+    AuditItem {
+        time: entry.timestamp.with_timezone(&Local).format("%d/%m %H:%M").to_string().into(),
+        source: entry.source.as_str().into(),
+        text: format!("{}: {}", entry.action, entry.detail).into(),
+    }
+}
+
+/// Forwards the actions of the sensor mapping wizard page to the wizard task.
+/// Must be called on the UI thread.
+pub fn connect_wizard(ui: &AppWindow, commands: mpsc::Sender<WizardCommand>) {
+    ui.on_wizard_detect({
+        let commands = commands.clone();
+        move |position| {
+            let _ = commands.try_send(WizardCommand::Detect(position.max(0) as usize));
+        }
+    });
+    ui.on_wizard_cancel({
+        let commands = commands.clone();
+        move || {
+            let _ = commands.try_send(WizardCommand::Cancel);
+        }
+    });
+    ui.on_wizard_save(move || {
+        let _ = commands.try_send(WizardCommand::Save);
+    });
+}
+
+/// Pushes the view of the sensor mapping wizard to the UI whenever it changes.
+pub async fn show_wizard(ui_weak: slint::Weak<AppWindow>, mut views: watch::Receiver<WizardView>) {
+    loop {
+        let view = views.borrow_and_update().clone();
+        let _ = slint::invoke_from_event_loop({
+            let ui_weak = ui_weak.clone();
+            move || {
+                if let Some(ui) = ui_weak.upgrade() {
+                    let positions: Vec<WizardPositionData> = view
+                        .positions
+                        .iter()
+                        .map(|p| WizardPositionData {
+                            name: p.name.as_str().into(),
+                            id: p.id.as_str().into(),
+                            assigned: p.assigned,
+                        })
+                        .collect();
+                    ui.set_wizard(WizardState {
+                        positions: slint::ModelRc::new(slint::VecModel::from(positions)),
+                        active: view.active.map_or(-1, |i| i as i32),
+                        status: view.status.into(),
+                    });
+                }
+            }
+        });
+        if views.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Forwards the PIN entered on the keypad and the lock button to the display lock.
+/// Must be called on the UI thread.
+pub fn connect_lock(ui: &AppWindow, lock: &DisplayLock) {
+    ui.on_unlock({
+        let lock = lock.clone();
+        move |pin| lock.unlock(&pin)
+    });
+    ui.on_lock({
+        let lock = lock.clone();
+        move || lock.lock()
+    });
+}
+
+/// Pushes the state of the display lock to the UI whenever it changes.
+pub async fn show_lock(ui_weak: slint::Weak<AppWindow>, lock: DisplayLock) {
+    let mut views = lock.subscribe();
+    loop {
+        let view = views.borrow_and_update().clone();
+        let _ = slint::invoke_from_event_loop({
+            let ui_weak = ui_weak.clone();
+            move || {
+                if let Some(ui) = ui_weak.upgrade() {
+                    ui.set_display_lock(LockState {
+                        enabled: view.enabled,
+                        locked: view.locked,
+                        status: view.status.into(),
+                    });
+                }
+            }
+        });
+        if views.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Forwards the actions of the maintenance page to the maintenance tasks.
+/// Must be called on the UI thread.
+pub fn connect_maintenance(ui: &AppWindow, maintenance: &Maintenance) {
+    let task_name = |maintenance: &Maintenance, index: i32| {
+        maintenance.status().get(index.max(0) as usize).map(|task| task.name.clone())
+    };
+    ui.on_maintenance_done({
+        let maintenance = maintenance.clone();
+        move |index| {
+            if let Some(name) = task_name(&maintenance, index)
+                && let Err(e) = maintenance.complete(&name, AuditSource::Ui)
+            {
+                warning!("Maintenance: {:#}", e);
+            }
+        }
+    });
+    ui.on_maintenance_snooze({
+        let maintenance = maintenance.clone();
+        move |index| {
+            if let Some(name) = task_name(&maintenance, index)
+                && let Err(e) = maintenance.snooze(&name, AuditSource::Ui)
+            {
+                warning!("Maintenance: {:#}", e);
+            }
+        }
+    });
+}
+
+/// Pushes the state of the maintenance tasks to the UI whenever it changes.
+pub async fn show_maintenance(ui_weak: slint::Weak<AppWindow>, maintenance: Maintenance) {
+    let mut statuses = maintenance.subscribe();
+    loop {
+        let tasks = statuses.borrow_and_update().clone();
+        let _ = slint::invoke_from_event_loop({
+            let ui_weak = ui_weak.clone();
+            move || {
+                if let Some(ui) = ui_weak.upgrade() {
+                    let items: Vec<MaintenanceItem> = tasks.iter().map(maintenance_item).collect();
+                    ui.set_maintenance(MaintenanceState {
+                        items: slint::ModelRc::new(slint::VecModel::from(items)),
+                        due: tasks.iter().any(|task| task.due),
+                    });
+                }
+            }
+        });
+        if statuses.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Converts the state of a maintenance task to its UI representation.
+fn maintenance_item(task: &TaskStatus) -> MaintenanceItem {
+    let status = if let Some(until) = task.snoozed_until {
+        format!("Reporté au {}", until.with_timezone(&Local).format("%d/%m"))
+    } else if task.due {
+        "À faire".to_string()
+    } else {
+        match task.limit {
+            Limit::Months => format!("Dans {} j", task.days_left.unwrap_or_default()),
+            Limit::HeatingHours => {
+                let hours = task.heating_hours_left.unwrap_or_default() as f32;
+                format!("Dans {} de chauffe", format::text(Unit::Hour, hours))
+            }
+            Limit::Litres => format!("Dans {}", format::text(Unit::Litre, task.litres_left.unwrap_or_default() as f32)),
+        }
+    };
+    MaintenanceItem {
+        label: task.label.as_str().into(),
+        status: status.into(),
+        due: task.due,
+        progress: task.progress as f32,
+    }
+}
+
+/// Pushes the efficiency report to the efficiency page whenever it changes.
+pub async fn show_efficiency(ui_weak: slint::Weak<AppWindow>, efficiency: Efficiency) {
+    let mut reports = efficiency.subscribe();
+    loop {
+        let report = reports.borrow_and_update().clone();
+        let _ = slint::invoke_from_event_loop({
+            let ui_weak = ui_weak.clone();
+            move || {
+                if let Some(ui) = ui_weak.upgrade() {
+                    let details: Vec<slint::SharedString> =
+                        efficiency_details(&report).into_iter().map(Into::into).collect();
+                    let insights: Vec<slint::SharedString> =
+                        report.insights.iter().map(|insight| insight_text(insight).into()).collect();
+                    ui.set_efficiency(EfficiencyState {
+                        score: report.score.map_or("–".to_string(), |score| format!("{}/100", score)).into(),
+                        details: slint::ModelRc::new(slint::VecModel::from(details)),
+                        insights: slint::ModelRc::new(slint::VecModel::from(insights)),
+                    });
+                }
+            }
+        });
+        if reports.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Pushes the season report to the efficiency page whenever it changes.
+pub async fn show_season(ui_weak: slint::Weak<AppWindow>, seasons: Seasons) {
+    let mut reports = seasons.subscribe();
+    loop {
+        let lines: Vec<slint::SharedString> =
+            season_lines(&reports.borrow_and_update()).into_iter().map(Into::into).collect();
+        let _ = slint::invoke_from_event_loop({
+            let ui_weak = ui_weak.clone();
+            move || {
+                if let Some(ui) = ui_weak.upgrade() {
+                    ui.set_season(slint::ModelRc::new(slint::VecModel::from(lines)));
+                }
+            }
+        });
+        if reports.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Current season and its comparison with the previous one, one line each.
+fn season_lines(report: &SeasonReport) -> Vec<String> {
+    let name = match report.today {
+        SeasonKind::Heating => "Saison de chauffage",
+        SeasonKind::Summer => "Été",
+    };
+    let mut lines = Vec::new();
+    let per_day = |kwh: Option<f64>| {
+        kwh.map_or("–".to_string(), |kwh| format!("{}/jour", format::text(Unit::KilowattHour, kwh as f32)))
+    };
+    match &report.current {
+        Some(current) => lines.push(format!(
+            "{} {} : {} sur {} jours",
+            name,
+            current.season,
+            per_day(current.heated_kwh_per_day),
+            current.days
+        )),
+        None => lines.push(name.to_string()),
+    }
+    if let Some(previous) = &report.previous {
+        let change = report.heated_change_percent.map_or(String::new(), |percent| format!(" ({:+} %)", percent));
+        lines.push(format!("{} : {}{}", previous.season, per_day(previous.heated_kwh_per_day), change));
+    }
+    lines
+}
+
+/// Changes the display preferences from the display preferences page.
+pub fn connect_preferences(ui: &AppWindow, preferences: &Preferences) {
+    ui.on_set_preference({
+        let preferences = preferences.clone();
+        move |name, value| {
+            if let Err(e) = preferences.set(&name, &value, AuditSource::Ui) {
+                warning!("Display preferences: {:#}", e);
+            }
+        }
+    });
+}
+
+/// Pushes the display preferences to the UI whenever they change.
+pub async fn show_preferences(ui_weak: slint::Weak<AppWindow>, preferences: Preferences, has_backlight: bool) {
+    let mut changes = preferences.subscribe();
+    loop {
+        let shown = changes.borrow_and_update().clone();
+        let _ = slint::invoke_from_event_loop({
+            let ui_weak = ui_weak.clone();
+            move || {
+                if let Some(ui) = ui_weak.upgrade() {
+                    let brightness = &shown.brightness;
+                    ui.set_preferences(PreferencesState {
+                        light: shown.theme == Theme::Light,
+                        fahrenheit: shown.temperature_unit == TemperatureUnit::Fahrenheit,
+                        chart_hours: shown.chart_hours.map_or(0, |hours| hours as i32),
+                        day_percent: i32::from(brightness.day_percent),
+                        night_percent: i32::from(brightness.night_percent),
+                        night: format!("{} – {}", brightness.night_start, brightness.night_end).into(),
+                        has_backlight,
+                    });
+                }
+            }
+        });
+        if changes.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Parts of the efficiency score, one line each.
+fn efficiency_details(report: &EfficiencyReport) -> Vec<String> {
+    let percent = |ratio: f64| format::text(Unit::Percent, (ratio * 100.0) as f32);
+    let mut lines = Vec::new();
+    if let Some(ratio) = report.useful_ratio {
+        lines.push(format!(
+            "Eau chaude utilisée : {} ({} puisés, {} perdus)",
+            percent(ratio),
+            format::text(Unit::KilowattHour, report.drawn_kwh as f32),
+            format::text(Unit::KilowattHour, report.standby_loss_kwh as f32)
+        ));
+    }
+    if let Some(ratio) = report.peak_ratio {
+        lines.push(format!("Chauffe en heures pleines : {}", percent(ratio)));
+    }
+    let disinfection = if report.legionella_ok { "faite" } else { "non atteinte" };
+    lines.push(format!("Désinfection anti-légionelle : {}", disinfection));
+    lines
+}
+
+/// Plain-language text of an insight.
+fn insight_text(insight: &Insight) -> String {
+    match insight {
+        Insight::StandbyLossChange { percent } if *percent > 0 => {
+            format!("Les pertes en veille ont augmenté de {} % depuis le mois dernier", percent)
+        }
+        Insight::StandbyLossChange { percent } => {
+            format!("Les pertes en veille ont baissé de {} % depuis le mois dernier", -percent)
+        }
+        Insight::LossesAboveDraws { loss_percent } => {
+            format!(
+                "{} % de l'énergie est perdue en veille : le ballon est peut-être surdimensionné ou trop chaud",
+                loss_percent
+            )
+        }
+        Insight::PeakHeating { percent } => {
+            format!("{} % de la chauffe a lieu en heures pleines : décaler la chauffe réduirait la facture", percent)
+        }
+        Insight::NoDisinfection { temp_c, minutes } => format!(
+            "Le haut du ballon n'a pas tenu {} pendant {} min cette semaine (risque de légionelle)",
+            format::text(Unit::Celsius, *temp_c),
+            minutes
+        ),
+    }
+}
+
+/// Forwards the burst sampling actions of the diagnostics page to the sampler.
+/// Must be called on the UI thread.
+pub fn connect_burst(ui: &AppWindow, bursts: &Bursts) {
+    ui.on_burst_start({
+        let bursts = bursts.clone();
+        move |sensor, minutes| {
+            let Some(name) = bursts.sensor_names().get(sensor.max(0) as usize).cloned() else {
+                return;
+            };
+            if let Err(e) = bursts.start(&name, minutes.max(0) as u32, AuditSource::Ui) {
+                warning!("Burst: {:#}", e);
+            }
+        }
+    });
+    ui.on_burst_stop({
+        let bursts = bursts.clone();
+        move || {
+            bursts.stop(AuditSource::Ui);
+        }
+    });
+}
+
+/// Pushes the state of the burst sampler to the diagnostics page whenever it changes.
+pub async fn show_burst(ui_weak: slint::Weak<AppWindow>, bursts: Bursts) {
+    let sensor_names = bursts.sensor_names();
+    let max_minutes = bursts.max_minutes() as i32;
+    let mut statuses = bursts.subscribe();
+    loop {
+        let status = statuses.borrow_and_update().clone();
+        let text = status.as_ref().map(burst_text).unwrap_or_default();
+        let active = status.is_some_and(|s| s.active);
+        let _ = slint::invoke_from_event_loop({
+            let ui_weak = ui_weak.clone();
+            let sensor_names = sensor_names.clone();
+            move || {
+                if let Some(ui) = ui_weak.upgrade() {
+                    let sensors: Vec<slint::SharedString> = sensor_names.iter().map(|n| n.as_str().into()).collect();
+                    ui.set_burst(BurstState {
+                        sensors: slint::ModelRc::new(slint::VecModel::from(sensors)),
+                        max_minutes,
+                        active,
+                        status: text.into(),
+                    });
+                }
+            }
+        });
+        if statuses.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Forwards the USB export button of the diagnostics page to the exporter.
+/// Must be called on the UI thread.
+pub fn connect_usb_export(ui: &AppWindow, usb_export: &UsbExport) {
+    ui.on_usb_export({
+        let usb_export = usb_export.clone();
+        move || {
+            if let Err(e) = usb_export.start(AuditSource::Ui) {
+                warning!("USB export: {:#}", e);
+            }
+        }
+    });
+}
+
+/// Pushes the state of the USB stick to the diagnostics page whenever it changes.
+pub async fn show_usb_export(ui_weak: slint::Weak<AppWindow>, usb_export: UsbExport) {
+    let mut statuses = usb_export.subscribe();
+    loop {
+        let status = statuses.borrow_and_update().clone();
+        let _ = slint::invoke_from_event_loop({
+            let ui_weak = ui_weak.clone();
+            move || {
+                if let Some(ui) = ui_weak.upgrade() {
+                    ui.set_usb_stick(UsbExportState {
+                        detected: status.mount.is_some(),
+                        busy: status.busy,
+                        status: usb_text(&status).into(),
+                    });
+                }
+            }
+        });
+        if statuses.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Status line of the USB stick on the diagnostics page.
+fn usb_text(status: &UsbStatus) -> String {
+    if status.mount.is_none() {
+        String::new()
+    } else if status.busy {
+        "Export en cours, ne pas retirer la clé".to_string()
+    } else if let Some(error) = &status.error {
+        format!("Échec de l'export : {}", error)
+    } else if let Some(directory) = &status.exported {
+        format!("Exporté dans {}, la clé peut être retirée", directory)
+    } else {
+        "Clé USB détectée".to_string()
+    }
+}
+
+/// Status line of a burst on the diagnostics page.
+fn burst_text(status: &BurstStatus) -> String {
+    if status.active {
+        format!(
+            "Capture {} : {} mesures, jusqu'à {}",
+            status.sensor,
+            status.samples,
+            status.until.with_timezone(&Local).format("%H:%M")
+        )
+    } else {
+        format!("Capture {} terminée : {} mesures, {} erreurs", status.sensor, status.samples, status.errors)
+    }
+}
+
+/// Filters of the log viewer page, shared by its callbacks and [`show_logs`].
+#[derive(Clone)]
+pub struct LogViewer {
+    view: Arc<Mutex<LogView>>,
+    changed: Arc<Notify>,
+}
+
+struct LogView {
+    filter: LogFilter,
+    following: bool,
+    /// Modules in the order they were first seen, so that the index chosen on the page
+    /// keeps naming the same module.
+    modules: Vec<&'static str>,
+}
+
+impl LogViewer {
+    /// Viewer showing every line kept, following the new ones.
+    pub fn new() -> Self {
+        let view = LogView {
+            filter: LogFilter {
+                level: LogLevel::Debug,
+                ..Default::default()
+            },
+            following: true,
+            modules: Vec::new(),
+        };
+        Self {
+            view: Arc::new(Mutex::new(view)),
+            changed: Arc::new(Notify::new()),
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut LogView)) {
+        change(&mut self.view.lock().unwrap_or_else(|e| e.into_inner()));
+        self.changed.notify_one();
+    }
+}
+
+/// Forwards the filters, the search keyboard and the pause button of the log viewer page.
+/// Must be called on the UI thread.
+pub fn connect_logs(ui: &AppWindow, viewer: &LogViewer) {
+    ui.on_log_filter({
+        let viewer = viewer.clone();
+        move |level, module| {
+            viewer.update(|view| {
+                view.filter.level = match level {
+                    0 => LogLevel::Warn,
+                    1 => LogLevel::Info,
+                    _ => LogLevel::Debug,
+                };
+                // The first choice is all the modules
+                let module = usize::try_from(module - 1).ok().and_then(|i| view.modules.get(i));
+                view.filter.module = module.map(|module| module.to_string());
+            });
+        }
+    });
+    ui.on_log_key({
+        let viewer = viewer.clone();
+        move |text| viewer.update(|view| view.filter.search.push_str(&text))
+    });
+    ui.on_log_backspace({
+        let viewer = viewer.clone();
+        move || {
+            viewer.update(|view| {
+                view.filter.search.pop();
+            })
+        }
+    });
+    ui.on_log_follow({
+        let viewer = viewer.clone();
+        move |following| viewer.update(|view| view.following = following)
+    });
+}
+
+/// Pushes the lines matching the filters to the log viewer page as they are written, and
+/// whenever the filters change. While paused, only a change of the filters refreshes them.
+pub async fn show_logs(ui_weak: slint::Weak<AppWindow>, viewer: LogViewer) {
+    let mut interval = time::interval(Duration::from_secs(1));
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+    let mut shown: Option<(u64, LogFilter, bool)> = None;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = viewer.changed.notified() => {}
+        }
+        let written = logging::written();
+        let (filter, following, modules) = {
+            let mut view = viewer.view.lock().unwrap_or_else(|e| e.into_inner());
+            for module in logging::modules() {
+                if !view.modules.contains(&module) {
+                    view.modules.push(module);
+                }
+            }
+            (view.filter.clone(), view.following, view.modules.clone())
+        };
+        if let Some((shown_written, shown_filter, shown_following)) = &shown
+            && *shown_filter == filter
+            && *shown_following == following
+            && (!following || *shown_written == written)
+        {
+            continue;
+        }
+        shown = Some((written, filter.clone(), following));
+
+        let lines: Vec<LogItem> = logging::recent(&filter).iter().rev().map(log_item).collect();
+        let count = format!("{} lignes", lines.len());
+        let level = match filter.level {
+            LogLevel::Warn => 0,
+            LogLevel::Info => 1,
+            LogLevel::Debug => 2,
+        };
+        let module = filter
+            .module
+            .as_deref()
+            .and_then(|selected| modules.iter().position(|&module| module == selected))
+            .map_or(0, |i| i as i32 + 1);
+        let modules: Vec<slint::SharedString> =
+            std::iter::once("Tous les modules").chain(modules).map(Into::into).collect();
+        let _ = slint::invoke_from_event_loop({
+            let ui_weak = ui_weak.clone();
+            move || {
+                if let Some(ui) = ui_weak.upgrade() {
+                    ui.set_logs(LogState {
+                        lines: slint::ModelRc::new(slint::VecModel::from(lines)),
+                        modules: slint::ModelRc::new(slint::VecModel::from(modules)),
+                        count: count.into(),
+                        level,
+                        module,
+                        search: filter.search.as_str().into(),
+                        following,
+                    });
+                }
+            }
+        });
+    }
+}
+
+/// Converts a line of the log to its UI representation.
+fn log_item(line: &LogLine) -> LogItem {
+    LogItem {
+        time: line.timestamp.with_timezone(&Local).format("%H:%M:%S").to_string().into(),
+        level: line.level.as_str().into(),
+        module: line.module.into(),
+        text: line.text.as_str().into(),
+    }
+}
@@ -0,0 +1,265 @@
+//! Self-update of the boilert binary (requires the `update` feature).
+//!
+//! A release is looked up from a JSON manifest or from GitHub releases, its
+//! minisign signature is verified, and the binary is swapped in place. The
+//! previous binary is kept next to it: if the new version does not reach a
+//! healthy state within a few boots, the previous one is restored.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::config::UpdateConfig;
+use crate::health::Health;
+use crate::logging::{info, warning};
+
+/// Number of boots a new version gets to become healthy before rollback.
+const MAX_BOOT_ATTEMPTS: u32 = 3;
+/// Delay after startup before a new version can be confirmed as good.
+const CONFIRM_AFTER: Duration = Duration::from_secs(120);
+/// Upper bound for a downloaded binary.
+const MAX_DOWNLOAD_BYTES: u64 = 64 * 1024 * 1024;
+
+/// A release available for download.
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    /// Version of the release (e.g., "1.1.0").
+    pub version: String,
+    /// URL of the binary.
+    pub url: String,
+    /// URL of the minisign signature (defaults to `{url}.minisig`).
+    pub signature_url: Option<String>,
+}
+
+/// Marker written next to the binary while a new version is on probation.
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingUpdate {
+    version: String,
+    boot_attempts: u32,
+}
+
+#[derive(Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    assets: Vec<GithubAsset>,
+}
+
+#[derive(Deserialize)]
+struct GithubAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Looks up the latest release from the configured source.
+pub fn latest_release(config: &UpdateConfig) -> Result<Release> {
+    if let Some(url) = &config.url {
+        return http_get(url)?
+            .into_json()
+            .with_context(|| format!("Invalid release manifest at {}", url));
+    }
+    let Some(repo) = &config.github else {
+        bail!("No update source configured: set [update] url or github");
+    };
+    let url = format!("https://api.github.com/repos/{}/releases/latest", repo);
+    let release: GithubRelease = http_get(&url)?.into_json().context("Invalid GitHub release")?;
+
+    // Binaries are published per architecture, e.g. "boilert-aarch64"
+    let binary_name = format!("boilert-{}", std::env::consts::ARCH);
+    let signature_name = format!("{}.minisig", binary_name);
+    let find = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.browser_download_url.clone())
+    };
+    let url = find(&binary_name)
+        .with_context(|| format!("Release {} has no {} asset", release.tag_name, binary_name))?;
+    Ok(Release {
+        version: release.tag_name.trim_start_matches('v').to_string(),
+        url,
+        signature_url: find(&signature_name),
+    })
+}
+
+/// Returns `true` if `version` is newer than the running one.
+pub fn is_newer(version: &str) -> Result<bool> {
+    let candidate = semver::Version::parse(version)
+        .with_context(|| format!("Invalid release version {}", version))?;
+    let current = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
+    Ok(candidate > current)
+}
+
+/// Downloads and verifies `release`, then installs it in place of the running binary.
+pub fn install(config: &UpdateConfig, release: &Release) -> Result<()> {
+    let Some(public_key) = &config.public_key else {
+        bail!("No [update] public_key configured: refusing to install an unverified binary");
+    };
+    let public_key = minisign_verify::PublicKey::from_base64(public_key)
+        .map_err(|e| anyhow::anyhow!("Invalid update public key: {}", e))?;
+
+    let binary = download(&release.url)?;
+    let signature_url = release
+        .signature_url
+        .clone()
+        .unwrap_or_else(|| format!("{}.minisig", release.url));
+    let signature = String::from_utf8(download(&signature_url)?).context("Invalid signature file")?;
+    let signature = minisign_verify::Signature::decode(&signature)
+        .map_err(|e| anyhow::anyhow!("Invalid signature: {}", e))?;
+    public_key
+        .verify(&binary, &signature, false)
+        .map_err(|e| anyhow::anyhow!("Signature verification failed: {}", e))?;
+    println!("Signature verified for {} ({} bytes)", release.version, binary.len());
+
+    let exe = std::env::current_exe().context("Failed to locate the boilert executable")?;
+    swap_binary(&exe, &binary)?;
+    let pending = PendingUpdate {
+        version: release.version.clone(),
+        boot_attempts: 0,
+    };
+    crate::state::write_atomic(&pending_path(&exe), &serde_json::to_vec(&pending)?)?;
+    println!("Installed boilert {} at {}", release.version, exe.display());
+    Ok(())
+}
+
+/// Restarts the service so that the new binary runs.
+pub fn restart_service(config: &UpdateConfig) -> Result<()> {
+    let status = std::process::Command::new("systemctl")
+        .args(["restart", &config.service])
+        .status()
+        .context("Failed to run systemctl")?;
+    if !status.success() {
+        bail!("systemctl restart {} failed ({})", config.service, status);
+    }
+    Ok(())
+}
+
+/// Called at startup: counts boot attempts of a freshly installed version and
+/// restores the previous binary when it keeps failing.
+///
+/// Returns `true` if a rollback happened, in which case the process should
+/// exit so that systemd restarts the previous version.
+pub fn check_pending_on_boot() -> bool {
+    let Ok(exe) = std::env::current_exe() else {
+        return false;
+    };
+    let path = pending_path(&exe);
+    let Ok(content) = std::fs::read(&path) else {
+        return false;
+    };
+    let mut pending: PendingUpdate = match serde_json::from_slice(&content) {
+        Ok(pending) => pending,
+        Err(e) => {
+            warning!("Ignoring invalid update marker {}: {}", path.display(), e);
+            let _ = std::fs::remove_file(&path);
+            return false;
+        }
+    };
+
+    pending.boot_attempts += 1;
+    if pending.boot_attempts <= MAX_BOOT_ATTEMPTS {
+        info!(
+            "Running boilert {} on probation (boot {}/{})",
+            pending.version, pending.boot_attempts, MAX_BOOT_ATTEMPTS
+        );
+        if let Err(e) = serde_json::to_vec(&pending)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| crate::state::write_atomic(&path, &bytes))
+        {
+            warning!("Failed to update {}: {:#}", path.display(), e);
+        }
+        return false;
+    }
+
+    warning!(
+        "boilert {} failed to start {} times, rolling back",
+        pending.version, MAX_BOOT_ATTEMPTS
+    );
+    match std::fs::rename(previous_path(&exe), &exe) {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&path);
+            true
+        }
+        Err(e) => {
+            warning!("Rollback failed: {}", e);
+            false
+        }
+    }
+}
+
+/// Confirms a freshly installed version once the application has been healthy
+/// for a while, ending its probation.
+pub async fn confirm_when_healthy(health: Health) {
+    let Ok(exe) = std::env::current_exe() else {
+        return;
+    };
+    let path = pending_path(&exe);
+    if !path.exists() {
+        return;
+    }
+    tokio::time::sleep(CONFIRM_AFTER).await;
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        if health.is_alive() {
+            match std::fs::remove_file(&path) {
+                Ok(()) => info!("Update confirmed, boilert {} is healthy", env!("CARGO_PKG_VERSION")),
+                Err(e) => warning!("Failed to remove {}: {}", path.display(), e),
+            }
+            return;
+        }
+    }
+}
+
+/// Replaces `exe` by `binary`, keeping the current binary as `{exe}.previous`.
+fn swap_binary(exe: &Path, binary: &[u8]) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let new_path = sibling(exe, "new");
+    crate::state::write_atomic(&new_path, binary)
+        .with_context(|| format!("Failed to write {} (are you root? try sudo)", new_path.display()))?;
+    std::fs::set_permissions(&new_path, std::fs::Permissions::from_mode(0o755))?;
+
+    std::fs::rename(exe, previous_path(exe)).context("Failed to keep the previous binary")?;
+    if let Err(e) = std::fs::rename(&new_path, exe) {
+        let _ = std::fs::rename(previous_path(exe), exe);
+        return Err(e).context("Failed to install the new binary");
+    }
+    Ok(())
+}
+
+fn http_get(url: &str) -> Result<ureq::Response> {
+    ureq::get(url)
+        .set("User-Agent", concat!("boilert/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(60))
+        .call()
+        .with_context(|| format!("Request to {} failed", url))
+}
+
+fn download(url: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    http_get(url)?
+        .into_reader()
+        .take(MAX_DOWNLOAD_BYTES)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to download {}", url))?;
+    Ok(bytes)
+}
+
+fn sibling(exe: &Path, suffix: &str) -> PathBuf {
+    let mut name = exe.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn previous_path(exe: &Path) -> PathBuf {
+    sibling(exe, "previous")
+}
+
+fn pending_path(exe: &Path) -> PathBuf {
+    sibling(exe, "pending")
+}
@@ -0,0 +1,277 @@
+//! Export to a USB stick, for service technicians without network access to the device.
+//! The mount points of removable drives are watched: once a stick is mounted (by the
+//! desktop automounter or `usbmount`), the diagnostics page offers to copy the alert
+//! history, the audit log, the recent sensor history and the season totals to it as CSV
+//! files.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use boilert::model::{AuditEntry, AuditSource};
+use chrono::{Local, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, watch};
+
+use crate::alerts::{AlertEvent, Alerts};
+use crate::audit::{self, AuditLog};
+use crate::format::{self, Unit};
+use crate::history::{HistoryPoint, SensorHistory};
+use crate::logging::{info, warning};
+use crate::season::{SeasonTotals, Seasons};
+
+/// Interval between two checks of the mounted drives.
+const DETECT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// USB export settings from the `[usb_export]` section.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct UsbExportConfig {
+    /// Directories under which removable drives are mounted.
+    pub mount_roots: Vec<String>,
+    /// Hours of sensor history exported, up to the whole history.
+    pub history_hours: f32,
+}
+
+impl Default for UsbExportConfig {
+    fn default() -> Self {
+        Self {
+            mount_roots: vec!["/media".to_string(), "/run/media".to_string(), "/mnt".to_string()],
+            history_hours: 168.0,
+        }
+    }
+}
+
+impl UsbExportConfig {
+    /// Checks the exported duration.
+    pub fn validate(&self) -> Result<()> {
+        if !self.history_hours.is_finite() || self.history_hours <= 0.0 {
+            bail!("[usb_export] history_hours must be positive");
+        }
+        Ok(())
+    }
+}
+
+/// State of the USB stick and of the last export.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsbStatus {
+    /// Mount point of the detected stick.
+    pub mount: Option<String>,
+    /// `true` while an export is running.
+    pub busy: bool,
+    /// Directory written by the last export to the current stick.
+    pub exported: Option<String>,
+    /// Why the last export to the current stick failed.
+    pub error: Option<String>,
+}
+
+/// Shared USB exporter.
+///
+/// Cloning a `UsbExport` handle is cheap; all clones share the same state.
+#[derive(Clone)]
+pub struct UsbExport {
+    config: UsbExportConfig,
+    alerts: Alerts,
+    audit: AuditLog,
+    /// Names of the histories, in order.
+    names: Vec<String>,
+    history: Arc<Mutex<Vec<SensorHistory>>>,
+    seasons: Seasons,
+    status: watch::Sender<UsbStatus>,
+}
+
+impl UsbExport {
+    pub fn new(
+        config: &UsbExportConfig,
+        alerts: Alerts,
+        audit: AuditLog,
+        names: Vec<String>,
+        history: Arc<Mutex<Vec<SensorHistory>>>,
+        seasons: Seasons,
+    ) -> Self {
+        Self {
+            config: config.clone(),
+            alerts,
+            audit,
+            names,
+            history,
+            seasons,
+            status: watch::channel(UsbStatus::default()).0,
+        }
+    }
+
+    /// Follows the state of the stick and of the exports.
+    pub fn subscribe(&self) -> watch::Receiver<UsbStatus> {
+        self.status.subscribe()
+    }
+
+    /// Starts exporting to the detected stick.
+    ///
+    /// # Errors
+    /// Returns an error if no stick is detected or an export is already running.
+    pub fn start(&self, source: AuditSource) -> Result<()> {
+        let mut mount = None;
+        // Check and claim in one step, a second tap must not start another export
+        self.status.send_if_modified(|status| {
+            if status.busy || status.mount.is_none() {
+                return false;
+            }
+            status.busy = true;
+            mount = status.mount.clone();
+            true
+        });
+        let Some(mount) = mount else {
+            bail!("No USB stick detected or export already running");
+        };
+        self.audit.record(source, "usb export", mount.as_str());
+        tokio::spawn(self.clone().export(PathBuf::from(mount)));
+        Ok(())
+    }
+
+    /// Writes the files to a new directory on the stick, then reports the result.
+    async fn export(self, mount: PathBuf) {
+        let since = Utc::now() - chrono::Duration::seconds((self.config.history_hours * 3600.0) as i64);
+        let history: Vec<(String, Vec<HistoryPoint>)> = {
+            let history = self.history.lock().await;
+            self.names
+                .iter()
+                .zip(history.iter())
+                .map(|(name, h)| (name.clone(), h.points().iter().filter(|p| p.timestamp >= since).copied().collect()))
+                .collect()
+        };
+        let alerts = self.alerts.history();
+        let seasons = self.seasons.totals();
+        let audit = self.audit.clone();
+        let directory = mount.join(format!("boilert-{}", Local::now().format("%Y%m%d-%H%M%S")));
+        let result = tokio::task::spawn_blocking({
+            let directory = directory.clone();
+            move || write_export(&directory, &alerts, &audit.all(), &history, &seasons)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+        match &result {
+            Ok(()) => info!("Exported to {}", directory.display()),
+            Err(e) => warning!("USB export failed: {:#}", e),
+        }
+        self.status.send_modify(|status| {
+            status.busy = false;
+            // The stick may have been swapped during the export
+            if status.mount.as_deref() == mount.to_str() {
+                status.exported = result.as_ref().ok().map(|()| directory.display().to_string());
+                status.error = result.as_ref().err().map(|e| format!("{:#}", e));
+            }
+        });
+    }
+
+    /// Checks the mounted drives every couple of seconds and tracks the stick.
+    pub async fn detect_loop(self) {
+        let mut ticker = tokio::time::interval(DETECT_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let mount = std::fs::read_to_string("/proc/mounts")
+                .ok()
+                .and_then(|mounts| removable_mount(&mounts, &self.config.mount_roots));
+            self.status.send_if_modified(|status| {
+                if status.mount == mount {
+                    return false;
+                }
+                match &mount {
+                    Some(mount) => info!("USB stick mounted on {}", mount),
+                    None => info!("USB stick removed"),
+                }
+                status.mount = mount.clone();
+                status.exported = None;
+                status.error = None;
+                true
+            });
+        }
+    }
+}
+
+/// First writable USB drive of `/proc/mounts` mounted under one of `roots`.
+fn removable_mount(mounts: &str, roots: &[String]) -> Option<String> {
+    mounts.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let (device, mount, _, options) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+        // Spaces and tabs in mount points are escaped in octal
+        let mount = mount.replace("\\040", " ").replace("\\011", "\t");
+        let path = Path::new(&mount);
+        let under_root = roots.iter().any(|root| path.starts_with(root) && path != Path::new(root));
+        (device.starts_with("/dev/sd") && under_root && options.split(',').any(|o| o == "rw")).then_some(mount)
+    })
+}
+
+/// Writes `alerts.csv`, `audit.csv`, `history.csv` and `seasons.csv` to `directory`,
+/// flushed to the stick.
+fn write_export(
+    directory: &Path,
+    alerts: &[AlertEvent],
+    audit: &[AuditEntry],
+    history: &[(String, Vec<HistoryPoint>)],
+    seasons: &[SeasonTotals],
+) -> Result<()> {
+    std::fs::create_dir(directory).with_context(|| format!("Failed to create {}", directory.display()))?;
+    write_file(directory, "alerts.csv", |out| {
+        writeln!(out, "timestamp,alert,state,message")?;
+        for event in alerts {
+            let state = if event.raised { "raised" } else { "cleared" };
+            let message = audit::csv_field(&event.message);
+            writeln!(out, "{},{},{},{}", event.timestamp.to_rfc3339(), event.id, state, message)?;
+        }
+        Ok(())
+    })?;
+    write_file(directory, "audit.csv", |out| audit::write_csv(out, audit))?;
+    write_file(directory, "history.csv", |out| {
+        writeln!(out, "timestamp,sensor,value")?;
+        for (name, points) in history {
+            for point in points {
+                let value = format::data(Unit::Celsius, point.value);
+                writeln!(out, "{},{},{}", point.timestamp.to_rfc3339(), audit::csv_field(name), value)?;
+            }
+        }
+        Ok(())
+    })?;
+    write_file(directory, "seasons.csv", |out| {
+        writeln!(out, "season,kind,first_day,last_day,days,heated_kwh,drawn_kwh,standby_loss_kwh,outdoor_mean_c")?;
+        for season in seasons {
+            let kwh = |value: f64| format::data(Unit::KilowattHour, value as f32);
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{}",
+                season.label(),
+                season.kind.as_str(),
+                season.first_day,
+                season.last_day,
+                season.days,
+                kwh(season.heated_kwh),
+                kwh(season.drawn_kwh),
+                kwh(season.standby_loss_kwh),
+                season.outdoor_mean_c().map_or(String::new(), |mean| format::data(Unit::Celsius, mean as f32))
+            )?;
+        }
+        Ok(())
+    })?;
+    // Persist the directory entries too, the stick may be pulled right away
+    File::open(directory)?.sync_all()?;
+    Ok(())
+}
+
+/// Creates `name` in `directory` and writes it with `write`, waiting for the data to
+/// reach the drive.
+fn write_file(
+    directory: &Path,
+    name: &str,
+    write: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+) -> Result<()> {
+    let path = directory.join(name);
+    let file = File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    write(&mut out)
+        .and_then(|()| out.flush())
+        .and_then(|()| out.get_ref().sync_all())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,120 @@
+//! Heat stored in the water of the tank.
+//! The heat a liter of water holds per kelvin is not quite constant: it drops by about 2.5 %
+//! from cold water to 80 °C, as the water expands. `[boiler] energy_coefficient` is either
+//! one value for every temperature, one value per temperature range, or `"water"` to use
+//! the density and specific heat of water at each temperature.
+
+use anyhow::{Result, bail};
+use serde::Deserialize;
+
+/// Density (kg/l) and specific heat (Wh/kg·K) of water at atmospheric pressure, every 10 °C.
+const WATER_PROPERTIES: [(f32, f32, f32); 11] = [
+    (0.0, 0.99984, 1.17156),
+    (10.0, 0.99970, 1.16447),
+    (20.0, 0.99821, 1.16161),
+    (30.0, 0.99565, 1.16067),
+    (40.0, 0.99222, 1.16069),
+    (50.0, 0.98804, 1.16128),
+    (60.0, 0.98320, 1.16231),
+    (70.0, 0.97776, 1.16375),
+    (80.0, 0.97179, 1.16564),
+    (90.0, 0.96531, 1.16806),
+    (100.0, 0.95835, 1.17108),
+];
+
+/// Step of the integration of the specific heat, in K.
+const INTEGRATION_STEP_K: f32 = 0.5;
+
+/// Heat stored per liter and per kelvin, from `[boiler] energy_coefficient`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum EnergyCoefficient {
+    /// Same coefficient at every temperature, in Wh/l·K.
+    Constant(f32),
+    /// `"water"`: from the properties of water at each temperature.
+    Table(PropertyTable),
+    /// Coefficient per temperature range, in increasing `up_to_c` order.
+    Ranges(Vec<CoefficientRange>),
+}
+
+/// Property table selected by name.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PropertyTable {
+    Water,
+}
+
+/// Coefficient of a temperature range, from the upper bound of the previous range (or
+/// any lower temperature) to `up_to_c`. The last range also covers the temperatures above.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct CoefficientRange {
+    pub up_to_c: f32,
+    /// Wh/l·K.
+    pub value: f32,
+}
+
+impl EnergyCoefficient {
+    /// Checks that the coefficients are positive and the ranges in order.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            EnergyCoefficient::Constant(value) if !value.is_finite() || *value <= 0.0 => {
+                bail!("[boiler] energy_coefficient must be positive");
+            }
+            EnergyCoefficient::Ranges(ranges) => {
+                if ranges.is_empty() {
+                    bail!("[boiler] energy_coefficient needs at least one range");
+                }
+                if ranges.iter().any(|range| !range.value.is_finite() || range.value <= 0.0) {
+                    bail!("[boiler] energy_coefficient values must be positive");
+                }
+                if ranges.windows(2).any(|pair| pair[0].up_to_c >= pair[1].up_to_c) {
+                    bail!("[boiler] energy_coefficient ranges must be in increasing up_to_c order");
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Heat held by a liter of water at `temp_c` above the same water at
+    /// `reference_temp_c`, in Wh; 0 below the reference.
+    pub fn wh_per_l(&self, reference_temp_c: f32, temp_c: f32) -> f32 {
+        if temp_c <= reference_temp_c {
+            return 0.0;
+        }
+        match self {
+            EnergyCoefficient::Constant(value) => value * (temp_c - reference_temp_c),
+            EnergyCoefficient::Ranges(ranges) => {
+                let mut low = reference_temp_c;
+                let mut wh = 0.0;
+                for (i, range) in ranges.iter().enumerate() {
+                    let high = if i + 1 == ranges.len() { temp_c } else { range.up_to_c.min(temp_c) };
+                    if high > low {
+                        wh += range.value * (high - low);
+                        low = high;
+                    }
+                }
+                wh
+            }
+            // The mass of the liter at its temperature, heated from the reference
+            EnergyCoefficient::Table(PropertyTable::Water) => {
+                let steps = ((temp_c - reference_temp_c) / INTEGRATION_STEP_K).ceil().max(1.0);
+                let step = (temp_c - reference_temp_c) / steps;
+                let specific_heat = (0..steps as u32)
+                    .map(|i| water_property(reference_temp_c + (i as f32 + 0.5) * step, |(_, _, cp)| cp))
+                    .sum::<f32>()
+                    * step;
+                water_property(temp_c, |(_, density, _)| density) * specific_heat
+            }
+        }
+    }
+}
+
+/// Property of water at `temp_c`, interpolated in [`WATER_PROPERTIES`] (clamped to 0-100 °C).
+fn water_property(temp_c: f32, property: impl Fn((f32, f32, f32)) -> f32) -> f32 {
+    let temp_c = temp_c.clamp(0.0, 100.0);
+    let upper = WATER_PROPERTIES.iter().position(|&(t, _, _)| t >= temp_c).unwrap_or(0).max(1);
+    let (low, high) = (WATER_PROPERTIES[upper - 1], WATER_PROPERTIES[upper]);
+    let fraction = (temp_c - low.0) / (high.0 - low.0);
+    property(low) + (property(high) - property(low)) * fraction
+}
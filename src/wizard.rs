@@ -0,0 +1,259 @@
+//! Sensor mapping wizard.
+//! Guides the assignment of the 1-Wire probes to their positions: the user selects a
+//! position and warms its probe (finger, warm water); the wizard finds the device whose
+//! temperature rises and assigns its ID to the position. The mapping is then written
+//! to `config.toml`.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use boilert::model::AuditSource;
+use tokio::sync::{mpsc, watch};
+
+use crate::audit::AuditLog;
+use crate::board::Board;
+use crate::config::Config;
+use crate::logging::warning;
+use crate::sensors::{self, SensorBackend, TemperatureSensor, W1Sensor};
+
+/// Interval between two reads of the probes while waiting for a rise.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Rise over the starting temperature that identifies the warmed probe, in °C.
+const RISE_THRESHOLD_C: f32 = 1.0;
+/// Detection is abandoned after this long without a rise.
+const DETECTION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Action requested from the UI.
+#[derive(Debug, Clone, Copy)]
+pub enum WizardCommand {
+    /// Start detecting the probe of the position at this index.
+    Detect(usize),
+    /// Stop the detection in progress.
+    Cancel,
+    /// Write the assignments to `config.toml`.
+    Save,
+}
+
+/// A sensor position and the probe assigned to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WizardPosition {
+    pub name: String,
+    pub id: String,
+    /// `true` when `id` was detected by the wizard and is not saved yet.
+    pub assigned: bool,
+}
+
+/// What the wizard page shows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WizardView {
+    pub positions: Vec<WizardPosition>,
+    /// Position whose probe is being detected.
+    pub active: Option<usize>,
+    /// Instructions or outcome of the last action.
+    pub status: String,
+}
+
+/// Detection in progress.
+struct Detection {
+    position: usize,
+    /// Temperature of each probe on the bus when the detection started.
+    baseline: Vec<(String, f32)>,
+    started_at: Instant,
+}
+
+/// The wizard task: handles the UI commands and publishes the view.
+pub struct MappingWizard {
+    pub board: Board,
+    pub config_path: PathBuf,
+    pub commands: mpsc::Receiver<WizardCommand>,
+    pub view: watch::Sender<WizardView>,
+    pub audit: AuditLog,
+}
+
+impl MappingWizard {
+    /// Lists the 1-Wire positions of `config` for the initial view.
+    pub fn initial_view(config: &Config, board: &Board) -> WizardView {
+        let positions: Vec<WizardPosition> = config
+            .sensors
+            .iter()
+            .filter(|s| sensors::backend(s, board) == SensorBackend::W1)
+            .map(|s| WizardPosition {
+                name: s.name.clone(),
+                id: s.id.clone(),
+                assigned: false,
+            })
+            .collect();
+        let status = if positions.is_empty() {
+            "Aucun capteur 1-Wire configuré"
+        } else {
+            "Choisissez une position, puis chauffez sa sonde"
+        };
+        WizardView {
+            positions,
+            active: None,
+            status: status.to_string(),
+        }
+    }
+
+    /// Handles the UI commands until the UI goes away.
+    pub async fn run(mut self) {
+        let mut detection: Option<Detection> = None;
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                command = self.commands.recv() => match command {
+                    Some(WizardCommand::Detect(position)) => detection = self.start(position).await,
+                    Some(WizardCommand::Cancel) => {
+                        detection = None;
+                        self.update(None, "Détection annulée");
+                    }
+                    Some(WizardCommand::Save) => {
+                        detection = None;
+                        self.save();
+                    }
+                    None => return,
+                },
+                _ = ticker.tick(), if detection.is_some() => {
+                    if let Some(current) = detection.take() {
+                        detection = self.poll(current).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the starting temperature of every probe on the bus.
+    async fn start(&self, position: usize) -> Option<Detection> {
+        let name = self.view.borrow().positions.get(position)?.name.clone();
+        let baseline = read_all(self.board.clone()).await;
+        if baseline.is_empty() {
+            self.update(None, "Aucun capteur détecté sur le bus 1-Wire");
+            return None;
+        }
+        self.update(Some(position), &format!("Chauffez la sonde de {}...", name));
+        Some(Detection {
+            position,
+            baseline,
+            started_at: Instant::now(),
+        })
+    }
+
+    /// Looks for the probe that warmed up; returns the detection if it goes on.
+    async fn poll(&self, detection: Detection) -> Option<Detection> {
+        let readings = read_all(self.board.clone()).await;
+        let warmest = readings
+            .iter()
+            .filter_map(|(id, temp)| {
+                let (_, start) = detection.baseline.iter().find(|(b, _)| b == id)?;
+                Some((id, temp - start))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match warmest {
+            Some((id, rise)) if rise >= RISE_THRESHOLD_C => {
+                self.assign(detection.position, id);
+                None
+            }
+            _ if detection.started_at.elapsed() >= DETECTION_TIMEOUT => {
+                self.update(None, "Aucune sonde ne s'est réchauffée, réessayez");
+                None
+            }
+            _ => Some(detection),
+        }
+    }
+
+    /// Assigns `id` to the position, swapping IDs with the position that had it.
+    fn assign(&self, position: usize, id: &str) {
+        self.view.send_modify(|view| {
+            let previous = view.positions[position].id.clone();
+            if let Some(other) = view.positions.iter_mut().find(|p| p.id == id) {
+                other.id = previous;
+                other.assigned = true;
+            }
+            let target = &mut view.positions[position];
+            target.id = id.to_string();
+            target.assigned = true;
+            view.status = format!("{} attribué à {}", id, target.name);
+            view.active = None;
+        });
+    }
+
+    /// Writes the assigned IDs to the configuration file.
+    fn save(&self) {
+        let changed: Vec<WizardPosition> =
+            self.view.borrow().positions.iter().filter(|p| p.assigned).cloned().collect();
+        if changed.is_empty() {
+            self.update(None, "Aucune modification à enregistrer");
+            return;
+        }
+        match write_mapping(&self.config_path, &changed) {
+            Ok(()) => {
+                for position in &changed {
+                    self.audit
+                        .record(AuditSource::Ui, "sensor mapped", format!("{} = {}", position.name, position.id));
+                }
+                self.view.send_modify(|view| {
+                    view.positions.iter_mut().for_each(|p| p.assigned = false);
+                    view.active = None;
+                    view.status = "Enregistré, redémarrez boilert pour appliquer".to_string();
+                });
+            }
+            Err(e) => {
+                warning!("Failed to save the sensor mapping: {:#}", e);
+                self.update(None, "Échec de l'enregistrement");
+            }
+        }
+    }
+
+    fn update(&self, active: Option<usize>, status: &str) {
+        self.view.send_modify(|view| {
+            view.active = active;
+            view.status = status.to_string();
+        });
+    }
+}
+
+/// Reads every temperature sensor on the 1-Wire bus, skipping failed reads.
+async fn read_all(board: Board) -> Vec<(String, f32)> {
+    tokio::task::spawn_blocking(move || {
+        sensors::discover_w1(&board)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|id| {
+                let temp = W1Sensor::new(&board, &id).read().ok()?;
+                Some((id, temp))
+            })
+            .collect()
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Sets the `id` of the `[[sensors]]` entries named after `positions`, keeping the
+/// rest of the file (comments, formatting) unchanged. Positions missing from the file
+/// (auto-discovered sensors) are appended.
+fn write_mapping(path: &std::path::Path, positions: &[WizardPosition]) -> Result<()> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut doc: toml_edit::DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    let sensors = doc
+        .entry("sensors")
+        .or_insert_with(|| toml_edit::ArrayOfTables::new().into())
+        .as_array_of_tables_mut()
+        .context("`sensors` is not an array of tables")?;
+    for position in positions {
+        let existing = sensors
+            .iter_mut()
+            .find(|t| t.get("name").and_then(|n| n.as_str()) == Some(position.name.as_str()));
+        if let Some(table) = existing {
+            table["id"] = toml_edit::value(position.id.as_str());
+        } else {
+            let mut table = toml_edit::Table::new();
+            table["name"] = toml_edit::value(position.name.as_str());
+            table["id"] = toml_edit::value(position.id.as_str());
+            sensors.push(table);
+        }
+    }
+    crate::state::write_atomic(path, doc.to_string().as_bytes())
+}